@@ -0,0 +1,458 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use router_config_lib::AltManagerConfig;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_program::address_lookup_table::instruction::{
+    create_lookup_table, extend_lookup_table,
+};
+use solana_program::address_lookup_table::state::AddressLookupTable;
+use solana_program::address_lookup_table::AddressLookupTableAccount;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::message::Message;
+use solana_sdk::signature::Signer;
+use solana_sdk::transaction::Transaction;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::key_source::{sign_data, WalletSigner};
+use crate::prelude::*;
+use crate::util::tokio_spawn;
+
+/// Hard limit of the address lookup table program
+const MAX_ADDRESSES_PER_TABLE: usize = 256;
+/// Keeps each extend transaction well under the packet size
+const MAX_ADDRESSES_PER_EXTEND: usize = 20;
+/// Offset of the authority pubkey in the lookup table account (after the option tag)
+const AUTHORITY_OFFSET: usize = 22;
+
+const DEFAULT_REFRESH_INTERVAL_SECS: u64 = 60;
+const DEFAULT_MIN_ACCOUNT_HITS: u32 = 3;
+const DEFAULT_MAX_TABLES: usize = 8;
+const DEFAULT_WARMUP_MS: u64 = 1_000;
+
+struct ManagedTable {
+    key: Pubkey,
+    /// addresses usable in transactions
+    active: Vec<Pubkey>,
+    /// addresses extended recently, usable once the warm-up delay has elapsed
+    pending: Vec<(Instant, Vec<Pubkey>)>,
+}
+
+impl ManagedTable {
+    fn len(&self) -> usize {
+        self.active.len() + self.pending.iter().map(|(_, p)| p.len()).sum::<usize>()
+    }
+
+    fn contains(&self, address: &Pubkey) -> bool {
+        self.active.contains(address) || self.pending.iter().any(|(_, p)| p.contains(address))
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct AltPlan {
+    /// (table, addresses to append)
+    pub extend: Vec<(Pubkey, Vec<Pubkey>)>,
+    /// addresses of each table to create
+    pub create: Vec<Vec<Pubkey>>,
+}
+
+impl AltPlan {
+    pub fn is_empty(&self) -> bool {
+        self.extend.is_empty() && self.create.is_empty()
+    }
+}
+
+/// Maintains wallet-owned lookup tables holding the accounts of the swaps we send
+/// that are not covered by the tables returned with the swap
+pub struct AltManager {
+    authority: Pubkey,
+    dry_run: bool,
+    min_account_hits: u32,
+    max_tables: usize,
+    warmup: Duration,
+    /// account -> number of sent swaps using it, halved after each planning round
+    usage: Mutex<HashMap<Pubkey, u32>>,
+    tables: RwLock<Vec<ManagedTable>>,
+}
+
+impl AltManager {
+    pub fn new(authority: Pubkey, config: &AltManagerConfig) -> Self {
+        Self {
+            authority,
+            dry_run: config.dry_run.unwrap_or(false),
+            min_account_hits: config.min_account_hits.unwrap_or(DEFAULT_MIN_ACCOUNT_HITS),
+            max_tables: config.max_tables.unwrap_or(DEFAULT_MAX_TABLES),
+            warmup: Duration::from_millis(config.warmup_ms.unwrap_or(DEFAULT_WARMUP_MS)),
+            usage: Default::default(),
+            tables: Default::default(),
+        }
+    }
+
+    pub fn record_usage(&self, accounts: &[Pubkey]) {
+        let mut usage = self.usage.lock().unwrap();
+        for account in accounts.iter().filter(|a| **a != self.authority) {
+            *usage.entry(*account).or_default() += 1;
+        }
+    }
+
+    /// Pools of a route that was not built (rejected by pre-flight, no free inventory):
+    /// the route is still hot, once its pools are in a table the pre-flight lets it through
+    pub fn record_route_usage(&self, edges: &[Arc<Edge>], alts: &[AddressLookupTableAccount]) {
+        let uncovered = edges
+            .iter()
+            .map(|edge| edge.key())
+            .filter(|key| !alts.iter().any(|alt| alt.addresses.contains(key)))
+            .collect::<Vec<_>>();
+        self.record_usage(&uncovered);
+    }
+
+    /// Tables with at least one warmed-up address
+    pub fn tables(&self) -> Vec<AddressLookupTableAccount> {
+        self.tables
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|t| !t.active.is_empty())
+            .map(|t| AddressLookupTableAccount {
+                key: t.key,
+                addresses: t.active.clone(),
+            })
+            .collect()
+    }
+
+    fn add_table(&self, key: Pubkey, active: Vec<Pubkey>) {
+        self.tables.write().unwrap().push(ManagedTable {
+            key,
+            active,
+            pending: vec![],
+        });
+    }
+
+    fn add_pending(&self, key: &Pubkey, addresses: Vec<Pubkey>) {
+        let mut tables = self.tables.write().unwrap();
+        if let Some(table) = tables.iter_mut().find(|t| t.key == *key) {
+            table.pending.push((Instant::now(), addresses));
+        }
+    }
+
+    fn promote_warmed_up(&self) {
+        let mut tables = self.tables.write().unwrap();
+        for table in tables.iter_mut() {
+            let (ready, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut table.pending)
+                .into_iter()
+                .partition(|(at, _)| at.elapsed() >= self.warmup);
+            table.pending = pending;
+            for (_, addresses) in ready {
+                table.active.extend(addresses);
+            }
+        }
+    }
+
+    fn decay_usage(&self) {
+        let mut usage = self.usage.lock().unwrap();
+        usage.values_mut().for_each(|hits| *hits /= 2);
+        usage.retain(|_, hits| *hits > 0);
+    }
+
+    /// Hot accounts missing from our tables, most used first, spread over the free
+    /// space of existing tables and then over new tables
+    pub fn plan(&self) -> AltPlan {
+        let usage = self.usage.lock().unwrap();
+        let tables = self.tables.read().unwrap();
+
+        let mut hot = usage
+            .iter()
+            .filter(|(account, hits)| {
+                **hits >= self.min_account_hits && !tables.iter().any(|t| t.contains(account))
+            })
+            .map(|(account, hits)| (*account, *hits))
+            .collect::<Vec<_>>();
+        hot.sort_by_key(|(account, hits)| (Reverse(*hits), *account));
+        let mut hot = hot.into_iter().map(|(account, _)| account);
+
+        let mut plan = AltPlan::default();
+        for table in tables.iter() {
+            let free = MAX_ADDRESSES_PER_TABLE.saturating_sub(table.len());
+            let addresses = hot.by_ref().take(free).collect::<Vec<_>>();
+            if !addresses.is_empty() {
+                plan.extend.push((table.key, addresses));
+            }
+        }
+
+        for _ in tables.len()..self.max_tables {
+            let addresses = hot.by_ref().take(MAX_ADDRESSES_PER_TABLE).collect::<Vec<_>>();
+            if addresses.is_empty() {
+                break;
+            }
+            plan.create.push(addresses);
+        }
+
+        plan
+    }
+
+    /// Load the active tables owned by the wallet
+    pub async fn load_owned_tables(&self, rpc: &RpcClient) -> anyhow::Result<()> {
+        let accounts = rpc
+            .get_program_accounts_with_config(
+                &solana_program::address_lookup_table::program::id(),
+                RpcProgramAccountsConfig {
+                    filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                        AUTHORITY_OFFSET,
+                        self.authority.to_bytes().to_vec(),
+                    ))]),
+                    account_config: RpcAccountInfoConfig {
+                        encoding: Some(UiAccountEncoding::Base64),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        for (key, account) in accounts {
+            let Ok(table) = AddressLookupTable::deserialize(&account.data) else {
+                warn!("skipping unreadable lookup table {}", key);
+                continue;
+            };
+            if table.meta.deactivation_slot != u64::MAX {
+                continue;
+            }
+            info!(
+                "managing lookup table {} ({} addresses)",
+                key,
+                table.addresses.len()
+            );
+            self.add_table(key, table.addresses.to_vec());
+        }
+
+        Ok(())
+    }
+
+    async fn apply(
+        &self,
+        rpc: &RpcClient,
+        signer: &WalletSigner,
+        plan: AltPlan,
+    ) -> anyhow::Result<()> {
+        for (table, addresses) in plan.extend {
+            self.extend(rpc, signer, table, addresses).await?;
+        }
+
+        for addresses in plan.create {
+            let recent_slot = rpc
+                .get_slot_with_commitment(CommitmentConfig::finalized())
+                .await?;
+            let (ix, table) = create_lookup_table(self.authority, self.authority, recent_slot);
+            send_ix(rpc, signer, ix).await?;
+            info!("created lookup table {}", table);
+
+            self.add_table(table, vec![]);
+            self.extend(rpc, signer, table, addresses).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn extend(
+        &self,
+        rpc: &RpcClient,
+        signer: &WalletSigner,
+        table: Pubkey,
+        addresses: Vec<Pubkey>,
+    ) -> anyhow::Result<()> {
+        for chunk in addresses.chunks(MAX_ADDRESSES_PER_EXTEND) {
+            let ix = extend_lookup_table(
+                table,
+                self.authority,
+                Some(self.authority),
+                chunk.to_vec(),
+            );
+            send_ix(rpc, signer, ix).await?;
+            info!("extended lookup table {} with {} addresses", table, chunk.len());
+            self.add_pending(&table, chunk.to_vec());
+        }
+        Ok(())
+    }
+}
+
+async fn send_ix(
+    rpc: &RpcClient,
+    signer: &WalletSigner,
+    ix: Instruction,
+) -> anyhow::Result<()> {
+    let blockhash = rpc.get_latest_blockhash().await?;
+    let mut tx = Transaction::new_unsigned(Message::new_with_blockhash(
+        &[ix],
+        Some(&signer.pubkey()),
+        &blockhash,
+    ));
+    tx.signatures = vec![sign_data(signer, tx.message_data()).await?];
+    rpc.send_and_confirm_transaction(&tx).await?;
+    Ok(())
+}
+
+fn log_plan(plan: &AltPlan) {
+    for (table, addresses) in &plan.extend {
+        info!("[dry-run] would extend lookup table {} with:", table);
+        addresses.iter().for_each(|a| info!("[dry-run]   {}", a));
+    }
+    for addresses in &plan.create {
+        info!(
+            "[dry-run] would create a lookup table with {} addresses:",
+            addresses.len()
+        );
+        addresses.iter().for_each(|a| info!("[dry-run]   {}", a));
+    }
+}
+
+pub fn spawn_alt_manager_job(
+    config: &AltManagerConfig,
+    manager: Arc<AltManager>,
+    rpc: RpcClient,
+    signer: WalletSigner,
+    mut exit: broadcast::Receiver<()>,
+) -> JoinHandle<()> {
+    let refresh_interval = Duration::from_secs(
+        config
+            .refresh_interval_secs
+            .unwrap_or(DEFAULT_REFRESH_INTERVAL_SECS),
+    );
+
+    tokio_spawn("alt_manager", async move {
+        if let Err(e) = manager.load_owned_tables(&rpc).await {
+            warn!("failed to load owned lookup tables: {:?}", e);
+        }
+
+        let mut refresh = tokio::time::interval(refresh_interval);
+        // 新地址需要等待下一个slot才能使用
+        let mut warmup = tokio::time::interval(manager.warmup.max(Duration::from_millis(100)));
+
+        loop {
+            tokio::select! {
+                _ = exit.recv() => {
+                    info!("shutting down alt manager task");
+                    break;
+                }
+                _ = warmup.tick() => {
+                    manager.promote_warmed_up();
+                }
+                _ = refresh.tick() => {
+                    let plan = manager.plan();
+                    manager.decay_usage();
+                    if plan.is_empty() {
+                        continue;
+                    }
+
+                    if manager.dry_run {
+                        log_plan(&plan);
+                        continue;
+                    }
+
+                    if let Err(e) = manager.apply(&rpc, &signer, plan).await {
+                        error!("failed to update lookup tables: {:?}", e);
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::test::mock_edge;
+
+    fn edge() -> Arc<Edge> {
+        let [pool, input_mint, output_mint] = [(); 3].map(|_| Pubkey::new_unique());
+        Arc::new(mock_edge(pool, input_mint, output_mint, 1.0))
+    }
+
+    fn manager(max_tables: usize) -> AltManager {
+        AltManager::new(
+            Pubkey::new_unique(),
+            &AltManagerConfig {
+                enabled: true,
+                dry_run: Some(true),
+                refresh_interval_secs: None,
+                min_account_hits: Some(2),
+                max_tables: Some(max_tables),
+                warmup_ms: Some(0),
+            },
+        )
+    }
+
+    #[test]
+    fn should_plan_only_hot_uncovered_accounts() {
+        let manager = manager(1);
+        let covered = Pubkey::new_unique();
+        let hot = Pubkey::new_unique();
+        let cold = Pubkey::new_unique();
+        let table = Pubkey::new_unique();
+        manager.add_table(table, vec![covered]);
+
+        manager.record_usage(&[covered, hot, cold, manager.authority]);
+        manager.record_usage(&[covered, hot, manager.authority]);
+
+        let plan = manager.plan();
+        assert_eq!(plan.extend, vec![(table, vec![hot])]);
+        assert!(plan.create.is_empty());
+    }
+
+    #[test]
+    fn should_record_the_uncovered_pools_of_unsent_routes() {
+        let manager = manager(1);
+        let table = Pubkey::new_unique();
+        manager.add_table(table, vec![]);
+        let (covered, uncovered) = (edge(), edge());
+        let alts = vec![AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![covered.key()],
+        }];
+
+        let edges = vec![covered, uncovered.clone()];
+        manager.record_route_usage(&edges, &alts);
+        manager.record_route_usage(&edges, &alts);
+
+        let plan = manager.plan();
+        assert_eq!(plan.extend, vec![(table, vec![uncovered.key()])]);
+    }
+
+    #[test]
+    fn should_create_tables_when_existing_ones_are_full() {
+        let manager = manager(2);
+        let table = Pubkey::new_unique();
+        manager.add_table(
+            table,
+            (0..MAX_ADDRESSES_PER_TABLE).map(|_| Pubkey::new_unique()).collect(),
+        );
+
+        let hot = Pubkey::new_unique();
+        manager.record_usage(&[hot]);
+        manager.record_usage(&[hot]);
+
+        let plan = manager.plan();
+        assert!(plan.extend.is_empty());
+        assert_eq!(plan.create, vec![vec![hot]]);
+    }
+
+    #[test]
+    fn should_publish_addresses_after_warmup() {
+        let manager = manager(1);
+        let table = Pubkey::new_unique();
+        let address = Pubkey::new_unique();
+        manager.add_table(table, vec![]);
+        manager.add_pending(&table, vec![address]);
+
+        assert!(manager.tables().is_empty());
+        manager.promote_warmed_up();
+        assert_eq!(manager.tables()[0].addresses, vec![address]);
+    }
+}
@@ -0,0 +1,582 @@
+//! Associated token accounts of the pool wallets. Loaded from the RPC, kept up to date
+//! from the geyser wallet writes and refreshed periodically to catch closed accounts.
+//! Routes through a mint without an ATA get an idempotent create instruction in the
+//! swap, its rent is a cost of the route. The pre-create job can instead create the ATAs of all active rings ahead of time.
+//! The rent it spends is saved to a file, the budget holds across restarts.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anchor_spl::token::spl_token;
+use router_config_lib::AtaProviderConfig;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::message::Message;
+use solana_sdk::signature::Signer;
+use solana_sdk::transaction::Transaction;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_associated_token_account::{
+    get_associated_token_address, get_associated_token_address_with_program_id,
+};
+use tokio::task::JoinHandle;
+
+use crate::control::RuntimeControl;
+use crate::key_source::{sign_data, WalletSigner};
+use crate::metrics;
+use crate::prelude::*;
+use crate::routing_types::Route;
+use crate::source::token_cache::TokenCache;
+use crate::source::wallet_writes::{parse_token_account, WalletWrite};
+use crate::swap::Swap;
+use crate::util::tokio_spawn;
+use crate::utils::get_source_atas;
+
+const DEFAULT_REFRESH_INTERVAL_SECS: u64 = 300;
+const DEFAULT_PRECREATE_BATCH_SIZE: usize = 5;
+/// spl-token 账户的免租金额，Token-2022 账户的大小取决于扩展，不预先创建
+pub const TOKEN_ACCOUNT_RENT: u64 = 2_039_280;
+/// compute units of an idempotent create that creates the account
+pub const CREATE_ATA_CU: u32 = 25_000;
+
+/// Rent the pre-create job may still spend, the spent amount is saved after every change
+struct RentBudget {
+    limit: u64,
+    spent: u64,
+    path: Option<PathBuf>,
+}
+
+impl RentBudget {
+    fn load(limit: u64, path: Option<PathBuf>) -> Self {
+        let spent = match &path {
+            Some(path) if path.exists() => match fs::read_to_string(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|s| Ok(s.trim().parse::<u64>()?))
+            {
+                Ok(spent) => spent,
+                Err(e) => {
+                    // 读不出来时不再花费，避免重复使用预算
+                    error!("failed to read the spent ATA rent from {}: {:?}", path.display(), e);
+                    limit
+                }
+            },
+            _ => 0,
+        };
+        if spent > 0 {
+            info!("ATA rent budget: {} of {} lamports already spent", spent, limit);
+        }
+        Self { limit, spent, path }
+    }
+
+    fn left(&self) -> u64 {
+        self.limit.saturating_sub(self.spent)
+    }
+
+    fn charge(&mut self, lamports: u64) {
+        self.spent += lamports;
+        self.save();
+    }
+
+    fn refund(&mut self, lamports: u64) {
+        self.spent = self.spent.saturating_sub(lamports);
+        self.save();
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        // 先写临时文件再改名，中途被杀掉也不会留下半个文件
+        let tmp = path.with_extension("tmp");
+        let saved = fs::write(&tmp, self.spent.to_string()).and_then(|_| fs::rename(&tmp, path));
+        if let Err(e) = saved {
+            error!("failed to save the spent ATA rent to {}: {:?}", path.display(), e);
+        }
+    }
+}
+
+pub struct AtaProvider {
+    create_missing: bool,
+    token_cache: Arc<TokenCache>,
+    /// mint -> ATA of each wallet, spl-token and Token-2022 accounts
+    atas: RwLock<HashMap<Pubkey, HashMap<Pubkey, Pubkey>>>,
+}
+
+impl AtaProvider {
+    pub fn new(config: Option<&AtaProviderConfig>, token_cache: Arc<TokenCache>) -> Self {
+        Self {
+            create_missing: config.and_then(|c| c.create_missing).unwrap_or(true),
+            token_cache,
+            atas: Default::default(),
+        }
+    }
+
+    /// Reloads the token accounts of the wallets, keeps the known ones of a wallet the RPC fails for
+    pub async fn refresh(&self, rpc: &RpcClient, wallets: &[Pubkey]) {
+        for wallet in wallets {
+            match get_source_atas(rpc, wallet).await {
+                Ok(atas) => {
+                    debug!("wallet {} has {} ATAs", wallet, atas.len());
+                    self.atas.write().unwrap().insert(*wallet, atas);
+                }
+                Err(e) => warn!("failed to load the token accounts of {}: {:?}", wallet, e),
+            }
+        }
+    }
+
+    pub fn source_atas(&self, wallet: &Pubkey) -> HashMap<Pubkey, Pubkey> {
+        self.atas
+            .read()
+            .unwrap()
+            .get(wallet)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn ata(&self, wallet: &Pubkey, mint: &Pubkey) -> Option<Pubkey> {
+        self.atas.read().unwrap().get(wallet)?.get(mint).copied()
+    }
+
+    /// Token account write of a wallet: a new ATA is usable right away, a closed one is forgotten
+    pub fn on_wallet_write(&self, write: &WalletWrite) {
+        let Some((mint, wallet)) = parse_token_account(&write.owner, &write.data) else {
+            return;
+        };
+        // 账户的 owner 就是它的代币程序
+        let ata = get_associated_token_address_with_program_id(&wallet, &mint, &write.owner);
+        if write.pubkey != ata {
+            return;
+        }
+
+        let mut atas = self.atas.write().unwrap();
+        let wallet_atas = atas.entry(wallet).or_default();
+        if write.lamports == 0 {
+            wallet_atas.remove(&mint);
+        } else if wallet_atas.insert(mint, write.pubkey).is_none() {
+            info!("wallet {} has a new ATA for {}", wallet, mint);
+        }
+    }
+
+    fn token_program(&self, mint: &Pubkey) -> Pubkey {
+        self.token_cache
+            .token(*mint)
+            .ok()
+            .and_then(|t| t.mint_info)
+            .map_or(spl_token::ID, |info| info.token_program)
+    }
+
+    /// Create instructions for the `mints` the wallet has no ATA for and `setup` does not create
+    fn missing_atas(
+        &self,
+        wallet: &Pubkey,
+        mints: impl IntoIterator<Item = Pubkey>,
+        setup: &[Instruction],
+    ) -> Vec<Instruction> {
+        let atas = self.atas.read().unwrap();
+        let known = atas.get(wallet);
+        let mut created: HashSet<Pubkey> = setup
+            .iter()
+            .filter(|ix| ix.program_id == spl_associated_token_account::ID)
+            .filter_map(|ix| ix.accounts.get(1).map(|a| a.pubkey))
+            .collect();
+
+        let mut ixs = vec![];
+        for mint in mints {
+            if known.map_or(false, |k| k.contains_key(&mint)) {
+                continue;
+            }
+            let token_program = self.token_program(&mint);
+            let ata = get_associated_token_address_with_program_id(wallet, &mint, &token_program);
+            if created.insert(ata) {
+                ixs.push(create_associated_token_account_idempotent(
+                    wallet,
+                    wallet,
+                    &mint,
+                    &token_program,
+                ));
+            }
+        }
+        ixs
+    }
+
+    /// Number of ATAs `with_missing_atas` creates at most for the route, known before the swap
+    /// is built so that their size, compute units and rent are counted when checking the route
+    pub fn missing_ata_count(&self, wallet: &Pubkey, route: &Route) -> usize {
+        if !self.create_missing {
+            return 0;
+        }
+        self.missing_atas(wallet, route_mints(route), &[]).len()
+    }
+
+    /// Prepends the creation of the ATAs the route needs and the wallet does not have yet,
+    /// and raises the compute unit estimate for them
+    pub fn with_missing_atas(&self, wallet: &Pubkey, route: &Route, swap: Arc<Swap>) -> Arc<Swap> {
+        if !self.create_missing {
+            return swap;
+        }
+        let mut setup_instructions =
+            self.missing_atas(wallet, route_mints(route), &swap.setup_instructions);
+        if setup_instructions.is_empty() {
+            return swap;
+        }
+        debug!(
+            "creating {} missing ATA(s) for wallet {}",
+            setup_instructions.len(),
+            wallet
+        );
+        metrics::ATAS_CREATED_IN_SWAP.inc_by(setup_instructions.len() as u64);
+        let cu_estimate = swap.cu_estimate + CREATE_ATA_CU * setup_instructions.len() as u32;
+        setup_instructions.extend(swap.setup_instructions.iter().cloned());
+        Arc::new(Swap {
+            setup_instructions,
+            cu_estimate,
+            ..(*swap).clone()
+        })
+    }
+
+    /// spl-token mints of the active rings the wallet has no ATA for
+    fn precreate_mints(&self, wallet: &Pubkey, control: &RuntimeControl) -> Vec<Pubkey> {
+        let state = control.snapshot();
+        let atas = self.source_atas(wallet);
+        control
+            .rings()
+            .iter()
+            .filter(|ring| state.allows_ring(ring))
+            .flat_map(|ring| ring.edges.iter().map(|e| e.output_mint))
+            .unique()
+            .filter(|mint| !atas.contains_key(mint) && self.token_program(mint) == spl_token::ID)
+            .collect()
+    }
+
+    /// Creates the missing ATAs of the active rings while `budget` covers their rent
+    async fn precreate(
+        &self,
+        rpc: &RpcClient,
+        signer: &WalletSigner,
+        control: &RuntimeControl,
+        batch_size: usize,
+        budget: &mut RentBudget,
+    ) {
+        let wallet = signer.pubkey();
+        let mints = self.precreate_mints(&wallet, control);
+        if mints.is_empty() {
+            return;
+        }
+        let affordable = (budget.left() / TOKEN_ACCOUNT_RENT) as usize;
+        if affordable < mints.len() {
+            warn!(
+                "ATA rent budget left for {} of the {} missing ATAs of wallet {}",
+                affordable,
+                mints.len(),
+                wallet
+            );
+        }
+
+        for batch in mints[..affordable.min(mints.len())].chunks(batch_size) {
+            let ixs = batch
+                .iter()
+                .map(|mint| {
+                    create_associated_token_account_idempotent(
+                        &wallet,
+                        &wallet,
+                        mint,
+                        &spl_token::ID,
+                    )
+                })
+                .collect_vec();
+            // 发送前先扣除，确认超时的交易仍可能落地
+            budget.charge(TOKEN_ACCOUNT_RENT * batch.len() as u64);
+            let created = match send_ixs(rpc, signer, &ixs).await {
+                Ok(()) => batch.to_vec(),
+                Err(e) => {
+                    error!("failed to pre-create ATAs for wallet {}: {:?}", wallet, e);
+                    match existing_atas(rpc, &wallet, batch).await {
+                        Ok(created) => {
+                            budget.refund(TOKEN_ACCOUNT_RENT * (batch.len() - created.len()) as u64);
+                            created
+                        }
+                        Err(e) => {
+                            // 不知道是否落地，按已花费计算
+                            warn!("failed to check the pre-created ATAs of wallet {}: {:?}", wallet, e);
+                            vec![]
+                        }
+                    }
+                }
+            };
+            if !created.is_empty() {
+                metrics::ATAS_PRECREATED.inc_by(created.len() as u64);
+                metrics::ATA_RENT_SPENT.inc_by(TOKEN_ACCOUNT_RENT * created.len() as u64);
+                info!(
+                    "pre-created {} ATA(s) for wallet {}: {}",
+                    created.len(),
+                    wallet,
+                    created.iter().join(",")
+                );
+                let mut atas = self.atas.write().unwrap();
+                let wallet_atas = atas.entry(wallet).or_default();
+                for mint in &created {
+                    wallet_atas.insert(*mint, get_associated_token_address(&wallet, mint));
+                }
+            }
+            if created.len() < batch.len() {
+                return;
+            }
+        }
+    }
+}
+
+fn route_mints(route: &Route) -> impl Iterator<Item = Pubkey> + '_ {
+    route
+        .steps
+        .iter()
+        .flat_map(|s| [s.edge.input_mint, s.edge.output_mint])
+        .unique()
+}
+
+async fn send_ixs(
+    rpc: &RpcClient,
+    signer: &WalletSigner,
+    ixs: &[Instruction],
+) -> anyhow::Result<()> {
+    let blockhash = rpc.get_latest_blockhash().await?;
+    let mut tx = Transaction::new_unsigned(Message::new_with_blockhash(
+        ixs,
+        Some(&signer.pubkey()),
+        &blockhash,
+    ));
+    tx.signatures = vec![sign_data(signer, tx.message_data()).await?];
+    rpc.send_and_confirm_transaction(&tx).await?;
+    Ok(())
+}
+
+/// The `mints` the wallet holds an ATA for
+async fn existing_atas(
+    rpc: &RpcClient,
+    wallet: &Pubkey,
+    mints: &[Pubkey],
+) -> anyhow::Result<Vec<Pubkey>> {
+    let atas = mints
+        .iter()
+        .map(|mint| get_associated_token_address(wallet, mint))
+        .collect_vec();
+    let accounts = rpc.get_multiple_accounts(&atas).await?;
+    Ok(mints
+        .iter()
+        .zip(accounts)
+        .filter(|(_, account)| account.is_some())
+        .map(|(mint, _)| *mint)
+        .collect())
+}
+
+/// Pre-creates in its own task, the confirmations must not hold up the wallet writes
+fn spawn_precreate_job(
+    config: &AtaProviderConfig,
+    provider: Arc<AtaProvider>,
+    rpc: RpcClient,
+    signers: Vec<WalletSigner>,
+    control: Arc<RuntimeControl>,
+    interval: Duration,
+    mut exit: broadcast::Receiver<()>,
+) -> JoinHandle<()> {
+    let batch_size = config
+        .precreate_batch_size
+        .unwrap_or(DEFAULT_PRECREATE_BATCH_SIZE);
+    let mut budget = RentBudget::load(
+        config.rent_budget_lamports.unwrap_or_default(),
+        config
+            .rent_spent_path
+            .as_ref()
+            .map(|p| PathBuf::from(shellexpand::tilde(p).to_string())),
+    );
+
+    tokio_spawn("ata_precreate", async move {
+        let mut tick = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = exit.recv() => break,
+                _ = tick.tick() => {}
+            }
+            let precreate = async {
+                for signer in &signers {
+                    provider
+                        .precreate(&rpc, signer, &control, batch_size, &mut budget)
+                        .await;
+                }
+            };
+            // 已发送的交易在退出后仍可能落地，租金已经扣除
+            tokio::select! {
+                _ = exit.recv() => break,
+                _ = precreate => {}
+            }
+        }
+        info!("shutting down ata pre-create task");
+    })
+}
+
+pub fn spawn_ata_provider_job(
+    config: &AtaProviderConfig,
+    provider: Arc<AtaProvider>,
+    rpc: RpcClient,
+    signers: Vec<WalletSigner>,
+    control: Arc<RuntimeControl>,
+    wallet_writes: async_channel::Receiver<WalletWrite>,
+    mut exit: broadcast::Receiver<()>,
+) -> JoinHandle<()> {
+    let refresh_interval = Duration::from_secs(
+        config
+            .refresh_interval_secs
+            .unwrap_or(DEFAULT_REFRESH_INTERVAL_SECS),
+    );
+    let wallets = signers.iter().map(|s| s.pubkey()).collect_vec();
+    let mut precreate_job = config.precreate.then(|| {
+        spawn_precreate_job(
+            config,
+            provider.clone(),
+            RpcClient::new_with_commitment(rpc.url(), rpc.commitment()),
+            signers,
+            control,
+            refresh_interval,
+            exit.resubscribe(),
+        )
+    });
+
+    tokio_spawn("ata_provider", async move {
+        let mut refresh = tokio::time::interval(refresh_interval);
+        loop {
+            tokio::select! {
+                _ = exit.recv() => {
+                    info!("shutting down ata provider task");
+                    if let Some(precreate_job) = precreate_job.take() {
+                        let _ = precreate_job.await;
+                    }
+                    break;
+                }
+                write = wallet_writes.recv() => {
+                    match write {
+                        Ok(write) => provider.on_wallet_write(&write),
+                        Err(e) => {
+                            error!("wallet writes channel closed: {}", e);
+                            break;
+                        }
+                    }
+                }
+                _ = refresh.tick() => {
+                    provider.refresh(&rpc, &wallets).await;
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_spl::token_2022::spl_token_2022;
+    use solana_sdk::signature::Signature;
+
+    const TOKEN_ACCOUNT_LEN: usize = 165;
+
+    fn token_account_write(
+        program: Pubkey,
+        wallet: Pubkey,
+        mint: Pubkey,
+        data_len: usize,
+    ) -> WalletWrite {
+        let mut data = vec![0u8; data_len];
+        data[0..32].copy_from_slice(mint.as_ref());
+        data[32..64].copy_from_slice(wallet.as_ref());
+        WalletWrite {
+            pubkey: get_associated_token_address_with_program_id(&wallet, &mint, &program),
+            owner: program,
+            slot: 1,
+            write_version: 1,
+            lamports: TOKEN_ACCOUNT_RENT,
+            data,
+            txn_signature: Some(Signature::default()),
+        }
+    }
+
+    #[test]
+    fn should_create_only_the_atas_the_wallet_does_not_have() {
+        let provider = AtaProvider::new(None, Arc::new(TokenCache::new(HashMap::new())));
+        let wallet = Pubkey::new_unique();
+        let (held, missing, created_by_jup) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        let write = token_account_write(spl_token::ID, wallet, held, TOKEN_ACCOUNT_LEN);
+        provider.on_wallet_write(&write);
+        assert_eq!(
+            provider.source_atas(&wallet).get(&held),
+            Some(&write.pubkey)
+        );
+
+        let setup = vec![create_associated_token_account_idempotent(
+            &wallet,
+            &wallet,
+            &created_by_jup,
+            &spl_token::ID,
+        )];
+        let ixs = provider.missing_atas(&wallet, [held, missing, created_by_jup, missing], &setup);
+        assert_eq!(ixs.len(), 1);
+        assert_eq!(
+            ixs[0].accounts[1].pubkey,
+            get_associated_token_address(&wallet, &missing)
+        );
+
+        // 关闭的账户需要重新创建
+        provider.on_wallet_write(&WalletWrite {
+            lamports: 0,
+            ..write
+        });
+        assert_eq!(provider.missing_atas(&wallet, [held], &[]).len(), 1);
+    }
+
+    #[test]
+    fn should_track_token_2022_atas_created_in_a_swap() {
+        let provider = AtaProvider::new(None, Arc::new(TokenCache::new(HashMap::new())));
+        let wallet = Pubkey::new_unique();
+        let (plain, with_extensions) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let program = spl_token_2022::ID;
+        let write = token_account_write(program, wallet, plain, TOKEN_ACCOUNT_LEN);
+        provider.on_wallet_write(&write);
+        // 带扩展的账户：165 字节之后是账户类型 2 和扩展数据
+        let len = TOKEN_ACCOUNT_LEN + 8;
+        let mut write = token_account_write(program, wallet, with_extensions, len);
+        write.data[TOKEN_ACCOUNT_LEN] = 2;
+        provider.on_wallet_write(&write);
+        let atas = provider.source_atas(&wallet);
+        assert_eq!(atas.len(), 2);
+        assert_eq!(atas.get(&with_extensions), Some(&write.pubkey));
+
+        // 带扩展的 mint 账户类型是 1，不是 token 账户
+        let mint = Pubkey::new_unique();
+        let mut write = token_account_write(program, wallet, mint, len);
+        write.data[TOKEN_ACCOUNT_LEN] = 1;
+        provider.on_wallet_write(&write);
+        assert!(!provider.source_atas(&wallet).contains_key(&mint));
+    }
+
+    #[test]
+    fn should_keep_the_spent_rent_across_restarts() {
+        let path =
+            std::env::temp_dir().join(format!("fly-router-ata-rent-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut budget = RentBudget::load(10 * TOKEN_ACCOUNT_RENT, Some(path.clone()));
+        assert_eq!(budget.left(), 10 * TOKEN_ACCOUNT_RENT);
+        budget.charge(3 * TOKEN_ACCOUNT_RENT);
+        budget.refund(TOKEN_ACCOUNT_RENT);
+
+        let budget = RentBudget::load(10 * TOKEN_ACCOUNT_RENT, Some(path.clone()));
+        assert_eq!(budget.left(), 8 * TOKEN_ACCOUNT_RENT);
+
+        // 无法读取时不再花费
+        fs::write(&path, "garbage").unwrap();
+        assert_eq!(RentBudget::load(10 * TOKEN_ACCOUNT_RENT, Some(path.clone())).left(), 0);
+        fs::remove_file(&path).unwrap();
+    }
+}
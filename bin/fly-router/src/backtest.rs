@@ -0,0 +1,459 @@
+//! Offline backtest: replays a stream recorded by fly-router (`[recorder]`) through the edge
+//! updaters and the ring executor, and writes the routes the bot would have sent.
+//!
+//! RUST_LOG=info fly-backtest --config my_config.toml --stream stream.lz4 --rpc-dump rpc_dump.lz4
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Context;
+use clap::Parser;
+use mango_feeds_connector::chain_data::{AccountData, ChainData, SlotData, SlotStatus};
+use router_config_lib::check_file;
+use router_feed_lib::account_write::{AccountOrSnapshotUpdate, AccountWrite};
+use router_feed_lib::get_program_account::FeedMetadata;
+use router_lib::dex::{AccountProviderView, ChainDataAccountProvider};
+use router_lib::price_feeds::price_cache::PriceCache;
+use router_lib::price_feeds::price_feed::PriceUpdate;
+use router_lib::test_tools::rpc::rpc_replayer_client;
+use serde_derive::Serialize;
+use solana_sdk::account::WritableAccount;
+use solana_sdk::clock::Epoch;
+use tokio::task::JoinHandle;
+
+use fly_router::control::{RuntimeControl, RuntimeParams};
+use fly_router::edge_updater::spawn_updater_job;
+use fly_router::hot_mints::HotMintsCache;
+use fly_router::ix_builder::default_cu_estimate;
+use fly_router::ix_sender_executor::DEFAULT_COMPUTE_UNIT_PRICE_MICRO_LAMPORTS;
+use fly_router::prelude::*;
+use fly_router::routing_types::Route;
+use fly_router::sender::jito_ix_sender::{bundle_fees, calculate_tip, COMPUTE_UNIT_LIMIT_MARGIN};
+use fly_router::sender::tip_pricer::TipPricer;
+use fly_router::source::stream_recorder::{RecordedEvent, StreamReader};
+use fly_router::source::token_cache::TokenCache;
+use fly_router::source::token_universe::TokenUniverse;
+use fly_router::{dex, ring_executor, syscallstubs};
+
+#[derive(Parser, Debug)]
+#[clap(name = "fly-backtest", about = "Replay a recorded account stream through the ring engine")]
+struct Args {
+    /// fly-router config file
+    #[clap(long)]
+    config: String,
+    /// stream log written by the recorder
+    #[clap(long)]
+    stream: String,
+    /// RPC dump of the dex initialization written by the recorder
+    #[clap(long)]
+    rpc_dump: String,
+    /// replay speed relative to the recording, 0 replays as fast as possible
+    #[clap(long, default_value = "0")]
+    speed: f64,
+    /// overrides sender.in_amounts, comma separated
+    #[clap(long, use_value_delimiter = true)]
+    in_amounts: Option<Vec<u64>>,
+    /// overrides sender.expected_gain
+    #[clap(long)]
+    expected_gain: Option<u64>,
+    /// overrides ring.max_path_length
+    #[clap(long)]
+    max_path_length: Option<usize>,
+    /// USD prices as MINT=PRICE, comma separated, to value the tip and fees of rings not
+    /// starting in SOL; SOL needs a price too
+    #[clap(long, use_value_delimiter = true)]
+    prices: Option<Vec<String>>,
+    /// JSONL output of the simulated routes
+    #[clap(long, default_value = "backtest_routes.jsonl")]
+    out: String,
+    /// time given to the ring executor to drain after the last replayed event
+    #[clap(long, default_value = "2000")]
+    grace_ms: u64,
+}
+
+#[derive(Serialize)]
+struct SimulatedRoute {
+    slot: u64,
+    input_mint: String,
+    in_amount: u64,
+    out_amount: u64,
+    /// tip and transaction fees in lamports, None without a price for the input mint
+    tip: Option<u64>,
+    fees: Option<u64>,
+    /// out_amount - in_amount minus the tip and fees valued in the input mint
+    pnl: i128,
+    pnl_ui: f64,
+    steps: Vec<String>,
+}
+
+#[derive(Default)]
+struct MintSummary {
+    routes: u64,
+    pnl_ui: f64,
+    /// routes whose tip and fees could not be valued, counted in pnl_ui without them
+    unpriced: u64,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> anyhow::Result<()> {
+    router_feed_lib::utils::tracing_subscriber_init();
+    syscallstubs::deactivate_program_logs();
+
+    let args = Args::parse();
+    let mut config = check_file(&args.config)?;
+    if let Some(in_amounts) = &args.in_amounts {
+        config.sender.in_amounts = Some(in_amounts.clone());
+    }
+    if let Some(expected_gain) = args.expected_gain {
+        config.sender.expected_gain = Some(expected_gain);
+    }
+    if let Some(max_path_length) = args.max_path_length {
+        config.ring.max_path_length = Some(max_path_length);
+    }
+
+    let source_config = config
+        .sources
+        .iter()
+        .find(|x| x.region.is_none())
+        .cloned()
+        .context("did not find a source config for default region")?;
+
+    // 离线回放时代币列表应来自 pinned_snapshot，避免和录制时不一致
+    let universe_config = config
+        .token_universe
+        .clone()
+        .unwrap_or_else(TokenUniverse::default_config);
+    if universe_config.pinned_snapshot.is_none() {
+        warn!("token_universe.pinned_snapshot is not set, the token list may differ from the recording");
+    }
+    let token_cache = Arc::new(TokenCache::new(
+        TokenUniverse {
+            config: &universe_config,
+            rpc_http_url: &source_config.rpc_http_url,
+            max_gma_accounts: source_config.number_of_accounts_per_gma.unwrap_or(100),
+            birdeye_token: &source_config.birdeye_token,
+        }
+        .build()
+        .await?,
+    ));
+
+    let (mut router_rpc, _) = rpc_replayer_client(&args.rpc_dump);
+    let dexs = dex::build_dexs(&config, &mut router_rpc, &token_cache, &None, None).await?;
+    let edges = dexs.iter().flat_map(|x| x.edges()).collect_vec();
+    info!("replaying with {} dexes, {} edges", dexs.len(), edges.len());
+
+    let (exit_sender, _) = broadcast::channel::<()>(1);
+    let (account_update_sender, _) = broadcast::channel(4 * 1024 * 1024);
+    let (slot_sender, _) = broadcast::channel::<u64>(1024);
+    let (metadata_sender, _) = broadcast::channel::<FeedMetadata>(500);
+    // 只有 --prices 给出的价格，其他边价格走 implied/fallback
+    let prices = args
+        .prices
+        .iter()
+        .flatten()
+        .map(|p| parse_price(p))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let (price_sender, _) = broadcast::channel::<PriceUpdate>(prices.len().max(1));
+    let (register_mint_sender, _register_mint_receiver) = async_channel::unbounded::<Pubkey>();
+    let (edge_price_sender, edge_price_updates) = async_channel::unbounded::<Arc<Edge>>();
+    let (route_sender, route_receiver) = async_channel::unbounded::<Arc<Route>>();
+
+    let chain_data = Arc::new(RwLock::new(ChainData::new()));
+    let chain_data_wrapper =
+        Arc::new(ChainDataAccountProvider::new(chain_data.clone())) as AccountProviderView;
+    let (price_cache, price_cache_job) =
+        PriceCache::new(exit_sender.subscribe(), price_sender.subscribe());
+    for price in prices {
+        let _ = price_sender.send(price);
+    }
+
+    let path_warming_amounts = config
+        .routing
+        .path_warming_amounts
+        .clone()
+        .unwrap_or(vec![100]);
+
+    let ready_channels = dexs
+        .iter()
+        .map(|_| async_channel::bounded::<()>(1))
+        .collect_vec();
+    // 回放的代币固定，不会在运行时新增边
+    let (dex_edge_sender, _) = broadcast::channel(1);
+    let (_ring_edge_sender, ring_edge_receiver) = async_channel::unbounded();
+    let update_jobs = dexs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, dex)| {
+            spawn_updater_job(
+                dex,
+                &config,
+                chain_data_wrapper.clone(),
+                token_cache.clone(),
+                price_cache.clone(),
+                path_warming_amounts.clone(),
+                register_mint_sender.clone(),
+                ready_channels[i].0.clone(),
+                slot_sender.subscribe(),
+                account_update_sender.subscribe(),
+                metadata_sender.subscribe(),
+                price_sender.subscribe(),
+                dex_edge_sender.subscribe(),
+                exit_sender.subscribe(),
+                edge_price_sender.clone(),
+            )
+        })
+        .collect_vec();
+
+    let hot_mints = Arc::new(RwLock::new(HotMintsCache::new(&config.hot_mints)));
+    let control = Arc::new(RuntimeControl::new(&config));
+    let ring_executor_job = ring_executor::spawn_ring_executor_job(
+        &config,
+        chain_data_wrapper.clone(),
+        token_cache.clone(),
+        path_warming_amounts,
+        edges,
+        edge_price_updates,
+        route_sender,
+        token_cache.subscribe(),
+        ring_edge_receiver,
+        hot_mints,
+        control.clone(),
+        None,
+        exit_sender.subscribe(),
+    );
+
+    let costs = RouteCosts {
+        params: control.snapshot().params.clone(),
+        compute_unit_price_micro_lamports: config
+            .sender
+            .compute_unit_price_micro_lamports
+            .unwrap_or(DEFAULT_COMPUTE_UNIT_PRICE_MICRO_LAMPORTS),
+        tip_pricer: TipPricer::new(price_cache.clone(), token_cache.clone()),
+    };
+    let collector_job =
+        spawn_route_collector(&args.out, token_cache.clone(), costs, route_receiver)?;
+
+    let replayed = replay(
+        &args,
+        &chain_data,
+        &account_update_sender,
+        &slot_sender,
+        &metadata_sender,
+    )
+    .await?;
+    info!("replayed {} events, waiting {}ms for the ring executor", replayed, args.grace_ms);
+    tokio::time::sleep(Duration::from_millis(args.grace_ms)).await;
+
+    let _ = exit_sender.send(());
+    for job in update_jobs {
+        let _ = job.await;
+    }
+    let _ = ring_executor_job.await;
+    let _ = price_cache_job.await;
+    // ring executor 退出后 route channel 关闭，collector 随之结束
+    let summary = collector_job.await?;
+
+    println!(
+        "simulated routes: {}",
+        summary.values().map(|s| s.routes).sum::<u64>()
+    );
+    for (mint, s) in summary.iter().sorted_by_key(|(m, _)| m.to_string()) {
+        let symbol = token_cache
+            .get_symbol_by_mint(*mint)
+            .unwrap_or_else(|| mint.to_string());
+        println!("  {}: {} routes, pnl {:.6}", symbol, s.routes, s.pnl_ui);
+        if s.unpriced > 0 {
+            println!(
+                "    {} routes without tip and fees, pass --prices for {} and SOL",
+                s.unpriced, symbol
+            );
+        }
+    }
+    println!("routes written to {}", args.out);
+
+    Ok(())
+}
+
+/// Feeds the recorded events into chain data and the updater channels, paced by `--speed`
+async fn replay(
+    args: &Args,
+    chain_data: &Arc<RwLock<ChainData>>,
+    account_update_sender: &broadcast::Sender<(Pubkey, Pubkey, u64)>,
+    slot_sender: &broadcast::Sender<u64>,
+    metadata_sender: &broadcast::Sender<FeedMetadata>,
+) -> anyhow::Result<u64> {
+    let reader = StreamReader::open(&args.stream)?;
+    let started_at = tokio::time::Instant::now();
+    let mut first_received_at = None;
+    let mut count = 0u64;
+
+    for entry in reader {
+        if args.speed > 0.0 {
+            let first = *first_received_at.get_or_insert(entry.received_at_ms);
+            let offset = entry.received_at_ms.saturating_sub(first) as f64 / args.speed;
+            tokio::time::sleep_until(started_at + Duration::from_millis(offset as u64)).await;
+        }
+        count += 1;
+
+        if let Some(metadata) = entry.event.metadata() {
+            let _ = metadata_sender.send(metadata);
+            continue;
+        }
+        match entry.event {
+            RecordedEvent::Slot {
+                slot,
+                parent,
+                status,
+            } => {
+                let status: SlotStatus = status.into();
+                chain_data.write().unwrap().update_slot(SlotData {
+                    slot,
+                    parent,
+                    status,
+                    chain: 0,
+                });
+                if status == SlotStatus::Processed {
+                    let _ = slot_sender.send(slot);
+                }
+            }
+            event => {
+                let writes = match event.into_account_update() {
+                    Some(AccountOrSnapshotUpdate::AccountUpdate(write)) => vec![write],
+                    Some(AccountOrSnapshotUpdate::SnapshotUpdate(writes)) => writes,
+                    None => continue,
+                };
+                let mut chain_data = chain_data.write().unwrap();
+                for write in writes {
+                    apply_account_write(&mut chain_data, account_update_sender, write);
+                }
+            }
+        }
+
+        // 全速回放时让出执行权，避免 broadcast 积压导致 updater lag
+        if count % 1024 == 0 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    Ok(count)
+}
+
+fn apply_account_write(
+    chain_data: &mut ChainData,
+    account_update_sender: &broadcast::Sender<(Pubkey, Pubkey, u64)>,
+    write: AccountWrite,
+) {
+    chain_data.update_account(
+        write.pubkey,
+        AccountData {
+            slot: write.slot,
+            write_version: write.write_version,
+            account: WritableAccount::create(
+                write.lamports,
+                write.data,
+                write.owner,
+                write.executable,
+                write.rent_epoch as Epoch,
+            ),
+        },
+    );
+    let _ = account_update_sender.send((write.pubkey, write.owner, write.slot));
+}
+
+fn parse_price(s: &str) -> anyhow::Result<PriceUpdate> {
+    let (mint, price) = s
+        .split_once('=')
+        .with_context(|| format!("price {} is not MINT=PRICE", s))?;
+    Ok(PriceUpdate {
+        mint: Pubkey::from_str(mint.trim()).with_context(|| format!("invalid mint {}", mint))?,
+        price: price
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid price {}", price))?,
+    })
+}
+
+/// What the jito sender would pay to send a route
+struct RouteCosts {
+    params: RuntimeParams,
+    compute_unit_price_micro_lamports: u64,
+    tip_pricer: TipPricer,
+}
+
+impl RouteCosts {
+    /// Tip and fees in lamports, and their sum in native units of the input mint
+    fn of(&self, route: &Route) -> anyhow::Result<(u64, u64, u64)> {
+        // 回测不构建指令，按默认的每跳 CU 估算
+        let compute_unit_limit = default_cu_estimate(route.steps.len()) + COMPUTE_UNIT_LIMIT_MARGIN;
+        let profit = route.out_amount.saturating_sub(route.in_amount);
+        let profit = self.tip_pricer.to_lamports(&route.input_mint, profit)?;
+        let tip = calculate_tip(&self.params, profit, compute_unit_limit);
+        let fees = bundle_fees(compute_unit_limit, self.compute_unit_price_micro_lamports);
+        let cost = self
+            .tip_pricer
+            .from_lamports(&route.input_mint, tip + fees)?;
+        Ok((tip, fees, cost))
+    }
+}
+
+/// Writes every route the ring executor emits, returns the route count and UI pnl per input mint
+fn spawn_route_collector(
+    out: &str,
+    token_cache: Arc<TokenCache>,
+    costs: RouteCosts,
+    route_receiver: async_channel::Receiver<Arc<Route>>,
+) -> anyhow::Result<JoinHandle<HashMap<Pubkey, MintSummary>>> {
+    let mut writer = BufWriter::new(
+        File::create(out).with_context(|| format!("creating backtest output {}", out))?,
+    );
+
+    Ok(tokio::spawn(async move {
+        let mut summary: HashMap<Pubkey, MintSummary> = HashMap::new();
+        while let Ok(route) = route_receiver.recv().await {
+            let (tip, fees, cost) = match costs.of(&route) {
+                Ok((tip, fees, cost)) => (Some(tip), Some(fees), cost),
+                Err(e) => {
+                    debug!("cannot value the tip of route {}: {:#}", route.id, e);
+                    (None, None, 0)
+                }
+            };
+            let pnl = route.out_amount as i128 - route.in_amount as i128 - cost as i128;
+            let decimals = token_cache
+                .token(route.input_mint)
+                .map(|t| t.decimals)
+                .unwrap_or(0);
+            let pnl_ui = pnl as f64 / 10f64.powi(decimals as i32);
+
+            let record = SimulatedRoute {
+                slot: route.slot,
+                input_mint: route.input_mint.to_string(),
+                in_amount: route.in_amount,
+                out_amount: route.out_amount,
+                tip,
+                fees,
+                pnl,
+                pnl_ui,
+                steps: route.steps.iter().map(|s| s.edge.desc()).collect(),
+            };
+            match serde_json::to_string(&record) {
+                Ok(line) => {
+                    if let Err(e) = writeln!(writer, "{}", line) {
+                        error!("failed to write simulated route: {:?}", e);
+                    }
+                }
+                Err(e) => error!("failed to serialize simulated route: {:?}", e),
+            }
+
+            let entry = summary.entry(route.input_mint).or_default();
+            entry.routes += 1;
+            entry.pnl_ui += pnl_ui;
+            if tip.is_none() {
+                entry.unpriced += 1;
+            }
+        }
+        let _ = writer.flush();
+        summary
+    }))
+}
@@ -0,0 +1,323 @@
+//! Reloads the config file on SIGHUP, or when it changes on disk, without restarting.
+//! Only the settings held by `RuntimeControl` are applied, any other change is logged
+//! and ignored until the next restart.
+
+use std::fmt::Debug;
+use std::time::{Duration, SystemTime};
+
+use router_config_lib::{Config, ConfigReloadConfig};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinHandle;
+
+use crate::control::{parse_lookup_tables, parse_trading_mints, RuntimeControl, RuntimeParams};
+use crate::prelude::*;
+use crate::util::tokio_spawn;
+
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigChange {
+    /// toml path, e.g. `sender.in_amounts`
+    pub path: &'static str,
+    /// applied to the running jobs, otherwise needs a restart
+    pub hot: bool,
+    /// old and new values, only kept for hot settings so secrets are not logged
+    pub values: Option<(String, String)>,
+}
+
+fn debug_eq<T: Debug>(old: &T, new: &T) -> bool {
+    format!("{:?}", old) == format!("{:?}", new)
+}
+
+/// Every setting that differs between the two configs
+pub fn diff(old: &Config, new: &Config) -> Vec<ConfigChange> {
+    let mut changes = vec![];
+
+    macro_rules! hot {
+        ($($section:ident . $field:ident),* $(,)?) => {$(
+            if !debug_eq(&old.$section.$field, &new.$section.$field) {
+                changes.push(ConfigChange {
+                    path: concat!(stringify!($section), ".", stringify!($field)),
+                    hot: true,
+                    values: Some((
+                        format!("{:?}", old.$section.$field),
+                        format!("{:?}", new.$section.$field),
+                    )),
+                });
+            }
+        )*};
+    }
+    macro_rules! cold {
+        ($($($path:ident).+),* $(,)?) => {$(
+            if !debug_eq(&old.$($path).+, &new.$($path).+) {
+                changes.push(ConfigChange {
+                    path: stringify!($($path).+),
+                    hot: false,
+                    values: None,
+                });
+            }
+        )*};
+    }
+
+    hot!(
+        ring.trading_mints,
+        sender.in_amounts,
+        sender.expected_gain,
+        sender.trading_mint_amounts,
+        sender.jito_tip_bps,
+        sender.jito_max_tip,
+        sender.jito_regions,
+        sender.lookup_tables,
+        sender.slippage_bps,
+        sender.ring_cooldown_secs,
+    );
+    cold!(
+        ring.max_path_length,
+        ring.priority,
+        sender.name,
+        sender.send_mode,
+        sender.compute_unit_price_micro_lamports,
+        sender.region_send_type,
+        sender.jupiter_url,
+        sender.blockhash_refresh_interval_ms,
+        sender.max_blockhash_age_ms,
+        sender.min_blockhash_blocks_left,
+        sender.dry_run,
+        routing,
+        server,
+        metrics,
+        sources,
+        price_feed,
+        orca,
+        cropper,
+        raydium_cp,
+        raydium,
+        saber,
+        safety_checks,
+        hot_mints,
+        debug_config,
+        snapshot_timeout_in_seconds,
+        snapshot_cache,
+        alt_manager,
+        token_universe,
+        recorder,
+        journal,
+        pnl_tracker,
+        control_api,
+        config_reload,
+        key_source,
+        wallet_pool,
+        ata_provider,
+        shutdown,
+    );
+    // HashMap 的 Debug 输出顺序不固定，排序后再比较
+    let sorted_simulation = |config: &Config| {
+        config.sender.simulation.as_ref().map(|s| {
+            s.iter()
+                .map(|(k, v)| (k.clone(), format!("{:?}", v)))
+                .collect::<std::collections::BTreeMap<_, _>>()
+        })
+    };
+    if sorted_simulation(old) != sorted_simulation(new) {
+        changes.push(ConfigChange {
+            path: "sender.simulation",
+            hot: false,
+            values: None,
+        });
+    }
+    changes
+}
+
+/// Applies the hot changes of `new` to `control` and to `current`, returns every change found.
+/// Settings not changed in the file keep their runtime value, e.g. one set through the control api.
+pub fn apply(
+    current: &mut Config,
+    new: &Config,
+    control: &RuntimeControl,
+) -> anyhow::Result<Vec<ConfigChange>> {
+    let changes = diff(current, new);
+    let hot_changes = changes.iter().filter(|c| c.hot).collect_vec();
+    if hot_changes.is_empty() {
+        return Ok(changes);
+    }
+
+    let params = RuntimeParams::from_config(new);
+    let trading_mints = parse_trading_mints(new)?;
+    let lookup_tables = parse_lookup_tables(new)?;
+    control.update(|state| {
+        for change in hot_changes.iter() {
+            match change.path {
+                "ring.trading_mints" => state.trading_mints = trading_mints.clone(),
+                "sender.in_amounts" => state.params.in_amounts = params.in_amounts.clone(),
+                "sender.expected_gain" => state.params.expected_gain = params.expected_gain,
+                "sender.trading_mint_amounts" => {
+                    state.params.trading_mint_amounts = params.trading_mint_amounts.clone()
+                }
+                "sender.jito_tip_bps" => state.params.jito_tip_bps = params.jito_tip_bps,
+                "sender.jito_max_tip" => state.params.jito_max_tip = params.jito_max_tip,
+                "sender.jito_regions" => state.params.jito_regions = params.jito_regions.clone(),
+                "sender.lookup_tables" => state.lookup_tables = lookup_tables.clone(),
+                "sender.slippage_bps" => state.params.slippage_bps = params.slippage_bps,
+                "sender.ring_cooldown_secs" => {
+                    state.params.ring_cooldown_secs = params.ring_cooldown_secs
+                }
+                path => bail!("{} is not hot swappable", path),
+            }
+        }
+        Ok(())
+    })?;
+
+    // 需要重启的设置保持旧值，下次重新加载时仍会报告
+    current.ring.trading_mints = new.ring.trading_mints.clone();
+    current.sender.in_amounts = new.sender.in_amounts.clone();
+    current.sender.expected_gain = new.sender.expected_gain;
+    current.sender.trading_mint_amounts = new.sender.trading_mint_amounts.clone();
+    current.sender.jito_tip_bps = new.sender.jito_tip_bps;
+    current.sender.jito_max_tip = new.sender.jito_max_tip;
+    current.sender.jito_regions = new.sender.jito_regions.clone();
+    current.sender.lookup_tables = new.sender.lookup_tables.clone();
+    current.sender.slippage_bps = new.sender.slippage_bps;
+    current.sender.ring_cooldown_secs = new.sender.ring_cooldown_secs;
+    Ok(changes)
+}
+
+fn reload(path: &str, current: &mut Config, control: &RuntimeControl) {
+    let new = match router_config_lib::check_file(path) {
+        Ok(config) => config,
+        Err(errors) => {
+            error!("config reload failed, keeping the running config: {}", errors);
+            return;
+        }
+    };
+    match apply(current, &new, control) {
+        Ok(changes) if changes.is_empty() => info!("config reloaded, nothing changed"),
+        Ok(changes) => {
+            for change in changes {
+                match (change.hot, change.values) {
+                    (true, Some((old, new))) => {
+                        info!("config {} applied: {} -> {}", change.path, old, new)
+                    }
+                    _ => error!(
+                        "config {} changed but needs a restart, ignored",
+                        change.path
+                    ),
+                }
+            }
+        }
+        Err(e) => error!("config reload rejected, nothing applied: {:#}", e),
+    }
+}
+
+fn modified_at(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+pub fn spawn_config_reload_job(
+    reload_config: &ConfigReloadConfig,
+    path: String,
+    config: Config,
+    control: Arc<RuntimeControl>,
+    mut exit: broadcast::Receiver<()>,
+) -> Option<JoinHandle<()>> {
+    if !reload_config.enabled {
+        return None;
+    }
+    // 0 表示只在 SIGHUP 时重新加载
+    let poll_interval_secs = reload_config
+        .poll_interval_secs
+        .unwrap_or(DEFAULT_POLL_INTERVAL_SECS);
+    let poll_enabled = poll_interval_secs > 0;
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!("failed to listen for SIGHUP, config reload disabled: {}", e);
+            return None;
+        }
+    };
+
+    let job = tokio_spawn("config_reload", async move {
+        let mut current = config;
+        let mut last_modified = modified_at(&path);
+        let mut poll = tokio::time::interval(Duration::from_secs(poll_interval_secs.max(1)));
+        info!("config reload enabled for {}", path);
+
+        loop {
+            tokio::select! {
+                _ = exit.recv() => {
+                    info!("shutting down config reload task");
+                    break;
+                }
+                _ = hangup.recv() => {
+                    info!("received SIGHUP, reloading {}", path);
+                    last_modified = modified_at(&path);
+                    reload(&path, &mut current, &control);
+                }
+                _ = poll.tick(), if poll_enabled => {
+                    let modified = modified_at(&path);
+                    if modified != last_modified {
+                        last_modified = modified;
+                        info!("{} changed on disk, reloading", path);
+                        reload(&path, &mut current, &control);
+                    }
+                }
+            }
+        }
+    });
+    Some(job)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_apply_hot_changes_and_keep_the_others() {
+        let mut current = Config::default();
+        let control = RuntimeControl::new(&current);
+        control
+            .update(|state| {
+                state.params.jito_max_tip = 42;
+                state.blacklist.mints.insert(Pubkey::new_unique());
+                Ok(())
+            })
+            .unwrap();
+
+        let mut new = Config::default();
+        new.sender.in_amounts = Some(vec![100, 300]);
+        new.sender.slippage_bps = Some(50);
+        new.ring.max_path_length = Some(4);
+        new.routing.path_cache_validity_ms = 1;
+
+        let changes = apply(&mut current, &new, &control).unwrap();
+        let hot = changes
+            .iter()
+            .filter(|c| c.hot)
+            .map(|c| c.path)
+            .collect_vec();
+        let cold = changes
+            .iter()
+            .filter(|c| !c.hot)
+            .map(|c| c.path)
+            .collect_vec();
+        assert_eq!(hot, vec!["sender.in_amounts", "sender.slippage_bps"]);
+        assert_eq!(cold, vec!["ring.max_path_length", "routing"]);
+
+        let state = control.snapshot();
+        assert_eq!(state.params.in_amounts, vec![300, 100]);
+        assert_eq!(state.params.slippage_bps, 50);
+        // 文件中没有改变的设置保留运行时的值
+        assert_eq!(state.params.jito_max_tip, 42);
+        assert_eq!(state.blacklist.mints.len(), 1);
+
+        // 需要重启的修改再次加载时仍会报告
+        let changes = apply(&mut current, &new, &control).unwrap();
+        assert!(changes.iter().all(|c| !c.hot));
+        assert_eq!(changes.len(), 2);
+
+        // 无效的交易代币拒绝整个重新加载
+        new.ring.trading_mints = Some(vec!["not a mint".to_string()]);
+        new.sender.expected_gain = Some(1);
+        assert!(apply(&mut current, &new, &control).is_err());
+        assert_ne!(control.snapshot().params.expected_gain, 1);
+    }
+}
@@ -0,0 +1,369 @@
+//! Runtime parameters and route filters that the control API can change without a restart.
+//! Readers take one `snapshot()` per refresh / per route, a change is never seen half applied.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use router_config_lib::{Config, JITO_REGIONS};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::ring::Ring;
+use crate::routing_types::Route;
+
+const DEFAULT_IN_AMOUNTS: [u64; 3] = [10_00_000_000, 500_000_000, 100_000_000];
+const DEFAULT_EXPECTED_GAIN: u64 = 1_000_000;
+const DEFAULT_JITO_TIP_BPS: f32 = 0.65;
+const DEFAULT_JITO_MAX_TIP: u64 = 10_000_000;
+const DEFAULT_JITO_REGION: &str = "frankfurt";
+const DEFAULT_RING_COOLDOWN_SECS: u64 = 30;
+const DEFAULT_TRADING_MINT: &str = "So11111111111111111111111111111111111111112";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeParams {
+    pub expected_gain: u64,
+    /// largest first
+    pub in_amounts: Vec<u64>,
+    /// share of the profit paid as tip, 0 to size the tip from the compute units
+    pub jito_tip_bps: f32,
+    pub jito_max_tip: u64,
+    pub jito_regions: Vec<String>,
+    pub slippage_bps: i32,
+    /// a failing ring is skipped for this long times n * 1.2^n on its n-th failure, n capped
+    /// at 5: x1, x3, x5, x8, then x12
+    #[serde(default = "default_ring_cooldown_secs")]
+    pub ring_cooldown_secs: u64,
+    /// own in_amounts / expected_gain of some trading mints, in their native units, keyed by mint
+    #[serde(default)]
+    pub trading_mint_amounts: BTreeMap<String, TradingMintAmounts>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TradingMintAmounts {
+    /// largest first, the general in_amounts when not set
+    pub in_amounts: Option<Vec<u64>>,
+    pub expected_gain: Option<u64>,
+}
+
+impl RuntimeParams {
+    pub fn from_config(config: &Config) -> Self {
+        let mut in_amounts = config
+            .sender
+            .in_amounts
+            .clone()
+            .unwrap_or(DEFAULT_IN_AMOUNTS.to_vec());
+        in_amounts.sort_by(|a, b| b.cmp(a));
+        let mut jito_regions = config.sender.jito_regions.clone().unwrap_or_default();
+        if jito_regions.is_empty() {
+            jito_regions.push(DEFAULT_JITO_REGION.to_string());
+        }
+        let trading_mint_amounts = config
+            .sender
+            .trading_mint_amounts
+            .iter()
+            .flatten()
+            .map(|(mint, amounts)| {
+                let in_amounts = amounts.in_amounts.clone().map(|mut in_amounts| {
+                    in_amounts.sort_by(|a, b| b.cmp(a));
+                    in_amounts
+                });
+                let amounts = TradingMintAmounts {
+                    in_amounts,
+                    expected_gain: amounts.expected_gain,
+                };
+                (mint.clone(), amounts)
+            })
+            .collect();
+        Self {
+            expected_gain: config.sender.expected_gain.unwrap_or(DEFAULT_EXPECTED_GAIN),
+            in_amounts,
+            jito_tip_bps: config.sender.jito_tip_bps.unwrap_or(DEFAULT_JITO_TIP_BPS),
+            jito_max_tip: config.sender.jito_max_tip.unwrap_or(DEFAULT_JITO_MAX_TIP),
+            jito_regions,
+            slippage_bps: config.sender.slippage_bps.unwrap_or(0),
+            ring_cooldown_secs: config
+                .sender
+                .ring_cooldown_secs
+                .unwrap_or(DEFAULT_RING_COOLDOWN_SECS),
+            trading_mint_amounts,
+        }
+    }
+
+    /// in_amounts and expected_gain of rings starting in `mint`
+    pub fn amounts_for(&self, mint: &Pubkey) -> (&[u64], u64) {
+        match self.trading_mint_amounts.get(&mint.to_string()) {
+            Some(amounts) => (
+                amounts.in_amounts.as_deref().unwrap_or(&self.in_amounts),
+                amounts.expected_gain.unwrap_or(self.expected_gain),
+            ),
+            None => (&self.in_amounts, self.expected_gain),
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.in_amounts.is_empty() || self.in_amounts.contains(&0) {
+            bail!("in_amounts must be non empty and non zero");
+        }
+        for (mint, amounts) in &self.trading_mint_amounts {
+            Pubkey::from_str(mint).with_context(|| format!("invalid trading mint {}", mint))?;
+            if let Some(in_amounts) = &amounts.in_amounts {
+                if in_amounts.is_empty() || in_amounts.contains(&0) {
+                    bail!("in_amounts of {} must be non empty and non zero", mint);
+                }
+            }
+        }
+        if !(0.0..=1.0).contains(&self.jito_tip_bps) {
+            bail!("jito_tip_bps must be between 0 and 1");
+        }
+        if self.jito_regions.is_empty() {
+            bail!("jito_regions must not be empty");
+        }
+        if let Some(region) = self
+            .jito_regions
+            .iter()
+            .find(|r| !JITO_REGIONS.contains(&r.as_str()))
+        {
+            bail!("unknown jito region {}, expected one of {:?}", region, JITO_REGIONS);
+        }
+        if !(0..=10_000).contains(&self.slippage_bps) {
+            bail!("slippage_bps must be between 0 and 10000");
+        }
+        if self.ring_cooldown_secs == 0 {
+            bail!("ring_cooldown_secs must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+/// ring.trading_mints, SOL when not set
+pub fn parse_trading_mints(config: &Config) -> anyhow::Result<Vec<Pubkey>> {
+    let mut trading_mints = config.ring.trading_mints.clone().unwrap_or_default();
+    if trading_mints.is_empty() {
+        trading_mints.push(DEFAULT_TRADING_MINT.to_string());
+    }
+    trading_mints
+        .iter()
+        .map(|m| Pubkey::from_str(m).with_context(|| format!("Invalid mint address: {}", m)))
+        .collect()
+}
+
+pub fn parse_lookup_tables(config: &Config) -> anyhow::Result<Vec<Pubkey>> {
+    config
+        .sender
+        .lookup_tables
+        .iter()
+        .flatten()
+        .map(|t| Pubkey::from_str(t).with_context(|| format!("Invalid lookup table: {}", t)))
+        .collect()
+}
+
+fn default_ring_cooldown_secs() -> u64 {
+    DEFAULT_RING_COOLDOWN_SECS
+}
+
+impl Default for RuntimeParams {
+    fn default() -> Self {
+        Self::from_config(&Config::default())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RouteFilter {
+    pub rings: HashSet<String>,
+    pub pools: HashSet<Pubkey>,
+    pub mints: HashSet<Pubkey>,
+}
+
+impl RouteFilter {
+    pub fn is_empty(&self) -> bool {
+        self.rings.is_empty() && self.pools.is_empty() && self.mints.is_empty()
+    }
+
+    /// Any of the ring, its pools or its mints is listed
+    fn matches_any(&self, ring_id: Option<&str>, edges: &[&Arc<Edge>]) -> bool {
+        ring_id.map_or(false, |id| self.rings.contains(id))
+            || edges.iter().any(|e| {
+                self.pools.contains(&e.key())
+                    || self.mints.contains(&e.input_mint)
+                    || self.mints.contains(&e.output_mint)
+            })
+    }
+
+    /// Every non empty list accepts the ring: its id, all of its pools, all of its mints
+    fn matches_all(&self, ring_id: Option<&str>, edges: &[&Arc<Edge>]) -> bool {
+        (self.rings.is_empty() || ring_id.map_or(false, |id| self.rings.contains(id)))
+            && (self.pools.is_empty() || edges.iter().all(|e| self.pools.contains(&e.key())))
+            && (self.mints.is_empty()
+                || edges.iter().all(|e| {
+                    self.mints.contains(&e.input_mint) && self.mints.contains(&e.output_mint)
+                }))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ControlState {
+    pub params: RuntimeParams,
+    pub blacklist: RouteFilter,
+    /// when not empty, only these rings / pools / mints are traded
+    pub whitelist: RouteFilter,
+    /// rings are rebuilt when they change
+    pub trading_mints: Vec<Pubkey>,
+    /// used by the sender pre-flight, reloaded when they change
+    pub lookup_tables: Vec<Pubkey>,
+}
+
+impl ControlState {
+    fn allows(&self, ring_id: Option<&str>, edges: &[&Arc<Edge>]) -> bool {
+        !self.blacklist.matches_any(ring_id, edges) && self.whitelist.matches_all(ring_id, edges)
+    }
+
+    pub fn allows_ring(&self, ring: &Ring) -> bool {
+        self.allows(Some(&ring.ring_id), &ring.edges.iter().collect_vec())
+    }
+
+    pub fn allows_route(&self, route: &Route) -> bool {
+        self.allows(
+            route.ring_id.as_deref(),
+            &route.steps.iter().map(|s| &s.edge).collect_vec(),
+        )
+    }
+}
+
+/// Shared by the ring executor, the sender and the control API
+#[derive(Default)]
+pub struct RuntimeControl {
+    paused: AtomicBool,
+    // 关闭前不再接收新路由，不能恢复
+    shutting_down: AtomicBool,
+    state: RwLock<Arc<ControlState>>,
+    /// published by the ring executor after each rebuild
+    rings: RwLock<Vec<Arc<Ring>>>,
+}
+
+impl RuntimeControl {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            paused: AtomicBool::new(false),
+            shutting_down: AtomicBool::new(false),
+            state: RwLock::new(Arc::new(ControlState {
+                params: RuntimeParams::from_config(config),
+                trading_mints: parse_trading_mints(config).unwrap_or_else(|e| panic!("{:#}", e)),
+                lookup_tables: parse_lookup_tables(config).unwrap_or_else(|e| panic!("{:#}", e)),
+                ..Default::default()
+            })),
+            rings: Default::default(),
+        }
+    }
+
+    pub fn snapshot(&self) -> Arc<ControlState> {
+        self.state.read().unwrap().clone()
+    }
+
+    /// Applies `change` to a copy of the state and swaps it in, nothing is changed on error
+    pub fn update(
+        &self,
+        change: impl FnOnce(&mut ControlState) -> anyhow::Result<()>,
+    ) -> anyhow::Result<Arc<ControlState>> {
+        let mut guard = self.state.write().unwrap();
+        let mut state = ControlState::clone(&guard);
+        change(&mut state)?;
+        state.params.validate()?;
+        state.params.in_amounts.sort_by(|a, b| b.cmp(a));
+        *guard = Arc::new(state);
+        Ok(guard.clone())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        if self.paused.swap(paused, Ordering::Relaxed) != paused {
+            info!("sending {}", if paused { "paused" } else { "resumed" });
+        }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Relaxed)
+    }
+
+    /// The sender refuses new routes from now on
+    pub fn begin_shutdown(&self) {
+        if !self.shutting_down.swap(true, Ordering::Relaxed) {
+            info!("shutting down, no new routes are sent");
+        }
+    }
+
+    pub fn set_rings(&self, rings: Vec<Arc<Ring>>) {
+        *self.rings.write().unwrap() = rings;
+    }
+
+    pub fn rings(&self) -> Vec<Arc<Ring>> {
+        self.rings.read().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::test::mock_edge;
+
+    fn edge(pool: Pubkey, input_mint: Pubkey, output_mint: Pubkey) -> Arc<Edge> {
+        Arc::new(mock_edge(pool, input_mint, output_mint, 1.0))
+    }
+
+    #[test]
+    fn should_filter_rings_by_black_and_white_lists() {
+        let [sol, a, b, p1, p2, p3] = [(); 6].map(|_| Pubkey::new_unique());
+        let ring = Ring::new(
+            sol,
+            vec![edge(p1, sol, a), edge(p2, a, b), edge(p3, b, sol)],
+            HashSet::new(),
+        );
+        let control = RuntimeControl::new(&Config::default());
+        assert!(control.snapshot().allows_ring(&ring));
+
+        control
+            .update(|state| {
+                state.blacklist.mints.insert(b);
+                Ok(())
+            })
+            .unwrap();
+        assert!(!control.snapshot().allows_ring(&ring));
+
+        control
+            .update(|state| {
+                state.blacklist = RouteFilter::default();
+                state.whitelist.pools.extend([p1, p2]);
+                Ok(())
+            })
+            .unwrap();
+        assert!(!control.snapshot().allows_ring(&ring));
+
+        control
+            .update(|state| {
+                state.whitelist.pools.insert(p3);
+                Ok(())
+            })
+            .unwrap();
+        assert!(control.snapshot().allows_ring(&ring));
+
+        // 无效参数不会生效
+        assert!(control
+            .update(|state| {
+                state.params.in_amounts = vec![];
+                state.whitelist = RouteFilter::default();
+                Ok(())
+            })
+            .is_err());
+        assert_eq!(control.snapshot().whitelist.pools.len(), 3);
+    }
+
+    #[test]
+    fn should_reject_unknown_jito_regions() {
+        let mut params = RuntimeParams::default();
+        params.validate().unwrap();
+        params.jito_regions = vec!["frankfurt".to_string(), "mars".to_string()];
+        assert!(params.validate().is_err());
+    }
+}
@@ -1 +1,133 @@
 pub mod generic;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use dex_orca::OrcaDex;
+use router_config_lib::Config;
+use router_feed_lib::router_rpc_client::RouterRpcClient;
+use router_lib::dex::POOL_MINT_OPTION;
+use router_lib::mango::mango_fetcher::MangoMetadata;
+use solana_program::pubkey::Pubkey;
+
+use crate::edge_updater::Dex;
+use crate::source::token_cache::TokenCache;
+
+/// Initialize the enabled dex adapters, shared by the router and the backtest.
+/// With `pool_mint` only the pools trading that mint are loaded
+pub async fn build_dexs(
+    config: &Config,
+    router_rpc: &mut RouterRpcClient,
+    token_cache: &Arc<TokenCache>,
+    mango_data: &Option<MangoMetadata>,
+    pool_mint: Option<Pubkey>,
+) -> anyhow::Result<Vec<Dex>> {
+    let token_cache_mints = token_cache.string_tokens();
+    let options = |mut options: HashMap<String, String>| {
+        if let Some(mint) = pool_mint {
+            options.insert(POOL_MINT_OPTION.to_string(), mint.to_string());
+        }
+        options
+    };
+
+    let mut orca_config = HashMap::new();
+    orca_config.insert(
+        "program_id".to_string(),
+        "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc".to_string(),
+    );
+    orca_config.insert("program_name".to_string(), "Orca".to_string());
+    let mut cropper = HashMap::new();
+    cropper.insert(
+        "program_id".to_string(),
+        "H8W3ctz92svYg6mkn1UtGfu2aQr2fnUFHM1RhScEtQDt".to_string(),
+    );
+    cropper.insert("program_name".to_string(), "Cropper".to_string());
+
+    let dexs: Vec<Dex> = [
+        generic::build_dex!(
+            OrcaDex::initialize(router_rpc, options(orca_config),config.orca.take_all_mints,
+                //&config.orca.mints
+                &token_cache_mints
+            ).await?,
+            mango_data,
+            token_cache.clone(),
+            config.orca.enabled,
+            config.orca.add_mango_tokens,
+            config.orca.take_all_mints,
+            //&config.orca.mints
+            &token_cache_mints
+        ),
+        generic::build_dex!(
+            OrcaDex::initialize(router_rpc, options(cropper),
+                config.cropper.take_all_mints,
+                &token_cache_mints).await?,
+            mango_data,
+            token_cache.clone(),
+            config.cropper.enabled,
+            config.cropper.add_mango_tokens,
+            config.cropper.take_all_mints,
+            &token_cache_mints
+        ),
+        generic::build_dex!(
+            dex_saber::SaberDex::initialize(router_rpc, options(HashMap::new()),
+            config.saber.take_all_mints,
+            &token_cache_mints).await?,
+            mango_data,
+            token_cache.clone(),
+            config.saber.enabled,
+            config.saber.add_mango_tokens,
+            config.saber.take_all_mints,
+            &token_cache_mints
+        ),
+        generic::build_dex!(
+            dex_raydium_cp::RaydiumCpDex::initialize(router_rpc, options(HashMap::new()),
+            config.raydium_cp.take_all_mints,
+            &token_cache_mints).await?,
+            mango_data,
+            token_cache.clone(),
+            config.raydium_cp.enabled,
+            config.raydium_cp.add_mango_tokens,
+            config.raydium_cp.take_all_mints,
+            &token_cache_mints
+        ),
+        generic::build_dex!(
+            dex_raydium::RaydiumDex::initialize(router_rpc, options(HashMap::new()),config.raydium.take_all_mints,
+            &token_cache_mints).await?,
+            mango_data,
+            token_cache.clone(),
+            config.raydium.enabled,
+            config.raydium.add_mango_tokens,
+            config.raydium.take_all_mints,
+            &token_cache_mints
+        ),
+        // dex::generic::build_dex!(
+        //     dex_openbook_v2::OpenbookV2Dex::initialize(router_rpc, HashMap::new(),).await?,
+        //     mango_data,
+        //     config.openbook_v2.enabled,
+        //     config.openbook_v2.add_mango_tokens,
+        //     config.openbook_v2.take_all_mints,
+        //     &config.openbook_v2.mints
+        // ),
+        // dex::generic::build_dex!(
+        //     dex_infinity::InfinityDex::initialize(router_rpc, HashMap::new(),).await?,
+        //     mango_data,
+        //     config.infinity.enabled,
+        //     false,
+        //     true,
+        //     &vec![]
+        // ),
+        // dex::generic::build_dex!(
+        //     dex_invariant::InvariantDex::initialize(router_rpc, HashMap::new(),).await?,
+        //     mango_data,
+        //     config.invariant.enabled,
+        //     config.invariant.take_all_mints,
+        //     config.invariant.add_mango_tokens,
+        //     &config.invariant.mints
+        // ),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    Ok(dexs)
+}
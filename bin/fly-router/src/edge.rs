@@ -23,6 +23,9 @@ use std::time::Duration;
 // 引入标准库中的 hash 模块，用于哈希操作
 use std::hash::{Hash, Hasher};
 
+/// 输入代币既没有价格也无法推算时使用的美元价格
+pub const FALLBACK_PRICE_UI: f64 = 1.0;
+
 // 定义一个可克隆、可调试、可序列化和反序列化的边状态结构体
 #[derive(Clone, Debug, Default, serde_derive::Serialize, serde_derive::Deserialize)]
 pub struct EdgeState {
@@ -189,65 +192,59 @@ impl Edge {
         price: f64,
         path_warming_amounts: &Vec<u64>,
     ) {
-        //在环中更新价格，边不再更新自己价格
-        // // 计算乘数
-        // let multiplier = 10u64.pow(decimals as u32) as f64;
-        // // 计算不同输入金额对应的数量
-        // let amounts = path_warming_amounts
-        //    .iter()
-        //    .map(|amount| {
-        //         let quantity_ui = *amount as f64 / price;
-        //         let quantity_native = quantity_ui * multiplier;
-        //         quantity_native.ceil() as u64
-        //     })
-        //    .collect_vec();
-
-        // // 记录价格数据的调试信息
-        // debug!(input_mint = %self.input_mint, pool = %self.key(), multiplier = multiplier, price = price, amounts = amounts.iter().join(";"), "price_data");
-
-        // // 检查是否有溢出情况
-        // let overflow = amounts.iter().any(|x| *x == u64::MAX);
-        // if overflow {
-        //     if self.state.read().unwrap().is_valid {
-        //         debug!("amount error, disabling edge {}", self.desc());
-        //     }
-
-        //     // 获取边状态的写锁
-        //     let mut state = self.state.write().unwrap();
-        //     // 更新上次更新时间
-        //     state.last_update = millis_since_epoch();
-        //     // 更新上次更新槽位
-        //     state.last_update_slot = chain_data.newest_processed_slot();
-        //     // 清空缓存价格
-        //     state.cached_prices.clear();
-        //     // 标记状态无效
-        //     state.is_valid = false;
-        //     return;
-        // }
-
-        // // 准备报价
-        // let prepared_quote = self.prepare(chain_data);
-
-        // // 计算不同输入金额的报价结果
-        // let quote_results_in = amounts
-        //    .iter()
-        //    .map(|&amount| match &prepared_quote {
-        //         Ok(p) => (amount, self.quote(&p, chain_data, amount)),
-        //         Err(e) => (
-        //             amount,
-        //             anyhow::Result::<Quote>::Err(anyhow::format_err!("{}", e)),
-        //         ),
-        //     })
-        //    .collect_vec();
-
-        // // 检查是否有报价错误
-        // if let Some((_, err)) = quote_results_in.iter().find(|v| v.1.is_err()) {
-        //     if self.state.read().unwrap().is_valid {
-        //         warn!("quote error, disabling edge: {} {err:?}", self.desc());
-        //     } else {
-        //         info!("edge update_internal quote error: {} {err:?}", self.desc());
-        //     }
-        // }
+        // 计算乘数
+        let multiplier = 10u64.pow(decimals as u32) as f64;
+        // 把以美元计的预热金额换算成输入代币的原生数量
+        let amounts = path_warming_amounts
+            .iter()
+            .map(|amount| {
+                let quantity_ui = *amount as f64 / price;
+                let quantity_native = quantity_ui * multiplier;
+                quantity_native.ceil() as u64
+            })
+            .collect_vec();
+
+        // 记录价格数据的调试信息
+        debug!(input_mint = %self.input_mint, pool = %self.key(), multiplier = multiplier, price = price, amounts = amounts.iter().join(";"), "price_data");
+
+        // 检查是否有溢出情况
+        let overflow = amounts.iter().any(|x| *x == u64::MAX);
+        if overflow {
+            if self.state.read().unwrap().is_valid {
+                debug!("amount error, disabling edge {}", self.desc());
+            }
+
+            let mut state = self.state.write().unwrap();
+            state.last_update = millis_since_epoch();
+            state.last_update_slot = chain_data.newest_processed_slot();
+            state.cached_prices.clear();
+            state.is_valid = false;
+            return;
+        }
+
+        // 准备报价
+        let prepared_quote = self.prepare(chain_data);
+
+        // 计算不同输入金额的报价结果
+        let quote_results_in = amounts
+            .iter()
+            .map(|&amount| match &prepared_quote {
+                Ok(p) => (amount, self.quote(p, chain_data, amount)),
+                Err(e) => (
+                    amount,
+                    anyhow::Result::<Quote>::Err(anyhow::format_err!("{}", e)),
+                ),
+            })
+            .collect_vec();
+
+        // 检查是否有报价错误
+        if let Some((_, err)) = quote_results_in.iter().find(|v| v.1.is_err()) {
+            if self.state.read().unwrap().is_valid {
+                warn!("quote error, disabling edge: {} {err:?}", self.desc());
+            } else {
+                debug!("edge update_internal quote error: {} {err:?}", self.desc());
+            }
+        }
 
         // 获取边状态的写锁
         let mut state = self.state.write().unwrap();
@@ -267,30 +264,49 @@ impl Edge {
             }
         };
 
-        // let mut has_at_least_one_non_zero = false;
-        // for quote_result in quote_results_in {
-        //     if let (in_amount, Ok(quote)) = quote_result {
-        //         // 计算价格
-        //         let price = quote.out_amount as f64 / in_amount as f64;
-        //         if price.is_nan() {
-        //             state.is_valid = false;
-        //             continue;
-        //         }
-        //         if price > 0.0000001 {
-        //             has_at_least_one_non_zero = true;
-        //         }
-        //         // 将价格和其对数存入缓存
-        //         state.cached_prices.push((in_amount, price, f64::ln(price)));
-        //     } else {
-        //         // 如果报价失败，标记状态无效
-        //         state.is_valid = false;
-        //     };
-        // }
-
-        // // 如果没有至少一个非零价格，标记状态无效
-        // if !has_at_least_one_non_zero {
-        //     state.is_valid = false;
-        // }
+        let mut has_at_least_one_non_zero = false;
+        for quote_result in quote_results_in {
+            if let (in_amount, Ok(quote)) = quote_result {
+                // 计算价格
+                let price = quote.out_amount as f64 / in_amount as f64;
+                if price.is_nan() {
+                    state.is_valid = false;
+                    continue;
+                }
+                if price > 0.0000001 {
+                    has_at_least_one_non_zero = true;
+                }
+                // 将价格和其对数存入缓存
+                state.cached_prices.push((in_amount, price, f64::ln(price)));
+            } else {
+                // 如果报价失败，标记状态无效
+                state.is_valid = false;
+            };
+        }
+
+        // 如果没有至少一个非零价格，标记状态无效
+        if !has_at_least_one_non_zero {
+            state.is_valid = false;
+        }
+    }
+
+    /// 输入代币没有美元价格时，用输出代币的价格和一个单位输入代币的报价推算
+    fn implied_price_ui(
+        &self,
+        chain_data: &AccountProviderView,
+        token_cache: &TokenCache,
+        price_cache: &PriceCache,
+        decimals: u8,
+    ) -> Option<f64> {
+        let output_price = price_cache.price_ui(self.output_mint)?;
+        let output_decimals = token_cache.token(self.output_mint).ok()?.decimals;
+        let one_ui = 10u64.checked_pow(decimals as u32)?;
+
+        let prepared_quote = self.prepare(chain_data).ok()?;
+        let quote = self.quote(&prepared_quote, chain_data, one_ui).ok()?;
+        let out_ui = quote.out_amount as f64 / 10f64.powi(output_decimals as i32);
+        let price = out_ui * output_price;
+        (price.is_finite() && price > 0.0).then_some(price)
     }
 
     // 更新边的状态
@@ -311,15 +327,24 @@ impl Edge {
             state.is_valid = false;
             return;
         };
-        // 获取输入代币的价格
-        // let Some(price) = price_cache.price_ui(self.input_mint) else {
-        //     let mut state = self.state.write().unwrap();
-        //     state.is_valid = false;
-        //     trace!("no price for {}", self.input_mint);
-        //     return;
-        // };
-
-        let price =1.0; // TODO: 这里需要获取输入代币的价格
+        // 获取输入代币的价格，依次回退：
+        // 1. 价格源中的输入代币价格
+        // 2. 由输出代币价格和一个单位输入代币的报价推算
+        // 3. FALLBACK_PRICE_UI，预热金额按代币数量而不是美元解释，
+        //    缓存价格仍来自真实报价，只是采样点不按美元对齐
+        let price = match price_cache.price_ui(self.input_mint) {
+            Some(price) if price > 0.0 => price,
+            _ => match self.implied_price_ui(chain_data, token_cache, price_cache, decimals) {
+                Some(price) => {
+                    trace!("implied price {} for {}", price, self.input_mint);
+                    price
+                }
+                None => {
+                    trace!("no price for {}, using fallback", self.input_mint);
+                    FALLBACK_PRICE_UI
+                }
+            },
+        };
 
         // 调用内部更新方法
         self.update_internal(chain_data, decimals, price, path_warming_amounts);
@@ -411,4 +436,33 @@ impl EdgeState {
             Some(current) => Some(current.max(until)),
         };
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::test::mock_edge;
+    use router_lib::chain_data::ChainDataArcRw;
+    use router_lib::dex::ChainDataAccountProvider;
+
+    #[test]
+    fn should_cache_prices_for_usd_warming_amounts() {
+        let chain_data = Arc::new(ChainDataAccountProvider::new(ChainDataArcRw::new(
+            Default::default(),
+        ))) as AccountProviderView;
+        let input_mint = Pubkey::new_unique();
+        let output_mint = Pubkey::new_unique();
+        let edge = mock_edge(Pubkey::new_unique(), input_mint, output_mint, 0.15);
+
+        // 150 美元一个 SOL，9 位小数
+        edge.update_internal(&chain_data, 9, 150.0, &vec![15, 150]);
+
+        let state = edge.state.read().unwrap();
+        assert!(state.is_valid());
+        let amounts = state.cached_prices.iter().map(|p| p.0).collect_vec();
+        assert_eq!(amounts, vec![100_000_000, 1_000_000_000]);
+        let (price, ln_price) = state.cached_price_for(500_000_000).unwrap();
+        assert!((price - 0.15).abs() < 1e-9);
+        assert!((ln_price - 0.15f64.ln()).abs() < 1e-9);
+    }
+}
@@ -132,6 +132,7 @@ pub fn spawn_updater_job(
     mut account_updates: broadcast::Receiver<(Pubkey, Pubkey, u64)>,
     mut metadata_updates: broadcast::Receiver<FeedMetadata>,
     mut price_updates: broadcast::Receiver<PriceUpdate>,
+    mut new_edges: broadcast::Receiver<Dex>,
     mut exit: broadcast::Receiver<()>,
     edge_price_sender: async_channel::Sender<Arc<Edge>>,
 ) -> Option<JoinHandle<()>> {
@@ -263,6 +264,12 @@ pub fn spawn_updater_job(
                         }
                     };
                 },
+                // 运行时新增代币的边，只处理本 dex 的
+                Ok(dex) = new_edges.recv() => {
+                    if dex.name == updater.dex.name {
+                        updater.add_edges(dex);
+                    }
+                },
                 // 处理刷新间隔事件
                 _ = refresh_one_interval.tick() => {
                     if !updater.state.is_ready && init_timeout < Instant::now() {
@@ -369,6 +376,36 @@ impl EdgeUpdater {
         let _ = self.ready_sender.try_send(());
     }
 
+    // 添加运行时新增代币的边，账户写入到达后和其它边一样刷新
+    fn add_edges(&mut self, dex: Dex) {
+        let mut mints = HashSet::new();
+        for (pk, edges) in dex.edges_per_pk {
+            for edge in &edges {
+                for mint in [edge.input_mint, edge.output_mint] {
+                    self.state
+                        .edges_per_mint
+                        .entry(mint)
+                        .or_default()
+                        .push(edge.clone());
+                    mints.insert(mint);
+                }
+                self.state.dirty_edges.insert(edge.unique_id(), edge.clone());
+            }
+            self.dex.edges_per_pk.entry(pk).or_default().extend(edges);
+        }
+        info!(
+            "{} - added edges for {} mints at runtime",
+            self.dex.name,
+            mints.len()
+        );
+
+        for mint in mints {
+            if self.register_mint_sender.try_send(mint).is_err() {
+                warn!("Failed to register mint '{}' for price update", mint);
+            }
+        }
+    }
+
     // 处理元数据更新事件
     fn on_metadata_update(&mut self, res: Result<FeedMetadata, RecvError>) {
         let state = &mut self.state;
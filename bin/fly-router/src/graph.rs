@@ -147,6 +147,67 @@ impl Graph {
             })
             .collect()
     }
+
+    /// 按 (poolkey, input_mint) 查找边
+    pub fn edge(&self, pool: &Pubkey, input_mint: &Pubkey) -> Option<Arc<Edge>> {
+        self.edges.get(&(*pool, *input_mint)).cloned()
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = &Arc<Edge>> {
+        self.edges.values()
+    }
+
+    /// 寻找从 from 到 to、不超过 max_hops 条边的所有路径
+    /// 要求：路径中每个代币最多经过一次
+    pub fn find_paths(&self, from: Pubkey, to: Pubkey, max_hops: usize) -> Vec<Vec<Arc<Edge>>> {
+        let mut results = Vec::new();
+        if from == to {
+            return results;
+        }
+        let mut path = Vec::new();
+        let mut visited = HashSet::from([from]);
+
+        fn dfs(
+            graph: &Graph,
+            current: Pubkey,
+            to: Pubkey,
+            max_hops: usize,
+            path: &mut Vec<Arc<Edge>>,
+            visited: &mut HashSet<Pubkey>,
+            results: &mut Vec<Vec<Arc<Edge>>>,
+        ) {
+            if current == to {
+                results.push(path.clone());
+                return;
+            }
+            if path.len() == max_hops {
+                return;
+            }
+            let Some(neighbors) = graph.adjacency.get(&current) else {
+                return;
+            };
+            for (eid, next) in neighbors {
+                if visited.contains(next) {
+                    continue;
+                }
+                let edge = graph
+                    .edges
+                    .get(&(*eid, current))
+                    .expect("edge must exist")
+                    .clone();
+                visited.insert(*next);
+                path.push(edge);
+
+                dfs(graph, *next, to, max_hops, path, visited, results);
+
+                path.pop();
+                visited.remove(next);
+            }
+        }
+
+        dfs(self, from, to, max_hops, &mut path, &mut visited, &mut results);
+        results
+    }
 }
 
 
@@ -543,6 +604,37 @@ mod tests {
        assert_eq!(cycles.len(), length_2_cycles); // 所有的环路都是长度为2的环路
    }
 
+    #[test]
+    fn test_find_paths_between_mints() {
+        let mut g = Graph::new();
+        let chain_data = Arc::new(ChainDataAccountProvider::new(ChainDataArcRw::new(
+            Default::default(),
+        ))) as AccountProviderView;
+        let dex = Arc::new(MockDexInterface {}) as Arc<dyn DexInterface>;
+
+        let a = pubkey_from_u8(1);
+        let b = pubkey_from_u8(2);
+        let c = pubkey_from_u8(3);
+        let [pool_ab, pool_bc, pool_ac] = [(); 3].map(|_| Pubkey::new_unique());
+
+        for (pool, x, y) in [(pool_ab, a, b), (pool_bc, b, c), (pool_ac, a, c)] {
+            g.add_edge(Arc::new(make_edge(&dex, &pool, &x, &y, &chain_data, 6, 1.0, 1.0)));
+            g.add_edge(Arc::new(make_edge(&dex, &pool, &y, &x, &chain_data, 6, 1.0, 1.0)));
+        }
+
+        // a -> c 直连，以及 a -> b -> c
+        let paths = g.find_paths(a, c, 3);
+        assert_eq!(paths.len(), 2);
+        assert!(paths.iter().any(|p| p.len() == 1 && p[0].key() == pool_ac));
+        assert!(paths
+            .iter()
+            .any(|p| p.iter().map(|e| e.key()).collect_vec() == vec![pool_ab, pool_bc]));
+
+        assert_eq!(g.find_paths(a, c, 1).len(), 1);
+        assert!(g.find_paths(a, a, 3).is_empty());
+        assert_eq!(g.edge(&pool_bc, &c).unwrap().output_mint, b);
+    }
+
     fn make_edge(
         dex: &Arc<dyn DexInterface>,
         key: &Pubkey,
@@ -3,7 +3,7 @@ use router_config_lib::HotMintsConfig;
 use solana_program::pubkey::Pubkey;
 use std::collections::{HashSet, VecDeque};
 use std::str::FromStr;
-use tracing::debug;
+use tracing::{debug, error};
 
 //一个简单的缓存机制，用于管理热门铸币公钥，并提供了相应的测试来验证其功能
 
@@ -30,7 +30,13 @@ impl HotMintsCache {
             always_hot: config
                 .always_hot_mints
                 .iter()
-                .map(|x| Pubkey::from_str(x).unwrap())
+                .filter_map(|x| match Pubkey::from_str(x) {
+                    Ok(mint) => Some(mint),
+                    Err(_) => {
+                        error!("ignoring invalid hot_mints.always_hot_mints entry {}", x);
+                        None
+                    }
+                })
                 .collect(),
             latest_unordered: Default::default(),
             latest_ordered: Default::default(),
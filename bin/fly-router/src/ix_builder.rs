@@ -12,6 +12,11 @@ use std::sync::Arc;
 const CU_PER_HOP_DEFAULT: u32 = 80_000;
 const CU_BASE: u32 = 150_000;
 
+/// Compute units of a route when no instruction was built for it, e.g. in the backtest
+pub fn default_cu_estimate(hops: usize) -> u32 {
+    CU_BASE + CU_PER_HOP_DEFAULT * hops as u32
+}
+
 pub trait SwapStepInstructionBuilder {
     fn build_ix(
         &self,
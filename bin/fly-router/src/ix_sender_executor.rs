@@ -1,35 +1,62 @@
+use anchor_spl::token::spl_token::native_mint;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_program::address_lookup_table::AddressLookupTableAccount;
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::signature::Signer;
+use solana_sdk::transaction::VersionedTransaction;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::broadcast;
 use tokio::task::JoinHandle;
 
+use crate::alt::alt_manager::AltManager;
+use crate::ata_provider::{AtaProvider, TOKEN_ACCOUNT_RENT};
 use crate::alt::alt_optimizer;
 use crate::ix_builder::SwapInstructionsBuilder;
+use crate::control::RuntimeControl;
+use crate::journal::{self, Outcome};
+use crate::key_source::WalletSigner;
+use crate::metrics;
+use crate::pnl_tracker::{PnlHandle, SentBundle};
 use crate::prelude::*;
 use crate::routing_types::Route;
 use crate::sender::ix_sender::{generate_ix_sender, IxSender, SendMode};
+use crate::sender::simulator::TxSimulator;
+use crate::sender::tip_pricer::TipPricer;
 use crate::server::alt_provider::{self, AltProvider};
 use crate::server::client_provider::ClientProvider;
 use crate::server::hash_provider::HashProvider;
+use crate::shutdown;
 use crate::swap::Swap;
+use crate::tx_preflight::{TxEstimate, TxPreflightError};
 use crate::util::tokio_spawn;
-use crate::utils::get_source_atas;
+use crate::wallet_pool::WalletPool;
 
 use router_config_lib::Config;
 use router_lib::dex::{AccountProvider, SwapMode};
 
-// make sure the transaction can be executed
-const MAX_ACCOUNTS_PER_TX: usize = 64;
-const MAX_TX_SIZE: usize = 1232;
-const DEFAULT_COMPUTE_UNIT_PRICE_MICRO_LAMPORTS: u64 = 10_000;
+pub const DEFAULT_COMPUTE_UNIT_PRICE_MICRO_LAMPORTS: u64 = 10_000;
+const DEFAULT_MAX_BLOCKHASH_AGE_MS: u64 = 2_000;
+const DEFAULT_MIN_BLOCKHASH_BLOCKS_LEFT: u64 = 20;
+const TIP_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+/// 区块哈希过期后 tx1 不会再落地，临时小费账户的余额不再变化
+const TIP_SWEEP_MIN_AGE: Duration = Duration::from_secs(120);
+
+/// 钱包池中每个钱包有自己的发送器
+struct WalletSender {
+    wallet_pk: Pubkey,
+    ix_sender: Arc<Box<dyn IxSender + Send + Sync + 'static>>,
+}
 
 #[derive(Default)]
 struct SenderExecutorState {
     // 是否准备好
     pub is_ready: bool,
+    // 实际收益跟踪，回撤或余额不足时暂停交易
+    pub pnl: Option<PnlHandle>,
+    // 控制接口：暂停发送和黑白名单
+    pub control: Arc<RuntimeControl>,
 }
 
 pub struct SenderExecutor<
@@ -42,20 +69,29 @@ pub struct SenderExecutor<
     // 准备就绪信号发送器
     // ready_sender: async_channel::Sender<()>,
     //keypair: Keypair,
-    wallet_pk: Pubkey,
+    wallets: HashMap<Pubkey, WalletSender>,
 
-    source_atas: HashMap<Pubkey, Pubkey>,
+    // 按可用库存把路由分配给钱包
+    wallet_pool: Arc<WalletPool>,
+
+    // 钱包的 ATA，缺少的在交易中创建
+    ata_provider: Arc<AtaProvider>,
+
+    // 把 ATA 租金折算成输入代币，测试中为 None，只有 SOL 环可以支付租金
+    tip_pricer: Option<TipPricer>,
 
     swap_mode: SwapMode,
 
     //wrap_and_unwrap_sol: bool,
     compute_unit_price_micro_lamports: u64,
 
-    //auto_create_out_ata: bool,
-    slippage_bps: i32,
+    // 当前已加载的查找表，配置重新加载后可能与控制状态不同
+    lookup_tables: Vec<Pubkey>,
 
     alt_accounts: Vec<AddressLookupTableAccount>,
 
+    alt_manager: Option<Arc<AltManager>>,
+
     hash_provider: Arc<THashProvider>,
 
     alt_provider: Arc<TAltProvider>,
@@ -64,7 +100,13 @@ pub struct SenderExecutor<
 
     ix_builder: Arc<TIxBuilder>,
 
-    ix_sender: Arc<Box<dyn IxSender + Send + Sync + 'static>>,
+    simulator: Option<TxSimulator>,
+
+    // 定期回收临时小费账户
+    rpc: Option<RpcClient>,
+
+    // 关闭时没能回收的临时小费钱包保存在这里
+    tip_account_path: Option<String>,
 
     pub state: SenderExecutorState,
 }
@@ -80,35 +122,31 @@ impl<
     pub async fn new(
         config: &Config,
         rpc: RpcClient,
-        keypair: Keypair,
+        signers: Vec<WalletSigner>,
+        wallet_pool: Arc<WalletPool>,
+        ata_provider: Arc<AtaProvider>,
         hash_provider: Arc<THashProvider>,
         alt_provider: Arc<TAltProvider>,
         account_provider: Arc<TAccountProvider>,
         ix_builder: Arc<TIxBuilder>,
+        alt_manager: Option<Arc<AltManager>>,
+        control: Arc<RuntimeControl>,
+        tip_pricer: TipPricer,
         //ix_sender: Arc<TIxSender>,
-    ) -> Self {
-        let state = SenderExecutorState::default();
-        let address_lookup_table_addresses = match &config.sender.lookup_tables {
-            Some(lookup_tables) => lookup_tables
-                .iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<String>>(),
-            None => vec![],
+        pnl: Option<PnlHandle>,
+    ) -> anyhow::Result<Self> {
+        let state = SenderExecutorState {
+            control: control.clone(),
+            pnl: pnl.clone(),
+            ..Default::default()
         };
-
+        let lookup_tables = control.snapshot().lookup_tables.clone();
         let alt_accounts = alt_provider::load_all_alts(
-            address_lookup_table_addresses.clone(),
+            lookup_tables.iter().map(|x| x.to_string()).collect(),
             alt_provider.clone(),
         )
         .await;
 
-        let wallet_pk = keypair.pubkey();
-
-        //TODO: 这里需要ata_proviter来动态刷新ATA账户，以减少重启和计算CU
-        let source_atas = get_source_atas(&rpc, &keypair.pubkey())
-            .await
-            .unwrap_or_default(); // 获取所有的ATA账户
-
         //TODO：固定输入模式，其他模式后续再支持
         let swap_mode = SwapMode::ExactIn; // 交换模式，默认ExactIn
 
@@ -119,69 +157,280 @@ impl<
             .compute_unit_price_micro_lamports
             .unwrap_or(DEFAULT_COMPUTE_UNIT_PRICE_MICRO_LAMPORTS); // 计算单元价格
                                                                    //let auto_create_out_ata = config.sender.auto_create_out_ata.unwrap_or(false); // 是否自动创建输出ATA
-        let slippage_bps = config.sender.slippage_bps.unwrap_or(0); // 滑点设置
 
         let send_mode = config
             .sender
             .send_mode
             .clone()
             .unwrap_or("JitoBundle".to_string()); // 发送模式
-        let send_mode = SendMode::from_str(&send_mode).unwrap_or(SendMode::JitoBundle); // 发送模式
+        // 启动时已经校验过，这里不再静默回退到 JitoBundle
+        let send_mode = SendMode::from_str(&send_mode)
+            .map_err(|_| anyhow!("unknown sender.send_mode {}", send_mode))?; // 发送模式
         let name = config
             .sender
             .name
             .clone()
             .unwrap_or("fly_router".to_string()); // 发送器名称
-        let jito_tip_bps = config.sender.jito_tip_bps.unwrap_or(0.65); // Jito小费
-        let jito_max_tip = config.sender.jito_max_tip.unwrap_or(10_000_000); // Jito最大小费
-        let mut jito_regions = config.sender.jito_regions.clone().unwrap_or_default(); // Jito区域
-        if jito_regions.is_empty() {
-            jito_regions = vec!["frankfurt".to_string()]; // 默认区域
-        }
         let region_send_type = config
             .sender
             .region_send_type
             .clone()
             .unwrap_or("serial".to_string()); // Jito区域发送类型
-
-        let ix_sender = generate_ix_sender(
-            send_mode,
-            name,
-            keypair,
-            alt_provider.clone(),
-            compute_unit_price_micro_lamports,
-            jito_tip_bps,
-            jito_max_tip,
-            jito_regions,
-            region_send_type,
-            hash_provider.clone(),
-            Arc::new(ClientProvider::new().unwrap()),
-        )
-        .unwrap_or_else(|_| {
-            panic!(
-                "Failed to generate ix sender for mode: {}",
-                send_mode.to_string()
+        let max_blockhash_age = Duration::from_millis(
+            config
+                .sender
+                .max_blockhash_age_ms
+                .unwrap_or(DEFAULT_MAX_BLOCKHASH_AGE_MS),
+        ); // 区块hash最大存活时间
+        let min_blockhash_blocks_left = config
+            .sender
+            .min_blockhash_blocks_left
+            .unwrap_or(DEFAULT_MIN_BLOCKHASH_BLOCKS_LEFT); // 区块hash最少剩余区块数
+
+        let client_provider = Arc::new(ClientProvider::new().unwrap());
+        let mut wallets = HashMap::new();
+        for signer in signers {
+            let wallet_pk = signer.pubkey();
+            let ix_sender = generate_ix_sender(
+                send_mode,
+                name.clone(),
+                signer,
+                alt_provider.clone(),
+                compute_unit_price_micro_lamports,
+                control.clone(),
+                region_send_type.clone(),
+                hash_provider.clone(),
+                max_blockhash_age,
+                min_blockhash_blocks_left,
+                alt_manager.clone(),
+                client_provider.clone(),
+                config.sender.dry_run.clone().unwrap_or_default(),
+                rpc.url(),
+                tip_pricer.clone(),
+                pnl.clone(),
             )
-        });
+            .with_context(|| {
+                format!(
+                    "Failed to generate ix sender for mode: {}",
+                    send_mode.to_string()
+                )
+            })?;
+            wallets.insert(
+                wallet_pk,
+                WalletSender {
+                    wallet_pk,
+                    ix_sender,
+                },
+            );
+        }
+        info!("sending from {} wallet(s)", wallets.len());
+        let tip_rpc = RpcClient::new_with_commitment(rpc.url(), rpc.commitment());
 
-        Self {
+        // 按发送模式配置的模拟
+        let simulator = config
+            .sender
+            .simulation
+            .as_ref()
+            .and_then(|s| s.get(&send_mode.to_string()))
+            .filter(|s| s.enabled)
+            .map(|s| TxSimulator::new(rpc, s));
+
+        Ok(Self {
             // keypair,
-            wallet_pk,
-            source_atas,
+            wallets,
+            wallet_pool,
+            ata_provider,
+            tip_pricer: Some(tip_pricer),
             swap_mode,
             // wrap_and_unwrap_sol,
             compute_unit_price_micro_lamports,
             //  auto_create_out_ata,
-            slippage_bps,
+            lookup_tables,
             alt_accounts,
+            alt_manager,
             hash_provider,
             alt_provider,
             account_provider,
             ix_builder,
-            ix_sender,
+            simulator,
+            rpc: Some(tip_rpc),
+            tip_account_path: config
+                .shutdown
+                .as_ref()
+                .and_then(|s| s.tip_account_path.as_ref())
+                .map(|p| shellexpand::tilde(p).to_string()),
             state,
+        })
+    }
+}
+
+impl<
+        THashProvider: HashProvider + Send + Sync + 'static,
+        TAltProvider: AltProvider + Send + Sync + 'static,
+        TAccountProvider: AccountProvider + Send + Sync + 'static,
+        TIxBuilder: SwapInstructionsBuilder + Send + Sync + 'static,
+    > SenderExecutor<THashProvider, TAltProvider, TAccountProvider, TIxBuilder>
+{
+    /// 不访问 RPC 的执行器，发送器由调用方提供
+    #[cfg(test)]
+    pub(crate) fn with_ix_sender(
+        config: &Config,
+        wallet_pk: Pubkey,
+        hash_provider: Arc<THashProvider>,
+        alt_provider: Arc<TAltProvider>,
+        account_provider: Arc<TAccountProvider>,
+        ix_builder: Arc<TIxBuilder>,
+        ix_sender: Arc<Box<dyn IxSender + Send + Sync + 'static>>,
+    ) -> Self {
+        let wallet = WalletSender {
+            wallet_pk,
+            ix_sender,
+        };
+        Self {
+            wallets: HashMap::from([(wallet_pk, wallet)]),
+            wallet_pool: Arc::new(WalletPool::new(vec![wallet_pk], None)),
+            ata_provider: Arc::new(AtaProvider::new(
+                None,
+                Arc::new(crate::source::token_cache::TokenCache::new(HashMap::new())),
+            )),
+            tip_pricer: None,
+            swap_mode: SwapMode::ExactIn,
+            compute_unit_price_micro_lamports: config
+                .sender
+                .compute_unit_price_micro_lamports
+                .unwrap_or(DEFAULT_COMPUTE_UNIT_PRICE_MICRO_LAMPORTS),
+            lookup_tables: vec![],
+            alt_accounts: vec![],
+            alt_manager: None,
+            hash_provider,
+            alt_provider,
+            account_provider,
+            ix_builder,
+            simulator: None,
+            rpc: None,
+            tip_account_path: None,
+            state: SenderExecutorState::default(),
         }
     }
+
+    /// 配置中的查找表改变后重新加载
+    async fn sync_lookup_tables(&mut self, lookup_tables: &[Pubkey]) {
+        if self.lookup_tables == lookup_tables {
+            return;
+        }
+        self.alt_accounts = alt_provider::load_all_alts(
+            lookup_tables.iter().map(|x| x.to_string()).collect(),
+            self.alt_provider.clone(),
+        )
+        .await;
+        self.lookup_tables = lookup_tables.to_vec();
+        info!("{} lookup tables loaded", self.alt_accounts.len());
+    }
+
+    /// 在请求jupiter之前估算交易大小和账户数量，包括需要创建的 ATA。
+    /// 按所有账户都能从查找表加载估算，只拒绝一定超限的路由：jupiter 返回的查找表在这里
+    /// 还不知道，编译后的交易由 check_transaction 精确检查
+    fn preflight(&self, route: &Route, created_atas: usize) -> Result<(), TxPreflightError> {
+        let edges = route.steps.iter().map(|s| s.edge.clone()).collect::<Vec<_>>();
+        TxEstimate::best_case(&edges)
+            .with_created_atas(created_atas)
+            .check()
+    }
+
+    /// 需要创建的 ATA 的租金计入路由成本，扣除后收益仍需达到 expected_gain
+    fn check_ata_rent(
+        &self,
+        route: &Route,
+        created_atas: usize,
+        expected_gain: u64,
+    ) -> anyhow::Result<()> {
+        if created_atas == 0 {
+            return Ok(());
+        }
+        let rent = TOKEN_ACCOUNT_RENT * created_atas as u64;
+        let rent = match &self.tip_pricer {
+            Some(tip_pricer) => tip_pricer.from_lamports(&route.input_mint, rent)?,
+            None if route.input_mint == native_mint::ID => rent,
+            None => bail!("cannot value the ATA rent in {}", route.input_mint),
+        };
+        let profit = route.out_amount.saturating_sub(route.in_amount);
+        if profit < rent + expected_gain {
+            bail!(
+                "profit {} does not cover the rent {} of {} ATA(s) and the expected gain {}",
+                profit,
+                rent,
+                created_atas,
+                expected_gain
+            );
+        }
+        Ok(())
+    }
+
+    /// 没有构建交易的路由也计入 alt 使用统计，否则它们的池子永远进不了 alt
+    fn record_alt_usage(&self, route: &Route) {
+        if let Some(alt_manager) = &self.alt_manager {
+            let edges = route.steps.iter().map(|s| s.edge.clone()).collect::<Vec<_>>();
+            alt_manager.record_route_usage(&edges, &self.alt_accounts);
+        }
+    }
+
+    /// 模拟交易，记录CU并检查收益
+    async fn simulate(
+        &self,
+        route: &Route,
+        wallet: &WalletSender,
+        transactions: &HashMap<String, Vec<VersionedTransaction>>,
+    ) -> anyhow::Result<()> {
+        let Some(simulator) = &self.simulator else {
+            return Ok(());
+        };
+        if !simulator.should_simulate() {
+            return Ok(());
+        }
+        let Some(tx) = transactions.values().next().and_then(|txs| txs.first()) else {
+            return Ok(());
+        };
+        let source_ata = self
+            .ata_provider
+            .ata(&wallet.wallet_pk, &route.input_mint)
+            .ok_or_else(|| anyhow!("no source ata for {}", route.input_mint))?;
+
+        let result = simulator.simulate(tx, &source_ata).await?;
+        debug!("simulation result: {:?}", result);
+        if let Some(units_consumed) = result.units_consumed {
+            simulator.record_cu(route, units_consumed);
+        }
+        simulator.check(&result)?;
+        Ok(())
+    }
+
+    /// 使用模拟测得的CU替换jupiter的估算
+    fn with_measured_cu(&self, route: &Route, swap: Arc<Swap>) -> Arc<Swap> {
+        let measured = self
+            .simulator
+            .as_ref()
+            .and_then(|simulator| simulator.cu_estimate(route));
+        match measured {
+            Some(cu_estimate) => Arc::new(Swap {
+                cu_estimate,
+                ..(*swap).clone()
+            }),
+            None => swap,
+        }
+    }
+}
+
+fn observe_latency(route: &Route, stage: &str) {
+    metrics::ROUTE_SEND_LATENCY
+        .with_label_values(&[stage])
+        .observe(route.created_at.elapsed().as_secs_f64());
+}
+
+fn cooldown_ring(route: &Route, control: &RuntimeControl) {
+    if let Some(ring_state) = &route.ring_state {
+        let cooldown = Duration::from_secs(control.snapshot().params.ring_cooldown_secs);
+        ring_state.write().unwrap().add_cooldown(&cooldown);
+    }
 }
 
 async fn build_swap_tx<TIxBuilder>(
@@ -252,33 +501,145 @@ pub async fn spawn_sender_executor_job<
 >(
     config: &Config,
     rpc: RpcClient,
-    keypair: Keypair,
+    signers: Vec<WalletSigner>,
+    wallet_pool: Arc<WalletPool>,
+    ata_provider: Arc<AtaProvider>,
     hash_provider: Arc<THashProvider>,
     alt_provider: Arc<TAltProvider>,
     account_provider: Arc<TAccountProvider>,
     ix_builder: Arc<TIxBuilder>,
+    alt_manager: Option<Arc<AltManager>>,
+    control: Arc<RuntimeControl>,
+    tip_pricer: TipPricer,
     //ix_sender: Arc<TIxSender>,
+    pnl: Option<PnlHandle>,
     route_receiver: async_channel::Receiver<Arc<Route>>,
-    mut exit: broadcast::Receiver<()>,
-) -> JoinHandle<()> {
-    let mut executor =
+    exit: broadcast::Receiver<()>,
+) -> anyhow::Result<JoinHandle<()>> {
+    let executor =
         SenderExecutor::<THashProvider, TAltProvider, TAccountProvider, TIxBuilder>::new(
             config,
             rpc,
-            keypair,
+            signers,
+            wallet_pool,
+            ata_provider,
             hash_provider,
             alt_provider,
             account_provider,
             ix_builder,
+            alt_manager,
+            control,
+            tip_pricer,
             //ix_sender,
+            pnl,
         )
-        .await;
+        .await?;
 
     // let swap_mode: SwapMode = SwapMode::from_str(&input.quote_response.swap_mode)
     // .map_err(|_| anyhow::Error::msg("Invalid SwapMode"))?;
 
+    Ok(spawn_sender_loop(executor, route_receiver, exit))
+}
+
+async fn sweep_tip_accounts(
+    rpc: &RpcClient,
+    senders: &[(Pubkey, Arc<Box<dyn IxSender + Send + Sync + 'static>>)],
+    min_age: Duration,
+) {
+    for (wallet_pk, ix_sender) in senders {
+        match ix_sender.sweep_tip_accounts(rpc, min_age).await {
+            Ok(0) => {}
+            Ok(closed) => info!("closed {} tip account(s) of wallet {}", closed, wallet_pk),
+            Err(e) => warn!(
+                "failed to close the tip accounts of wallet {}: {:?}",
+                wallet_pk, e
+            ),
+        }
+    }
+}
+
+/// 定期回收临时小费账户，自己的任务中运行，不阻塞发送；
+/// 退出时（在途的 bundle 已经等待过）回收剩下的，回收失败的保存下来
+fn spawn_tip_sweeper_job(
+    rpc: RpcClient,
+    senders: Vec<(Pubkey, Arc<Box<dyn IxSender + Send + Sync + 'static>>)>,
+    tip_account_path: Option<String>,
+    mut exit: broadcast::Receiver<()>,
+) -> JoinHandle<()> {
+    tokio_spawn("tip_sweeper", async move {
+        if let (Some(path), Some((wallet_pk, ix_sender))) = (&tip_account_path, senders.first()) {
+            match shutdown::load_tip_accounts(Path::new(path)) {
+                Ok(keypairs) if !keypairs.is_empty() => {
+                    info!(
+                        "sweeping {} tip account(s) left by the previous run to wallet {}",
+                        keypairs.len(),
+                        wallet_pk
+                    );
+                    ix_sender.restore_tip_accounts(keypairs);
+                }
+                Ok(_) => {}
+                Err(e) => warn!("failed to load the tip accounts from {}: {:?}", path, e),
+            }
+        }
+
+        let mut interval = tokio::time::interval(TIP_SWEEP_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = exit.recv() => break,
+                _ = interval.tick() => sweep_tip_accounts(&rpc, &senders, TIP_SWEEP_MIN_AGE).await,
+            }
+        }
+
+        sweep_tip_accounts(&rpc, &senders, Duration::ZERO).await;
+        let left = senders
+            .iter()
+            .flat_map(|(_, ix_sender)| ix_sender.take_tip_accounts())
+            .collect_vec();
+        match &tip_account_path {
+            Some(path) => {
+                if !left.is_empty() {
+                    warn!("{} tip account(s) not swept, saved to {}", left.len(), path);
+                }
+                if let Err(e) = shutdown::save_tip_accounts(Path::new(path), &left) {
+                    error!("failed to save the tip accounts to {}: {:?}", path, e);
+                }
+            }
+            None if !left.is_empty() => error!(
+                "{} tip account(s) not swept and shutdown.tip_account_path is not set: {:?}",
+                left.len(),
+                left.iter().map(|k| k.pubkey()).collect_vec()
+            ),
+            None => {}
+        }
+    })
+}
+
+/// 消费 ring executor 发出的路由，构建、模拟并发送交易
+pub(crate) fn spawn_sender_loop<
+    THashProvider: HashProvider + Send + Sync + 'static,
+    TAltProvider: AltProvider + Send + Sync + 'static,
+    TAccountProvider: AccountProvider + Send + Sync + 'static,
+    TIxBuilder: SwapInstructionsBuilder + Send + Sync + 'static,
+>(
+    mut executor: SenderExecutor<THashProvider, TAltProvider, TAccountProvider, TIxBuilder>,
+    route_receiver: async_channel::Receiver<Arc<Route>>,
+    mut exit: broadcast::Receiver<()>,
+) -> JoinHandle<()> {
     executor.state.is_ready = true;
     //info!("sender executor is ready");
+    let mut tip_sweeper = executor.rpc.take().map(|rpc| {
+        let senders = executor
+            .wallets
+            .values()
+            .map(|w| (w.wallet_pk, w.ix_sender.clone()))
+            .collect_vec();
+        spawn_tip_sweeper_job(
+            rpc,
+            senders,
+            executor.tip_account_path.clone(),
+            exit.resubscribe(),
+        )
+    });
 
     // 生成 Tokio 任务
     let listener_job = tokio_spawn("sender_executor", async move {
@@ -290,6 +651,9 @@ pub async fn spawn_sender_executor_job<
                 // 处理退出信号
                 _ = exit.recv() => {
                     info!("shutting down sender executor task");
+                    if let Some(tip_sweeper) = tip_sweeper.take() {
+                        let _ = tip_sweeper.await;
+                    }
                     break;
                 }
                 route = route_receiver.recv() => {
@@ -297,34 +661,120 @@ pub async fn spawn_sender_executor_job<
                         Ok(route) => {
                             // let gain = route.out_amount - route.in_amount;
                             info!("route: {:?}", route.display_steps());
+                            journal::record_route(&route);
+                            observe_latency(&route, "received");
+                            if executor.state.control.is_shutting_down() {
+                                debug!("shutting down, dropping route");
+                                journal::record_outcome(&route, Outcome::ShuttingDown, None);
+                                continue;
+                            }
+                            if executor.state.control.is_paused()
+                                || executor.state.pnl.as_ref().map_or(false, |pnl| pnl.is_paused())
+                            {
+                                debug!("trading paused, dropping route");
+                                journal::record_outcome(&route, Outcome::Paused, None);
+                                continue;
+                            }
+                            // 路由生成后名单可能已经改变
+                            let control = executor.state.control.snapshot();
+                            if !control.allows_route(&route) {
+                                debug!("route filtered by the control lists");
+                                journal::record_outcome(&route, Outcome::Filtered, None);
+                                continue;
+                            }
+                            executor.sync_lookup_tables(&control.lookup_tables).await;
+                            // 选择有足够库存、没有交易在途的钱包
+                            let Some(reservation) = executor.wallet_pool.reserve(route.input_mint, route.in_amount) else {
+                                debug!("no wallet with {} free inventory of {}", route.in_amount, route.input_mint);
+                                journal::record_outcome(&route, Outcome::NoInventory, None);
+                                executor.record_alt_usage(&route);
+                                continue;
+                            };
+                            // 缺少的 ATA 取决于选中的钱包
+                            let created_atas = executor.ata_provider.missing_ata_count(&reservation.wallet, &route);
+                            if let Err(e) = executor.preflight(&route, created_atas) {
+                                warn!("route rejected by pre-flight: {}", e);
+                                executor.wallet_pool.release(&reservation);
+                                journal::record_outcome(&route, Outcome::RejectedPreflight, Some(e.to_string()));
+                                cooldown_ring(&route, &executor.state.control);
+                                executor.record_alt_usage(&route);
+                                continue;
+                            }
+                            let (_, expected_gain) = control.params.amounts_for(&route.input_mint);
+                            if let Err(e) = executor.check_ata_rent(&route, created_atas, expected_gain) {
+                                debug!("route rejected: {:#}", e);
+                                executor.wallet_pool.release(&reservation);
+                                journal::record_outcome(&route, Outcome::AtaRentNotCovered, Some(e.to_string()));
+                                continue;
+                            }
+                            let wallet = &executor.wallets[&reservation.wallet];
                             // let swap = executor.build_swap_tx(route.clone()).await;
                             let swap = build_swap_tx(
                                 executor.ix_builder.clone(),
                                 route.clone(),
-                                executor.wallet_pk,
-                                executor.source_atas.clone(),
-                                executor.slippage_bps,
+                                wallet.wallet_pk,
+                                executor.ata_provider.source_atas(&wallet.wallet_pk),
+                                control.params.slippage_bps,
                                 executor.swap_mode,
                             ).await;
                             info!("swap tx: {:?}", swap);
                             match swap {
                                 Ok(swap) => {
                                     //let swap = Arc::new(swap);
-                                    let transactions = executor.ix_sender.instructuin_extend(swap,route.clone()).await;
+                                    // 测得的 CU 不包括本次新建 ATA 的消耗，先替换再加上
+                                    let swap = executor.with_measured_cu(&route, swap);
+                                    let swap = executor.ata_provider.with_missing_atas(&wallet.wallet_pk, &route, swap);
+                                    journal::record_swap(&route, &swap);
+                                    observe_latency(&route, "built");
+                                    let transactions = wallet.ix_sender.instructuin_extend(swap,route.clone()).await;
                                     match transactions {
                                         Ok(transactions) => {
+                                            observe_latency(&route, "extended");
+                                            if let Err(e) = executor.simulate(&route, wallet, &transactions).await {
+                                                warn!("dropping route after simulation: {:?}", e);
+                                                executor.wallet_pool.release(&reservation);
+                                                journal::record_outcome(&route, Outcome::SimulationDropped, Some(e.to_string()));
+                                                cooldown_ring(&route, &executor.state.control);
+                                                continue;
+                                            }
                                             // 发送交易
                                             info!("sending transaction: {:?}", transactions);
-                                            let _ = executor.ix_sender.send_tx(transactions).await;
+                                            let sent = SentBundle::new(&route, &reservation, &transactions);
+                                            match wallet.ix_sender.send_tx(transactions).await {
+                                                Ok(()) if wallet.ix_sender.is_dry_run() => {
+                                                    // 模拟盘的 bundle 不会落地，不交给收益跟踪，库存立即释放
+                                                    executor.wallet_pool.release(&reservation);
+                                                    observe_latency(&route, "sent");
+                                                    journal::record_outcome(&route, Outcome::DryRun, None)
+                                                }
+                                                Ok(()) => {
+                                                    if let Some(pnl) = &executor.state.pnl {
+                                                        pnl.record_sent(sent);
+                                                    }
+                                                    observe_latency(&route, "sent");
+                                                    journal::record_outcome(&route, Outcome::Sent, None)
+                                                }
+                                                Err(e) => {
+                                                    executor.wallet_pool.release(&reservation);
+                                                    journal::record_outcome(&route, Outcome::SendFailed, Some(e.to_string()))
+                                                }
+                                            }
                                         }
                                         Err(e) => {
+                                            if e.downcast_ref::<TxPreflightError>().is_some() {
+                                                cooldown_ring(&route, &executor.state.control);
+                                            }
                                             error!("Failed to extend instruction: {:?}", e);
+                                            executor.wallet_pool.release(&reservation);
+                                            journal::record_outcome(&route, Outcome::ExtendFailed, Some(e.to_string()));
                                         }
                                     }
 
                                 }
                                 Err(e) => {
                                     error!("Failed to build swap transaction: {:?}", e);
+                                    executor.wallet_pool.release(&reservation);
+                                    journal::record_outcome(&route, Outcome::BuildFailed, Some(e.to_string()));
                                 }
                             }
                         }
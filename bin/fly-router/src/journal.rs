@@ -0,0 +1,612 @@
+//! Append-only JSONL journal of the routes handed to the sender and of what happened to them,
+//! and the `fly-router journal` command summarizing it.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use anchor_spl::token::spl_token::native_mint;
+use anyhow::Context;
+use chrono::{TimeZone, Utc};
+use clap::Parser;
+use once_cell::sync::OnceCell;
+use router_config_lib::JournalConfig;
+use serde_derive::{Deserialize, Serialize};
+use solana_sdk::transaction::VersionedTransaction;
+
+use crate::prelude::*;
+use crate::routing_types::Route;
+use crate::swap::Swap;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+static JOURNAL: OnceCell<Journal> = OnceCell::new();
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JournalStep {
+    pub dex: String,
+    pub pool: String,
+    pub input_mint: String,
+    pub output_mint: String,
+    pub in_amount: u64,
+    pub out_amount: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JournalEvent {
+    Route {
+        route_id: u64,
+        ring_id: Option<String>,
+        input_mint: String,
+        in_amount: u64,
+        out_amount: u64,
+        slot: u64,
+        steps: Vec<JournalStep>,
+    },
+    Swap {
+        route_id: u64,
+        cu_estimate: u32,
+        instructions: usize,
+        accounts: usize,
+        lookup_tables: Vec<String>,
+    },
+    /// one bundle per region, `signatures` in bundle order, `tip` in lamports
+    Bundle {
+        route_id: u64,
+        region: String,
+        signatures: Vec<String>,
+        tip: u64,
+        /// the tip in native units of the input mint at the prices it was sized with
+        #[serde(default)]
+        input_mint_tip: Option<u64>,
+        compute_unit_limit: u32,
+    },
+    /// block engine answer, linked to its bundle by the first signature
+    BundleResult {
+        signature: String,
+        region: String,
+        bundle_id: Option<String>,
+        error: Option<String>,
+    },
+    Outcome {
+        route_id: u64,
+        outcome: Outcome,
+        detail: Option<String>,
+    },
+    /// a sent bundle landed, `realized` is the balance change of the input mint
+    /// measured by the pnl tracker, tip and fees included for SOL rings
+    Landed {
+        route_id: u64,
+        realized: i64,
+    },
+    /// a sent bundle did not land within the pnl tracker's bundle ttl
+    Expired {
+        route_id: u64,
+    },
+}
+
+/// What the sender did with a route, `Landed` / `Expired` follow for the sent ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Paused,
+    Filtered,
+    RejectedPreflight,
+    /// the profit does not cover the rent of the ATAs the swap would create
+    AtaRentNotCovered,
+    /// dropped while draining before shutdown
+    ShuttingDown,
+    /// no wallet of the pool had enough free inventory
+    NoInventory,
+    BuildFailed,
+    ExtendFailed,
+    SimulationDropped,
+    SendFailed,
+    Sent,
+    /// simulated and written to the dry run ledger instead of being sent
+    DryRun,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub event: JournalEvent,
+}
+
+enum Message {
+    Entry(JournalEntry),
+    /// acknowledged once the buffered entries are written out
+    Flush(mpsc::Sender<()>),
+}
+
+struct Journal {
+    sender: mpsc::Sender<Message>,
+}
+
+/// Opens the journal, entries recorded before or without it are dropped
+pub fn init(config: &JournalConfig) -> anyhow::Result<()> {
+    let path = shellexpand::tilde(&config.path).to_string();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("opening journal {}", path))?;
+    let mut writer = BufWriter::new(file);
+    let (sender, receiver) = mpsc::channel::<Message>();
+
+    std::thread::Builder::new()
+        .name("journal".to_string())
+        .spawn(move || {
+            let mut last_flush = Instant::now();
+            while let Ok(message) = receiver.recv() {
+                let entry = match message {
+                    Message::Entry(entry) => entry,
+                    Message::Flush(ack) => {
+                        let _ = writer.flush();
+                        last_flush = Instant::now();
+                        let _ = ack.send(());
+                        continue;
+                    }
+                };
+                let written = serde_json::to_string(&entry)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|line| Ok(writeln!(writer, "{}", line)?));
+                if let Err(e) = written {
+                    error!("failed to write journal entry: {:?}", e);
+                }
+                if last_flush.elapsed() > FLUSH_INTERVAL {
+                    let _ = writer.flush();
+                    last_flush = Instant::now();
+                }
+            }
+            let _ = writer.flush();
+        })?;
+
+    if JOURNAL.set(Journal { sender }).is_err() {
+        bail!("journal already initialized");
+    }
+    info!("journaling routes to {}", path);
+    Ok(())
+}
+
+pub fn record(event: JournalEvent) {
+    if let Some(journal) = JOURNAL.get() {
+        let _ = journal.sender.send(Message::Entry(JournalEntry {
+            timestamp_ms: millis_since_epoch(),
+            event,
+        }));
+    }
+}
+
+/// Writes out the buffered entries, called on shutdown since the writer thread never ends
+pub fn flush(timeout: Duration) {
+    let Some(journal) = JOURNAL.get() else {
+        return;
+    };
+    let (ack_sender, ack) = mpsc::channel();
+    if journal.sender.send(Message::Flush(ack_sender)).is_ok()
+        && ack.recv_timeout(timeout).is_err()
+    {
+        warn!("journal was not flushed within {:?}", timeout);
+    }
+}
+
+pub fn is_enabled() -> bool {
+    JOURNAL.get().is_some()
+}
+
+pub fn record_route(route: &Route) {
+    if !is_enabled() {
+        return;
+    }
+    record(JournalEvent::Route {
+        route_id: route.id,
+        ring_id: route.ring_id.clone(),
+        input_mint: route.input_mint.to_string(),
+        in_amount: route.in_amount,
+        out_amount: route.out_amount,
+        slot: route.slot,
+        steps: route
+            .steps
+            .iter()
+            .map(|s| JournalStep {
+                dex: s.edge.dex.name(),
+                pool: s.edge.key().to_string(),
+                input_mint: s.edge.input_mint.to_string(),
+                output_mint: s.edge.output_mint.to_string(),
+                in_amount: s.in_amount,
+                out_amount: s.out_amount,
+            })
+            .collect(),
+    });
+}
+
+pub fn record_swap(route: &Route, swap: &Swap) {
+    if !is_enabled() {
+        return;
+    }
+    record(JournalEvent::Swap {
+        route_id: route.id,
+        cu_estimate: swap.cu_estimate,
+        instructions: swap.setup_instructions.len() + 1 + swap.cleanup_instructions.len(),
+        accounts: swap.accounts().len(),
+        lookup_tables: swap.address_lookup_table_addresses.clone(),
+    });
+}
+
+pub fn record_bundles(
+    route: &Route,
+    transactions: &HashMap<String, Vec<VersionedTransaction>>,
+    tip: u64,
+    input_mint_tip: Option<u64>,
+    compute_unit_limit: u32,
+) {
+    if !is_enabled() {
+        return;
+    }
+    for (region, txs) in transactions {
+        record(JournalEvent::Bundle {
+            route_id: route.id,
+            region: region.clone(),
+            signatures: txs
+                .iter()
+                .filter_map(|tx| tx.signatures.first())
+                .map(|s| s.to_string())
+                .collect(),
+            tip,
+            input_mint_tip,
+            compute_unit_limit,
+        });
+    }
+}
+
+pub fn record_outcome(route: &Route, outcome: Outcome, detail: Option<String>) {
+    record(JournalEvent::Outcome {
+        route_id: route.id,
+        outcome,
+        detail,
+    });
+}
+
+pub fn record_landed(route_id: u64, realized: i64) {
+    record(JournalEvent::Landed { route_id, realized });
+}
+
+pub fn record_expired(route_id: u64) {
+    record(JournalEvent::Expired { route_id });
+}
+
+#[derive(Parser, Debug)]
+#[clap(name = "fly-router journal", about = "Summarize the route journal")]
+pub struct JournalArgs {
+    /// journal file written by fly-router
+    pub path: String,
+    /// ring, dex, mint or hour
+    #[clap(long, default_value = "ring")]
+    pub by: String,
+    /// only show the top groups by expected net PnL
+    #[clap(long, default_value = "20")]
+    pub top: usize,
+}
+
+#[derive(Default)]
+struct RouteSummary {
+    ring_id: String,
+    input_mint: String,
+    hour: String,
+    dexes: Vec<String>,
+    gross: i128,
+    /// in native units of the input mint, None when it could not be valued
+    tip: Option<u64>,
+    sent: bool,
+    realized: Option<i64>,
+}
+
+#[derive(Default, Debug, PartialEq)]
+struct GroupSummary {
+    routes: u64,
+    sent: u64,
+    /// out - in of the sent routes
+    gross: i128,
+    /// tips of the sent routes in the input mint
+    tips: u128,
+    /// sent routes whose tip could not be valued in the input mint, left out of `tips`
+    unpriced_tips: u64,
+    landed: u64,
+    /// measured on the landed routes
+    realized: i128,
+}
+
+impl GroupSummary {
+    fn net(&self) -> i128 {
+        self.gross - self.tips as i128
+    }
+}
+
+/// Expected PnL of the sent routes and realized PnL of the landed ones,
+/// grouped by `by` and keyed by (group, input mint)
+fn summarize(
+    entries: impl Iterator<Item = JournalEntry>,
+    by: &str,
+) -> anyhow::Result<HashMap<(String, String), GroupSummary>> {
+    if !["ring", "dex", "mint", "hour"].contains(&by) {
+        bail!("unknown grouping '{}', expected ring, dex, mint or hour", by);
+    }
+
+    let mut routes: HashMap<u64, RouteSummary> = HashMap::new();
+    for entry in entries {
+        match entry.event {
+            JournalEvent::Route {
+                route_id,
+                ring_id,
+                input_mint,
+                in_amount,
+                out_amount,
+                steps,
+                ..
+            } => {
+                let hour = Utc
+                    .timestamp_millis_opt(entry.timestamp_ms as i64)
+                    .single()
+                    .map(|t| t.format("%Y-%m-%d %H:00").to_string())
+                    .unwrap_or_default();
+                routes.insert(
+                    route_id,
+                    RouteSummary {
+                        ring_id: ring_id.unwrap_or_default(),
+                        input_mint,
+                        hour,
+                        dexes: steps.into_iter().map(|s| s.dex).unique().collect(),
+                        gross: out_amount as i128 - in_amount as i128,
+                        ..Default::default()
+                    },
+                );
+            }
+            JournalEvent::Bundle {
+                route_id,
+                tip,
+                input_mint_tip,
+                ..
+            } => {
+                if let Some(route) = routes.get_mut(&route_id) {
+                    // 旧的日志没有折算后的小费，只有 SOL 环的小费可以直接使用
+                    route.tip = input_mint_tip
+                        .or((route.input_mint == native_mint::ID.to_string()).then_some(tip));
+                }
+            }
+            // 模拟盘的路由按已发送统计预期收益
+            JournalEvent::Outcome {
+                route_id,
+                outcome: Outcome::Sent | Outcome::DryRun,
+                ..
+            } => {
+                if let Some(route) = routes.get_mut(&route_id) {
+                    route.sent = true;
+                }
+            }
+            JournalEvent::Landed { route_id, realized } => {
+                if let Some(route) = routes.get_mut(&route_id) {
+                    route.realized = Some(realized);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut groups: HashMap<(String, String), GroupSummary> = HashMap::new();
+    for route in routes.into_values() {
+        let keys = match by {
+            "ring" => vec![route.ring_id.clone()],
+            "dex" => route.dexes.clone(),
+            "mint" => vec![route.input_mint.clone()],
+            _ => vec![route.hour.clone()],
+        };
+        for key in keys {
+            let group = groups.entry((key, route.input_mint.clone())).or_default();
+            group.routes += 1;
+            if route.sent {
+                group.sent += 1;
+                group.gross += route.gross;
+                match route.tip {
+                    Some(tip) => group.tips += tip as u128,
+                    None => group.unpriced_tips += 1,
+                }
+            }
+            if let Some(realized) = route.realized {
+                group.landed += 1;
+                group.realized += realized as i128;
+            }
+        }
+    }
+    Ok(groups)
+}
+
+pub fn run_cli(args: JournalArgs) -> anyhow::Result<()> {
+    let path = shellexpand::tilde(&args.path).to_string();
+    let file = File::open(&path).with_context(|| format!("opening journal {}", path))?;
+    let entries = BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<JournalEntry>(&line).ok());
+    let groups = summarize(entries, &args.by)?;
+
+    println!(
+        "{:<48} {:<44} {:>8} {:>8} {:>16} {:>14} {:>16} {:>8} {:>16}",
+        args.by, "input mint", "routes", "sent", "gross", "tips", "net", "landed", "realized"
+    );
+    let mut unpriced_tips = false;
+    for ((key, mint), group) in groups
+        .iter()
+        .sorted_by(|a, b| b.1.net().cmp(&a.1.net()).then_with(|| a.0.cmp(b.0)))
+        .take(args.top)
+    {
+        println!(
+            "{:<48} {:<44} {:>8} {:>8} {:>16} {:>14} {:>16} {:>8} {:>16}",
+            key,
+            mint,
+            group.routes,
+            group.sent,
+            group.gross,
+            group.tips,
+            group.net(),
+            group.landed,
+            group.realized
+        );
+        unpriced_tips |= group.unpriced_tips > 0;
+    }
+    println!(
+        "amounts in native units of the input mint, tips converted at the prices they were sized with; gross, tips and net are expected, realized is measured on the landed bundles"
+    );
+    if unpriced_tips {
+        println!("some sent routes have a tip that could not be valued in their input mint, it is left out of tips and net");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(route_id: u64, ring_id: &str, dexes: &[&str], gross: u64) -> JournalEntry {
+        route_in(native_mint::ID, route_id, ring_id, dexes, gross)
+    }
+
+    fn route_in(
+        input_mint: Pubkey,
+        route_id: u64,
+        ring_id: &str,
+        dexes: &[&str],
+        gross: u64,
+    ) -> JournalEntry {
+        JournalEntry {
+            timestamp_ms: 0,
+            event: JournalEvent::Route {
+                route_id,
+                ring_id: Some(ring_id.to_string()),
+                input_mint: input_mint.to_string(),
+                in_amount: 1_000,
+                out_amount: 1_000 + gross,
+                slot: 1,
+                steps: dexes
+                    .iter()
+                    .map(|dex| JournalStep {
+                        dex: dex.to_string(),
+                        pool: String::new(),
+                        input_mint: String::new(),
+                        output_mint: String::new(),
+                        in_amount: 0,
+                        out_amount: 0,
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    fn event(event: JournalEvent) -> JournalEntry {
+        JournalEntry {
+            timestamp_ms: 0,
+            event,
+        }
+    }
+
+    #[test]
+    fn should_summarize_sent_routes_by_ring_and_dex() {
+        let (sol, usdc) = (native_mint::ID.to_string(), Pubkey::new_unique());
+        let entries = vec![
+            route(1, "r1", &["orca", "raydium"], 100),
+            event(JournalEvent::Bundle {
+                route_id: 1,
+                region: "serial".to_string(),
+                signatures: vec![],
+                tip: 30,
+                input_mint_tip: None,
+                compute_unit_limit: 0,
+            }),
+            event(JournalEvent::Outcome {
+                route_id: 1,
+                outcome: Outcome::Sent,
+                detail: None,
+            }),
+            event(JournalEvent::Landed {
+                route_id: 1,
+                realized: 60,
+            }),
+            route(2, "r1", &["orca"], 50),
+            event(JournalEvent::Outcome {
+                route_id: 2,
+                outcome: Outcome::SimulationDropped,
+                detail: None,
+            }),
+            route(3, "r2", &["orca"], 80),
+            event(JournalEvent::Outcome {
+                route_id: 3,
+                outcome: Outcome::Sent,
+                detail: None,
+            }),
+            event(JournalEvent::Expired { route_id: 3 }),
+            // USDC 环的小费按发送时的价格折算
+            route_in(usdc, 4, "r3", &["orca"], 500),
+            event(JournalEvent::Bundle {
+                route_id: 4,
+                region: "serial".to_string(),
+                signatures: vec![],
+                tip: 2_000,
+                input_mint_tip: Some(200),
+                compute_unit_limit: 0,
+            }),
+            event(JournalEvent::Outcome {
+                route_id: 4,
+                outcome: Outcome::Sent,
+                detail: None,
+            }),
+            route_in(usdc, 5, "r3", &["orca"], 500),
+            event(JournalEvent::Bundle {
+                route_id: 5,
+                region: "serial".to_string(),
+                signatures: vec![],
+                tip: 2_000,
+                input_mint_tip: None,
+                compute_unit_limit: 0,
+            }),
+            event(JournalEvent::Outcome {
+                route_id: 5,
+                outcome: Outcome::Sent,
+                detail: None,
+            }),
+        ];
+
+        // 经过 JSON 往返，确保格式可以读回
+        let entries = entries
+            .into_iter()
+            .map(|e| serde_json::from_str(&serde_json::to_string(&e).unwrap()).unwrap())
+            .collect_vec();
+
+        let by_ring = summarize(entries.clone().into_iter(), "ring").unwrap();
+        let r1 = &by_ring[&("r1".to_string(), sol.clone())];
+        assert_eq!(
+            r1,
+            &GroupSummary {
+                routes: 2,
+                sent: 1,
+                gross: 100,
+                tips: 30,
+                unpriced_tips: 0,
+                landed: 1,
+                realized: 60,
+            }
+        );
+        assert_eq!(r1.net(), 70);
+        let r2 = &by_ring[&("r2".to_string(), sol.clone())];
+        assert_eq!((r2.sent, r2.landed, r2.realized), (1, 0, 0));
+        let r3 = &by_ring[&("r3".to_string(), usdc.to_string())];
+        assert_eq!((r3.gross, r3.tips, r3.unpriced_tips), (1_000, 200, 1));
+
+        let by_dex = summarize(entries.into_iter(), "dex").unwrap();
+        assert_eq!(by_dex[&("orca".to_string(), sol.clone())].routes, 3);
+        assert_eq!(by_dex[&("orca".to_string(), sol.clone())].realized, 60);
+        assert_eq!(by_dex[&("raydium".to_string(), sol)].net(), 70);
+    }
+}
@@ -13,11 +13,12 @@ use warp::filters::body::json;
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{debug, error, info, trace, warn};
 
 use crate::ix_builder::SwapInstructionsBuilder;
+use crate::metrics;
 use crate::routing_types::{Route, RouteStep};
 use crate::swap::{self, Swap};
 
@@ -178,6 +179,7 @@ async fn request_jup_swap(
     let request_data:JupRequestData = JupRequestData {
         user_public_key: wallet_pk.to_string(), // 替换为实际用户公钥
         as_legacy_transaction: false,
+        // 使用钱包的 WSOL ATA，经过 SOL 的环缺少它时由 AtaProvider 创建
         wrap_and_unwrap_sol: false,
         use_shared_accounts: false,
         compute_unit_price_micro_lamports: 1,
@@ -316,7 +318,13 @@ impl SwapInstructionsBuilder for JupSwapStepInstructionBuilder {
 
         info!("JupRoute: {:?}", jup_route);
         let jup_url = self.jup_url.clone();
-        let mut jup_swap = request_jup_swap(wallet_pk,&jup_url, jup_route, self.client.clone()).await?;
+        let request_start = Instant::now();
+        let jup_swap = request_jup_swap(wallet_pk,&jup_url, jup_route, self.client.clone()).await;
+        metrics::JUP_BUILDER_TIMING.observe(request_start.elapsed().as_secs_f64());
+        let mut jup_swap = jup_swap.map_err(|e| {
+            metrics::JUP_BUILDER_ERRORS.with_label_values(&["request"]).inc();
+            e
+        })?;
         info!("jup_swap: {:?}", jup_swap);
         
 
@@ -327,8 +335,9 @@ impl SwapInstructionsBuilder for JupSwapStepInstructionBuilder {
                 .filter(|ix| {
                     let mut keep = true;
                     if ix.program_id == ATA_PROGRAM_ID.to_string() && ix.accounts[0].pubkey == wallet_pk.to_string() && ix.data == "AQ==".to_string() {
+                        // 钱包已有的 ATA 不需要再创建，source_atas 是 mint -> ATA
                         let ata = Pubkey::from_str(&ix.accounts[1].pubkey).unwrap();
-                        if source_atas.contains_key(&ata) {
+                        if source_atas.values().any(|a| *a == ata) {
                             keep = false;
                         }
                     }
@@ -340,7 +349,10 @@ impl SwapInstructionsBuilder for JupSwapStepInstructionBuilder {
 
         info!("jup_swap: {:?}", jup_swap);
 
-        let mut swap = transfer_to_swap(jup_swap)?;
+        let mut swap = transfer_to_swap(jup_swap).map_err(|e| {
+            metrics::JUP_BUILDER_ERRORS.with_label_values(&["decode"]).inc();
+            e
+        })?;
         
 
         info!("swap 2 : {:?}", swap);
@@ -0,0 +1,328 @@
+//! Where the wallet key comes from. Everything that signs holds a `WalletSigner`,
+//! so with a remote signer the key never enters this process.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use router_config_lib::{KeySourceConfig, KeySourceKind};
+use serde_derive::{Deserialize, Serialize};
+use solana_sdk::message::VersionedMessage;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signature, Signer, SignerError};
+use solana_sdk::transaction::VersionedTransaction;
+
+use crate::prelude::*;
+
+pub type WalletSigner = Arc<dyn Signer + Send + Sync>;
+
+/// 本地签名服务，超时后放弃这笔交易
+const REMOTE_SIGNER_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// `encrypted_key_arg` is the second command line argument, used by the encrypted source
+/// when `key_source.encrypted_key` is not set
+pub fn load_signer(
+    config: Option<&KeySourceConfig>,
+    encrypted_key_arg: Option<&str>,
+) -> anyhow::Result<WalletSigner> {
+    let default_config = KeySourceConfig::default();
+    let config = config.unwrap_or(&default_config);
+    let signer: WalletSigner = match config.kind {
+        KeySourceKind::Encrypted => {
+            let encrypted_key = config
+                .encrypted_key
+                .as_deref()
+                .or(encrypted_key_arg)
+                .context(
+                "no encrypted key, set key_source.encrypted_key or pass it as the second argument",
+            )?;
+            let passphrase = read_passphrase(config)?;
+            let private_key = myrust::mycrypt::my_decrypt(encrypted_key, &passphrase);
+            let bytes = bs58::decode(private_key.trim())
+                .into_vec()
+                .map_err(|_| anyhow!("decrypted key is not base58, wrong passphrase?"))?;
+            Arc::new(Keypair::from_bytes(&bytes).context("decrypted key is not a keypair")?)
+        }
+        KeySourceKind::JsonFile => {
+            let path = config
+                .path
+                .as_deref()
+                .context("key_source.path is not set")?;
+            let path = shellexpand::tilde(path).to_string();
+            let keypair = read_keypair_file(&path)
+                .map_err(|e| anyhow!("failed to read keypair file {}: {}", path, e))?;
+            Arc::new(keypair)
+        }
+        KeySourceKind::RemoteSigner => {
+            let socket_path = config
+                .socket_path
+                .as_deref()
+                .context("key_source.socket_path is not set")?;
+            let expected = config
+                .pubkey
+                .as_deref()
+                .map(Pubkey::from_str)
+                .transpose()
+                .context("invalid key_source.pubkey")?;
+            Arc::new(RemoteSigner::connect(
+                shellexpand::tilde(socket_path).to_string(),
+                expected,
+            )?)
+        }
+    };
+    Ok(signer)
+}
+
+fn read_passphrase(config: &KeySourceConfig) -> anyhow::Result<String> {
+    if let Some(name) = &config.passphrase_env {
+        let passphrase = std::env::var(name)
+            .with_context(|| format!("environment variable {} is not set", name))?;
+        // 不要传给子进程
+        std::env::remove_var(name);
+        return Ok(passphrase);
+    }
+    if let Some(fd) = config.passphrase_fd {
+        // 文件描述符由启动方（systemd、容器）传入，只读取一次
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        let mut passphrase = String::new();
+        file.read_to_string(&mut passphrase)
+            .with_context(|| format!("failed to read the passphrase from fd {}", fd))?;
+        return Ok(passphrase.trim_end_matches(['\r', '\n']).to_string());
+    }
+    myrust::mycrypt::read_secret("输入密钥: ").map_err(|_| anyhow!("failed to read the passphrase"))
+}
+
+/// Signs a message whose only required signer is `signer`
+pub fn sign_message(
+    signer: &dyn Signer,
+    message: VersionedMessage,
+) -> anyhow::Result<VersionedTransaction> {
+    let required = message.header().num_required_signatures as usize;
+    let payer = message.static_account_keys().first().copied();
+    if required != 1 || payer != Some(signer.pubkey()) {
+        bail!(
+            "message needs {} signers with payer {:?}, only the wallet {} can sign",
+            required,
+            payer,
+            signer.pubkey()
+        );
+    }
+    let signature = signer.try_sign_message(&message.serialize())?;
+    Ok(VersionedTransaction {
+        signatures: vec![signature],
+        message,
+    })
+}
+
+/// `sign_message` on the blocking pool, the remote signer waits on its socket
+pub async fn sign_message_async(
+    signer: &WalletSigner,
+    message: VersionedMessage,
+) -> anyhow::Result<VersionedTransaction> {
+    let signer = signer.clone();
+    tokio::task::spawn_blocking(move || sign_message(signer.as_ref(), message)).await?
+}
+
+/// Signature of `data` by `signer`, on the blocking pool like `sign_message_async`
+pub async fn sign_data(signer: &WalletSigner, data: Vec<u8>) -> anyhow::Result<Signature> {
+    let signer = signer.clone();
+    Ok(tokio::task::spawn_blocking(move || signer.try_sign_message(&data)).await??)
+}
+
+/// Remote signer protocol, one JSON line per request and per response:
+/// `{"method":"pubkey"}` -> `{"pubkey":"<base58>"}`,
+/// `{"method":"sign","message":"<base64>"}` -> `{"signature":"<base58>"}`,
+/// or `{"error":"..."}` to refuse
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+enum SignerRequest {
+    Pubkey,
+    Sign {
+        /// base64 of the serialized message
+        message: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct SignerResponse {
+    #[serde(default)]
+    pubkey: Option<String>,
+    #[serde(default)]
+    signature: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Signer process listening on a local unix socket
+pub struct RemoteSigner {
+    socket_path: PathBuf,
+    pubkey: Pubkey,
+}
+
+impl RemoteSigner {
+    pub fn connect(
+        socket_path: impl Into<PathBuf>,
+        expected: Option<Pubkey>,
+    ) -> anyhow::Result<Self> {
+        let socket_path = socket_path.into();
+        let response = request(&socket_path, &SignerRequest::Pubkey).with_context(|| {
+            format!(
+                "remote signer at {} is not reachable",
+                socket_path.display()
+            )
+        })?;
+        let pubkey = response
+            .pubkey
+            .as_deref()
+            .map(Pubkey::from_str)
+            .transpose()?
+            .context("remote signer did not return its pubkey")?;
+        if let Some(expected) = expected {
+            if expected != pubkey {
+                bail!("remote signer has key {}, expected {}", pubkey, expected);
+            }
+        }
+        info!(
+            "using remote signer {} at {}",
+            pubkey,
+            socket_path.display()
+        );
+        Ok(Self {
+            socket_path,
+            pubkey,
+        })
+    }
+
+    fn sign(&self, message: &[u8]) -> anyhow::Result<Signature> {
+        let response = request(
+            &self.socket_path,
+            &SignerRequest::Sign {
+                message: BASE64_STANDARD.encode(message),
+            },
+        )?;
+        let signature = response
+            .signature
+            .as_deref()
+            .map(Signature::from_str)
+            .transpose()?
+            .context("remote signer did not return a signature")?;
+        // 签名服务可能换了密钥，发送前先确认
+        if !signature.verify(self.pubkey.as_ref(), message) {
+            bail!(
+                "remote signer returned a signature not made by {}",
+                self.pubkey
+            );
+        }
+        Ok(signature)
+    }
+}
+
+/// 每次请求一个连接，本地 socket 的开销很小，签名服务重启也不影响
+fn request(socket_path: &Path, request: &SignerRequest) -> anyhow::Result<SignerResponse> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    stream.set_read_timeout(Some(REMOTE_SIGNER_TIMEOUT))?;
+    stream.set_write_timeout(Some(REMOTE_SIGNER_TIMEOUT))?;
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    let response: SignerResponse = serde_json::from_str(&response)?;
+    if let Some(error) = response.error {
+        bail!("remote signer refused: {}", error);
+    }
+    Ok(response)
+}
+
+impl Signer for RemoteSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.pubkey)
+    }
+
+    /// Blocks up to `REMOTE_SIGNER_TIMEOUT`, async code signs through `sign_message_async`
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        self.sign(message)
+            .map_err(|e| SignerError::Custom(format!("{:#}", e)))
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::message::v0;
+    use solana_sdk::system_instruction;
+    use std::os::unix::net::UnixListener;
+
+    /// Minimal signer process, answers `connections` requests with `keypair`
+    fn spawn_signer(socket_path: PathBuf, keypair: Keypair, connections: usize) {
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(connections) {
+                let mut stream = stream.unwrap();
+                let mut line = String::new();
+                BufReader::new(&stream).read_line(&mut line).unwrap();
+                let response = match serde_json::from_str(&line).unwrap() {
+                    SignerRequest::Pubkey => SignerResponse {
+                        pubkey: Some(keypair.pubkey().to_string()),
+                        ..Default::default()
+                    },
+                    SignerRequest::Sign { message } => SignerResponse {
+                        signature: Some(
+                            keypair
+                                .sign_message(&BASE64_STANDARD.decode(message).unwrap())
+                                .to_string(),
+                        ),
+                        ..Default::default()
+                    },
+                };
+                let mut response = serde_json::to_string(&response).unwrap();
+                response.push('\n');
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn should_sign_through_the_remote_signer() {
+        let keypair = Keypair::new();
+        let wallet = keypair.pubkey();
+        let socket_path = std::env::temp_dir().join(format!("fly-signer-{}.sock", wallet));
+        let _ = std::fs::remove_file(&socket_path);
+        spawn_signer(socket_path.clone(), keypair, 4);
+
+        // 服务的密钥与配置不一致时拒绝启动
+        assert!(RemoteSigner::connect(&socket_path, Some(Pubkey::new_unique())).is_err());
+        let signer = RemoteSigner::connect(&socket_path, Some(wallet)).unwrap();
+
+        let ix = system_instruction::transfer(&wallet, &Pubkey::new_unique(), 1);
+        let compile = |payer: &Pubkey| {
+            VersionedMessage::V0(
+                v0::Message::try_compile(payer, &[ix.clone()], &[], Hash::default()).unwrap(),
+            )
+        };
+        let tx = sign_message(&signer, compile(&wallet)).unwrap();
+        assert_eq!(tx.verify_with_results(), vec![true]);
+        let signer: WalletSigner = Arc::new(signer);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let tx = runtime
+            .block_on(sign_message_async(&signer, compile(&wallet)))
+            .unwrap();
+        assert_eq!(tx.verify_with_results(), vec![true]);
+
+        // 只能签自己付费的交易
+        assert!(sign_message(signer.as_ref(), compile(&Pubkey::new_unique())).is_err());
+        let _ = std::fs::remove_file(&socket_path);
+    }
+}
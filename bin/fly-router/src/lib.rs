@@ -1,4 +1,42 @@
+//! Modules shared by the fly-router and fly-backtest binaries
+
+pub mod alt;
+pub mod ata_provider;
+pub mod config_reload;
+pub mod control;
+pub mod debug_tools;
+pub mod dex;
+pub mod edge;
+pub mod edge_updater;
+pub mod graph;
+pub mod hot_mints;
+pub mod ix_builder;
+pub mod ix_sender_executor;
+pub mod journal;
+pub mod jup_ix_builder;
+pub mod key_source;
 pub mod metrics;
-//pub mod graph;
+pub mod mint_manager;
+pub mod mint_watcher;
+pub mod mock;
+pub mod onchain_price_feed;
+pub mod pnl_tracker;
+pub mod prelude;
+pub mod ring;
+pub mod ring_executor;
+pub mod ring_scheduler;
+pub mod routing_types;
+pub mod sender;
+pub mod server;
+pub mod shutdown;
+pub mod slot_watcher;
 pub mod source;
+pub mod swap;
+pub mod syscallstubs;
+pub mod test_utils;
+pub mod tx_preflight;
+pub mod util;
+pub mod utils;
+pub mod wallet_pool;
+
 mod tests;
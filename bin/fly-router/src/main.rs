@@ -6,33 +6,48 @@ use std::sync::atomic::Ordering;
 use anchor_spl::mint;
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
-use jup_ix_builder::JupSwapStepInstructionBuilder;
-use routing_types::Route;
-use server::live_account_provider::LiveAccountProvider;
+use fly_router::jup_ix_builder::JupSwapStepInstructionBuilder;
+use fly_router::routing_types::Route;
+use fly_router::server::live_account_provider::LiveAccountProvider;
 use tokio::sync::broadcast;
 use tokio::task::JoinHandle;
 
 use anyhow::Result;
 use num_cpus;
-
-use crate::edge_updater::{spawn_updater_job, Dex};
-use crate::util::tokio_spawn;
-use crate::source::token_cache::{Decimals, TokenCache,Token};
-use crate::source::mint_accounts_source::request_v24h_usd_mint_metadata_by_birdeye;
-use crate::hot_mints::HotMintsCache;
-
-use crate::ix_builder::{SwapInstructionsBuilderImpl, SwapStepInstructionBuilderImpl};
-use crate::server::alt_provider::RpcAltProvider;
-use crate::server::hash_provider::RpcHashProvider;
+use clap::Parser;
+
+use fly_router::edge_updater::{spawn_updater_job, Dex};
+use fly_router::util::tokio_spawn;
+use fly_router::source::token_cache::{Decimals, TokenCache,Token};
+use fly_router::source::account_subscriptions::AccountSubscriptions;
+use fly_router::source::token_universe::TokenUniverse;
+use fly_router::source::snapshot_cache::{self, SnapshotCache};
+use fly_router::source::stream_recorder::StreamRecorder;
+use fly_router::source::wallet_writes::WalletWatch;
+use fly_router::hot_mints::HotMintsCache;
+
+use fly_router::ix_builder::{SwapInstructionsBuilderImpl, SwapStepInstructionBuilderImpl};
+use fly_router::alt::alt_manager::{spawn_alt_manager_job, AltManager};
+use fly_router::ata_provider::{spawn_ata_provider_job, AtaProvider};
+use fly_router::shutdown::Shutdown;
+use fly_router::server::alt_provider::RpcAltProvider;
+use fly_router::server::hash_provider::StreamHashProvider;
+use fly_router::server::{control_api, http_server, metrics_server};
+use fly_router::server::route_provider::RouteProvider;
+use fly_router::control::RuntimeControl;
+use fly_router::mint_manager::{spawn_mint_manager_job, MintManager};
+use fly_router::wallet_pool::WalletPool;
+use fly_router::sender::tip_pricer::TipPricer;
 
 use mango_feeds_connector::chain_data::ChainData;
 use mango_feeds_connector::SlotUpdate;
 use router_lib::mango;
-use router_lib::price_feeds::composite::CompositePriceFeed;
+use fly_router::onchain_price_feed::OnChainPriceFeed;
 use router_lib::price_feeds::price_cache::PriceCache;
 use router_lib::price_feeds::price_feed::PriceFeed;
 
 use router_lib::chain_data::ChainDataArcRw;
+use router_lib::test_tools::rpc::rpc_dumper_client;
 use router_lib::dex::{
     AccountProviderView, ChainDataAccountProvider, DexInterface, DexSubscriptionMode,
 };
@@ -43,48 +58,20 @@ use router_feed_lib::get_program_account::FeedMetadata;
 use router_feed_lib::router_rpc_client::RouterRpcClient;
 use router_feed_lib::router_rpc_wrapper::RouterRpcWrapper;
 
-use source::geyser;
+use fly_router::source::geyser;
+use fly_router::{
+    config_reload, debug_tools, dex, ix_sender_executor, journal, key_source, mint_watcher,
+    pnl_tracker, ring_executor, shutdown, slot_watcher, syscallstubs,
+};
 
 
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_client::rpc_client::RpcClient as BlockingRpcClient;
 use solana_sdk::commitment_config::CommitmentConfig;
-use solana_sdk::signature::{Keypair, Signer};
 
-use prelude::*;
+use fly_router::prelude::*;
 use itertools::chain;
 
-use dex_orca::OrcaDex;
-
-mod debug_tools;
-mod dex;
-pub mod edge;
-pub mod mock;
-pub mod routing_types;
-mod ring_executor;
-mod ring;
-mod edge_updater;
-mod slot_watcher;
-pub mod prelude;
-mod metrics;
-mod util;
-mod utils;
-mod hot_mints;
-mod source;
-mod swap;
-mod syscallstubs;
-
-pub mod graph;
-
-mod server;
-mod alt;
-pub mod ix_builder;
-pub mod jup_ix_builder;
-mod ix_sender_executor;
-mod sender;
-mod test_utils;
-
-
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 90)]
 async fn main() -> Result<()> {
@@ -94,26 +81,57 @@ async fn main() -> Result<()> {
     
     //读取配置文件路径参数 参考  启动命令 RUST_LOG=info router my_config.toml
     let args: Vec<String> = std::env::args().collect();
-    if args.len() < 3 {
+    // fly-router journal <path> [--by ring|dex|mint|hour]
+    if args.get(1).map(String::as_str) == Some("journal") {
+        return journal::run_cli(journal::JournalArgs::parse_from(&args[1..]));
+    }
+    // fly-router check-config <path>
+    if args.get(1).map(String::as_str) == Some("check-config") {
+        let Some(path) = args.get(2) else {
+            eprintln!("usage: fly-router check-config <config.toml>");
+            std::process::exit(2);
+        };
+        match router_config_lib::check_file(path) {
+            Ok(_) => {
+                println!("{}: ok", path);
+                return Ok(());
+            }
+            Err(errors) => {
+                eprint!("{}: {}", path, errors);
+                std::process::exit(1);
+            }
+        }
+    }
+    if args.len() < 2 {
         eprintln!("Please enter a config file path argument.");
         return Ok(());
     }
 
-    let config = Config::load(&args[1])?;
+    // 启动前检查整个配置，一次报告所有错误
+    let config = router_config_lib::check_file(&args[1])?;
     info!("Config loaded: {:?}", config);  // 添加配置加载日志
     //let router_version = RouterVersion::OverestimateAmount;
-    let encrypted_private_key = args[2].clone();
-    let password = myrust::mycrypt::read_secret("输入密钥: ").unwrap_or_else(|_| {
-        panic!("Failed to read password");
-
-    });
-    
-    let private_key = myrust::mycrypt::my_decrypt(&encrypted_private_key, &password);
-    let keypair = Keypair::from_base58_string(&private_key);
-    info!("pubkey: {:?}", keypair.pubkey().to_string());  // 添加密钥加载日志
-    let _ = encrypted_private_key.trim();
-    let _ = password.trim();
-    let _ = private_key.trim();
+    // 加密密钥（默认，第二个参数）、密钥文件或远程签名服务
+    let signer = key_source::load_signer(
+        config.key_source.as_ref(),
+        args.get(2).map(String::as_str),
+    )?;
+    let wallet_pk = signer.pubkey();
+    info!("pubkey: {:?}", wallet_pk.to_string());  // 添加密钥加载日志
+    // 钱包池：主钱包加上 wallet_pool.wallets，每个钱包使用自己的库存
+    let mut signers = vec![signer.clone()];
+    for key_source in config.wallet_pool.iter().flat_map(|p| p.wallets.iter()) {
+        let signer = key_source::load_signer(Some(key_source), None)?;
+        if signers.iter().any(|s| s.pubkey() == signer.pubkey()) {
+            bail!("wallet {} is configured twice", signer.pubkey());
+        }
+        info!("pool wallet: {}", signer.pubkey());
+        signers.push(signer);
+    }
+    let wallet_pool = Arc::new(WalletPool::new(
+        signers.iter().map(|s| s.pubkey()).collect(),
+        config.wallet_pool.as_ref(),
+    ));
 
 
     //获取配置文件中的hot_mints 初始化HotMintsCache
@@ -148,27 +166,25 @@ async fn main() -> Result<()> {
     //每次gma的账号数？？？jupiter quote时用的maxAccounts????
     let number_of_accounts_per_gma = source_config.number_of_accounts_per_gma.unwrap_or(100);
 
-    // 处理 SIGINT 信号（通常是用户按下 Ctrl+C 时发送给程序的信号），并在接收到该信号时执行相应的关闭操作
-    let exit_flag: Arc<atomic::AtomicBool> = Arc::new(atomic::AtomicBool::new(false));
+    // SIGINT / SIGTERM 触发有序关闭，所有任务在关闭流程的最后通过 exit_sender 停止
     let (exit_sender, _) = broadcast::channel(1);
-    {
-        let exit_flag = exit_flag.clone();
-        let exit_sender = exit_sender.clone();
-        tokio::spawn(async move {
-            tokio::signal::ctrl_c().await.unwrap();
-            info!("Received SIGINT, shutting down...");
-            exit_flag.store(true, atomic::Ordering::Relaxed);
-            exit_sender.send(()).unwrap();
-        });
-    }
+    let mut shutdown_requested = shutdown::spawn_signal_handler();
 
     let birdeye_token = source_config.birdeye_token.clone();
     
     let token_cache = {
-            let mint_metadata = request_v24h_usd_mint_metadata_by_birdeye(
-                birdeye_token,
-            )
-            .await;
+            let universe_config = config
+                .token_universe
+                .clone()
+                .unwrap_or_else(TokenUniverse::default_config);
+            let mint_metadata = TokenUniverse {
+                config: &universe_config,
+                rpc_http_url: &source_config.rpc_http_url,
+                max_gma_accounts: number_of_accounts_per_gma,
+                birdeye_token: &birdeye_token,
+            }
+            .build()
+            .await?;
             // let mut data: HashMap<Pubkey, Token> = HashMap::new();
             // for (mint_pubkey, Token { mint, decimals, symbol}) in mint_metadata {
             //     assert_eq!(mint_pubkey, mint);
@@ -193,23 +209,41 @@ async fn main() -> Result<()> {
     let (route_sender, route_receiver) =
         async_channel::unbounded::<Arc<Route>>();
 
+    if let Some(journal_config) = config.journal.as_ref().filter(|c| c.enabled) {
+        journal::init(journal_config)?;
+    }
+
+    // 录制 geyser 数据流，供 fly-backtest 离线回放
+    let (recorder, _recorder_thread) = match config.recorder.as_ref().filter(|c| c.enabled) {
+        Some(recorder_config) => {
+            let (recorder, thread) = StreamRecorder::create(&recorder_config.path)?;
+            (Some(recorder), Some(thread))
+        }
+        None => (None, None),
+    };
+
     let chain_data = Arc::new(RwLock::new(ChainData::new()));
     start_chaindata_updating(
         chain_data.clone(),
         account_write_receiver,
         slot_receiver,
         account_update_sender.clone(),
+        recorder.clone(),
         exit_sender.subscribe(),
     );
 
     //let (metadata_update_sender, _) = broadcast::channel(500);
     let (metadata_update_sender, _) = broadcast::channel::<FeedMetadata>(500);
     let metadata_update_sender_clone = metadata_update_sender.clone();
+    let metadata_recorder = recorder.clone();
     let metadata_job = tokio_spawn("metadata_relayer", async move {
         loop {
             let msg = metadata_write_receiver.recv().await;
             match msg {
                 Ok(msg) => {
+                    if let Some(recorder) = &metadata_recorder {
+                        recorder.record_metadata(&msg);
+                    }
                     if metadata_update_sender_clone.send(msg).is_err() {
                         error!("Failed to write metadata update");
                         break;
@@ -241,10 +275,9 @@ async fn main() -> Result<()> {
     if config.metrics.output_stdout {
         warn!("metrics output to stdout is not supported yet");
     }
-
-    let (mut price_feed, price_feed_job) = build_price_feed(&config, &exit_sender);
-    let (price_cache, price_cache_job) =
-        PriceCache::new(exit_sender.subscribe(), price_feed.receiver());
+    // 指标服务失败不影响交易，不放入关键任务
+    let _metrics_server_job =
+        metrics_server::spawn_metrics_server(&config.metrics, exit_sender.subscribe());
 
     let path_warming_amounts = config
         .routing
@@ -252,19 +285,6 @@ async fn main() -> Result<()> {
         .clone()
         .unwrap_or(vec![100]);
 
-    let mut orca_config = HashMap::new();
-    orca_config.insert(
-        "program_id".to_string(),
-        "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc".to_string(),
-    );
-    orca_config.insert("program_name".to_string(), "Orca".to_string());
-    let mut cropper = HashMap::new();
-    cropper.insert(
-        "program_id".to_string(),
-        "H8W3ctz92svYg6mkn1UtGfu2aQr2fnUFHM1RhScEtQDt".to_string(),
-    );
-    cropper.insert("program_name".to_string(), "Cropper".to_string());
-
     let gpa_compression_enabled = source_config.rpc_support_compression.unwrap_or(false);
     let mut router_rpc = RouterRpcClient {
         rpc: Box::new(RouterRpcWrapper {
@@ -274,93 +294,28 @@ async fn main() -> Result<()> {
         gpa_compression_enabled,
     };
 
-    let token_cache_mints = token_cache.string_tokens();
-
-    let dexs: Vec<Dex> = [
-        dex::generic::build_dex!(
-            OrcaDex::initialize(&mut router_rpc, orca_config,config.orca.take_all_mints,
-                //&config.orca.mints
-                &token_cache_mints
-            ).await?,
-            &mango_data,
-            token_cache.clone(),
-            config.orca.enabled,
-            config.orca.add_mango_tokens,
-            config.orca.take_all_mints,
-            //&config.orca.mints
-            &token_cache_mints
-        ),
-        dex::generic::build_dex!(
-            OrcaDex::initialize(&mut router_rpc, cropper,
-                config.cropper.take_all_mints,
-                &token_cache_mints).await?,
-            &mango_data,
-            token_cache.clone(),
-            config.cropper.enabled,
-            config.cropper.add_mango_tokens,
-            config.cropper.take_all_mints,
-            &token_cache_mints
-        ),
-        dex::generic::build_dex!(
-            dex_saber::SaberDex::initialize(&mut router_rpc, HashMap::new(),
-            config.saber.take_all_mints,
-            &token_cache_mints).await?,
-            &mango_data,
-            token_cache.clone(),
-            config.saber.enabled,
-            config.saber.add_mango_tokens,
-            config.saber.take_all_mints,
-            &token_cache_mints
-        ),
-        dex::generic::build_dex!(
-            dex_raydium_cp::RaydiumCpDex::initialize(&mut router_rpc, HashMap::new(),
-            config.raydium_cp.take_all_mints,
-            &token_cache_mints).await?,
-            &mango_data,
-            token_cache.clone(),
-            config.raydium_cp.enabled,
-            config.raydium_cp.add_mango_tokens,
-            config.raydium_cp.take_all_mints,
-            &token_cache_mints
-        ),
-        dex::generic::build_dex!(
-            dex_raydium::RaydiumDex::initialize(&mut router_rpc, HashMap::new(),config.raydium.take_all_mints,
-            &token_cache_mints).await?,
-            &mango_data,
-            token_cache.clone(),
-            config.raydium.enabled,
-            config.raydium.add_mango_tokens,
-            config.raydium.take_all_mints,
-            &token_cache_mints
-        ),
-        // dex::generic::build_dex!(
-        //     dex_openbook_v2::OpenbookV2Dex::initialize(&mut router_rpc, HashMap::new(),).await?,
-        //     &mango_data,
-        //     config.openbook_v2.enabled,
-        //     config.openbook_v2.add_mango_tokens,
-        //     config.openbook_v2.take_all_mints,
-        //     &config.openbook_v2.mints
-        // ),
-        // dex::generic::build_dex!(
-        //     dex_infinity::InfinityDex::initialize(&mut router_rpc, HashMap::new(),).await?,
-        //     &mango_data,
-        //     config.infinity.enabled,
-        //     false,
-        //     true,
-        //     &vec![]
-        // ),
-        // dex::generic::build_dex!(
-        //     dex_invariant::InvariantDex::initialize(&mut router_rpc, HashMap::new(),).await?,
-        //     &mango_data,
-        //     config.invariant.enabled,
-        //     config.invariant.take_all_mints,
-        //     config.invariant.add_mango_tokens,
-        //     &config.invariant.mints
-        // ),
-    ]
-    .into_iter()
-    .flatten()
-    .collect();
+    // 启动快照缓存：dex 初始化时的 gPA 结果落盘，重启时直接加载
+    let snapshot_cache = match config.snapshot_cache.as_ref().filter(|c| c.enabled) {
+        Some(cache_config) => Some(Arc::new(SnapshotCache::new(cache_config)?)),
+        None => None,
+    };
+    let rpc_dump_path = config
+        .recorder
+        .as_ref()
+        .filter(|c| c.enabled)
+        .and_then(|c| c.rpc_dump_path.as_ref())
+        .map(|p| shellexpand::tilde(p).to_string());
+    if let Some(path) = &rpc_dump_path {
+        // 录制时所有 RPC 调用都要进入 dump，不经过快照缓存
+        info!("dumping dex initialization RPC calls to {}", path);
+        router_rpc = rpc_dumper_client(string_or_env(source_config.rpc_http_url.clone()), path).0;
+    } else if let Some(cache) = &snapshot_cache {
+        router_rpc = snapshot_cache::wrap_rpc_client(router_rpc, cache.clone());
+    }
+
+    let dexs = dex::build_dexs(&config, &mut router_rpc, &token_cache, &mango_data, None).await?;
+    // dump 在 drop 时写入
+    drop(router_rpc);
 
     let edges = dexs.iter().flat_map(|x| x.edges()).collect_vec();
 
@@ -405,6 +360,21 @@ async fn main() -> Result<()> {
     let chain_data_wrapper =
         Arc::new(ChainDataAccountProvider::new(chain_data.clone())) as AccountProviderView;
 
+    let (mut price_feed, price_feed_jobs) = build_price_feed(
+        &config,
+        &dexs,
+        chain_data_wrapper.clone(),
+        token_cache.clone(),
+        &account_update_sender,
+        &exit_sender,
+    );
+    let (price_cache, price_cache_job) =
+        PriceCache::new(exit_sender.subscribe(), price_feed.receiver());
+
+    // 运行时新增代币的边，发给对应的 edge updater 和环执行器
+    let (dex_edge_sender, _) = broadcast::channel::<Dex>(64);
+    let (ring_edge_sender, ring_edge_receiver) = async_channel::unbounded::<Vec<Arc<Edge>>>();
+
     let update_jobs = dexs
         .iter()
         .enumerate()
@@ -422,12 +392,20 @@ async fn main() -> Result<()> {
                 account_update_sender.subscribe(),
                 metadata_update_sender.subscribe(),
                 price_feed.receiver(),
+                dex_edge_sender.subscribe(),
                 exit_sender.subscribe(),
                 edge_price_sender.clone(),
             )
         })
         .collect_vec();
 
+    // 交易中的代币和边涉及的代币，运行时新增代币需要先在流上看到其 mint 账户
+    let watched_mints = token_cache
+        .tokens()
+        .into_iter()
+        .chain(edges.iter().flat_map(|e| [e.input_mint, e.output_mint]))
+        .collect::<HashSet<_>>();
+
     let filters = dexs
         .iter()
         .flat_map(|x| x.edges_per_pk.keys())
@@ -441,6 +419,7 @@ async fn main() -> Result<()> {
                 })
                 .flatten(),
         )
+        .chain(watched_mints.iter().copied())
         .collect::<HashSet<_>>();
 
     debug_tools::set_global_filters(&filters);
@@ -450,7 +429,40 @@ async fn main() -> Result<()> {
         filters.len()
     );
 
-    let subscribed_accounts = dexs
+    // 钱包的写入同时转发给 ATA 跟踪和收益跟踪
+    let (ata_write_sender, ata_write_receiver) = async_channel::unbounded();
+    let mut wallet_write_senders = vec![ata_write_sender];
+
+    // 跟踪钱包余额，计算实际收益
+    let (pnl_handle, pnl_tracker_job) =
+        match config.pnl_tracker.as_ref().filter(|c| c.enabled) {
+            Some(pnl_config) => {
+                let (wallet_write_sender, wallet_write_receiver) = async_channel::unbounded();
+                let (handle, job) = pnl_tracker::spawn_pnl_tracker_job(
+                    pnl_config,
+                    build_rpc(&source_config),
+                    wallet_pool.clone(),
+                    TipPricer::new(price_cache.clone(), token_cache.clone()),
+                    wallet_write_receiver,
+                    exit_sender.subscribe(),
+                );
+                wallet_write_senders.push(wallet_write_sender);
+                (Some(handle), Some(job))
+            }
+            None => {
+                if wallet_pool.wallets().len() > 1 {
+                    warn!("pnl_tracker is disabled, the inventory of the pool wallets is not tracked");
+                }
+                (None, None)
+            }
+        };
+    let wallet_watch = WalletWatch {
+        wallets: wallet_pool.wallets().iter().copied().collect(),
+        senders: wallet_write_senders,
+    };
+    let watched_wallets = wallet_pool.wallets().to_vec();
+
+    let subscribed_accounts: HashSet<Pubkey> = dexs
         .iter()
         .flat_map(|x| match &x.subscription_mode {
             DexSubscriptionMode::Accounts(x) => x.clone().into_iter(),
@@ -458,6 +470,8 @@ async fn main() -> Result<()> {
             DexSubscriptionMode::Mixed(m) => m.accounts.clone().into_iter(),
             DexSubscriptionMode::Disabled => HashSet::new().into_iter(),
         })
+        .chain(watched_mints.iter().copied())
+        .chain(watched_wallets.iter().copied())
         .collect();
 
     let subscribed_programs = dexs
@@ -477,8 +491,55 @@ async fn main() -> Result<()> {
             DexSubscriptionMode::Programs(_) => HashSet::new().into_iter(),
             DexSubscriptionMode::Mixed(m) => m.token_accounts_for_owner.clone().into_iter(),
         })
+        .chain(watched_wallets.iter().copied())
         .collect();
 
+    if let Some(cache) = &snapshot_cache {
+        cache
+            .replay(
+                &account_write_sender,
+                &metadata_write_sender,
+                &subscribed_programs,
+                &filters,
+            )
+            .await;
+    }
+
+    let account_subscriptions = AccountSubscriptions::new(subscribed_accounts, filters);
+
+    // 点对点报价服务，和环引擎共用同一批边与链上数据
+    let route_provider = Arc::new(RouteProvider::new(
+        edges.clone(),
+        chain_data_wrapper.clone(),
+        token_cache.clone(),
+        price_cache.clone(),
+        config.routing.max_path_length.unwrap_or(3),
+    ));
+
+    // 运行时增删代币，dex 重新初始化时不经过快照缓存
+    let mint_manager = MintManager::new(
+        token_cache.clone(),
+        &edges,
+        account_subscriptions.clone(),
+        dex_edge_sender,
+        ring_edge_sender,
+        Some(route_provider.clone()),
+    );
+    let mint_router_rpc = RouterRpcClient {
+        rpc: Box::new(RouterRpcWrapper {
+            rpc: build_rpc(&source_config),
+            gpa_compression_enabled,
+        }),
+        gpa_compression_enabled,
+    };
+    let (mint_manager_handle, mint_manager_job) = spawn_mint_manager_job(
+        &config,
+        mint_manager,
+        mint_router_rpc,
+        build_rpc(&source_config),
+        exit_sender.subscribe(),
+    );
+
     let ef = exit_sender.subscribe();
     let sc = source_config.clone();
     let account_update_job = tokio_spawn("geyser", async move {
@@ -491,10 +552,10 @@ async fn main() -> Result<()> {
                 account_write_sender,
                 metadata_write_sender,
                 slot_sender,
-                &subscribed_accounts,
+                &account_subscriptions,
                 &subscribed_programs,
                 &subscribed_token_accounts,
-                &filters,
+                Some(wallet_watch),
             )
             .await;
         }
@@ -511,6 +572,22 @@ async fn main() -> Result<()> {
     });
 
 
+    // 控制接口与环执行器、发送器共享同一份运行时参数
+    let control = Arc::new(RuntimeControl::new(&config));
+    let _control_api_job = control_api::spawn_control_api(
+        &config.control_api.clone().unwrap_or_default(),
+        control.clone(),
+        Some(mint_manager_handle),
+        exit_sender.subscribe(),
+    );
+    let _config_reload_job = config_reload::spawn_config_reload_job(
+        &config.config_reload.clone().unwrap_or_default(),
+        args[1].clone(),
+        config.clone(),
+        control.clone(),
+        exit_sender.subscribe(),
+    );
+
     let ring_executor_job = ring_executor::spawn_ring_executor_job(
         &config,
   //      ready_sender,  
@@ -520,15 +597,42 @@ async fn main() -> Result<()> {
         edges.clone(),
         edge_price_updates,
         route_sender,
+        token_cache.subscribe(),
+        ring_edge_receiver,
+        hot_mints.clone(),
+        control.clone(),
+        Some(wallet_pool.clone()),
         exit_sender.subscribe(),
     );
 
+    // 环在执行器创建时已经建好，恢复上次运行保存的冷却
+    let shutdown = Shutdown::new(
+        config.shutdown.as_ref(),
+        control.clone(),
+        pnl_handle.clone(),
+    );
+    shutdown.restore_ring_states();
+
+    let mint_watcher_job = mint_watcher::spawn_mint_watcher_job(
+        token_cache.clone(),
+        chain_data_wrapper.clone(),
+        watched_mints,
+        account_update_sender.subscribe(),
+        exit_sender.subscribe(),
+    );
 
-    let rpc = build_rpc(&source_config);
-    let hash_provider = Arc::new(RpcHashProvider {
-        rpc_client: rpc,
-        last_update: Default::default(),
-    });
+
+    let (hash_provider, hash_provider_job) = StreamHashProvider::spawn(
+        build_rpc(&source_config),
+        chain_data_wrapper.clone(),
+        Duration::from_millis(
+            config
+                .sender
+                .blockhash_refresh_interval_ms
+                .unwrap_or(1_000),
+        ),
+        exit_sender.subscribe(),
+    );
 
     let alt_provider = Arc::new(RpcAltProvider {
         rpc_client: build_rpc(&source_config),
@@ -554,25 +658,72 @@ async fn main() -> Result<()> {
     let ix_builder = Arc::new(JupSwapStepInstructionBuilder::new(
         jup_url,
     ));
+
+    let _http_server_job = http_server::spawn_http_server(
+        &config.server,
+        route_provider,
+        ix_builder.clone(),
+        exit_sender.subscribe(),
+    );
     
 
+    // 钱包的 ATA 先从 RPC 加载，之后由 geyser 写入和定期刷新更新
+    let ata_provider_config = config.ata_provider.clone().unwrap_or_default();
+    let ata_provider = Arc::new(AtaProvider::new(
+        Some(&ata_provider_config),
+        token_cache.clone(),
+    ));
+    ata_provider
+        .refresh(&build_rpc(&source_config), wallet_pool.wallets())
+        .await;
+    let ata_provider_job = spawn_ata_provider_job(
+        &ata_provider_config,
+        ata_provider.clone(),
+        build_rpc(&source_config),
+        signers.clone(),
+        control.clone(),
+        ata_write_receiver,
+        exit_sender.subscribe(),
+    );
+
+    let alt_manager_config = config.alt_manager.clone().unwrap_or_default();
+    let (alt_manager, alt_manager_job) = if alt_manager_config.enabled {
+        let alt_manager = Arc::new(AltManager::new(wallet_pk, &alt_manager_config));
+        let job = spawn_alt_manager_job(
+            &alt_manager_config,
+            alt_manager.clone(),
+            build_rpc(&source_config),
+            signer,
+            exit_sender.subscribe(),
+        );
+        (Some(alt_manager), Some(job))
+    } else {
+        (None, None)
+    };
+
     let sender_executor_job = ix_sender_executor::spawn_sender_executor_job(
         &config,
         build_rpc(&source_config),
-        keypair,
+        signers,
+        wallet_pool,
+        ata_provider,
         hash_provider,
         alt_provider,
         live_account_provider,
         ix_builder,
+        alt_manager,
+        control,
+        TipPricer::new(price_cache.clone(), token_cache.clone()),
        // ix_sender,
+        pnl_handle,
         route_receiver,
         exit_sender.subscribe(),
-    ).await;
+    ).await?;
 
     let mut jobs: futures::stream::FuturesUnordered<_> = vec![
 //        server_job.join_handle,
-        price_feed_job,
         price_cache_job,
+        hash_provider_job,
         metadata_job,
         slot_job,
 //       tx_sender_job,
@@ -580,25 +731,37 @@ async fn main() -> Result<()> {
         account_update_job,
  //       liquidity_job,
         ring_executor_job,
+        mint_watcher_job,
+        ata_provider_job,
         sender_executor_job,
+        mint_manager_job,
     ]
     .into_iter()
     .chain(update_jobs.into_iter())
+    .chain(alt_manager_job.into_iter())
+    .chain(pnl_tracker_job.into_iter())
+    .chain(price_feed_jobs.into_iter())
 //    .chain(mango_watcher_job.into_iter())
 //    .chain(path_warmer_job.into_iter())
     .collect();
 
-    loop {
+    let exit_code = loop {
         tokio::select!(
             _ = jobs.next() => {
                 error!("A critical job exited, aborting run..");
-                exit(-1);
+                break shutdown::EXIT_JOB_FAILED;
+            },
+            Ok(_) = &mut shutdown_requested => {
+                break shutdown::EXIT_REQUESTED;
             },
             Ok(_) = ready_receiver.recv() => {
                 info!("fly-router setup complete");
             },
         );
-    }
+    };
+
+    shutdown.run(&exit_sender, &mut jobs).await;
+    exit(exit_code);
 
 }
 
@@ -616,6 +779,7 @@ fn start_chaindata_updating(
     account_writes: async_channel::Receiver<AccountOrSnapshotUpdate>,
     slot_updates: async_channel::Receiver<SlotUpdate>,
     account_update_sender: broadcast::Sender<(Pubkey, Pubkey, u64)>,
+    recorder: Option<StreamRecorder>,
     mut exit: broadcast::Receiver<()>,
 ) -> JoinHandle<()> {
     use mango_feeds_connector::chain_data::SlotData;
@@ -636,6 +800,9 @@ fn start_chaindata_updating(
                         continue;
                     };
 
+                    if let Some(recorder) = &recorder {
+                        recorder.record_update(&update);
+                    }
                     let mut writer = chain_data.write().unwrap();
                     handle_updated_account(&mut most_recent_seen_slot, &mut writer, update, &account_update_sender);
 
@@ -643,6 +810,9 @@ fn start_chaindata_updating(
                     let started_at = Instant::now();
                     'batch_loop: while let Ok(update) = account_writes.try_recv() {
                         batchsize += 1;
+                        if let Some(recorder) = &recorder {
+                            recorder.record_update(&update);
+                        }
 
                         handle_updated_account(&mut most_recent_seen_slot, &mut writer, update, &account_update_sender);
 
@@ -662,6 +832,10 @@ fn start_chaindata_updating(
                     debug!("chain_data updater got slot: {} ({:?}) -- channel sizes: {} {}", slot_update.slot, slot_update.status,
                     slot_updates.len(), account_writes.len());
 
+                    if let Some(recorder) = &recorder {
+                        recorder.record_slot(&slot_update);
+                    }
+
                     chain_data.write().unwrap().update_slot(SlotData {
                         slot: slot_update.slot,
                         parent: slot_update.parent,
@@ -748,14 +922,41 @@ fn handle_updated_account(
 
 fn build_price_feed(
     config: &Config,
+    dexs: &[Dex],
+    chain_data: AccountProviderView,
+    token_cache: Arc<TokenCache>,
+    account_update_sender: &broadcast::Sender<(Pubkey, Pubkey, u64)>,
     exit_sender: &broadcast::Sender<()>,
-) -> (Box<dyn PriceFeed>, JoinHandle<()>) {
-    let (price_feed, join_handle) = CompositePriceFeed::start(
+) -> (Box<dyn PriceFeed>, Vec<JoinHandle<()>>) {
+    if config
+        .price_feed
+        .onchain
+        .as_ref()
+        .is_some_and(|onchain| onchain.enabled)
+    {
+        let mut edges_per_pk: HashMap<Pubkey, Vec<Arc<Edge>>> = HashMap::new();
+        for dex in dexs {
+            for (pk, edges) in dex.edges_per_pk.iter() {
+                edges_per_pk.entry(*pk).or_default().extend(edges.iter().cloned());
+            }
+        }
+        let (price_feed, join_handles) = OnChainPriceFeed::spawn(
+            &config.price_feed,
+            edges_per_pk,
+            chain_data,
+            token_cache,
+            account_update_sender.subscribe(),
+            exit_sender,
+        );
+        return (Box::new(price_feed), join_handles);
+    }
+
+    let (price_feed, join_handle) = OnChainPriceFeed::start_http_only(
         config.price_feed.clone(), 
         exit_sender.subscribe()
     );
     
-    (Box::new(price_feed), join_handle)
+    (Box::new(price_feed), vec![join_handle])
 }
 
 fn build_blocking_rpc(source_config: &AccountDataSourceConfig) -> BlockingRpcClient {
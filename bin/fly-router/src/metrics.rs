@@ -1,8 +1,8 @@
 use prometheus::core::GenericGauge;
 use prometheus::{
-    histogram_opts, opts, register_gauge_vec, register_histogram_vec, register_int_counter,
-    register_int_counter_vec, register_int_gauge, register_int_gauge_vec, GaugeVec, HistogramVec,
-    IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    histogram_opts, opts, register_gauge_vec, register_histogram, register_histogram_vec,
+    register_int_counter, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    GaugeVec, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
 };
 
 lazy_static::lazy_static! {
@@ -82,4 +82,59 @@ lazy_static::lazy_static! {
     pub static ref REPRICING_DIFF_BPS: GaugeVec =
         register_gauge_vec!(opts!("router_repricing_diff_bps", "Router chaindata/live repricing diff (bps)"), &["pair"]).unwrap();
 
+    pub static ref RING_REFRESH_EVALUATED: IntCounterVec =
+        register_int_counter_vec!(opts!("router_ring_refresh_evaluated", "Dirty rings evaluated, by priority tier"), &["tier"]).unwrap();
+    pub static ref RING_REFRESH_DROPPED: IntCounterVec =
+        register_int_counter_vec!(opts!("router_ring_refresh_dropped", "Dirty rings dropped by the refresh timeout, by priority tier"), &["tier"]).unwrap();
+    pub static ref RING_DIRTY_QUEUE: IntGauge =
+        register_int_gauge!("router_ring_dirty_queue", "Dirty rings waiting for the next refresh").unwrap();
+    pub static ref RING_REFRESH_RINGS: HistogramVec =
+        register_histogram_vec!(
+            histogram_opts!("router_ring_refresh_rings", "Rings per refresh, evaluated or timed out",
+                vec![0.0, 1.0, 10.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0]),
+            &["result"]).unwrap();
+    pub static ref RING_REFRESH_TIMING: Histogram =
+        register_histogram!(
+            histogram_opts!("router_ring_refresh_timing", "Ring refresh duration in seconds",
+                vec![1e-3, 5e-3, 10e-3, 25e-3, 50e-3, 100e-3, 250e-3, 500e-3, 1.0, 2.0])).unwrap();
+    pub static ref RINGS_PROFITABLE: IntCounter =
+        register_int_counter!("router_rings_profitable", "Rings found above the expected gain and sent as routes").unwrap();
+    pub static ref ROUTE_SEND_LATENCY: HistogramVec =
+        register_histogram_vec!(
+            histogram_opts!("router_route_send_latency", "Seconds from route creation to the end of each sender stage",
+                vec![1e-3, 2e-3, 5e-3, 10e-3, 25e-3, 50e-3, 100e-3, 200e-3, 500e-3, 1.0, 2.0, 5.0]),
+            &["stage"]).unwrap();
+    pub static ref JUP_BUILDER_TIMING: Histogram =
+        register_histogram!(
+            histogram_opts!("router_jup_builder_timing", "Jupiter swap-instructions request in seconds",
+                vec![5e-3, 10e-3, 25e-3, 50e-3, 100e-3, 200e-3, 500e-3, 1.0, 2.0, 5.0])).unwrap();
+    pub static ref JUP_BUILDER_ERRORS: IntCounterVec =
+        register_int_counter_vec!(opts!("router_jup_builder_errors", "Jupiter builder failures, by stage"), &["stage"]).unwrap();
+    pub static ref BUNDLES_SENT: IntCounterVec =
+        register_int_counter_vec!(opts!("router_bundles_sent", "Bundles sent to the block engine, by region and result"), &["region", "result"]).unwrap();
+
+    pub static ref REALIZED_PNL: IntGaugeVec =
+        register_int_gauge_vec!(opts!("router_realized_pnl", "Balance change of each wallet attributed to landed bundles, tips and fees included (native units)"), &["wallet", "mint"]).unwrap();
+    pub static ref EXPECTED_PNL_LANDED: IntGaugeVec =
+        register_int_gauge_vec!(opts!("router_expected_pnl_landed", "Quoted profit of the landed bundles by wallet (native units)"), &["wallet", "mint"]).unwrap();
+    pub static ref WALLET_BALANCE: IntGaugeVec =
+        register_int_gauge_vec!(opts!("router_wallet_balance", "Balance of each wallet by mint, SOL includes WSOL (native units)"), &["wallet", "mint"]).unwrap();
+    pub static ref LANDED_BUNDLES: IntCounter =
+        register_int_counter!("router_landed_bundles", "Sent bundles seen changing the wallet balances").unwrap();
+    pub static ref PNL_DRAWDOWN_LAMPORTS: IntGauge =
+        register_int_gauge!("router_pnl_drawdown_lamports", "Realized PnL of all mints below its peak (lamports)").unwrap();
+    pub static ref TRADING_PAUSED: IntGauge =
+        register_int_gauge!("router_trading_paused", "1 while the pnl tracker has paused trading").unwrap();
+    pub static ref WALLET_ROUTES: IntCounterVec =
+        register_int_counter_vec!(opts!("router_wallet_routes", "Routes assigned to each wallet of the pool"), &["wallet"]).unwrap();
+    pub static ref RING_SKIPPED_NO_INVENTORY: IntCounterVec =
+        register_int_counter_vec!(opts!("router_ring_skipped_no_inventory", "Ring evaluations skipped because no in_amount fits the free inventory of the trading mint"), &["mint"]).unwrap();
+    pub static ref ROUTES_WITHOUT_WALLET: IntCounter =
+        register_int_counter!("router_routes_without_wallet", "Routes dropped because no wallet had enough free inventory").unwrap();
+    pub static ref ATAS_CREATED_IN_SWAP: IntCounter =
+        register_int_counter!("router_atas_created_in_swap", "Idempotent create-ATA instructions added to swaps for mints the wallet had no ATA for").unwrap();
+    pub static ref ATAS_PRECREATED: IntCounter =
+        register_int_counter!("router_atas_precreated", "ATAs created ahead of time for the mints of active rings").unwrap();
+    pub static ref ATA_RENT_SPENT: IntCounter =
+        register_int_counter!("router_ata_rent_spent_lamports", "Rent spent by the ATA pre-create job").unwrap();
 }
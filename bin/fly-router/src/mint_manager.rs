@@ -0,0 +1,239 @@
+//! Starts and stops trading mints at runtime, e.g. a new listing, from the control API.
+//! Adding a mint initializes the dex adapters again restricted to the pools of the mint,
+//! hands the edges touching it to the edge updaters and the ring executor, subscribes
+//! their accounts, and only then puts the mint in the token cache, which rebuilds the rings.
+//! Removing a mint only takes it out of the token cache, its edges stay known so adding
+//! it back only hands over the pools listed since.
+
+use router_config_lib::Config;
+use router_feed_lib::router_rpc_client::RouterRpcClient;
+use serde_derive::Serialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::dex;
+use crate::edge_updater::Dex;
+use crate::prelude::*;
+use crate::server::route_provider::RouteProvider;
+use crate::source::account_subscriptions::AccountSubscriptions;
+use crate::source::token_cache::{decode_mint, TokenCache};
+use crate::util::tokio_spawn;
+
+#[derive(Debug)]
+pub enum MintCommand {
+    Add {
+        mint: Pubkey,
+        /// short mint address when not set
+        symbol: Option<String>,
+    },
+    Remove {
+        mint: Pubkey,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MintChange {
+    pub mint: String,
+    /// false when the mint was already in the requested state
+    pub changed: bool,
+    /// edges found for the mint that were not known yet
+    pub new_edges: usize,
+}
+
+type MintRequest = (MintCommand, oneshot::Sender<anyhow::Result<MintChange>>);
+
+#[derive(Clone)]
+pub struct MintManagerHandle {
+    sender: async_channel::Sender<MintRequest>,
+}
+
+impl MintManagerHandle {
+    pub async fn execute(&self, command: MintCommand) -> anyhow::Result<MintChange> {
+        let (reply_sender, reply) = oneshot::channel();
+        self.sender
+            .send((command, reply_sender))
+            .await
+            .map_err(|_| anyhow::anyhow!("mint manager is not running"))?;
+        reply
+            .await
+            .map_err(|_| anyhow::anyhow!("mint manager stopped before answering"))?
+    }
+}
+
+pub struct MintManager {
+    token_cache: Arc<TokenCache>,
+    /// edges handed to the ring executor so far, by unique id
+    known_edges: HashSet<(Pubkey, Pubkey)>,
+    dex_edge_sender: broadcast::Sender<Dex>,
+    ring_edge_sender: async_channel::Sender<Vec<Arc<Edge>>>,
+    subscriptions: AccountSubscriptions,
+    /// quotes the new mint too, None when the quote server is not built
+    route_provider: Option<Arc<RouteProvider>>,
+}
+
+impl MintManager {
+    pub fn new(
+        token_cache: Arc<TokenCache>,
+        edges: &[Arc<Edge>],
+        subscriptions: AccountSubscriptions,
+        dex_edge_sender: broadcast::Sender<Dex>,
+        ring_edge_sender: async_channel::Sender<Vec<Arc<Edge>>>,
+        route_provider: Option<Arc<RouteProvider>>,
+    ) -> Self {
+        Self {
+            token_cache,
+            known_edges: edges.iter().map(|e| e.unique_id()).collect(),
+            dex_edge_sender,
+            ring_edge_sender,
+            subscriptions,
+            route_provider,
+        }
+    }
+
+    /// Hands the edges of `dexs` touching `mint` that are not known yet to the edge updaters,
+    /// the ring executor and the route provider, and subscribes to their accounts and to the mint account
+    pub fn add_edges(&mut self, mint: Pubkey, dexs: Vec<Dex>) -> usize {
+        let mut new_edges = vec![];
+        let mut accounts = vec![mint];
+        for dex in dexs {
+            let mut edges_per_pk = HashMap::new();
+            for (pk, edges) in dex.edges_per_pk {
+                let edges = edges
+                    .into_iter()
+                    .filter(|e| e.input_mint == mint || e.output_mint == mint)
+                    .filter(|e| self.known_edges.insert(e.unique_id()))
+                    .collect_vec();
+                if !edges.is_empty() {
+                    new_edges.extend(edges.iter().cloned());
+                    edges_per_pk.insert(pk, edges);
+                    accounts.push(pk);
+                }
+            }
+            if edges_per_pk.is_empty() {
+                continue;
+            }
+            info!(
+                "dex {} has {} pools for mint {}",
+                dex.name,
+                edges_per_pk.len(),
+                mint
+            );
+            // 没有接收者时说明没有对应的 edge updater，例如 dex 被禁用
+            let _ = self.dex_edge_sender.send(Dex {
+                name: dex.name,
+                edges_per_pk,
+                subscription_mode: dex.subscription_mode,
+            });
+        }
+
+        let added = self.subscriptions.add(accounts);
+        debug!(
+            "subscribed to {} new accounts for mint {}",
+            added.len(),
+            mint
+        );
+        let count = new_edges.len();
+        if let Some(route_provider) = &self.route_provider {
+            route_provider.add_edges(new_edges.clone());
+        }
+        if count > 0 && self.ring_edge_sender.try_send(new_edges).is_err() {
+            error!(
+                "failed to send the edges of mint {} to the ring executor",
+                mint
+            );
+        }
+        count
+    }
+
+    async fn add_mint(
+        &mut self,
+        config: &Config,
+        router_rpc: &mut RouterRpcClient,
+        rpc: &RpcClient,
+        mint: Pubkey,
+        symbol: Option<String>,
+    ) -> anyhow::Result<MintChange> {
+        if self.token_cache.contains(&mint) {
+            return Ok(MintChange {
+                mint: mint.to_string(),
+                changed: false,
+                new_edges: 0,
+            });
+        }
+
+        let account = rpc
+            .get_account(&mint)
+            .await
+            .with_context(|| format!("failed to fetch mint account {}", mint))?;
+        let (decimals, info) = decode_mint(&account.owner, &account.data)
+            .with_context(|| format!("{} is not a mint", mint))?;
+        self.token_cache.update_mint(mint, decimals, info);
+
+        // 只在副本中加入新代币，边准备好之前环执行器看不到它
+        let universe = Arc::new(self.token_cache.detached());
+        universe.insert_from_stream(mint, symbol.clone())?;
+        let dexs = dex::build_dexs(config, router_rpc, &universe, &None, Some(mint)).await?;
+        let new_edges = self.add_edges(mint, dexs);
+
+        let changed = self.token_cache.insert_from_stream(mint, symbol)?;
+        info!("mint {} added with {} new edges", mint, new_edges);
+        Ok(MintChange {
+            mint: mint.to_string(),
+            changed,
+            new_edges,
+        })
+    }
+
+    fn remove_mint(&self, mint: Pubkey) -> MintChange {
+        let changed = self.token_cache.remove(&mint).is_some();
+        if changed {
+            info!("mint {} removed", mint);
+        }
+        MintChange {
+            mint: mint.to_string(),
+            changed,
+            new_edges: 0,
+        }
+    }
+}
+
+/// `router_rpc` should not go through the snapshot cache, the pools listed since the cache
+/// was written would be missing
+pub fn spawn_mint_manager_job(
+    config: &Config,
+    mut manager: MintManager,
+    mut router_rpc: RouterRpcClient,
+    rpc: RpcClient,
+    mut exit: broadcast::Receiver<()>,
+) -> (MintManagerHandle, JoinHandle<()>) {
+    let config = config.clone();
+    let (sender, receiver) = async_channel::unbounded::<MintRequest>();
+
+    let job = tokio_spawn("mint_manager", async move {
+        loop {
+            tokio::select! {
+                _ = exit.recv() => {
+                    info!("shutting down mint manager task");
+                    break;
+                }
+                // 没有控制接口时 handle 被丢弃，任务继续运行直到退出
+                Ok((command, reply)) = receiver.recv() => {
+                    let result = match command {
+                        MintCommand::Add { mint, symbol } => {
+                            manager
+                                .add_mint(&config, &mut router_rpc, &rpc, mint, symbol)
+                                .await
+                        }
+                        MintCommand::Remove { mint } => Ok(manager.remove_mint(mint)),
+                    };
+                    if let Err(e) = &result {
+                        error!("mint command failed: {:#}", e);
+                    }
+                    let _ = reply.send(result);
+                }
+            }
+        }
+    });
+    (MintManagerHandle { sender }, job)
+}
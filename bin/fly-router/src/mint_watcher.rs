@@ -0,0 +1,59 @@
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022;
+use router_lib::dex::AccountProviderView;
+use solana_sdk::account::ReadableAccount;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+use crate::prelude::*;
+use crate::source::token_cache::{decode_mint, TokenCache};
+use crate::util::tokio_spawn;
+
+/// Decodes the mint accounts received on the stream and keeps the token cache metadata in sync
+pub fn spawn_mint_watcher_job(
+    token_cache: Arc<TokenCache>,
+    chain_data: AccountProviderView,
+    mints: HashSet<Pubkey>,
+    mut account_updates: broadcast::Receiver<(Pubkey, Pubkey, u64)>,
+    mut exit: broadcast::Receiver<()>,
+) -> JoinHandle<()> {
+    tokio_spawn("mint_watcher", async move {
+        loop {
+            tokio::select! {
+                _ = exit.recv() => {
+                    info!("shutting down mint watcher task");
+                    break;
+                }
+                update = account_updates.recv() => {
+                    let (pubkey, owner) = match update {
+                        Ok((pubkey, owner, _slot)) => (pubkey, owner),
+                        Err(RecvError::Lagged(n)) => {
+                            warn!("mint watcher lagged {} account updates", n);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => {
+                            error!("account update channel closed");
+                            break;
+                        }
+                    };
+
+                    if owner != spl_token::ID && owner != spl_token_2022::ID {
+                        continue;
+                    }
+                    // 运行时新增的代币不在启动时的集合中
+                    if !mints.contains(&pubkey) && !token_cache.contains(&pubkey) {
+                        continue;
+                    }
+
+                    let Ok(account) = chain_data.account(&pubkey) else {
+                        continue;
+                    };
+                    match decode_mint(&owner, account.account.data()) {
+                        Ok((decimals, info)) => token_cache.update_mint(pubkey, decimals, info),
+                        Err(e) => debug!("failed to decode mint {}: {:?}", pubkey, e),
+                    }
+                }
+            }
+        }
+    })
+}
@@ -1,13 +1,27 @@
 pub mod test {
+    use crate::edge::Edge;
+    use crate::ix_builder::SwapInstructionsBuilder;
+    use crate::routing_types::Route;
+    use crate::sender::ix_sender::IxSender;
+    use crate::server::alt_provider::AltProvider;
+    use crate::server::hash_provider::HashProvider;
+    use crate::swap::Swap;
+    use mango_feeds_connector::chain_data::AccountData;
     use router_feed_lib::router_rpc_client::RouterRpcClient;
     use router_lib::dex::{
-        AccountProviderView, DexEdge, DexEdgeIdentifier, DexInterface, DexSubscriptionMode, Quote,
-        SwapInstruction,
+        AccountProvider, AccountProviderView, DexEdge, DexEdgeIdentifier, DexInterface,
+        DexSubscriptionMode, Quote, SwapInstruction, SwapMode,
     };
+    use solana_program::address_lookup_table::AddressLookupTableAccount;
+    use solana_program::hash::Hash;
+    use solana_program::instruction::Instruction;
     use solana_program::pubkey::Pubkey;
+    use solana_sdk::account::{ReadableAccount, WritableAccount};
+    use solana_sdk::transaction::VersionedTransaction;
     use std::any::Any;
     use std::collections::{HashMap, HashSet};
-    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex, RwLock};
 
     pub(crate) struct MockDexIdentifier {
         pub key: Pubkey,
@@ -141,4 +155,306 @@ pub mod test {
             })
         }
     }
+
+    /// Pool of `MockPoolDex`, its account data holds the price a->b then b->a as little endian f64
+    pub(crate) struct MockPoolIdentifier {
+        pub pool: Pubkey,
+        pub input_mint: Pubkey,
+        pub output_mint: Pubkey,
+        pub reversed: bool,
+    }
+
+    impl DexEdgeIdentifier for MockPoolIdentifier {
+        fn key(&self) -> Pubkey {
+            self.pool
+        }
+
+        fn desc(&self) -> String {
+            format!("mock_pool {} - {}", self.input_mint, self.output_mint)
+        }
+
+        fn input_mint(&self) -> Pubkey {
+            self.input_mint
+        }
+
+        fn output_mint(&self) -> Pubkey {
+            self.output_mint
+        }
+
+        fn accounts_needed(&self) -> usize {
+            8
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    pub(crate) struct MockPoolEdge {
+        pub price: f64,
+    }
+
+    impl DexEdge for MockPoolEdge {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    /// Edge of `MockDexInterface` quoting `pool` at a constant `price`, tests change the
+    /// other fields with struct update syntax
+    pub(crate) fn mock_edge(
+        pool: Pubkey,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        price: f64,
+    ) -> Edge {
+        Edge {
+            input_mint,
+            output_mint,
+            input_mint_symbol: "A".to_string(),
+            output_mint_symbol: "B".to_string(),
+            dex: Arc::new(MockDexInterface {}),
+            id: Arc::new(MockDexIdentifier {
+                key: pool,
+                input_mint,
+                output_mint,
+                price,
+            }),
+            accounts_needed: 10,
+            state: Default::default(),
+        }
+    }
+
+    pub(crate) fn mock_pool_data(price_ab: f64, price_ba: f64) -> Vec<u8> {
+        price_ab
+            .to_le_bytes()
+            .into_iter()
+            .chain(price_ba.to_le_bytes())
+            .collect()
+    }
+
+    /// Dex of constant price pools (pool, mint a, mint b) whose prices are read from chain data,
+    /// so that account updates drive the quotes like a real adapter
+    pub(crate) struct MockPoolDex {
+        pub program_id: Pubkey,
+        pub pools: Vec<(Pubkey, Pubkey, Pubkey)>,
+    }
+
+    #[async_trait::async_trait]
+    impl DexInterface for MockPoolDex {
+        async fn initialize(
+            _rpc: &mut RouterRpcClient,
+            _options: HashMap<String, String>,
+            _take_all_mints: bool,
+            _mints: &Vec<String>,
+        ) -> anyhow::Result<Arc<dyn DexInterface>>
+        where
+            Self: Sized,
+        {
+            todo!()
+        }
+
+        fn name(&self) -> String {
+            "mock_pool".to_string()
+        }
+
+        fn subscription_mode(&self) -> DexSubscriptionMode {
+            DexSubscriptionMode::Accounts(self.pools.iter().map(|p| p.0).collect())
+        }
+
+        fn edges_per_pk(&self) -> HashMap<Pubkey, Vec<Arc<dyn DexEdgeIdentifier>>> {
+            self.pools
+                .iter()
+                .map(|(pool, mint_a, mint_b)| {
+                    let edges: Vec<Arc<dyn DexEdgeIdentifier>> = vec![
+                        Arc::new(MockPoolIdentifier {
+                            pool: *pool,
+                            input_mint: *mint_a,
+                            output_mint: *mint_b,
+                            reversed: false,
+                        }),
+                        Arc::new(MockPoolIdentifier {
+                            pool: *pool,
+                            input_mint: *mint_b,
+                            output_mint: *mint_a,
+                            reversed: true,
+                        }),
+                    ];
+                    (*pool, edges)
+                })
+                .collect()
+        }
+
+        fn program_ids(&self) -> HashSet<Pubkey> {
+            HashSet::from([self.program_id])
+        }
+
+        fn load(
+            &self,
+            id: &Arc<dyn DexEdgeIdentifier>,
+            chain_data: &AccountProviderView,
+        ) -> anyhow::Result<Arc<dyn DexEdge>> {
+            let id = id.as_any().downcast_ref::<MockPoolIdentifier>().unwrap();
+            let account = chain_data.account(&id.pool)?;
+            let data = account.account.data();
+            let offset = if id.reversed { 8 } else { 0 };
+            let bytes: [u8; 8] = data
+                .get(offset..offset + 8)
+                .ok_or_else(|| anyhow::anyhow!("invalid mock pool {}", id.pool))?
+                .try_into()?;
+
+            Ok(Arc::new(MockPoolEdge {
+                price: f64::from_le_bytes(bytes),
+            }))
+        }
+
+        fn quote(
+            &self,
+            id: &Arc<dyn DexEdgeIdentifier>,
+            edge: &Arc<dyn DexEdge>,
+            _chain_data: &AccountProviderView,
+            in_amount: u64,
+        ) -> anyhow::Result<Quote> {
+            let edge = edge.as_any().downcast_ref::<MockPoolEdge>().unwrap();
+            let out_amount = (edge.price * in_amount as f64).floor() as u64;
+
+            Ok(Quote {
+                in_amount,
+                out_amount,
+                fee_amount: 0,
+                fee_mint: id.input_mint(),
+            })
+        }
+
+        fn build_swap_ix(
+            &self,
+            _id: &Arc<dyn DexEdgeIdentifier>,
+            _chain_data: &AccountProviderView,
+            _wallet_pk: &Pubkey,
+            _in_amount: u64,
+            _out_amount: u64,
+            _max_slippage_bps: i32,
+        ) -> anyhow::Result<SwapInstruction> {
+            todo!()
+        }
+
+        fn supports_exact_out(&self, _id: &Arc<dyn DexEdgeIdentifier>) -> bool {
+            false
+        }
+
+        fn quote_exact_out(
+            &self,
+            _id: &Arc<dyn DexEdgeIdentifier>,
+            _edge: &Arc<dyn DexEdge>,
+            _chain_data: &AccountProviderView,
+            _out_amount: u64,
+        ) -> anyhow::Result<Quote> {
+            anyhow::bail!("exact out not supported by mock pools")
+        }
+    }
+
+    /// In-memory account store, writes are visible to the edges immediately
+    #[derive(Default)]
+    pub(crate) struct MockAccountProvider {
+        accounts: RwLock<HashMap<Pubkey, AccountData>>,
+        slot: AtomicU64,
+    }
+
+    impl MockAccountProvider {
+        pub fn set_account(&self, pubkey: Pubkey, owner: Pubkey, data: Vec<u8>, slot: u64) {
+            self.slot.fetch_max(slot, Ordering::Relaxed);
+            self.accounts.write().unwrap().insert(
+                pubkey,
+                AccountData {
+                    slot,
+                    write_version: slot,
+                    account: WritableAccount::create(1_000_000, data, owner, false, 0),
+                },
+            );
+        }
+    }
+
+    impl AccountProvider for MockAccountProvider {
+        fn account(&self, address: &Pubkey) -> anyhow::Result<AccountData> {
+            self.accounts
+                .read()
+                .unwrap()
+                .get(address)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("account {} not found", address))
+        }
+
+        fn newest_processed_slot(&self) -> u64 {
+            self.slot.load(Ordering::Relaxed)
+        }
+    }
+
+    /// Records the routes handed to the sender instead of building and sending bundles
+    /// Clones share the recorded routes, keep one to inspect them once the sender is boxed
+    #[derive(Clone, Default)]
+    pub(crate) struct RecordingIxSender {
+        pub extended: Arc<Mutex<Vec<Arc<Route>>>>,
+        pub sent: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl IxSender for RecordingIxSender {
+        async fn instructuin_extend(
+            &self,
+            _swap: Arc<Swap>,
+            route: Arc<Route>,
+        ) -> anyhow::Result<HashMap<String, Vec<VersionedTransaction>>> {
+            self.extended.lock().unwrap().push(route);
+            Ok(HashMap::from([("mock".to_string(), vec![])]))
+        }
+
+        async fn send_tx(
+            &self,
+            _transactions: HashMap<String, Vec<VersionedTransaction>>,
+        ) -> anyhow::Result<()> {
+            self.sent.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    pub(crate) struct MockIxBuilder {}
+
+    #[async_trait::async_trait]
+    impl SwapInstructionsBuilder for MockIxBuilder {
+        async fn build_ixs(
+            &self,
+            _wallet_pk: &Pubkey,
+            route: Arc<Route>,
+            _source_atas: &HashMap<Pubkey, Pubkey>,
+            _max_slippage_bps: i32,
+            _other_amount_threshold: u64,
+            _swap_mode: SwapMode,
+        ) -> anyhow::Result<Arc<Swap>> {
+            Ok(Arc::new(Swap {
+                setup_instructions: vec![],
+                swap_instruction: Instruction::new_with_bytes(Pubkey::default(), &[], vec![]),
+                cleanup_instructions: vec![],
+                cu_estimate: 100_000 * route.steps.len() as u32,
+                address_lookup_table_addresses: vec![],
+            }))
+        }
+    }
+
+    pub(crate) struct MockHashProvider {}
+
+    #[async_trait::async_trait]
+    impl HashProvider for MockHashProvider {
+        async fn get_latest_hash(&self) -> anyhow::Result<Hash> {
+            Ok(Hash::default())
+        }
+    }
+
+    pub(crate) struct MockAltProvider {}
+
+    #[async_trait::async_trait]
+    impl AltProvider for MockAltProvider {
+        async fn get_alt(&self, address: Pubkey) -> anyhow::Result<AddressLookupTableAccount> {
+            anyhow::bail!("no lookup table {}", address)
+        }
+    }
 }
@@ -0,0 +1,521 @@
+use router_config_lib::{OnChainPriceFeedConfig, PriceFeedConfig};
+use router_lib::dex::AccountProviderView;
+use router_lib::price_feeds::composite::CompositePriceFeed;
+use router_lib::price_feeds::price_feed::{PriceFeed, PriceUpdate};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+use crate::prelude::*;
+use crate::source::token_cache::TokenCache;
+use crate::util::tokio_spawn;
+
+const SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+const USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+const USDT_MINT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+
+/// A pool that both buys and sells `mint` against a quote mint
+struct PoolPair {
+    /// mint -> quote mint
+    sell: Arc<Edge>,
+    /// quote mint -> mint
+    buy: Arc<Edge>,
+}
+
+/// Mid price of a mint measured on one pool, in USD
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoolPrice {
+    pub price: f64,
+    /// inverse of the round trip loss, deeper pools lose less
+    pub weight: f64,
+}
+
+/// Liquidity-weighted average of the deepest pools
+pub fn weighted_mid(prices: &[PoolPrice], max_pools: usize) -> Option<f64> {
+    let deepest = prices
+        .iter()
+        .filter(|p| p.price.is_finite() && p.price > 0.0 && p.weight > 0.0)
+        .sorted_by(|a, b| b.weight.total_cmp(&a.weight))
+        .take(max_pools)
+        .collect_vec();
+    let total_weight = deepest.iter().map(|p| p.weight).sum::<f64>();
+    if total_weight <= 0.0 {
+        return None;
+    }
+    Some(deepest.iter().map(|p| p.price * p.weight).sum::<f64>() / total_weight)
+}
+
+/// true when the on-chain price is too far from the HTTP reference
+pub fn is_outlier(price: f64, reference: f64, max_deviation_pct: f64) -> bool {
+    reference > 0.0 && ((price - reference).abs() / reference) * 100.0 > max_deviation_pct
+}
+
+/// USD prices derived from the router's own pools, updated on every account change of these pools.
+///
+/// SOL/USD comes from the anchor pools, USDC and USDT are assumed to be worth one dollar,
+/// every other registered mint is priced against the deepest pools to one of these three.
+/// When a birdeye token is configured the HTTP feed runs alongside: on-chain prices too far
+/// from it are dropped, and mints without any pool to a quote mint keep its prices.
+pub struct OnChainPriceFeed {
+    admin_channel_sender: async_channel::Sender<Pubkey>,
+    update_sender: broadcast::Sender<PriceUpdate>,
+}
+
+impl OnChainPriceFeed {
+    pub fn spawn(
+        config: &PriceFeedConfig,
+        edges_per_pk: HashMap<Pubkey, Vec<Arc<Edge>>>,
+        chain_data: AccountProviderView,
+        token_cache: Arc<TokenCache>,
+        account_updates: broadcast::Receiver<(Pubkey, Pubkey, u64)>,
+        exit: &broadcast::Sender<()>,
+    ) -> (Self, Vec<JoinHandle<()>>) {
+        let (admin_channel_sender, admin_channel_receiver) = async_channel::unbounded::<Pubkey>();
+        let (update_sender, _) = broadcast::channel::<PriceUpdate>(10_000);
+        let onchain_config = config.onchain.clone().unwrap_or_default();
+
+        let mut jobs = vec![];
+        let reference = if config.birdeye_token.is_empty() {
+            None
+        } else {
+            let (mut http_feed, http_job) =
+                CompositePriceFeed::start(config.clone(), exit.subscribe());
+            jobs.push(http_job);
+            Some((http_feed.register_mint_sender(), http_feed.receiver()))
+        };
+
+        let mut state = PoolPricer::new(&onchain_config, edges_per_pk, chain_data, token_cache);
+        let refresh_interval =
+            Duration::from_millis(onchain_config.refresh_interval_ms.unwrap_or(200));
+        let update_sender_clone = update_sender.clone();
+        let mut exit = exit.subscribe();
+        let mut account_updates = account_updates;
+
+        jobs.push(tokio_spawn("onchain_price_feed", async move {
+            // 没有 HTTP 参考价格时用一个永远不会收到消息的通道
+            let (http_register, mut http_updates, _no_reference) = match reference {
+                Some((register, updates)) => (Some(register), updates, None),
+                None => {
+                    let (sender, updates) = broadcast::channel(1);
+                    (None, updates, Some(sender))
+                }
+            };
+            let mut interval = tokio::time::interval(refresh_interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+            loop {
+                tokio::select! {
+                    _ = exit.recv() => {
+                        info!("Exit signal received, stopping on-chain price feed..");
+                        break;
+                    },
+                    Ok(new_mint) = admin_channel_receiver.recv() => {
+                        if let Some(register) = &http_register {
+                            let _ = register.try_send(new_mint);
+                        }
+                        if state.register(new_mint) {
+                            debug!("Adding {} to on-chain price feed", new_mint);
+                        }
+                    },
+                    update = http_updates.recv() => {
+                        match update {
+                            Ok(update) => {
+                                if let Some(forward) = state.on_reference_price(update) {
+                                    let _ = update_sender_clone.send(forward);
+                                }
+                            }
+                            Err(RecvError::Lagged(n)) => warn!("on-chain price feed lagged {} reference prices", n),
+                            Err(RecvError::Closed) => {
+                                error!("reference price channel closed");
+                                break;
+                            }
+                        }
+                    },
+                    update = account_updates.recv() => {
+                        match update {
+                            Ok((pubkey, _owner, _slot)) => state.on_account_update(&pubkey),
+                            Err(RecvError::Lagged(n)) => {
+                                warn!("on-chain price feed lagged {} account updates", n);
+                                state.mark_all_dirty();
+                            }
+                            Err(RecvError::Closed) => {
+                                error!("account update channel closed");
+                                break;
+                            }
+                        }
+                    },
+                    _ = interval.tick() => {
+                        for update in state.refresh() {
+                            let _ = update_sender_clone.send(update);
+                        }
+                    },
+                }
+            }
+
+            info!("on-chain price feed exited")
+        }));
+
+        let feed = OnChainPriceFeed {
+            admin_channel_sender,
+            update_sender,
+        };
+        (feed, jobs)
+    }
+}
+
+impl OnChainPriceFeed {
+    /// The HTTP feed alone, for setups without the router's pools
+    pub fn start_http_only(
+        configuration: PriceFeedConfig,
+        exit: broadcast::Receiver<()>,
+    ) -> (impl PriceFeed, JoinHandle<()>) {
+        CompositePriceFeed::start(configuration, exit)
+    }
+}
+
+impl PriceFeed for OnChainPriceFeed {
+    /// The on-chain prices need the router's pools, see `OnChainPriceFeed::spawn`.
+    /// Without them the feed relays the HTTP prices of the registered mints.
+    fn start(
+        configuration: PriceFeedConfig,
+        mut exit: broadcast::Receiver<()>,
+    ) -> (impl PriceFeed, JoinHandle<()>) {
+        let (admin_channel_sender, admin_channel_receiver) = async_channel::unbounded::<Pubkey>();
+        let (update_sender, _) = broadcast::channel::<PriceUpdate>(10_000);
+        let (mut http_feed, http_job) = Self::start_http_only(configuration, exit.resubscribe());
+        let http_register = http_feed.register_mint_sender();
+        let mut http_updates = http_feed.receiver();
+
+        let update_sender_clone = update_sender.clone();
+        let job = tokio_spawn("onchain_price_feed_relay", async move {
+            loop {
+                tokio::select! {
+                    _ = exit.recv() => break,
+                    Ok(new_mint) = admin_channel_receiver.recv() => {
+                        let _ = http_register.try_send(new_mint);
+                    },
+                    update = http_updates.recv() => {
+                        match update {
+                            Ok(update) => {
+                                let _ = update_sender_clone.send(update);
+                            }
+                            Err(RecvError::Lagged(n)) => warn!("price feed relay lagged {} prices", n),
+                            Err(RecvError::Closed) => break,
+                        }
+                    },
+                }
+            }
+            let _ = http_job.await;
+            info!("price feed relay exited")
+        });
+
+        let feed = OnChainPriceFeed {
+            admin_channel_sender,
+            update_sender,
+        };
+        (feed, job)
+    }
+
+    fn receiver(&mut self) -> broadcast::Receiver<PriceUpdate> {
+        self.update_sender.subscribe()
+    }
+
+    fn register_mint_sender(&self) -> async_channel::Sender<Pubkey> {
+        self.admin_channel_sender.clone()
+    }
+}
+
+struct PoolPricer {
+    chain_data: AccountProviderView,
+    token_cache: Arc<TokenCache>,
+    sol: Pubkey,
+    stables: [Pubkey; 2],
+    probe_usd: f64,
+    max_pools: usize,
+    max_deviation_pct: Option<f64>,
+    /// pools of each mint to one of the quote mints
+    pairs: HashMap<Pubkey, Vec<PoolPair>>,
+    /// SOL/stable pools used for SOL/USD
+    sol_anchors: Vec<PoolPair>,
+    /// mints priced by the pools reading this account
+    mints_per_pk: HashMap<Pubkey, HashSet<Pubkey>>,
+    registered: HashSet<Pubkey>,
+    dirty: HashSet<Pubkey>,
+    prices: HashMap<Pubkey, f64>,
+    reference_prices: HashMap<Pubkey, f64>,
+}
+
+impl PoolPricer {
+    fn new(
+        config: &OnChainPriceFeedConfig,
+        edges_per_pk: HashMap<Pubkey, Vec<Arc<Edge>>>,
+        chain_data: AccountProviderView,
+        token_cache: Arc<TokenCache>,
+    ) -> Self {
+        let sol = Pubkey::from_str(SOL_MINT).unwrap();
+        let stables = [
+            Pubkey::from_str(USDC_MINT).unwrap(),
+            Pubkey::from_str(USDT_MINT).unwrap(),
+        ];
+        let anchor_pools = config
+            .sol_usd_pools
+            .iter()
+            .flatten()
+            .filter_map(|p| match Pubkey::from_str(p) {
+                Ok(pk) => Some(pk),
+                Err(_) => {
+                    warn!("ignoring invalid SOL/USD anchor pool {}", p);
+                    None
+                }
+            })
+            .collect::<HashSet<_>>();
+
+        // 同一个池子的两个方向组成一对
+        let edges_by_direction = edges_per_pk
+            .values()
+            .flatten()
+            .map(|e| ((e.key(), e.input_mint, e.output_mint), e.clone()))
+            .collect::<HashMap<_, _>>();
+        let quote_mints = [sol, stables[0], stables[1]];
+
+        let mut pairs: HashMap<Pubkey, Vec<PoolPair>> = HashMap::new();
+        let mut sol_anchors = vec![];
+        let mut priced_mint_per_pool: HashMap<Pubkey, Pubkey> = HashMap::new();
+        for ((key, input_mint, output_mint), sell) in edges_by_direction.iter() {
+            if !quote_mints.contains(output_mint) || stables.contains(input_mint) {
+                continue;
+            }
+            let Some(buy) = edges_by_direction.get(&(*key, *output_mint, *input_mint)) else {
+                continue;
+            };
+            let pair = PoolPair {
+                sell: sell.clone(),
+                buy: buy.clone(),
+            };
+            if *input_mint == sol {
+                if !stables.contains(output_mint) {
+                    continue;
+                }
+                if anchor_pools.is_empty() || anchor_pools.contains(key) {
+                    sol_anchors.push(pair);
+                    priced_mint_per_pool.insert(*key, sol);
+                }
+                continue;
+            }
+            pairs.entry(*input_mint).or_default().push(pair);
+            priced_mint_per_pool.insert(*key, *input_mint);
+        }
+
+        // 池子依赖的账户（vault、tick array 等）变化时也要重新计算
+        let mut mints_per_pk: HashMap<Pubkey, HashSet<Pubkey>> = HashMap::new();
+        for (pk, edges) in edges_per_pk.iter() {
+            for edge in edges {
+                if let Some(mint) = priced_mint_per_pool.get(&edge.key()) {
+                    mints_per_pk.entry(*pk).or_default().insert(*mint);
+                }
+            }
+        }
+
+        info!(
+            "on-chain price feed: {} SOL/USD anchor pools, {} mints with pools to a quote mint",
+            sol_anchors.len(),
+            pairs.len()
+        );
+        if sol_anchors.is_empty() {
+            warn!("no SOL/USD anchor pool, mints quoted in SOL won't be priced on-chain");
+        }
+
+        Self {
+            chain_data,
+            token_cache,
+            sol,
+            stables,
+            probe_usd: config.probe_usd.unwrap_or(100.0),
+            max_pools: config.max_pools.unwrap_or(3).max(1),
+            max_deviation_pct: config.max_deviation_pct,
+            pairs,
+            sol_anchors,
+            mints_per_pk,
+            registered: HashSet::new(),
+            dirty: HashSet::from([sol]),
+            prices: HashMap::new(),
+            reference_prices: HashMap::new(),
+        }
+    }
+
+    fn register(&mut self, mint: Pubkey) -> bool {
+        let added = self.registered.insert(mint);
+        if added {
+            self.dirty.insert(mint);
+        }
+        added
+    }
+
+    fn mark_all_dirty(&mut self) {
+        self.dirty.insert(self.sol);
+        self.dirty.extend(self.registered.iter().copied());
+    }
+
+    fn on_account_update(&mut self, pubkey: &Pubkey) {
+        if let Some(mints) = self.mints_per_pk.get(pubkey) {
+            self.dirty.extend(mints.iter().copied());
+        }
+    }
+
+    /// Keep the HTTP price as reference, forward it when the mint can't be priced on-chain
+    fn on_reference_price(&mut self, update: PriceUpdate) -> Option<PriceUpdate> {
+        self.reference_prices.insert(update.mint, update.price);
+        let priced_onchain = update.mint == self.sol
+            || self.stables.contains(&update.mint)
+            || self.pairs.contains_key(&update.mint);
+        (!priced_onchain).then_some(update)
+    }
+
+    fn refresh(&mut self) -> Vec<PriceUpdate> {
+        if self.dirty.is_empty() {
+            return vec![];
+        }
+        let mut dirty = std::mem::take(&mut self.dirty);
+        let mut updates = vec![];
+
+        if self.prices.is_empty() {
+            for stable in self.stables {
+                self.prices.insert(stable, 1.0);
+                updates.push(PriceUpdate {
+                    mint: stable,
+                    price: 1.0,
+                });
+            }
+        }
+
+        if dirty.remove(&self.sol) {
+            let sol_prices = self
+                .sol_anchors
+                .iter()
+                .filter_map(|pair| self.pool_price(pair))
+                .collect_vec();
+            if let Some(price) = self.accept(self.sol, weighted_mid(&sol_prices, self.max_pools)) {
+                updates.push(PriceUpdate {
+                    mint: self.sol,
+                    price,
+                });
+                // SOL 价格变化后，以 SOL 计价的代币都要重新计算
+                dirty.extend(self.registered.iter().copied());
+            }
+        }
+
+        for mint in dirty {
+            if !self.registered.contains(&mint) || self.stables.contains(&mint) {
+                continue;
+            }
+            let Some(pairs) = self.pairs.get(&mint) else {
+                continue;
+            };
+            let pool_prices = pairs
+                .iter()
+                .filter_map(|pair| self.pool_price(pair))
+                .collect_vec();
+            if let Some(price) = self.accept(mint, weighted_mid(&pool_prices, self.max_pools)) {
+                updates.push(PriceUpdate { mint, price });
+            }
+        }
+
+        updates
+    }
+
+    /// Apply the outlier rejection and remember the accepted price
+    fn accept(&mut self, mint: Pubkey, price: Option<f64>) -> Option<f64> {
+        let price = price?;
+        if let (Some(max_deviation_pct), Some(reference)) =
+            (self.max_deviation_pct, self.reference_prices.get(&mint))
+        {
+            if is_outlier(price, *reference, max_deviation_pct) {
+                warn!(
+                    "on-chain price {} of {} rejected, reference price {}",
+                    price, mint, reference
+                );
+                return None;
+            }
+        }
+        self.prices.insert(mint, price);
+        Some(price)
+    }
+
+    fn decimals(&self, mint: Pubkey) -> Option<u8> {
+        match self.token_cache.token(mint) {
+            Ok(token) => Some(token.decimals),
+            Err(_) if mint == self.sol => Some(9),
+            Err(_) if self.stables.contains(&mint) => Some(6),
+            Err(_) => None,
+        }
+    }
+
+    /// Buy the mint with `probe_usd` of the quote mint and sell it back:
+    /// the mid price is the geometric mean of both sides and the round trip loss measures the depth
+    fn pool_price(&self, pair: &PoolPair) -> Option<PoolPrice> {
+        let mint = pair.sell.input_mint;
+        let quote_mint = pair.sell.output_mint;
+        let quote_price = *self.prices.get(&quote_mint)?;
+        let mint_decimals = self.decimals(mint)?;
+        let quote_decimals = self.decimals(quote_mint)?;
+
+        let quote_in =
+            (self.probe_usd / quote_price * 10f64.powi(quote_decimals as i32)).round() as u64;
+        if quote_in == 0 {
+            return None;
+        }
+
+        let buy = pair.buy.prepare(&self.chain_data).ok()?;
+        let bought = pair.buy.quote(&buy, &self.chain_data, quote_in).ok()?.out_amount;
+        if bought == 0 {
+            return None;
+        }
+        let sell = pair.sell.prepare(&self.chain_data).ok()?;
+        let sold = pair.sell.quote(&sell, &self.chain_data, bought).ok()?.out_amount;
+
+        let buy_price = quote_in as f64 / bought as f64;
+        let sell_price = sold as f64 / bought as f64;
+        let mid_native = (buy_price * sell_price).sqrt();
+        let mid_ui = mid_native * 10f64.powi(mint_decimals as i32 - quote_decimals as i32);
+        let loss = (1.0 - sold as f64 / quote_in as f64).max(1e-6);
+
+        Some(PoolPrice {
+            price: mid_ui * quote_price,
+            weight: 1.0 / loss,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_weight_deepest_pools() {
+        let prices = vec![
+            PoolPrice {
+                price: 10.0,
+                weight: 300.0,
+            },
+            PoolPrice {
+                price: 11.0,
+                weight: 100.0,
+            },
+            // 流动性最差的池子不参与平均
+            PoolPrice {
+                price: 100.0,
+                weight: 1.0,
+            },
+        ];
+        assert_eq!(weighted_mid(&prices, 2), Some(10.25));
+        assert_eq!(weighted_mid(&[], 2), None);
+    }
+
+    #[test]
+    fn should_reject_outliers() {
+        assert!(!is_outlier(101.0, 100.0, 5.0));
+        assert!(is_outlier(110.0, 100.0, 5.0));
+        assert!(is_outlier(90.0, 100.0, 5.0));
+        assert!(!is_outlier(90.0, 0.0, 5.0));
+    }
+}
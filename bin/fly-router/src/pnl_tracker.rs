@@ -0,0 +1,1053 @@
+//! Realized PnL: follows the wallets of the pool and their token accounts through geyser and
+//! attributes the balance changes to the bundles we sent, by transaction signature.
+//! The balances also feed the inventory of the wallet pool.
+//! Processed commitment, a bundle landing on a fork that is later dropped is still counted.
+//! The block engine is asked for the status of the bundles still waiting: a bundle it reports
+//! landed counts even before its writes arrive, one that failed in every region is expired.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use anchor_spl::token::spl_token::{self, native_mint};
+use router_config_lib::PnlTrackerConfig;
+use serde_json::{json, Value};
+use solana_client::client_error::reqwest::Client;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::program_pack::Pack;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+use tokio::task::JoinHandle;
+
+use crate::journal;
+use crate::metrics;
+use crate::prelude::*;
+use crate::routing_types::Route;
+use crate::sender::tip_pricer::TipPricer;
+use crate::source::wallet_writes::WalletWrite;
+use crate::util::tokio_spawn;
+use crate::utils::get_source_atas;
+use crate::wallet_pool::{Reservation, WalletPool};
+
+const DEFAULT_BUNDLE_TTL_SECS: u64 = 60;
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+/// the writes of a bundle arrive together, its outcome is journaled once they settled
+const LANDED_SETTLE: Duration = Duration::from_secs(5);
+/// the block engine's rate limit is shared with sendBundle, ask sparingly
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// bundles sent more recently are not asked for
+const STATUS_POLL_DELAY: Duration = Duration::from_secs(2);
+/// getInflightBundleStatuses takes at most 5 bundle ids
+const MAX_IDS_PER_STATUS_REQUEST: usize = 5;
+const STATUS_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A bundle handed to the block engine, every transaction signature of every region
+pub struct SentBundle {
+    pub route_id: u64,
+    pub signatures: Vec<Signature>,
+    pub wallet: Pubkey,
+    pub input_mint: Pubkey,
+    pub expected_profit: i64,
+    /// inventory spent by the bundle, released when it lands
+    pub reservation: Option<Reservation>,
+}
+
+impl SentBundle {
+    pub fn new(
+        route: &Route,
+        reservation: &Reservation,
+        transactions: &HashMap<String, Vec<VersionedTransaction>>,
+    ) -> Self {
+        let signatures = transactions
+            .values()
+            .flatten()
+            .filter_map(|tx| tx.signatures.first())
+            .copied()
+            .unique()
+            .collect_vec();
+        Self {
+            route_id: route.id,
+            signatures,
+            wallet: reservation.wallet,
+            input_mint: route.input_mint,
+            expected_profit: route.out_amount as i64 - route.in_amount as i64,
+            reservation: Some(reservation.clone()),
+        }
+    }
+}
+
+/// A bundle the block engine accepted, linked to the sent bundle by its first signature
+pub struct AcceptedBundle {
+    pub signature: Signature,
+    /// block engine url the bundle was sent to
+    pub url: String,
+    pub bundle_id: String,
+}
+
+/// getInflightBundleStatuses answer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EngineStatus {
+    Pending,
+    Landed,
+    /// failed or unknown to the block engine, it will not land
+    Failed,
+}
+
+impl EngineStatus {
+    fn parse(status: &str) -> Self {
+        match status {
+            "Landed" => Self::Landed,
+            "Failed" | "Invalid" => Self::Failed,
+            _ => Self::Pending,
+        }
+    }
+}
+
+/// Shared with the sender: reports the sent bundles and tells whether trading is paused
+#[derive(Clone)]
+pub struct PnlHandle {
+    paused: Arc<AtomicBool>,
+    // 已发送但还没有落地也没有过期的 bundle
+    pending: Arc<AtomicUsize>,
+    sent_sender: async_channel::Sender<SentBundle>,
+    accepted_sender: async_channel::Sender<AcceptedBundle>,
+}
+
+impl PnlHandle {
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Sent bundles that neither landed, failed at the block engine nor expired
+    pub fn pending_bundles(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// Only bundles the block engine accepted, a failed send can never land
+    pub fn record_sent(&self, bundle: SentBundle) {
+        let _ = self.sent_sender.try_send(bundle);
+    }
+
+    pub fn record_accepted(&self, bundle: AcceptedBundle) {
+        let _ = self.accepted_sender.try_send(bundle);
+    }
+}
+
+struct PendingBundle {
+    route_id: u64,
+    wallet: Pubkey,
+    input_mint: Pubkey,
+    expected_profit: i64,
+    sent_at: Instant,
+    landed_at: Option<Instant>,
+    /// balance change of the input mint caused by the bundle
+    realized: i64,
+    /// the `Landed` journal event was written
+    reported: bool,
+    reservation: Option<Reservation>,
+    /// one per region that accepted the bundle
+    engine_bundles: Vec<EngineBundle>,
+}
+
+struct EngineBundle {
+    url: String,
+    bundle_id: String,
+    failed: bool,
+}
+
+struct AccountBalance {
+    wallet: Pubkey,
+    mint: Pubkey,
+    amount: u64,
+    slot: u64,
+    write_version: u64,
+}
+
+struct PnlTracker {
+    wallets: Vec<Pubkey>,
+    pool: Option<Arc<WalletPool>>,
+    max_drawdown: Option<u64>,
+    /// values the realized PnL of the non-SOL mints for the drawdown, None counts SOL only
+    tip_pricer: Option<TipPricer>,
+    min_sol_balance: Option<u64>,
+    bundle_ttl: Duration,
+    balances: HashMap<Pubkey, AccountBalance>,
+    next_bundle_id: u64,
+    bundles: HashMap<u64, PendingBundle>,
+    by_signature: HashMap<Signature, u64>,
+    by_engine_id: HashMap<String, u64>,
+    // 按 (钱包, mint) 统计
+    realized: HashMap<(Pubkey, Pubkey), i64>,
+    expected_landed: HashMap<(Pubkey, Pubkey), i64>,
+    // 每个 mint 的已实现收益折算成 lamports，没有价格时保留上一次的值
+    realized_lamports: HashMap<Pubkey, i64>,
+    peak_pnl: i64,
+    // 回撤触发后保持暂停，直到重启
+    drawdown_breached: bool,
+    paused: Arc<AtomicBool>,
+}
+
+impl PnlTracker {
+    fn new(
+        config: &PnlTrackerConfig,
+        wallets: Vec<Pubkey>,
+        pool: Option<Arc<WalletPool>>,
+        tip_pricer: Option<TipPricer>,
+        paused: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            wallets,
+            pool,
+            max_drawdown: config.max_drawdown_lamports,
+            tip_pricer,
+            min_sol_balance: config.min_sol_balance_lamports,
+            bundle_ttl: Duration::from_secs(
+                config.bundle_ttl_secs.unwrap_or(DEFAULT_BUNDLE_TTL_SECS),
+            ),
+            balances: HashMap::new(),
+            next_bundle_id: 0,
+            bundles: HashMap::new(),
+            by_signature: HashMap::new(),
+            by_engine_id: HashMap::new(),
+            realized: HashMap::new(),
+            expected_landed: HashMap::new(),
+            realized_lamports: HashMap::new(),
+            peak_pnl: 0,
+            drawdown_breached: false,
+            paused,
+        }
+    }
+
+    /// Initial balances, writes older than `slot` are ignored
+    async fn load_balances(&mut self, rpc: &RpcClient) -> anyhow::Result<()> {
+        for wallet in self.wallets.clone() {
+            let atas = get_source_atas(rpc, &wallet).await?;
+            let pubkeys = std::iter::once(wallet)
+                .chain(atas.values().copied())
+                .collect_vec();
+            let response = rpc
+                .get_multiple_accounts_with_commitment(&pubkeys, CommitmentConfig::processed())
+                .await?;
+            let slot = response.context.slot;
+            for (pubkey, account) in pubkeys.into_iter().zip(response.value) {
+                let Some(account) = account else {
+                    continue;
+                };
+                if let Some((wallet, mint, amount)) =
+                    self.parse_balance(&pubkey, account.lamports, &account.data)
+                {
+                    self.balances.insert(
+                        pubkey,
+                        AccountBalance {
+                            wallet,
+                            mint,
+                            amount,
+                            slot,
+                            write_version: 0,
+                        },
+                    );
+                    self.update_inventory(&wallet, mint);
+                }
+            }
+            info!(
+                "pnl tracker: following the accounts of wallet {} from slot {}",
+                wallet, slot
+            );
+        }
+        Ok(())
+    }
+
+    fn parse_balance(
+        &self,
+        pubkey: &Pubkey,
+        lamports: u64,
+        data: &[u8],
+    ) -> Option<(Pubkey, Pubkey, u64)> {
+        if self.wallets.contains(pubkey) {
+            return Some((*pubkey, native_mint::ID, lamports));
+        }
+        let token_account = spl_token::state::Account::unpack(data).ok()?;
+        self.wallets.contains(&token_account.owner).then_some((
+            token_account.owner,
+            token_account.mint,
+            token_account.amount,
+        ))
+    }
+
+    /// Token balance of `mint` held by `wallet`, the inventory its routes can spend
+    fn token_balance(&self, wallet: &Pubkey, mint: &Pubkey) -> u64 {
+        self.balances
+            .iter()
+            .filter(|(pubkey, b)| *pubkey != wallet && b.wallet == *wallet && b.mint == *mint)
+            .map(|(_, b)| b.amount)
+            .sum()
+    }
+
+    fn update_inventory(&self, wallet: &Pubkey, mint: Pubkey) {
+        if let Some(pool) = &self.pool {
+            pool.set_balance(wallet, mint, self.token_balance(wallet, &mint));
+        }
+    }
+
+    fn on_sent(&mut self, bundle: SentBundle) {
+        let id = self.next_bundle_id;
+        self.next_bundle_id += 1;
+        for signature in bundle.signatures {
+            self.by_signature.insert(signature, id);
+        }
+        self.bundles.insert(
+            id,
+            PendingBundle {
+                route_id: bundle.route_id,
+                wallet: bundle.wallet,
+                input_mint: bundle.input_mint,
+                expected_profit: bundle.expected_profit,
+                sent_at: Instant::now(),
+                landed_at: None,
+                realized: 0,
+                reported: false,
+                reservation: bundle.reservation,
+                engine_bundles: vec![],
+            },
+        );
+    }
+
+    fn on_write(&mut self, write: WalletWrite) {
+        let Some((wallet, mint, amount)) =
+            self.parse_balance(&write.pubkey, write.lamports, &write.data)
+        else {
+            return;
+        };
+        let bundle_id = write
+            .txn_signature
+            .and_then(|signature| self.by_signature.get(&signature).copied());
+
+        let previous = match self.balances.get(&write.pubkey) {
+            Some(balance)
+                if (write.slot, write.write_version) <= (balance.slot, balance.write_version) =>
+            {
+                return;
+            }
+            Some(balance) => Some(balance.amount),
+            // 由我们的交易创建的账户从 0 开始计算
+            None if bundle_id.is_some() => Some(0),
+            None => None,
+        };
+        self.balances.insert(
+            write.pubkey,
+            AccountBalance {
+                wallet,
+                mint,
+                amount,
+                slot: write.slot,
+                write_version: write.write_version,
+            },
+        );
+        if write.pubkey != wallet {
+            self.update_inventory(&wallet, mint);
+        }
+
+        let (Some(previous), Some(bundle_id)) = (previous, bundle_id) else {
+            // 充值、提现等外部变动只更新余额
+            return;
+        };
+        let delta = amount as i64 - previous as i64;
+        *self.realized.entry((wallet, mint)).or_default() += delta;
+
+        if let Some(bundle) = self.bundles.get_mut(&bundle_id) {
+            if mint == bundle.input_mint {
+                bundle.realized += delta;
+            }
+            // 库存已经是落地后的余额，预留不再需要；没有落地的预留由钱包池按时过期
+            let spent = write.pubkey != wallet
+                && bundle.reservation.as_ref().is_some_and(|r| r.mint == mint);
+            if spent {
+                if let (Some(pool), Some(reservation)) = (&self.pool, bundle.reservation.take()) {
+                    pool.release(&reservation);
+                }
+            }
+        }
+        self.mark_landed(bundle_id);
+    }
+
+    fn mark_landed(&mut self, id: u64) {
+        let Some(bundle) = self.bundles.get_mut(&id) else {
+            return;
+        };
+        if bundle.landed_at.is_none() {
+            bundle.landed_at = Some(Instant::now());
+            metrics::LANDED_BUNDLES.inc();
+            *self
+                .expected_landed
+                .entry((bundle.wallet, bundle.input_mint))
+                .or_default() += bundle.expected_profit;
+        }
+    }
+
+    fn on_accepted(&mut self, accepted: AcceptedBundle) {
+        let Some(id) = self.by_signature.get(&accepted.signature).copied() else {
+            return;
+        };
+        if let Some(bundle) = self.bundles.get_mut(&id) {
+            self.by_engine_id.insert(accepted.bundle_id.clone(), id);
+            bundle.engine_bundles.push(EngineBundle {
+                url: accepted.url,
+                bundle_id: accepted.bundle_id,
+                failed: false,
+            });
+        }
+    }
+
+    /// Block engine bundle ids to ask for, by url and in chunks the endpoint accepts
+    fn status_requests(&self) -> Vec<(String, Vec<String>)> {
+        let mut by_url: HashMap<&str, Vec<String>> = HashMap::new();
+        for bundle in self.bundles.values() {
+            if bundle.landed_at.is_some() || bundle.sent_at.elapsed() < STATUS_POLL_DELAY {
+                continue;
+            }
+            for engine_bundle in bundle.engine_bundles.iter().filter(|b| !b.failed) {
+                by_url
+                    .entry(engine_bundle.url.as_str())
+                    .or_default()
+                    .push(engine_bundle.bundle_id.clone());
+            }
+        }
+        by_url
+            .into_iter()
+            .flat_map(|(url, ids)| {
+                ids.chunks(MAX_IDS_PER_STATUS_REQUEST)
+                    .map(|chunk| (url.to_string(), chunk.to_vec()))
+                    .collect_vec()
+            })
+            .collect()
+    }
+
+    fn on_engine_status(&mut self, bundle_id: &str, status: EngineStatus) {
+        let Some(id) = self.by_engine_id.get(bundle_id).copied() else {
+            return;
+        };
+        match status {
+            EngineStatus::Pending => {}
+            // 写入稍后到达时再计入实际收益
+            EngineStatus::Landed => self.mark_landed(id),
+            EngineStatus::Failed => {
+                let Some(bundle) = self.bundles.get_mut(&id) else {
+                    return;
+                };
+                for engine_bundle in &mut bundle.engine_bundles {
+                    if engine_bundle.bundle_id == bundle_id {
+                        engine_bundle.failed = true;
+                    }
+                }
+                // 所有区域都失败的 bundle 不会再落地，不必等到过期
+                if bundle.landed_at.is_none() && bundle.engine_bundles.iter().all(|b| b.failed) {
+                    journal::record_expired(bundle.route_id);
+                    if let (Some(pool), Some(reservation)) = (&self.pool, bundle.reservation.take()) {
+                        pool.release(&reservation);
+                    }
+                    self.remove_bundle(id);
+                }
+            }
+        }
+    }
+
+    fn remove_bundle(&mut self, id: u64) {
+        self.bundles.remove(&id);
+        self.by_signature.retain(|_, bundle_id| *bundle_id != id);
+        self.by_engine_id.retain(|_, bundle_id| *bundle_id != id);
+    }
+
+    /// Journals the bundles that landed at least `settle` ago
+    fn report_landed(&mut self, settle: Duration) {
+        for bundle in self.bundles.values_mut() {
+            let settled = bundle.landed_at.is_some_and(|at| at.elapsed() >= settle);
+            if settled && !bundle.reported {
+                journal::record_landed(bundle.route_id, bundle.realized);
+                bundle.reported = true;
+            }
+        }
+    }
+
+    fn expire_bundles(&mut self) {
+        self.report_landed(LANDED_SETTLE);
+        let ttl = self.bundle_ttl;
+        self.bundles.retain(|_, bundle| {
+            if bundle.sent_at.elapsed() < ttl {
+                return true;
+            }
+            if bundle.landed_at.is_none() {
+                journal::record_expired(bundle.route_id);
+            }
+            false
+        });
+        let bundles = &self.bundles;
+        self.by_signature.retain(|_, id| bundles.contains_key(id));
+        self.by_engine_id.retain(|_, id| bundles.contains_key(id));
+    }
+
+    /// Bundles not landed yet that may still land
+    fn pending_count(&self) -> usize {
+        self.bundles.values().filter(|b| b.landed_at.is_none()).count()
+    }
+
+    /// SOL and WSOL of all wallets
+    fn sol_balance(&self) -> u64 {
+        self.balances
+            .values()
+            .filter(|b| b.mint == native_mint::ID)
+            .map(|b| b.amount)
+            .sum()
+    }
+
+    /// SOL and WSOL of one wallet, None before its first balance is known
+    fn wallet_sol_balance(&self, wallet: &Pubkey) -> Option<u64> {
+        let mut balances = self
+            .balances
+            .values()
+            .filter(|b| b.wallet == *wallet && b.mint == native_mint::ID)
+            .peekable();
+        balances.peek()?;
+        Some(balances.map(|b| b.amount).sum())
+    }
+
+    /// Realized PnL of `mint` over all wallets
+    fn total_realized(&self, mint: &Pubkey) -> i64 {
+        self.realized
+            .iter()
+            .filter(|((_, m), _)| m == mint)
+            .map(|(_, pnl)| pnl)
+            .sum()
+    }
+
+    /// Realized PnL of every mint valued in lamports, as of the last `update_pause`
+    fn realized_pnl_lamports(&self) -> i64 {
+        self.realized_lamports.values().sum()
+    }
+
+    /// Values the realized PnL of the traded mints in lamports, the tips and fees of non-SOL
+    /// rings are paid in SOL while their gain lands in the input mint
+    fn value_realized(&mut self) {
+        let mints: HashSet<Pubkey> = self.realized.keys().map(|(_, mint)| *mint).collect();
+        for mint in mints {
+            let pnl = self.total_realized(&mint);
+            let value = if mint == native_mint::ID {
+                Ok(pnl)
+            } else if let Some(tip_pricer) = &self.tip_pricer {
+                tip_pricer
+                    .to_lamports(&mint, pnl.unsigned_abs())
+                    .map(|lamports| lamports as i64 * pnl.signum())
+            } else {
+                continue;
+            };
+            match value {
+                Ok(value) => {
+                    self.realized_lamports.insert(mint, value);
+                }
+                Err(e) => debug!("pnl tracker: cannot value the pnl of {}: {:?}", mint, e),
+            }
+        }
+    }
+
+    fn drawdown(&self) -> i64 {
+        let pnl = self.realized_pnl_lamports();
+        self.peak_pnl.max(pnl) - pnl
+    }
+
+    fn update_pause(&mut self) {
+        self.value_realized();
+        self.peak_pnl = self.peak_pnl.max(self.realized_pnl_lamports());
+
+        let drawdown = self.drawdown();
+        if let Some(max_drawdown) = self.max_drawdown {
+            if !self.drawdown_breached && drawdown > max_drawdown as i64 {
+                error!(
+                    "pnl tracker: drawdown of {} lamports exceeds {}, pausing trading",
+                    drawdown, max_drawdown
+                );
+                self.drawdown_breached = true;
+            }
+        }
+
+        // SOL 不足的钱包不再分配路由，全部不足时暂停交易
+        let mut low_wallets = 0;
+        for wallet in &self.wallets {
+            let low = match (self.min_sol_balance, self.wallet_sol_balance(wallet)) {
+                (Some(min), Some(balance)) => balance < min,
+                _ => false,
+            };
+            if let Some(pool) = &self.pool {
+                pool.set_low_sol(wallet, low);
+            }
+            low_wallets += low as usize;
+        }
+        let sol_balance = self.sol_balance();
+        let low_balance = !self.wallets.is_empty() && low_wallets == self.wallets.len();
+
+        let paused = self.drawdown_breached || low_balance;
+        if paused != self.paused.swap(paused, Ordering::Relaxed) {
+            if paused {
+                warn!("pnl tracker: trading paused, SOL balance {}", sol_balance);
+            } else {
+                info!("pnl tracker: trading resumed, SOL balance {}", sol_balance);
+            }
+        }
+    }
+
+    fn publish_metrics(&self) {
+        for ((wallet, mint), pnl) in &self.realized {
+            metrics::REALIZED_PNL
+                .with_label_values(&[&wallet.to_string(), &mint.to_string()])
+                .set(*pnl);
+        }
+        for ((wallet, mint), pnl) in &self.expected_landed {
+            metrics::EXPECTED_PNL_LANDED
+                .with_label_values(&[&wallet.to_string(), &mint.to_string()])
+                .set(*pnl);
+        }
+        let mut by_mint: HashMap<(Pubkey, Pubkey), u64> = HashMap::new();
+        for balance in self.balances.values() {
+            *by_mint.entry((balance.wallet, balance.mint)).or_default() += balance.amount;
+        }
+        for ((wallet, mint), amount) in by_mint {
+            metrics::WALLET_BALANCE
+                .with_label_values(&[&wallet.to_string(), &mint.to_string()])
+                .set(amount as i64);
+        }
+        metrics::PNL_DRAWDOWN_LAMPORTS.set(self.drawdown());
+        metrics::TRADING_PAUSED.set(self.paused.load(Ordering::Relaxed) as i64);
+    }
+}
+
+/// getInflightBundleStatuses of `bundle_ids`, the ones the block engine does not know are omitted
+async fn query_bundle_statuses(
+    client: &Client,
+    url: &str,
+    bundle_ids: &[String],
+) -> anyhow::Result<Vec<(String, EngineStatus)>> {
+    let body = json!({
+        "id": 1,
+        "jsonrpc": "2.0",
+        "method": "getInflightBundleStatuses",
+        "params": [bundle_ids],
+    });
+    let response: Value = client
+        .post(url)
+        .timeout(STATUS_REQUEST_TIMEOUT)
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    if let Some(error) = response.get("error") {
+        bail!("getInflightBundleStatuses failed: {}", error);
+    }
+    let statuses = response["result"]["value"]
+        .as_array()
+        .map(|statuses| {
+            statuses
+                .iter()
+                .filter_map(|status| {
+                    Some((
+                        status["bundle_id"].as_str()?.to_string(),
+                        EngineStatus::parse(status["status"].as_str()?),
+                    ))
+                })
+                .collect_vec()
+        })
+        .unwrap_or_default();
+    Ok(statuses)
+}
+
+pub fn spawn_pnl_tracker_job(
+    config: &PnlTrackerConfig,
+    rpc: RpcClient,
+    pool: Arc<WalletPool>,
+    tip_pricer: TipPricer,
+    wallet_writes: async_channel::Receiver<WalletWrite>,
+    mut exit: broadcast::Receiver<()>,
+) -> (PnlHandle, JoinHandle<()>) {
+    let paused = Arc::new(AtomicBool::new(false));
+    let pending = Arc::new(AtomicUsize::new(0));
+    let (sent_sender, sent_receiver) = async_channel::unbounded::<SentBundle>();
+    let (accepted_sender, accepted_receiver) = async_channel::unbounded::<AcceptedBundle>();
+    let (status_sender, status_receiver) = async_channel::unbounded::<(String, EngineStatus)>();
+    let mut tracker = PnlTracker::new(
+        config,
+        pool.wallets().to_vec(),
+        Some(pool.clone()),
+        Some(tip_pricer),
+        paused.clone(),
+    );
+
+    let job_pending = pending.clone();
+    let job = tokio_spawn("pnl_tracker", async move {
+        if let Err(e) = tracker.load_balances(&rpc).await {
+            // 余额从第一次写入开始计算
+            warn!("pnl tracker: failed to load initial balances: {:?}", e);
+        }
+        let mut tick = tokio::time::interval(TICK_INTERVAL);
+        let mut status_tick = tokio::time::interval(STATUS_POLL_INTERVAL);
+        let client = Client::new();
+        let mut status_query: Option<JoinHandle<()>> = None;
+
+        loop {
+            tokio::select! {
+                _ = exit.recv() => {
+                    info!("shutting down pnl tracker task");
+                    tracker.report_landed(Duration::ZERO);
+                    if let Some(query) = status_query.take() {
+                        query.abort();
+                    }
+                    break;
+                }
+                Ok(bundle) = sent_receiver.recv() => {
+                    tracker.on_sent(bundle);
+                    job_pending.store(tracker.pending_count(), Ordering::Relaxed);
+                }
+                Ok(accepted) = accepted_receiver.recv() => {
+                    tracker.on_accepted(accepted);
+                }
+                Ok((bundle_id, status)) = status_receiver.recv() => {
+                    tracker.on_engine_status(&bundle_id, status);
+                    job_pending.store(tracker.pending_count(), Ordering::Relaxed);
+                }
+                _ = status_tick.tick() => {
+                    // 上一轮查询还没结束时跳过，不占用发送 bundle 的限额
+                    if status_query.as_ref().is_some_and(|query| !query.is_finished()) {
+                        continue;
+                    }
+                    let requests = tracker.status_requests();
+                    if requests.is_empty() {
+                        continue;
+                    }
+                    let client = client.clone();
+                    let status_sender = status_sender.clone();
+                    status_query = Some(tokio_spawn("pnl_bundle_status", async move {
+                        for (url, bundle_ids) in requests {
+                            match query_bundle_statuses(&client, &url, &bundle_ids).await {
+                                Ok(statuses) => {
+                                    for status in statuses {
+                                        let _ = status_sender.try_send(status);
+                                    }
+                                }
+                                Err(e) => {
+                                    debug!("pnl tracker: bundle status query to {} failed: {:?}", url, e)
+                                }
+                            }
+                        }
+                    }));
+                }
+                write = wallet_writes.recv() => {
+                    match write {
+                        Ok(write) => tracker.on_write(write),
+                        Err(_) => {
+                            error!("wallet writes channel closed");
+                            break;
+                        }
+                    }
+                }
+                _ = tick.tick() => {
+                    tracker.expire_bundles();
+                    job_pending.store(tracker.pending_count(), Ordering::Relaxed);
+                    tracker.update_pause();
+                    tracker.publish_metrics();
+                }
+            }
+        }
+    });
+
+    (
+        PnlHandle {
+            paused,
+            pending,
+            sent_sender,
+            accepted_sender,
+        },
+        job,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::token_cache::{Token, TokenCache};
+    use router_lib::price_feeds::price_cache::PriceCache;
+    use router_lib::price_feeds::price_feed::PriceUpdate;
+
+    fn token_data(mint: Pubkey, owner: Pubkey, amount: u64) -> Vec<u8> {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        data
+    }
+
+    fn write(
+        pubkey: Pubkey,
+        slot: u64,
+        lamports: u64,
+        data: Vec<u8>,
+        signature: Option<Signature>,
+    ) -> WalletWrite {
+        static WRITE_VERSION: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+        WalletWrite {
+            pubkey,
+            owner: Pubkey::default(),
+            slot,
+            write_version: WRITE_VERSION.fetch_add(1, Ordering::Relaxed),
+            lamports,
+            data,
+            txn_signature: signature,
+        }
+    }
+
+    #[test]
+    fn should_attribute_balance_changes_to_sent_bundles() {
+        let wallet = Pubkey::new_unique();
+        let wsol_ata = Pubkey::new_unique();
+        let config = PnlTrackerConfig {
+            enabled: true,
+            max_drawdown_lamports: Some(1_000),
+            min_sol_balance_lamports: Some(500_000),
+            bundle_ttl_secs: None,
+        };
+        let mut tracker = PnlTracker::new(
+            &config,
+            vec![wallet],
+            None,
+            None,
+            Arc::new(AtomicBool::new(false)),
+        );
+
+        // 初始余额
+        tracker.on_write(write(wallet, 1, 1_000_000, vec![], None));
+        tracker.on_write(write(wsol_ata, 1, 0, token_data(native_mint::ID, wallet, 2_000_000), None));
+
+        let (tx1, tx2) = (Signature::new_unique(), Signature::new_unique());
+        tracker.on_sent(SentBundle {
+            route_id: 1,
+            signatures: vec![tx1, tx2],
+            wallet,
+            input_mint: native_mint::ID,
+            expected_profit: 600,
+            reservation: None,
+        });
+
+        // tx1: 兑换赚 500，手续费 10; tx2: 小费 90
+        tracker.on_write(write(wsol_ata, 2, 0, token_data(native_mint::ID, wallet, 2_000_500), Some(tx1)));
+        tracker.on_write(write(wallet, 2, 999_990, vec![], Some(tx1)));
+        tracker.on_write(write(wallet, 2, 999_900, vec![], Some(tx2)));
+        // 外部转入不计入 PnL
+        tracker.on_write(write(wallet, 3, 1_499_900, vec![], Some(Signature::new_unique())));
+        tracker.update_pause();
+
+        assert_eq!(tracker.realized[&(wallet, native_mint::ID)], 500 - 90 - 10);
+        assert_eq!(tracker.expected_landed[&(wallet, native_mint::ID)], 600);
+        assert_eq!(tracker.sol_balance(), 3_500_400);
+        assert!(!tracker.paused.load(Ordering::Relaxed));
+
+        // 亏损超过回撤上限后暂停
+        let tx3 = Signature::new_unique();
+        tracker.on_sent(SentBundle {
+            route_id: 1,
+            signatures: vec![tx3],
+            wallet,
+            input_mint: native_mint::ID,
+            expected_profit: 100,
+            reservation: None,
+        });
+        tracker.on_write(write(wsol_ata, 4, 0, token_data(native_mint::ID, wallet, 1_998_500), Some(tx3)));
+        tracker.update_pause();
+        assert_eq!(tracker.drawdown(), 2_000);
+        assert!(tracker.paused.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn should_value_the_drawdown_of_non_sol_rings_in_lamports() {
+        let (wallet, usdc_ata) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let usdc = Pubkey::new_unique();
+        let token_cache = TokenCache::new(HashMap::from([(
+            usdc,
+            Token {
+                mint: usdc,
+                decimals: 6,
+                symbol: "USDC".to_string(),
+                mint_info: None,
+            },
+        )]));
+        let (exit_sender, _) = broadcast::channel(1);
+        let (price_sender, price_receiver) = broadcast::channel(4);
+        let (price_cache, _job) = PriceCache::new(exit_sender.subscribe(), price_receiver);
+        price_sender.send(PriceUpdate { mint: usdc, price: 1.0 }).unwrap();
+        price_sender.send(PriceUpdate { mint: native_mint::ID, price: 100.0 }).unwrap();
+        while price_cache.price_ui(native_mint::ID).is_none() {
+            tokio::task::yield_now().await;
+        }
+
+        let config = PnlTrackerConfig {
+            enabled: true,
+            max_drawdown_lamports: Some(1_000_000),
+            min_sol_balance_lamports: None,
+            bundle_ttl_secs: None,
+        };
+        let mut tracker = PnlTracker::new(
+            &config,
+            vec![wallet],
+            None,
+            Some(TipPricer::new(price_cache, Arc::new(token_cache))),
+            Arc::new(AtomicBool::new(false)),
+        );
+        tracker.on_write(write(wallet, 1, 1_000_000_000, vec![], None));
+        tracker.on_write(write(usdc_ata, 1, 0, token_data(usdc, wallet, 10_000_000), None));
+
+        // 赚 3 USDC (0.03 SOL)，小费和手续费 0.02 SOL：净收益为正，不算回撤
+        let (tx1, tx2) = (Signature::new_unique(), Signature::new_unique());
+        tracker.on_sent(SentBundle {
+            route_id: 1,
+            signatures: vec![tx1, tx2],
+            wallet,
+            input_mint: usdc,
+            expected_profit: 3_000_000,
+            reservation: None,
+        });
+        tracker.on_write(write(usdc_ata, 2, 0, token_data(usdc, wallet, 13_000_000), Some(tx1)));
+        tracker.on_write(write(wallet, 2, 980_000_000, vec![], Some(tx2)));
+        tracker.update_pause();
+        assert_eq!(tracker.drawdown(), 0);
+        assert!(!tracker.paused.load(Ordering::Relaxed));
+
+        // 亏 2 USDC (0.02 SOL)
+        let tx3 = Signature::new_unique();
+        tracker.on_sent(SentBundle {
+            route_id: 1,
+            signatures: vec![tx3],
+            wallet,
+            input_mint: usdc,
+            expected_profit: 1_000_000,
+            reservation: None,
+        });
+        tracker.on_write(write(usdc_ata, 3, 0, token_data(usdc, wallet, 11_000_000), Some(tx3)));
+        tracker.update_pause();
+        assert_eq!(tracker.drawdown(), 20_000_000);
+        assert!(tracker.paused.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn should_release_the_reservation_when_the_bundle_lands() {
+        let wallet = Pubkey::new_unique();
+        let ata = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let pool = Arc::new(WalletPool::new(vec![wallet], None));
+        let config = PnlTrackerConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut tracker = PnlTracker::new(
+            &config,
+            vec![wallet],
+            Some(pool.clone()),
+            None,
+            Arc::new(AtomicBool::new(false)),
+        );
+        tracker.on_write(write(ata, 1, 0, token_data(mint, wallet, 1_000), None));
+
+        let reservation = pool.reserve(mint, 800).unwrap();
+        let tx = Signature::new_unique();
+        tracker.on_sent(SentBundle {
+            route_id: 1,
+            signatures: vec![tx],
+            wallet,
+            input_mint: mint,
+            expected_profit: 10,
+            reservation: Some(reservation),
+        });
+        assert!(pool.reserve(mint, 800).is_none());
+
+        // 落地后的余额已经包含了这笔交易，不再重复扣除预留
+        tracker.on_write(write(wallet, 2, 999_000, vec![], Some(tx)));
+        assert_eq!(pool.in_flight(), 1);
+        tracker.on_write(write(ata, 2, 0, token_data(mint, wallet, 1_010), Some(tx)));
+        assert_eq!(pool.in_flight(), 0);
+        assert_eq!(pool.max_free_balance(&mint), Some(1_010));
+        // 只计入输入代币的变化
+        assert_eq!(tracker.bundles[&0].realized, 10);
+    }
+
+    #[test]
+    fn should_follow_the_block_engine_status_of_pending_bundles() {
+        let wallet = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let pool = Arc::new(WalletPool::new(vec![wallet], None));
+        let config = PnlTrackerConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut tracker = PnlTracker::new(
+            &config,
+            vec![wallet],
+            Some(pool.clone()),
+            None,
+            Arc::new(AtomicBool::new(false)),
+        );
+
+        let mut send = |tracker: &mut PnlTracker, route_id: u64| {
+            let tx = Signature::new_unique();
+            tracker.on_sent(SentBundle {
+                route_id,
+                signatures: vec![tx],
+                wallet,
+                input_mint: mint,
+                expected_profit: 10,
+                reservation: pool.reserve(mint, 100),
+            });
+            for region in ["ny", "tokyo"] {
+                tracker.on_accepted(AcceptedBundle {
+                    signature: tx,
+                    url: region.to_string(),
+                    bundle_id: format!("{}-{}", region, route_id),
+                });
+            }
+        };
+        send(&mut tracker, 1);
+        send(&mut tracker, 2);
+        assert_eq!(tracker.pending_count(), 2);
+        assert_eq!(pool.in_flight(), 2);
+
+        // 刚发送的 bundle 暂不查询
+        assert!(tracker.status_requests().is_empty());
+        for bundle in tracker.bundles.values_mut() {
+            bundle.sent_at -= STATUS_POLL_DELAY;
+        }
+        let mut requests = tracker.status_requests();
+        requests.iter_mut().for_each(|(_, ids)| ids.sort());
+        requests.sort();
+        assert_eq!(
+            requests,
+            vec![
+                ("ny".to_string(), vec!["ny-1".to_string(), "ny-2".to_string()]),
+                ("tokyo".to_string(), vec!["tokyo-1".to_string(), "tokyo-2".to_string()]),
+            ]
+        );
+
+        // 一个区域落地即可
+        tracker.on_engine_status("ny-1", EngineStatus::Failed);
+        tracker.on_engine_status("tokyo-1", EngineStatus::Landed);
+        assert_eq!(tracker.pending_count(), 1);
+        assert_eq!(tracker.expected_landed[&(wallet, mint)], 10);
+
+        // 所有区域都失败后不再等待，预留也随之释放
+        tracker.on_engine_status("ny-2", EngineStatus::Failed);
+        assert_eq!(tracker.status_requests(), vec![("tokyo".to_string(), vec!["tokyo-2".to_string()])]);
+        tracker.on_engine_status("tokyo-2", EngineStatus::Failed);
+        assert_eq!(tracker.pending_count(), 0);
+        assert_eq!(tracker.bundles.len(), 1);
+        assert_eq!(pool.in_flight(), 1);
+        assert!(!tracker.by_engine_id.contains_key("tokyo-2"));
+    }
+}
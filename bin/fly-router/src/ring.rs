@@ -28,6 +28,8 @@ pub struct RingState {
     pub cooldown_event: u64,
     /// 这条环何时会再次可用
     pub cooldown_until: Option<u64>,
+    /// 上次计算这条环的时间戳（毫秒）
+    pub last_evaluated: Option<u64>,
 }
 
 pub struct Ring {
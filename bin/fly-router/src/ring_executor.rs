@@ -2,7 +2,8 @@ use crate::graph::Graph;
 use crate::ring;
 use crate::ring_executor;
 use crate::routing_types::Route;
-use crate::source::token_cache::TokenCache;
+use crate::source::token_cache::{TokenCache, TokenUpdate};
+use crate::tx_preflight::TxEstimate;
 use dashmap::DashMap;
 use futures::future::join_all;
 use rayon::prelude::*;
@@ -14,8 +15,13 @@ use tokio::sync::Semaphore;
 use tokio::task::JoinHandle;
 use tokio::time::{Duration, Instant};
 // 引入自定义的预导入模块，包含常用的类型和特性
+use crate::control::RuntimeControl;
+use crate::hot_mints::HotMintsCache;
+use crate::metrics;
 use crate::ring::Ring;
+use crate::ring_scheduler::RingScheduler;
 use crate::util::tokio_spawn;
+use crate::wallet_pool::WalletPool;
 use crate::{edge, prelude::*};
 
 use router_config_lib::Config;
@@ -49,15 +55,19 @@ pub struct RingExecutor {
 
     token_cache: Arc<TokenCache>,
 
+    trading_mints: Vec<Pubkey>,
+
+    // 所有的边，代币增删时用于重建环
+    all_edges: Vec<Arc<Edge>>,
+
     trading_mint_rings: HashMap<Pubkey, Vec<Arc<Ring>>>,
 
     edge_rings: HashMap<(Pubkey, Pubkey), HashMap<String, Arc<Ring>>>,
 
     path_warming_amounts: Vec<u64>,
 
-    in_amounts: Vec<u64>,
-
-    expected_gain: u64,
+    // 运行时可调整的 in_amounts、expected_gain 和黑白名单
+    control: Arc<RuntimeControl>,
 
     max_path_length: usize,
 
@@ -65,6 +75,14 @@ pub struct RingExecutor {
 
     route_sender: async_channel::Sender<Arc<Route>>,
 
+    // 最近成交过的代币
+    hot_mints: Arc<RwLock<HotMintsCache>>,
+
+    // 各交易代币的可用库存，超过库存的 in_amount 不再计算
+    wallet_pool: Option<Arc<WalletPool>>,
+
+    scheduler: RingScheduler,
+
     state: RingExecutorState,
 }
 
@@ -78,51 +96,97 @@ impl RingExecutor {
         path_warming_amounts: Vec<u64>,
         edges: Vec<Arc<Edge>>,
         route_sender: async_channel::Sender<Arc<Route>>,
+        hot_mints: Arc<RwLock<HotMintsCache>>,
+        control: Arc<RuntimeControl>,
+        wallet_pool: Option<Arc<WalletPool>>,
     ) -> Self {
         let max_path_length: usize = config.ring.max_path_length.unwrap_or(3);
-        let mut trading_mints: Vec<String> = config.ring.trading_mints.clone().unwrap_or_default();
-        if trading_mints.is_empty() {
-            trading_mints.push("So11111111111111111111111111111111111111112".to_string());
-        }
 
-        let mut in_amounts: Vec<u64> = config
-            .sender
-            .in_amounts
-            .clone()
-            .unwrap_or([10_00_000_000, 500_000_000, 100_000_000].to_vec());
-        in_amounts.sort_by(|a, b| b.cmp(a));
-        let expected_gain: u64 = config.sender.expected_gain.unwrap_or(1_000_000);
+        let scheduler = RingScheduler::new(
+            &config.ring.priority.clone().unwrap_or_default(),
+            control.snapshot().params.expected_gain,
+        );
+
+        let trading_mints = control.snapshot().trading_mints.clone();
+
+        let mut ring_executor = Self {
+            // ready_sender,
+            chain_data,
+            token_cache,
+            trading_mints,
+            all_edges: edges,
+            trading_mint_rings: HashMap::new(),
+            edge_rings: HashMap::new(),
+            //dirty_rings: HashMap::new(),
+            //edge_price_updates,
+            path_warming_amounts,
+            control,
+            max_path_length,
+            graph: Graph::new(),
+            route_sender,
+            hot_mints,
+            wallet_pool,
+            scheduler,
+            state: RingExecutorState::default(),
+        };
+        ring_executor.rebuild_rings();
+        ring_executor
+    }
+
+    /// 用代币缓存中仍在交易的代币重建图和环，已存在的环保留其状态
+    pub fn rebuild_rings(&mut self) {
+        let edges = self
+            .all_edges
+            .iter()
+            .filter(|e| {
+                self.token_cache.contains(&e.input_mint) && self.token_cache.contains(&e.output_mint)
+            })
+            .cloned()
+            .collect_vec();
+
+        let existing: HashMap<String, Arc<Ring>> = self
+            .trading_mint_rings
+            .values()
+            .flatten()
+            .map(|r| (r.get_ring_id(), r.clone()))
+            .collect();
 
         let mut ring_mint_rings: HashMap<Pubkey, Vec<Arc<Ring>>> = HashMap::new();
         let mut edge_rings: HashMap<(Pubkey, Pubkey), HashMap<String, Arc<Ring>>> = HashMap::new();
         let mut graph = Graph::new();
-        graph.add_edges(edges.clone());
-        for trading_mint_string in trading_mints.iter() {
-            let ring_mint = Pubkey::from_str(trading_mint_string)
-                .unwrap_or_else(|_| panic!("Invalid mint address: {}", trading_mint_string));
-            let cycles: Vec<Vec<Arc<Edge>>> = graph.find_cycles(ring_mint, max_path_length);
+        graph.add_edges(edges);
+        for ring_mint in self.trading_mints.clone() {
+            let cycles: Vec<Vec<Arc<Edge>>> = graph.find_cycles(ring_mint, self.max_path_length);
 
-            info!(
-                "ring trading mint {} have {} rings",
-                trading_mint_string,
-                cycles.len(),
-            );
+            let mut too_large_rings = 0;
             for cycle in cycles.iter() {
+                // 交易永远无法容纳的环直接丢弃
+                if let Err(e) = TxEstimate::best_case(cycle).check() {
+                    debug!("ring rejected: {}", e);
+                    too_large_rings += 1;
+                    continue;
+                }
+
                 let mut ring_ming_symbols: HashSet<String> = HashSet::new();
                 for edge in cycle.iter() {
                     let input_mint = edge.input_mint;
                     let output_mint = edge.output_mint;
-                    if let Some(input_symbol) = token_cache.get_symbol_by_mint(input_mint) {
+                    if let Some(input_symbol) = self.token_cache.get_symbol_by_mint(input_mint) {
                         ring_ming_symbols.insert(input_symbol);
                     }
-                    if let Some(output_symbol) = token_cache.get_symbol_by_mint(output_mint) {
+                    if let Some(output_symbol) = self.token_cache.get_symbol_by_mint(output_mint) {
                         ring_ming_symbols.insert(output_symbol);
                     }
                 }
-                let ring = Arc::new(Ring::new(ring_mint, cycle.clone(), ring_ming_symbols));
-                let mut ring_state = ring.ring_state.write().unwrap();
-                ring_state.set_valid(true);
-                ring_state.reset_cooldown();
+                let mut ring = Arc::new(Ring::new(ring_mint, cycle.clone(), ring_ming_symbols));
+                if let Some(existing_ring) = existing.get(&ring.get_ring_id()) {
+                    // 保留已有环的状态
+                    ring = existing_ring.clone();
+                } else {
+                    let mut ring_state = ring.ring_state.write().unwrap();
+                    ring_state.set_valid(true);
+                    ring_state.reset_cooldown();
+                }
                 ring_mint_rings
                     .entry(ring_mint)
                     .or_default()
@@ -135,24 +199,97 @@ impl RingExecutor {
                         .insert(ring.get_ring_id(), ring.clone());
                 }
             }
+
+            info!(
+                "ring trading mint {} have {} rings, {} rejected as too large for a transaction",
+                ring_mint,
+                cycles.len() - too_large_rings,
+                too_large_rings,
+            );
         }
 
-        Self {
-            // ready_sender,
-            chain_data,
-            token_cache,
-            trading_mint_rings: ring_mint_rings,
-            edge_rings,
-            //dirty_rings: HashMap::new(),
-            //edge_price_updates,
-            path_warming_amounts,
-            in_amounts,
-            expected_gain,
-            max_path_length,
-            graph,
-            route_sender,
-            state: RingExecutorState::default(),
+        let ring_count = ring_mint_rings.values().map(|r| r.len()).sum::<usize>();
+        let kept = ring_mint_rings
+            .values()
+            .flatten()
+            .filter(|r| existing.contains_key(&r.get_ring_id()))
+            .count();
+        if !existing.is_empty() {
+            info!(
+                "rings rebuilt: {} rings, {} new, {} dropped",
+                ring_count,
+                ring_count - kept,
+                existing.len() - kept
+            );
         }
+
+        // 不再存在的环不需要再计算
+        self.state
+            .dirty_rings
+            .retain(|ring_id, _| edge_rings.values().any(|r| r.contains_key(ring_id)));
+
+        self.control
+            .set_rings(ring_mint_rings.values().flatten().cloned().collect());
+        self.trading_mint_rings = ring_mint_rings;
+        self.edge_rings = edge_rings;
+        self.graph = graph;
+    }
+
+    /// 配置重新加载后交易代币可能改变，改变时重建环
+    pub fn sync_trading_mints(&mut self) {
+        let control = self.control.snapshot();
+        if control.trading_mints == self.trading_mints {
+            return;
+        }
+        info!(
+            "trading mints changed to {}, rebuilding rings",
+            control.trading_mints.iter().join(",")
+        );
+        self.trading_mints = control.trading_mints.clone();
+        self.rebuild_rings();
+    }
+
+    /// 运行时新增代币的边，代币可能已经在交易，直接重建环
+    pub fn add_edges(&mut self, edges: Vec<Arc<Edge>>) {
+        let known: HashSet<(Pubkey, Pubkey)> =
+            self.all_edges.iter().map(|e| e.unique_id()).collect();
+        let edges = edges
+            .into_iter()
+            .filter(|e| !known.contains(&e.unique_id()))
+            .collect_vec();
+        if edges.is_empty() {
+            return;
+        }
+        info!("{} edges added, rebuilding rings", edges.len());
+        self.all_edges.extend(edges);
+        self.rebuild_rings();
+    }
+
+    pub fn on_token_update(&mut self, update: TokenUpdate) {
+        match &update {
+            TokenUpdate::Added(token) => info!("token {} ({}) added", token.symbol, token.mint),
+            TokenUpdate::Removed(mint) => info!("token {} removed", mint),
+        }
+        self.rebuild_rings();
+    }
+
+    pub fn mark_ready(&mut self) {
+        self.state.is_ready = true;
+    }
+
+    pub fn ring_count(&self) -> usize {
+        self.trading_mint_rings.values().map(|r| r.len()).sum()
+    }
+
+    pub fn dirty_ring_count(&self) -> usize {
+        self.state.dirty_rings.len()
+    }
+
+    /// 环中的代币是否都还在交易
+    fn is_ring_active(&self, ring: &Ring) -> bool {
+        ring.edges.iter().all(|e| {
+            self.token_cache.contains(&e.input_mint) && self.token_cache.contains(&e.output_mint)
+        })
     }
 
     pub fn do_dirty_ring(&mut self, edge: Arc<Edge>) {
@@ -196,13 +333,14 @@ impl RingExecutor {
         }
     }
 
-    async fn refresh_some(&mut self) {
+    pub(crate) async fn refresh_some(&mut self) {
         if self.state.dirty_rings.is_empty() || !self.state.is_ready {
             return;
         }
 
         let started_at = Instant::now();
         let dirty_rings_len = self.state.dirty_rings.len();
+        metrics::RING_DIRTY_QUEUE.set(dirty_rings_len as i64);
         debug!(
             "ring executor refresh_some doing dirty rings, count: {}",
             dirty_rings_len
@@ -215,22 +353,60 @@ impl RingExecutor {
 
         // 将 DashMap 转换为 Vec 以避免长时间持有锁
         // 使用 take 而不是 clear，保留处理期间新增的 dirty rings
-        let rings: Vec<(String, Arc<Ring>)> = {
+        let mut rings: Vec<(String, Arc<Ring>)> = {
             let mut temp = DashMap::new();
             std::mem::swap(&mut temp, &mut self.state.dirty_rings);
             temp.into_iter().collect()
         };
 
+        // 整轮使用同一份参数，控制接口的修改在下一轮生效
+        let control = self.control.snapshot();
+        rings.retain(|(_, ring)| control.allows_ring(ring));
+        self.scheduler.set_expected_gain(control.params.expected_gain);
+        let dirty_rings_len = rings.len();
+
+        // 价值最高的环先计算，超时只会丢弃优先级最低的环
+        let hot_mints = self.hot_mints.read().unwrap().get();
+        let ordered = self.scheduler.order(rings, &hot_mints);
+        let tiers = ordered.iter().map(|r| r.2).collect_vec();
+        let rings = ordered
+            .into_iter()
+            .map(|(ring_id, ring, _)| (ring_id, ring))
+            .collect_vec();
+        let max_free_balances: HashMap<Pubkey, Option<u64>> = self
+            .trading_mints
+            .iter()
+            .map(|mint| {
+                let max_free = self
+                    .wallet_pool
+                    .as_ref()
+                    .and_then(|pool| pool.max_free_balance(mint));
+                (*mint, max_free)
+            })
+            .collect();
+
         for chunk in rings.chunks(BATCH_SIZE) {
             //let chunk = chunk.to_vec(); // 克隆当前批次的 rings
             let results = futures::future::join_all(chunk.to_vec().into_iter().map(|(ring_id, ring)| {
                 let chain_data = self.chain_data.clone();
-                let in_amounts = self.in_amounts.clone();
-                let expected_gain = self.expected_gain;
+                let (in_amounts, expected_gain) = control.params.amounts_for(&ring.trading_mint);
+                // 只计算钱包买得起的数量
+                let max_free = max_free_balances.get(&ring.trading_mint).copied().flatten();
+                let in_amounts = in_amounts
+                    .iter()
+                    .copied()
+                    .filter(|amount| max_free.map_or(true, |max_free| *amount <= max_free))
+                    .collect_vec();
+                if in_amounts.is_empty() {
+                    metrics::RING_SKIPPED_NO_INVENTORY
+                        .with_label_values(&[&ring.trading_mint.to_string()])
+                        .inc();
+                }
 
                 tokio::spawn(async move {
                     let mut snapshot = HashMap::new();
-                    let mut has_at_least_one_non_zero = false;
+                    // 库存不足跳过的环不算无效
+                    let mut has_at_least_one_non_zero = in_amounts.is_empty();
                     let mut best_route = None;
 
                     for &in_amount in &in_amounts {
@@ -255,8 +431,26 @@ impl RingExecutor {
             }))
             .await;
 
+            for tier in &tiers[processed_count..processed_count + chunk.len()] {
+                metrics::RING_REFRESH_EVALUATED
+                    .with_label_values(&[tier.as_str()])
+                    .inc();
+            }
+
+            let evaluated_at = millis_since_epoch();
             for result in results {
                 if let Ok((ring_id, ring, has_non_zero, best_route)) = result {
+                    if let Ok(mut state) = ring.ring_state.write() {
+                        state.last_evaluated = Some(evaluated_at);
+                        // 没有收益时清零，避免一次性的收益长期占用高优先级
+                        if best_route.is_none() {
+                            state.current_gain = 0;
+                        }
+                    }
+                    // 计算期间代币被移除的环不再发送
+                    if !self.is_ring_active(&ring) {
+                        continue;
+                    }
                     if let Some((route_steps, out_amount, context_slot, gain)) = best_route {
                         // 更新 ring state
                         if let Ok(mut state) = ring.ring_state.write() {
@@ -264,7 +458,11 @@ impl RingExecutor {
                         }
 
                         // 创建并发送路由
+                        metrics::RINGS_PROFITABLE.inc();
                         let route = Arc::new(Route {
+                            id: Route::next_id(),
+                            created_at: Instant::now(),
+                            ring_id: Some(ring_id.clone()),
                             input_mint: ring.trading_mint.clone(),
                             output_mint: ring.trading_mint.clone(),
                             in_amount: route_steps.first().map_or(0, |step| step.in_amount),
@@ -273,8 +471,16 @@ impl RingExecutor {
                             steps: route_steps,
                             slot: context_slot,
                             accounts: Default::default(),
+                            ring_state: Some(ring.ring_state.clone()),
                         });
 
+                        {
+                            let mut hot_mints = self.hot_mints.write().unwrap();
+                            for edge in ring.edges.iter() {
+                                hot_mints.add(edge.output_mint);
+                            }
+                        }
+
                         if let Err(e) = self.route_sender.send(route).await {
                             error!("Failed to send route for ring {}: {}", ring_id, e);
                         }
@@ -298,6 +504,19 @@ impl RingExecutor {
             }
         }
 
+        for tier in &tiers[processed_count..] {
+            metrics::RING_REFRESH_DROPPED
+                .with_label_values(&[tier.as_str()])
+                .inc();
+        }
+        metrics::RING_REFRESH_RINGS
+            .with_label_values(&["evaluated"])
+            .observe(processed_count as f64);
+        metrics::RING_REFRESH_RINGS
+            .with_label_values(&["timed_out"])
+            .observe((dirty_rings_len - processed_count) as f64);
+        metrics::RING_REFRESH_TIMING.observe(started_at.elapsed().as_secs_f64());
+
         let invalid_rings_len = invalid_rings.len();
 
         // 处理无效的 rings
@@ -332,6 +551,11 @@ pub fn spawn_ring_executor_job(
     edges: Vec<Arc<Edge>>,
     edge_price_updates: async_channel::Receiver<Arc<Edge>>,
     route_sender: async_channel::Sender<Arc<Route>>,
+    mut token_updates: broadcast::Receiver<TokenUpdate>,
+    new_edges: async_channel::Receiver<Vec<Arc<Edge>>>,
+    hot_mints: Arc<RwLock<HotMintsCache>>,
+    control: Arc<RuntimeControl>,
+    wallet_pool: Option<Arc<WalletPool>>,
     mut exit: broadcast::Receiver<()>,
 ) -> JoinHandle<()> {
     // Initialize the RingExecutor with the provided configuration and data
@@ -344,6 +568,9 @@ pub fn spawn_ring_executor_job(
         path_warming_amounts,
         edges.clone(),
         route_sender,
+        hot_mints,
+        control,
+        wallet_pool,
     );
 
     // // 获取初始化超时时间，默认为 5 分钟
@@ -358,7 +585,7 @@ pub fn spawn_ring_executor_job(
     //         break;
     //     }
     // }
-    ring_executor.state.is_ready = true;
+    ring_executor.mark_ready();
 
     // 生成 Tokio 任务
     let listener_job = tokio_spawn("ring_executor", async move {
@@ -395,9 +622,26 @@ pub fn spawn_ring_executor_job(
                     }
 
                 },
+                Ok(edges) = new_edges.recv() => {
+                    ring_executor.add_edges(edges);
+                },
+                // 代币增删时重建环
+                update = token_updates.recv() => {
+                    match update {
+                        Ok(update) => ring_executor.on_token_update(update),
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            warn!("ring executor lagged {} token updates, rebuilding rings", n);
+                            ring_executor.rebuild_rings();
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            error!("token update channel closed");
+                            break;
+                        }
+                    }
+                },
                 // 处理刷新间隔事件
                 _ = refresh_one_interval.tick() => {
-
+                    ring_executor.sync_trading_mints();
                     ring_executor.refresh_some().await;
                 }
             }
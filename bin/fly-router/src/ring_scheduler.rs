@@ -0,0 +1,235 @@
+use router_config_lib::RingPriorityConfig;
+
+use crate::prelude::*;
+use crate::ring::Ring;
+
+/// Priority tier of a dirty ring, from its score relative to the sum of the weights
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PriorityTier {
+    High,
+    Medium,
+    Low,
+}
+
+impl PriorityTier {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PriorityTier::High => "high",
+            PriorityTier::Medium => "medium",
+            PriorityTier::Low => "low",
+        }
+    }
+
+    fn from_normalized_score(score: f64) -> Self {
+        if score >= 0.5 {
+            PriorityTier::High
+        } else if score >= 0.25 {
+            PriorityTier::Medium
+        } else {
+            PriorityTier::Low
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RingPriority {
+    pub score: f64,
+    pub tier: PriorityTier,
+}
+
+/// Orders dirty rings so that the refresh timeout cuts off the least valuable ones.
+///
+/// Every criterion is scored in [0, 1]:
+/// - hot mints: share of the ring's intermediate mints that are hot
+/// - gain: last gain relative to the expected gain
+/// - liquidity: worst price decay between the smallest and largest cached amount of an edge
+/// - staleness: time since the ring was last evaluated
+pub struct RingScheduler {
+    hot_mint_weight: f64,
+    gain_weight: f64,
+    liquidity_weight: f64,
+    staleness_weight: f64,
+    staleness_half_life_ms: f64,
+    expected_gain: f64,
+}
+
+impl RingScheduler {
+    pub fn new(config: &RingPriorityConfig, expected_gain: u64) -> Self {
+        Self {
+            hot_mint_weight: config.hot_mint_weight.unwrap_or(1.0).max(0.0),
+            gain_weight: config.gain_weight.unwrap_or(2.0).max(0.0),
+            liquidity_weight: config.liquidity_weight.unwrap_or(1.0).max(0.0),
+            staleness_weight: config.staleness_weight.unwrap_or(1.0).max(0.0),
+            staleness_half_life_ms: config.staleness_half_life_ms.unwrap_or(1_000).max(1) as f64,
+            expected_gain: expected_gain.max(1) as f64,
+        }
+    }
+
+    pub fn set_expected_gain(&mut self, expected_gain: u64) {
+        self.expected_gain = expected_gain.max(1) as f64;
+    }
+
+    pub fn priority(&self, ring: &Ring, hot_mints: &HashSet<Pubkey>, now_ms: u64) -> RingPriority {
+        let (current_gain, last_evaluated) = {
+            let state = ring.ring_state.read().unwrap();
+            (state.current_gain, state.last_evaluated)
+        };
+
+        // 交易币种出现在每个环里，不参与热度计算
+        let intermediate_mints = ring
+            .edges
+            .iter()
+            .map(|e| e.output_mint)
+            .filter(|m| *m != ring.trading_mint)
+            .unique()
+            .collect_vec();
+        let hot = if intermediate_mints.is_empty() {
+            0.0
+        } else {
+            intermediate_mints
+                .iter()
+                .filter(|m| hot_mints.contains(m))
+                .count() as f64
+                / intermediate_mints.len() as f64
+        };
+
+        let gain = if current_gain > 0 {
+            let gain = current_gain as f64;
+            gain / (gain + self.expected_gain)
+        } else {
+            0.0
+        };
+
+        let liquidity = ring
+            .edges
+            .iter()
+            .map(|e| edge_liquidity(e))
+            .fold(1.0, f64::min);
+
+        let staleness = match last_evaluated {
+            None => 1.0,
+            Some(at) => {
+                let elapsed = now_ms.saturating_sub(at) as f64;
+                elapsed / (elapsed + self.staleness_half_life_ms)
+            }
+        };
+
+        let score = self.hot_mint_weight * hot
+            + self.gain_weight * gain
+            + self.liquidity_weight * liquidity
+            + self.staleness_weight * staleness;
+        let total_weight = self.hot_mint_weight
+            + self.gain_weight
+            + self.liquidity_weight
+            + self.staleness_weight;
+        let normalized = if total_weight > 0.0 {
+            score / total_weight
+        } else {
+            0.0
+        };
+
+        RingPriority {
+            score,
+            tier: PriorityTier::from_normalized_score(normalized),
+        }
+    }
+
+    /// Highest priority first
+    pub fn order(
+        &self,
+        rings: Vec<(String, Arc<Ring>)>,
+        hot_mints: &HashSet<Pubkey>,
+    ) -> Vec<(String, Arc<Ring>, PriorityTier)> {
+        let now_ms = millis_since_epoch();
+        rings
+            .into_iter()
+            .map(|(ring_id, ring)| {
+                let priority = self.priority(&ring, hot_mints, now_ms);
+                (ring_id, ring, priority)
+            })
+            .sorted_by(|a, b| b.2.score.total_cmp(&a.2.score))
+            .map(|(ring_id, ring, priority)| (ring_id, ring, priority.tier))
+            .collect()
+    }
+}
+
+/// Price at the largest cached amount relative to the smallest one, 1.0 for a pool without impact
+fn edge_liquidity(edge: &Edge) -> f64 {
+    let state = edge.state.read().unwrap();
+    match (state.cached_prices.first(), state.cached_prices.last()) {
+        (Some(first), Some(last)) if first.1 > 0.0 => (last.1 / first.1).clamp(0.0, 1.0),
+        // 没有缓存价格时取中间值
+        _ => 0.5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::test::mock_edge;
+
+    fn edge(
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        cached_prices: Vec<(u64, f64, f64)>,
+    ) -> Arc<Edge> {
+        let edge = mock_edge(Pubkey::new_unique(), input_mint, output_mint, 1.0);
+        edge.state.write().unwrap().cached_prices = cached_prices;
+        Arc::new(edge)
+    }
+
+    fn ring(trading_mint: Pubkey, other: Pubkey, deep: bool) -> Arc<Ring> {
+        let prices = if deep {
+            vec![(100, 1.0, 0.0), (1000, 0.99, 0.0)]
+        } else {
+            vec![(100, 1.0, 0.0), (1000, 0.5, 0.0)]
+        };
+        Arc::new(Ring::new(
+            trading_mint,
+            vec![
+                edge(trading_mint, other, prices.clone()),
+                edge(other, trading_mint, prices),
+            ],
+            HashSet::new(),
+        ))
+    }
+
+    #[test]
+    fn should_evaluate_hot_and_profitable_rings_first() {
+        let scheduler = RingScheduler::new(&RingPriorityConfig::default(), 1_000_000);
+        let sol = Pubkey::new_unique();
+        let hot = Pubkey::new_unique();
+        let cold = Pubkey::new_unique();
+        let hot_mints = HashSet::from([sol, hot]);
+
+        let cold_ring = ring(sol, cold, false);
+        let hot_ring = ring(sol, hot, true);
+        let profitable_ring = ring(sol, cold, true);
+        profitable_ring.ring_state.write().unwrap().current_gain = 10_000_000;
+
+        let ordered = scheduler.order(
+            vec![
+                ("cold".to_string(), cold_ring),
+                ("hot".to_string(), hot_ring),
+                ("profitable".to_string(), profitable_ring),
+            ],
+            &hot_mints,
+        );
+        let ids = ordered.iter().map(|r| r.0.as_str()).collect_vec();
+        assert_eq!(ids, vec!["profitable", "hot", "cold"]);
+        assert_eq!(ordered[0].2, PriorityTier::High);
+    }
+
+    #[test]
+    fn should_raise_priority_of_stale_rings() {
+        let scheduler = RingScheduler::new(&RingPriorityConfig::default(), 1_000_000);
+        let sol = Pubkey::new_unique();
+        let ring = ring(sol, Pubkey::new_unique(), true);
+        let now = millis_since_epoch();
+
+        ring.ring_state.write().unwrap().last_evaluated = Some(now);
+        let fresh = scheduler.priority(&ring, &HashSet::new(), now);
+        let later = scheduler.priority(&ring, &HashSet::new(), now + 5_000);
+        assert!(later.score > fresh.score);
+    }
+}
@@ -1,10 +1,13 @@
 use crate::edge::Edge;
+use crate::ring::RingState;
 use mango_feeds_connector::chain_data::AccountData;
 use solana_program::pubkey::Pubkey;
 use std::collections::HashMap;
 use std::fmt::{Debug, Display};
 use std::ops::{Deref, DerefMut, Index, IndexMut};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
 use std::vec::IntoIter;
 use tracing::log::trace;
 
@@ -21,6 +24,11 @@ pub struct RouteStep {
 
 // no clone
 pub struct Route {
+    /// unique per process, links the journal entries of a route
+    pub id: u64,
+    /// ring this route comes from
+    pub ring_id: Option<String>,
+    pub created_at: Instant,
     pub input_mint: Pubkey,
     pub output_mint: Pubkey,
     pub in_amount: u64,
@@ -30,6 +38,8 @@ pub struct Route {
     pub steps: Vec<RouteStep>,
     pub slot: u64,
     pub accounts: Option<HashMap<Pubkey, AccountData>>,
+    /// state of the ring this route comes from, used to cool it down when it cannot be sent
+    pub ring_state: Option<Arc<RwLock<RingState>>>,
 }
 
 #[derive(Clone)]
@@ -186,6 +196,11 @@ impl Display for EdgeIndex {
 }
 
 impl Route {
+    pub fn next_id() -> u64 {
+        static NEXT_ROUTE_ID: AtomicU64 = AtomicU64::new(1);
+        NEXT_ROUTE_ID.fetch_add(1, Ordering::Relaxed)
+    }
+
     pub fn display_steps(&self) -> String {
         let mut result = String::new();
         for step in &self.steps {
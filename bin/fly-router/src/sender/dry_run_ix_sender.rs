@@ -0,0 +1,319 @@
+use super::ix_sender::IxSender;
+use super::jito_ix_sender::{JitoIxSender, COMPUTE_UNIT_LIMIT_MARGIN};
+use super::simulator::TxSimulator;
+use crate::{
+    journal::{self, JournalEvent},
+    metrics,
+    routing_types::Route,
+    server::{alt_provider::AltProvider, hash_provider::HashProvider},
+    swap::Swap,
+};
+use anchor_spl::associated_token::get_associated_token_address;
+use anyhow::Context;
+use axum::async_trait;
+use router_config_lib::{DryRunConfig, SimulationConfig};
+use serde::Serialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, signature::Signature, transaction::VersionedTransaction,
+};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+pub use tracing::{debug, error, info, warn};
+
+const DEFAULT_LEDGER_PATH: &str = "dry_run_ledger.jsonl";
+/// pending bundles whose send was skipped (simulation drop, preflight...) are forgotten past this
+const MAX_PENDING: usize = 1_000;
+
+/// One bundle that would have been sent
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DryRunEntry {
+    pub timestamp_ms: u64,
+    /// slot of the quotes the route was built from
+    pub slot: u64,
+    pub signature: String,
+    pub input_mint: String,
+    pub in_amount: u64,
+    pub out_amount: u64,
+    pub expected_profit: i128,
+    pub tip: u64,
+    pub compute_unit_limit: u32,
+    pub route: Vec<String>,
+    pub simulated: bool,
+    pub simulation_error: Option<String>,
+    pub units_consumed: Option<u64>,
+    /// change of the source token account in the simulated swap transaction; the tip is
+    /// included for SOL rings only, the other rings pay it from the SOL reserve
+    pub net_gain: Option<i64>,
+    /// on-chain estimate: the simulation succeeded and did not lose money, None when not simulated
+    pub would_succeed: Option<bool>,
+}
+
+impl DryRunEntry {
+    fn from_route(route: &Route, signature: String, tip: u64, compute_unit_limit: u32) -> Self {
+        Self {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            slot: route.slot,
+            signature,
+            input_mint: route.input_mint.to_string(),
+            in_amount: route.in_amount,
+            out_amount: route.out_amount,
+            expected_profit: route.out_amount as i128 - route.in_amount as i128,
+            tip,
+            compute_unit_limit,
+            route: route
+                .steps
+                .iter()
+                .map(|s| {
+                    format!(
+                        "{} {} -> {} {} -> {}",
+                        s.edge.dex.name(),
+                        s.edge.input_mint_symbol,
+                        s.edge.output_mint_symbol,
+                        s.in_amount,
+                        s.out_amount
+                    )
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Appends entries to the JSONL ledger, flushed after every line so that it can be tailed
+pub struct DryRunLedger {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl DryRunLedger {
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let path = shellexpand::tilde(path).to_string();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("opening dry run ledger {}", path))?;
+        info!("dry run: bundles are logged to {} instead of being sent", path);
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    pub fn append(&self, entry: &DryRunEntry) -> anyhow::Result<()> {
+        let line = serde_json::to_string(entry)?;
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(writer, "{}", line)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+struct PendingBundle {
+    entry: DryRunEntry,
+    source_ata: Pubkey,
+}
+
+/// Paper trading: builds and signs the bundle exactly like `JitoIxSender`,
+/// then simulates the swap transaction and writes it to the ledger instead of sending it
+pub struct DryRunIxSender<
+    THashProvider: HashProvider + Send + Sync + 'static,
+    TAltProvider: AltProvider + Send + Sync + 'static,
+> {
+    inner: JitoIxSender<THashProvider, TAltProvider>,
+    simulator: Option<TxSimulator>,
+    ledger: DryRunLedger,
+    // 构建时的路由信息，按第一笔交易签名在发送时取回
+    pending: Mutex<HashMap<Signature, PendingBundle>>,
+}
+
+impl<
+        THashProvider: HashProvider + Send + Sync + 'static,
+        TAltProvider: AltProvider + Send + Sync + 'static,
+    > DryRunIxSender<THashProvider, TAltProvider>
+{
+    pub fn new(
+        inner: JitoIxSender<THashProvider, TAltProvider>,
+        config: &DryRunConfig,
+        rpc_url: String,
+    ) -> anyhow::Result<Self> {
+        let ledger = DryRunLedger::open(
+            config
+                .ledger_path
+                .as_deref()
+                .unwrap_or(DEFAULT_LEDGER_PATH),
+        )?;
+        let simulator = config.simulate.unwrap_or(true).then(|| {
+            TxSimulator::new(
+                RpcClient::new_with_commitment(rpc_url, CommitmentConfig::processed()),
+                &SimulationConfig {
+                    enabled: true,
+                    sample_rate: Some(1.0),
+                    min_net_gain: None,
+                },
+            )
+        });
+
+        Ok(Self {
+            inner,
+            simulator,
+            ledger,
+            pending: Default::default(),
+        })
+    }
+}
+
+fn first_signature(transactions: &HashMap<String, Vec<VersionedTransaction>>) -> Option<Signature> {
+    transactions
+        .values()
+        .filter_map(|txs| txs.first())
+        .filter_map(|tx| tx.signatures.first())
+        .next()
+        .copied()
+}
+
+#[async_trait]
+impl<
+        THashProvider: HashProvider + Send + Sync + 'static,
+        TAltProvider: AltProvider + Send + Sync + 'static,
+    > IxSender for DryRunIxSender<THashProvider, TAltProvider>
+{
+    async fn instructuin_extend(
+        &self,
+        swap: Arc<Swap>,
+        route: Arc<Route>,
+    ) -> anyhow::Result<HashMap<String, Vec<VersionedTransaction>>> {
+        let compute_unit_limit = swap.cu_estimate + COMPUTE_UNIT_LIMIT_MARGIN;
+        let transactions = self.inner.instructuin_extend(swap, route.clone()).await?;
+        self.inner.forget_tip_accounts();
+
+        if let Some(signature) = first_signature(&transactions) {
+            let tip = self.inner.route_tip(&route, compute_unit_limit)?;
+            let entry =
+                DryRunEntry::from_route(&route, signature.to_string(), tip, compute_unit_limit);
+            let source_ata = get_associated_token_address(&self.inner.wallet(), &route.input_mint);
+
+            let mut pending = self.pending.lock().unwrap();
+            if pending.len() >= MAX_PENDING {
+                pending.clear();
+            }
+            pending.insert(signature, PendingBundle { entry, source_ata });
+        }
+
+        Ok(transactions)
+    }
+
+    async fn send_tx(
+        &self,
+        transactions: HashMap<String, Vec<VersionedTransaction>>,
+    ) -> anyhow::Result<()> {
+        let Some(signature) = first_signature(&transactions) else {
+            return Ok(());
+        };
+        let Some(PendingBundle {
+            mut entry,
+            source_ata,
+        }) = self.pending.lock().unwrap().remove(&signature)
+        else {
+            warn!("dry run: no route recorded for bundle {}", signature);
+            return Ok(());
+        };
+
+        // 在当前链上状态上模拟兑换交易，估计这笔交易是否会成功
+        let tx = transactions.values().find_map(|txs| txs.first());
+        if let (Some(simulator), Some(tx)) = (&self.simulator, tx) {
+            entry.simulated = true;
+            match simulator.simulate(tx, &source_ata).await {
+                Ok(result) => {
+                    entry.units_consumed = result.units_consumed;
+                    entry.net_gain = result.net_gain;
+                    entry.would_succeed = Some(result.net_gain.map_or(true, |gain| gain >= 0));
+                }
+                Err(e) => {
+                    entry.simulation_error = Some(e.to_string());
+                    entry.would_succeed = Some(false);
+                }
+            }
+        }
+
+        info!(
+            "dry run: bundle {} profit {} tip {} would_succeed {:?}",
+            entry.signature, entry.expected_profit, entry.tip, entry.would_succeed
+        );
+        let result = if entry.would_succeed == Some(false) {
+            "error"
+        } else {
+            "ok"
+        };
+        metrics::BUNDLES_SENT
+            .with_label_values(&["dry_run", result])
+            .inc();
+        journal::record(JournalEvent::BundleResult {
+            signature: entry.signature.clone(),
+            region: "dry_run".to_string(),
+            bundle_id: None,
+            error: match entry.would_succeed {
+                Some(false) => Some(
+                    entry
+                        .simulation_error
+                        .clone()
+                        .unwrap_or_else(|| "simulated loss".to_string()),
+                ),
+                _ => None,
+            },
+        });
+        self.ledger.append(&entry)?;
+        Ok(())
+    }
+
+    fn is_dry_run(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_append_entries_to_ledger() {
+        let dir = std::env::temp_dir().join(format!("dry-run-ledger-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ledger.jsonl").to_string_lossy().to_string();
+
+        let ledger = DryRunLedger::open(&path).unwrap();
+        for profit in [10, -5] {
+            ledger
+                .append(&DryRunEntry {
+                    signature: "sig".to_string(),
+                    expected_profit: profit,
+                    tip: 3,
+                    would_succeed: Some(profit > 0),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        drop(ledger);
+
+        let lines = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["expected_profit"], 10);
+        assert_eq!(lines[0]["would_succeed"], true);
+        assert_eq!(lines[1]["would_succeed"], false);
+        assert!(lines[1]["units_consumed"].is_null());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
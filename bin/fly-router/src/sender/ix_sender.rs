@@ -1,21 +1,30 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use axum::async_trait;
 // Import Serialize and Deserialize macros from serde.
 use serde::{Serialize, Deserialize};
 
-use crate::{routing_types::Route, server::{alt_provider::AltProvider, client_provider::ClientProvider, hash_provider::HashProvider}, swap::Swap}; // Import Swap if it exists in another module or define it below.
+use crate::{alt::alt_manager::AltManager, control::RuntimeControl, key_source::WalletSigner, pnl_tracker::PnlHandle, routing_types::Route, server::{alt_provider::AltProvider, client_provider::ClientProvider, hash_provider::HashProvider}, swap::Swap}; // Import Swap if it exists in another module or define it below.
 use std::str::FromStr; // Import FromStr trait for parsing strings.
-use solana_sdk::{transaction::VersionedTransaction, signer::keypair::Keypair};
+use solana_sdk::signature::Keypair;
+use solana_sdk::transaction::VersionedTransaction;
+use solana_client::nonblocking::rpc_client::RpcClient;
 
+use super::dry_run_ix_sender::DryRunIxSender;
 use super::jito_ix_sender::JitoIxSender;
+use super::tip_pricer::TipPricer;
+use router_config_lib::DryRunConfig;
 
+/// Keep `router_config_lib::SEND_MODES` in sync
 #[derive(Clone, Copy, Hash, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum SendMode {
     #[default]
     JitoBundle = 0,
+    /// 构建并签名，但只模拟和记录，不发送
+    DryRun = 1,
 }
 
 #[derive(Debug)]
@@ -25,10 +34,10 @@ impl FromStr for SendMode {
     type Err = ParseSendModeError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s == "JitoBundle" {
-            Ok(Self::JitoBundle)
-        } else {
-            Err(ParseSendModeError)
+        match s {
+            "JitoBundle" => Ok(Self::JitoBundle),
+            "DryRun" => Ok(Self::DryRun),
+            _ => Err(ParseSendModeError),
         }
     }
 }
@@ -37,6 +46,7 @@ impl ToString for SendMode {
     fn to_string(&self) -> String {
         match &self {
             SendMode::JitoBundle => "JitoBundle".to_string(),
+            SendMode::DryRun => "DryRun".to_string(),
         }
     }
 }
@@ -45,19 +55,38 @@ impl ToString for SendMode {
 pub trait IxSender {
     async fn instructuin_extend(&self, swap: Arc<Swap>,route:Arc<Route>) -> anyhow::Result<HashMap<String, Vec<VersionedTransaction>>>;
     async fn send_tx(&self, transactions: HashMap<String, Vec<VersionedTransaction>>) -> anyhow::Result<()>;
+    /// 回收创建至少 `min_age` 的临时小费账户中剩余的资金，返回回收的账户数；
+    /// 回收失败的账户留到下一次
+    async fn sweep_tip_accounts(&self, _rpc: &RpcClient, _min_age: Duration) -> anyhow::Result<usize> {
+        Ok(0)
+    }
+    /// 关闭时仍未回收的临时小费钱包，保存后在下次启动时回收
+    fn take_tip_accounts(&self) -> Vec<Keypair> {
+        vec![]
+    }
+    fn restore_tip_accounts(&self, _keypairs: Vec<Keypair>) {}
+    /// 只模拟不发送，`send_tx` 成功的 bundle 也不会落地
+    fn is_dry_run(&self) -> bool {
+        false
+    }
 }
 
 pub fn generate_ix_sender<THashProvider, TAltProvider>(mode: SendMode,
     name: String,
-    keypair: Keypair,
+    signer: WalletSigner,
     alt_provider: Arc<TAltProvider>,
     compute_unit_price_micro_lamports: u64,
-    jito_tip_bps: f32,
-    jito_max_tip: u64,
-    jito_regions: Vec<String>,
+    control: Arc<RuntimeControl>,
     region_send_type: String,
     hash_provider: Arc<THashProvider>,
-    client_provider: Arc<ClientProvider>) -> anyhow::Result<Arc<Box<dyn IxSender + Send + Sync + 'static>>> 
+    max_blockhash_age: Duration,
+    min_blockhash_blocks_left: u64,
+    alt_manager: Option<Arc<AltManager>>,
+    client_provider: Arc<ClientProvider>,
+    dry_run_config: DryRunConfig,
+    rpc_url: String,
+    tip_pricer: TipPricer,
+    pnl: Option<PnlHandle>) -> anyhow::Result<Arc<Box<dyn IxSender + Send + Sync + 'static>>> 
     where 
     THashProvider: HashProvider + Send + Sync + 'static,
     TAltProvider: AltProvider + Send + Sync + 'static
@@ -66,16 +95,40 @@ pub fn generate_ix_sender<THashProvider, TAltProvider>(mode: SendMode,
         SendMode::JitoBundle => {
             let sender = JitoIxSender::new(
                 name,
-                keypair,
+                signer.clone(),
+                alt_provider,
+                compute_unit_price_micro_lamports,
+                control.clone(),
+                region_send_type,
+                hash_provider,
+                max_blockhash_age,
+                min_blockhash_blocks_left,
+                alt_manager,
+                client_provider,
+                tip_pricer,
+                pnl,
+            );
+            Ok(Arc::new(Box::new(sender)))
+        }
+        SendMode::DryRun => {
+            // 与 JitoBundle 完全相同的构建和签名
+            let jito_sender = JitoIxSender::new(
+                name,
+                signer,
                 alt_provider,
                 compute_unit_price_micro_lamports,
-                jito_tip_bps,
-                jito_max_tip,
-                jito_regions,
+                control.clone(),
                 region_send_type,
                 hash_provider,
+                max_blockhash_age,
+                min_blockhash_blocks_left,
+                alt_manager,
                 client_provider,
+                tip_pricer,
+                // 不发送，也就没有 block engine 的结果
+                None,
             );
+            let sender = DryRunIxSender::new(jito_sender, &dry_run_config, rpc_url)?;
             Ok(Arc::new(Box::new(sender)))
         }
     }
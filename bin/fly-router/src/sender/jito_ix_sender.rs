@@ -1,5 +1,12 @@
 use super::ix_sender::IxSender;
+use super::tip_pricer::TipPricer;
 use crate::{
+    alt::{alt_manager::AltManager, alt_optimizer},
+    control::{RuntimeControl, RuntimeParams},
+    journal::{self, JournalEvent},
+    key_source::{sign_data, sign_message_async, WalletSigner},
+    metrics,
+    pnl_tracker::{AcceptedBundle, PnlHandle},
     routing_types::Route,
     server::{
         alt_provider::{self, AltProvider},
@@ -7,6 +14,7 @@ use crate::{
         hash_provider::HashProvider,
     },
     swap::Swap,
+    tx_preflight::check_transaction,
 };
 use anchor_lang::prelude::AccountMeta;
 use anchor_spl::associated_token::get_associated_token_address;
@@ -19,21 +27,23 @@ use axum::async_trait;
 use base64::{engine::general_purpose, Engine as _};
 use chrono::{DateTime, Local};
 use clap::error;
+use itertools::Itertools;
 use once_cell::sync::Lazy;
 use rand::Rng;
 use serde_json::{json, Value};
+use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_program::{instruction::Instruction, pubkey::Pubkey};
 use solana_sdk::{
     address_lookup_table::AddressLookupTableAccount,
     compute_budget::ComputeBudgetInstruction,
     message::VersionedMessage,
-    signature::{Keypair, Signer},
+    signature::{Keypair, Signature, Signer},
     system_instruction,
     transaction::{Transaction, VersionedTransaction},
 };
 use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     str::FromStr,
     sync::{
         atomic::{AtomicUsize, Ordering},
@@ -63,24 +73,44 @@ static JITO_TIP_ACCOUNTS: Lazy<[Pubkey; 8]> = Lazy::new(|| {
     ]
 });
 //const JITO_MAX_TIP: u64 = 10_000_000;
+/// added on top of the swap's CU estimate for the bundle's extra instructions
+pub const COMPUTE_UNIT_LIMIT_MARGIN: u32 = 20000;
+/// rent of the temporary WSOL account
+const TOKEN_ACCOUNT_RENT: u64 = 2039280;
+/// base fee of the swap transaction, signed by the wallet only
+const SWAP_TX_FEE: u64 = 5000;
+/// fee of the tip transaction, paid by the temporary wallet
+const TIP_TX_FEE: u64 = 5000;
+/// two accounts per tip wallet, get_multiple_accounts takes at most 100
+const TIP_ACCOUNTS_PER_REQUEST: usize = 50;
+/// tip wallets restored from the previous run are swept right away
+const RESTORED_TIP_ACCOUNT_AGE: Duration = Duration::from_secs(3600);
 
 pub struct JitoIxSender<
     THashProvider: HashProvider + Send + Sync + 'static,
     TAltProvider: AltProvider + Send + Sync + 'static,
 > {
     name: String,
-    keypair: Keypair,
+    // 只通过 Signer 签名，远程签名时私钥不在本进程中
+    signer: WalletSigner,
     public_key: Pubkey,
     source_ata: Pubkey,
     alt_provider: Arc<TAltProvider>,
     compute_unit_price_micro_lamports: u64,
-    jito_tip_bps: f32,
-    jito_max_tip: u64,
-    jito_regions: Vec<String>,
+    // 小费参数和区域可通过控制接口或重新加载配置在运行时修改
+    control: Arc<RuntimeControl>,
     region_send_type: String,
-    jito_urls: Vec<String>,
     hash_provider: Arc<THashProvider>,
+    max_blockhash_age: Duration,
+    min_blockhash_blocks_left: u64,
+    alt_manager: Option<Arc<AltManager>>,
     client_provider: Arc<ClientProvider>,
+    // 非 SOL 交易代币的收益按价格折算成小费
+    tip_pricer: TipPricer,
+    // 最近的临时小费钱包，tx1 单独落地时资金会留在其中
+    tip_accounts: Mutex<VecDeque<(Instant, Keypair)>>,
+    // block engine 接受的 bundle 交给收益跟踪查询状态
+    pnl: Option<PnlHandle>,
     //send_counter: RwLock<SendCounter>,
 }
 #[async_trait]
@@ -98,15 +128,11 @@ impl<
 
         let mut ixs1 = vec![];
 
-        //let new_profit = route.out_amount - route.in_amount;
-        let profit = route
-            .out_amount
-            .checked_sub(route.in_amount)
-            .ok_or_else(|| anyhow::anyhow!("Profit calculation overflow"))?;
-
-        let compute_unit_limit = swap.cu_estimate + 20000;
+        let compute_unit_limit = swap.cu_estimate + COMPUTE_UNIT_LIMIT_MARGIN;
 
-        let jito_tip = self.calculate_tip(profit, compute_unit_limit);
+        let jito_tip = self.route_tip(&route, compute_unit_limit)?;
+        // SOL 环的小费来自 WSOL 收益，其他代币的环从钱包的 SOL 储备支付
+        let tip_from_reserve = route.input_mint != *NATIVE_MINT;
 
         let compute_budget_ixs = vec![
             ComputeBudgetInstruction::set_compute_unit_price(
@@ -156,38 +182,61 @@ impl<
         let destination_ata =
             get_associated_token_address(&NATIVE_MINT, &destination_keypair.pubkey());
 
-        // 1.7 创建ATA指令
-        ixs1.push(create_associated_token_account_idempotent(
-            &self.keypair.pubkey(),
-            &destination_ata,
-            &destination_keypair.pubkey(),
-            &NATIVE_MINT,
-        ));
+        if tip_from_reserve {
+            // 1.7 小费、租金和手续费直接从SOL储备转入
+            ixs1.push(system_instruction::transfer(
+                &self.public_key,
+                &destination_keypair.pubkey(),
+                jito_tip + TOKEN_ACCOUNT_RENT + TIP_TX_FEE,
+            ));
+        } else {
+            // 1.7 创建ATA指令
+            ixs1.push(create_associated_token_account_idempotent(
+                &self.public_key,
+                &destination_ata,
+                &destination_keypair.pubkey(),
+                &NATIVE_MINT,
+            ));
+
+            // 1.8 转移WSOL指令
+            ixs1.push(transfer(
+                &TOKEN_PROGRAM_ID,
+                &self.source_ata,
+                &destination_ata,
+                &self.public_key,
+                &[&self.public_key],
+                jito_tip + 10000,
+            )?);
 
-        // 1.8 转移WSOL指令
-        ixs1.push(transfer(
-            &TOKEN_PROGRAM_ID,
-            &self.source_ata,
-            &destination_ata,
-            &self.keypair.pubkey(),
-            &[&self.keypair.pubkey()],
-            jito_tip + 10000,
-        )?);
-
-        // 1.9 转移SOL指令
-        ixs1.push(system_instruction::transfer(
-            &self.keypair.pubkey(),
-            &destination_keypair.pubkey(),
-            2039280 + 5000,
-        ));
+            // 1.9 转移SOL指令
+            ixs1.push(system_instruction::transfer(
+                &self.public_key,
+                &destination_keypair.pubkey(),
+                TOKEN_ACCOUNT_RENT + TIP_TX_FEE,
+            ));
+        }
 
         // 1.10 获取所有alt
-        let alt_accounts: Vec<AddressLookupTableAccount> = alt_provider::load_all_alts(
+        let mut alt_accounts: Vec<AddressLookupTableAccount> = alt_provider::load_all_alts(
             swap.address_lookup_table_addresses.clone(),
             self.alt_provider.clone(),
         )
         .await;
 
+        // 1.11 未被alt覆盖的账户，记录使用并从自管理的alt中补充
+        if let Some(alt_manager) = &self.alt_manager {
+            let uncovered = swap
+                .accounts()
+                .into_iter()
+                .filter(|a| !alt_accounts.iter().any(|alt| alt.addresses.contains(a)))
+                .collect::<Vec<_>>();
+            alt_manager.record_usage(&uncovered);
+            alt_accounts.extend(alt_optimizer::get_best_alt(
+                &alt_manager.tables(),
+                &uncovered,
+            )?);
+        }
+
         // 构建第二个交易的指令
         let mut ixs2 = vec![];
 
@@ -195,13 +244,15 @@ impl<
         ixs2.push(ComputeBudgetInstruction::set_compute_unit_limit(5000));
 
         // 2.2 关闭WSOL账户
-        ixs2.push(close_account(
-            &TOKEN_PROGRAM_ID,
-            &destination_ata,
-            &destination_keypair.pubkey(),
-            &destination_keypair.pubkey(),
-            &[],
-        )?);
+        if !tip_from_reserve {
+            ixs2.push(close_account(
+                &TOKEN_PROGRAM_ID,
+                &destination_ata,
+                &destination_keypair.pubkey(),
+                &destination_keypair.pubkey(),
+                &[],
+            )?);
+        }
 
         // 2.3 发送小费
         let random_index = rand::thread_rng().gen_range(0, JITO_TIP_ACCOUNTS.len());
@@ -211,15 +262,20 @@ impl<
             jito_tip,
         ));
 
-        // 2.4 返还SOL
+        // 2.4 返还SOL，只留下手续费
+        let refund = if tip_from_reserve {
+            TOKEN_ACCOUNT_RENT
+        } else {
+            TOKEN_ACCOUNT_RENT * 2 + 10000
+        };
         ixs2.push(system_instruction::transfer(
             &destination_keypair.pubkey(),
-            &self.keypair.pubkey(),
-            2039280 * 2 + 10000,
+            &self.public_key,
+            refund,
         ));
 
         // 获取区块hash
-        let recent_blockhash = self.hash_provider.get_latest_hash().await?;
+        let recent_blockhash = self.get_fresh_hash().await?;
 
         let tx2_v0_message = solana_sdk::message::v0::Message::try_compile(
             &destination_keypair.pubkey(),
@@ -229,6 +285,8 @@ impl<
         )?;
         let tx2_message = VersionedMessage::V0(tx2_v0_message);
         let tx2 = VersionedTransaction::try_new(tx2_message, &[&destination_keypair])?;
+        // 小费交易只有固定的几条指令，检查一下以防以后加指令时超限
+        check_transaction(&tx2)?;
         //tx2.sign(&[&destination_keypair], recent_blockhash);
 
         // 根据发送类型构建交易
@@ -247,7 +305,8 @@ impl<
                 recent_blockhash,
             )?;
             let tx1_message = VersionedMessage::V0(tx1_v0_message);
-            let tx1 = VersionedTransaction::try_new(tx1_message, &[&self.keypair])?;
+            let tx1 = sign_message_async(&self.signer, tx1_message).await?;
+            check_transaction(&tx1)?;
             //tx1.partial_sign(&[&self.keypair], recent_blockhash);
 
             //let mut tx1 = Transaction::new_with_payer(&ixs1, Some(&self.public_key));
@@ -255,7 +314,7 @@ impl<
             //let mut tx2 = Transaction::new_with_payer(&ixs2, Some(&destination_keypair.pubkey()));
             transactions.insert("serial".to_string(), vec![tx1, tx2]);
         } else {
-            for url in self.jito_urls.iter() {
+            for url in self.jito_urls() {
                 let mut ixs1_copy = ixs1.clone();
                 let tx2_copy = tx2.clone();
                 let name = if !self.name.is_empty() {
@@ -272,12 +331,25 @@ impl<
                     recent_blockhash,
                 )?;
                 let tx1_message = VersionedMessage::V0(tx1_v0_message);
-                let tx1 = VersionedTransaction::try_new(tx1_message, &[&self.keypair])?;
+                let tx1 = sign_message_async(&self.signer, tx1_message).await?;
+                check_transaction(&tx1)?;
 
                 transactions.insert(url.clone(), vec![tx1, tx2_copy]);
             }
         }
 
+        let input_mint_tip = self
+            .tip_pricer
+            .from_lamports(&route.input_mint, jito_tip)
+            .ok();
+        journal::record_bundles(
+            &route,
+            &transactions,
+            jito_tip,
+            input_mint_tip,
+            compute_unit_limit,
+        );
+        self.keep_tip_account(destination_keypair);
         Ok(transactions)
     }
 
@@ -285,8 +357,77 @@ impl<
         &self,
         transactions: HashMap<String, Vec<VersionedTransaction>>,
     ) -> anyhow::Result<()> {
-        let _ = self.send_transaction_to_jito(transactions).await;
-        Ok(())
+        // 请求在后台发出，这里的错误表示 bundle 没有交给 block engine
+        self.send_transaction_to_jito(transactions).await
+    }
+
+    async fn sweep_tip_accounts(&self, rpc: &RpcClient, min_age: Duration) -> anyhow::Result<usize> {
+        let due = {
+            let mut tip_accounts = self.tip_accounts.lock().unwrap();
+            let (due, keep) = std::mem::take(&mut *tip_accounts)
+                .into_iter()
+                .partition::<VecDeque<_>, _>(|(at, _)| at.elapsed() >= min_age);
+            *tip_accounts = keep;
+            due
+        };
+        let mut due = Vec::from(due);
+        let mut closed = 0;
+        // 失败的账户放回队列，下次再回收，不会丢弃
+        let mut failed = vec![];
+        let mut result = Ok(());
+        while !due.is_empty() {
+            let chunk = due
+                .drain(..due.len().min(TIP_ACCOUNTS_PER_REQUEST))
+                .collect_vec();
+            let addresses = chunk
+                .iter()
+                .flat_map(|(_, k)| {
+                    [
+                        k.pubkey(),
+                        get_associated_token_address(&k.pubkey(), &NATIVE_MINT),
+                    ]
+                })
+                .collect_vec();
+            let accounts = match rpc.get_multiple_accounts(&addresses).await {
+                Ok(accounts) => accounts,
+                Err(e) => {
+                    failed.extend(chunk);
+                    failed.append(&mut due);
+                    result = Err(e.into());
+                    break;
+                }
+            };
+            for ((at, keypair), accounts) in chunk.into_iter().zip(accounts.chunks(2)) {
+                match self.close_tip_account(rpc, &keypair, &accounts[0], &accounts[1]).await {
+                    Ok(true) => closed += 1,
+                    Ok(false) => {}
+                    Err(e) => {
+                        warn!("failed to close tip account {}: {:?}", keypair.pubkey(), e);
+                        failed.push((at, keypair));
+                    }
+                }
+            }
+        }
+        self.tip_accounts.lock().unwrap().extend(failed);
+        result.map(|()| closed)
+    }
+
+    fn take_tip_accounts(&self) -> Vec<Keypair> {
+        std::mem::take(&mut *self.tip_accounts.lock().unwrap())
+            .into_iter()
+            .map(|(_, keypair)| keypair)
+            .collect()
+    }
+
+    fn restore_tip_accounts(&self, keypairs: Vec<Keypair>) {
+        // 上次运行留下的账户，下一次回收时就处理
+        let at = Instant::now()
+            .checked_sub(RESTORED_TIP_ACCOUNT_AGE)
+            .unwrap_or_else(Instant::now);
+        self.tip_accounts
+            .lock()
+            .unwrap()
+            .extend(keypairs.into_iter().map(|keypair| (at, keypair)));
     }
 }
 
@@ -295,44 +436,98 @@ impl<
         TAltProvider: AltProvider + Send + Sync + 'static,
     > JitoIxSender<THashProvider, TAltProvider>
 {
+    pub fn wallet(&self) -> Pubkey {
+        self.public_key
+    }
+
+    /// Kept until swept, see `sweep_tip_accounts`
+    fn keep_tip_account(&self, keypair: Keypair) {
+        self.tip_accounts
+            .lock()
+            .unwrap()
+            .push_back((Instant::now(), keypair));
+    }
+
+    /// The dry run never funds the temporary tip wallets
+    pub(crate) fn forget_tip_accounts(&self) {
+        self.tip_accounts.lock().unwrap().clear();
+    }
+
+    /// Sends what is left on a temporary tip wallet and its WSOL account back to the wallet
+    async fn close_tip_account(
+        &self,
+        rpc: &RpcClient,
+        keypair: &Keypair,
+        account: &Option<solana_sdk::account::Account>,
+        wsol_account: &Option<solana_sdk::account::Account>,
+    ) -> anyhow::Result<bool> {
+        let mut ixs = vec![];
+        if wsol_account.is_some() {
+            ixs.push(close_account(
+                &TOKEN_PROGRAM_ID,
+                &get_associated_token_address(&keypair.pubkey(), &NATIVE_MINT),
+                &self.public_key,
+                &keypair.pubkey(),
+                &[],
+            )?);
+        }
+        if let Some(account) = account.as_ref().filter(|a| a.lamports > 0) {
+            ixs.push(system_instruction::transfer(
+                &keypair.pubkey(),
+                &self.public_key,
+                account.lamports,
+            ));
+        }
+        if ixs.is_empty() {
+            return Ok(false);
+        }
+
+        // 钱包支付手续费，临时钱包的余额可以全部转回
+        let blockhash = rpc.get_latest_blockhash().await?;
+        let mut tx = Transaction::new_with_payer(&ixs, Some(&self.public_key));
+        tx.try_partial_sign(&[keypair], blockhash)?;
+        // 钱包是付费方，第一个签名
+        tx.signatures[0] = sign_data(&self.signer, tx.message_data()).await?;
+        rpc.send_and_confirm_transaction(&tx).await?;
+        info!("closed tip account {}", keypair.pubkey());
+        Ok(true)
+    }
+
     pub fn new(
         name: String,
-        keypair: Keypair,
+        signer: WalletSigner,
         alt_provider: Arc<TAltProvider>,
         compute_unit_price_micro_lamports: u64,
-        jito_tip_bps: f32,
-        jito_max_tip: u64,
-        jito_regions: Vec<String>,
+        control: Arc<RuntimeControl>,
         region_send_type: String,
         hash_provider: Arc<THashProvider>,
+        max_blockhash_age: Duration,
+        min_blockhash_blocks_left: u64,
+        alt_manager: Option<Arc<AltManager>>,
         client_provider: Arc<ClientProvider>,
+        tip_pricer: TipPricer,
+        pnl: Option<PnlHandle>,
     ) -> Self {
-        let source_ata = get_associated_token_address(&keypair.pubkey(), &NATIVE_MINT);
-        let public_key = keypair.pubkey();
-        let jito_urls = jito_regions
-            .iter()
-            .map(|region| {
-                format!(
-                    "https://{}.mainnet.block-engine.jito.wtf/api/v1/bundles",
-                    region
-                )
-            })
-            .collect::<Vec<_>>();
+        let public_key = signer.pubkey();
+        let source_ata = get_associated_token_address(&public_key, &NATIVE_MINT);
 
         Self {
             name,
-            keypair,
+            signer,
             public_key,
             source_ata,
             alt_provider,
             compute_unit_price_micro_lamports,
-            jito_tip_bps,
-            jito_max_tip,
-            jito_regions,
+            control,
             region_send_type,
-            jito_urls,
             hash_provider,
+            max_blockhash_age,
+            min_blockhash_blocks_left,
+            alt_manager,
             client_provider,
+            tip_pricer,
+            tip_accounts: Default::default(),
+            pnl,
             //send_counter: RwLock::new(SendCounter::new(keypair.pubkey().to_string(), 10, jito_urls.clone())),
         }
     }
@@ -389,7 +584,8 @@ impl<
                         ]
                     });
 
-                    let jito_url = &self.jito_urls[self.get_next_jito_url_index()];
+                    let jito_urls = self.jito_urls();
+                    let jito_url = &jito_urls[self.get_next_jito_url_index(jito_urls.len())];
 
                     // 异步发送请求
                     //let send_counter = self.send_counter.clone();
@@ -397,6 +593,8 @@ impl<
                     let clinet_index = self.client_provider.get_next_clinet_index();
                     let client = self.client_provider.get_next_client_by_index(clinet_index);
                     let url = jito_url.clone();
+                    let signature = first_signature(txs);
+                    let pnl = self.pnl.clone();
                     tokio::spawn(async move {
                         match client.post(&url).json(&body).send().await {
                             Ok(response) => {
@@ -420,8 +618,10 @@ impl<
                                     .unwrap();
                                 info!("Response status: {} || Bundle sent with UUID: {} || sender time {}", 
                                         status,bundle_uuid,send_start.elapsed().as_millis());
+                                record_bundle_result(pnl.as_ref(), signature, url, Ok(bundle_uuid.to_string()));
                             }
                             Err(e) => {
+                                record_bundle_result(pnl.as_ref(), signature, url.clone(), Err(e.to_string()));
                                 // let mut send_counter = self.send_counter.write().unwrap();
                                 // let duration = send_start.elapsed();
                                 // send_counter.send_error(e, duration.as_nanos() as u64, &url).await;
@@ -452,75 +652,78 @@ impl<
                 }
             }
             "parallel" => {
-                for url in &self.jito_urls {
-                    if let Some(txs) = transactions.get(url) {
-                        let body = json!({
-                            "id": 1,
-                            "jsonrpc": "2.0",
-                            "method": "sendBundle",
-                            "params": [
-                                txs.iter()
-                                    .map(|tx| general_purpose::STANDARD.encode(bincode::serialize(&tx).unwrap()))
-                                    .collect::<Vec<_>>(),
-                                {"encoding": "base64"}
-                            ]
-                        });
-
-                        let clinet_index = self.client_provider.get_next_clinet_index();
-                        let client = self.client_provider.get_next_client_by_index(clinet_index);
-
-                        let url = url.clone();
-                        tokio::spawn(async move {
-                            match client.post(&url).json(&body).send().await {
-                                Ok(response) => {
-                                    // let duration = send_start.elapsed();
-                                    // let mut send_counter = self.send_counter.write().unwrap();
-                                    // send_counter.send_success(duration.as_nanos() as u64, &url).await;
-                                    let status = response.status();
-                                    let body = response.json::<Value>().await.unwrap();
-                                    let bundle_uuid = body["result"]
-                                        .as_str()
-                                        .ok_or_else(|| {
+                // 按构建时的区域发送，期间区域可能已经改变
+                for (url, txs) in transactions.iter() {
+                    let body = json!({
+                        "id": 1,
+                        "jsonrpc": "2.0",
+                        "method": "sendBundle",
+                        "params": [
+                            txs.iter()
+                                .map(|tx| general_purpose::STANDARD.encode(bincode::serialize(&tx).unwrap()))
+                                .collect::<Vec<_>>(),
+                            {"encoding": "base64"}
+                        ]
+                    });
+
+                    let clinet_index = self.client_provider.get_next_clinet_index();
+                    let client = self.client_provider.get_next_client_by_index(clinet_index);
+
+                    let url = url.clone();
+                    let signature = first_signature(txs);
+                    let pnl = self.pnl.clone();
+                    tokio::spawn(async move {
+                        match client.post(&url).json(&body).send().await {
+                            Ok(response) => {
+                                // let duration = send_start.elapsed();
+                                // let mut send_counter = self.send_counter.write().unwrap();
+                                // send_counter.send_success(duration.as_nanos() as u64, &url).await;
+                                let status = response.status();
+                                let body = response.json::<Value>().await.unwrap();
+                                let bundle_uuid = body["result"]
+                                    .as_str()
+                                    .ok_or_else(|| {
+                                        error!(
+                                            "Failed to get bundle UUID from response: {}",
+                                            body
+                                        );
+                                        anyhow!("Failed to get bundle UUID from response")
+                                    })
+                                    .unwrap();
+                                info!("Response status: {} || Bundle sent with UUID: {} || sender time {}", 
+                                    status,bundle_uuid,send_start.elapsed().as_millis());
+                                record_bundle_result(pnl.as_ref(), signature, url, Ok(bundle_uuid.to_string()));
+                            }
+                            Err(e) => {
+                                record_bundle_result(pnl.as_ref(), signature, url.clone(), Err(e.to_string()));
+                                // let duration = send_start.elapsed();
+                                // let mut send_counter = self.send_counter.write().unwrap();
+                                // send_counter.send_error(e, duration.as_nanos() as u64, &url).await;
+
+                                if let Some(status) = e.status() {
+                                    match status.as_u16() {
+                                        429 => {
+                                            // 处理429错误
                                             error!(
-                                                "Failed to get bundle UUID from response: {}",
-                                                body
+                                                "Received 429 Too Many Requests from Jito: {}",
+                                                e
                                             );
-                                            anyhow!("Failed to get bundle UUID from response")
-                                        })
-                                        .unwrap();
-                                    info!("Response status: {} || Bundle sent with UUID: {} || sender time {}", 
-                                        status,bundle_uuid,send_start.elapsed().as_millis());
-                                }
-                                Err(e) => {
-                                    // let duration = send_start.elapsed();
-                                    // let mut send_counter = self.send_counter.write().unwrap();
-                                    // send_counter.send_error(e, duration.as_nanos() as u64, &url).await;
-
-                                    if let Some(status) = e.status() {
-                                        match status.as_u16() {
-                                            429 => {
-                                                // 处理429错误
-                                                error!(
-                                                    "Received 429 Too Many Requests from Jito: {}",
-                                                    e
-                                                );
-                                            }
-                                            400 => {
-                                                // 处理400错误
-                                                error!("Received 400 Bad Request from Jito: {}", e);
-                                            }
-                                            _ => {
-                                                // 处理其他错误
-                                                error!("Received {} from Jito: {}", status, e);
-                                            }
                                         }
-                                    } else {
-                                        error!("Error sending transaction to Jito: {}", e);
+                                        400 => {
+                                            // 处理400错误
+                                            error!("Received 400 Bad Request from Jito: {}", e);
+                                        }
+                                        _ => {
+                                            // 处理其他错误
+                                            error!("Received {} from Jito: {}", status, e);
+                                        }
                                     }
+                                } else {
+                                    error!("Error sending transaction to Jito: {}", e);
                                 }
                             }
-                        });
-                    }
+                        }
+                    });
                 }
             }
             _ => panic!("Invalid region send type"),
@@ -553,24 +756,61 @@ impl<
     //     })
     // }
 
-    fn get_next_jito_url_index(&self) -> usize {
+    // 获取区块hash，过旧或即将过期时拒绝发送
+    async fn get_fresh_hash(&self) -> anyhow::Result<solana_sdk::hash::Hash> {
+        let hash = self.hash_provider.get_latest_hash().await?;
+        if let Some(info) = self.hash_provider.latest_hash_info() {
+            if info.age() > self.max_blockhash_age {
+                anyhow::bail!(
+                    "blockhash {} is stale: {}ms old",
+                    info.hash,
+                    info.age().as_millis()
+                );
+            }
+            if info.blocks_left() < self.min_blockhash_blocks_left {
+                anyhow::bail!(
+                    "blockhash {} expires in {} blocks",
+                    info.hash,
+                    info.blocks_left()
+                );
+            }
+        }
+        Ok(hash)
+    }
+
+    fn get_next_jito_url_index(&self, len: usize) -> usize {
         static JITO_URL_INDEX: AtomicUsize = AtomicUsize::new(0);
         let current = JITO_URL_INDEX.fetch_add(1, Ordering::Relaxed);
-        current % self.jito_urls.len()
+        current % len
     }
 
-    fn calculate_tip(&self, profit: u64, compute_unit_limit: u32) -> u64 {
-        // JITO_TIP_BPS 是从配置中获取的常量
-        if self.jito_tip_bps == 0.0 {
-            // 默认使用 CU 的 4.5 倍作为基准
-            let cu_tip = (compute_unit_limit as f64 * 4.5) as u64;
-            let profit_tip = (profit as f64 * 0.65) as u64;
-            cu_tip.min(profit_tip)
-        } else {
-            // 使用配置的 TIP 比例
-            let compute_tip = (profit as f64 * self.jito_tip_bps as f64) as u64;
-            compute_tip.min(self.jito_max_tip)
-        }
+    fn jito_urls(&self) -> Vec<String> {
+        self.control
+            .snapshot()
+            .params
+            .jito_regions
+            .iter()
+            .map(|region| {
+                format!(
+                    "https://{}.mainnet.block-engine.jito.wtf/api/v1/bundles",
+                    region
+                )
+            })
+            .collect()
+    }
+
+    /// Tip in lamports, the profit of rings not starting in SOL is valued at the feed prices
+    pub(crate) fn route_tip(&self, route: &Route, compute_unit_limit: u32) -> anyhow::Result<u64> {
+        let profit = route
+            .out_amount
+            .checked_sub(route.in_amount)
+            .ok_or_else(|| anyhow::anyhow!("Profit calculation overflow"))?;
+        let profit = self.tip_pricer.to_lamports(&route.input_mint, profit)?;
+        Ok(calculate_tip(
+            &self.control.snapshot().params,
+            profit,
+            compute_unit_limit,
+        ))
     }
 
     fn create_memo_instruction(&self, memo: &str) -> Instruction {
@@ -665,3 +905,87 @@ impl<
 // fn date_format(format: &str, date: DateTime<Local>) -> String {
 //     date.format(format).to_string()
 // }
+
+fn first_signature(txs: &[VersionedTransaction]) -> String {
+    txs.first()
+        .and_then(|tx| tx.signatures.first())
+        .map(|s| s.to_string())
+        .unwrap_or_default()
+}
+
+/// Tip in lamports for a profit of `profit` lamports
+pub fn calculate_tip(params: &RuntimeParams, profit: u64, compute_unit_limit: u32) -> u64 {
+    if params.jito_tip_bps == 0.0 {
+        // 默认使用 CU 的 4.5 倍作为基准
+        let cu_tip = (compute_unit_limit as f64 * 4.5) as u64;
+        let profit_tip = (profit as f64 * 0.65) as u64;
+        cu_tip.min(profit_tip)
+    } else {
+        // 使用配置的 TIP 比例
+        let compute_tip = (profit as f64 * params.jito_tip_bps as f64) as u64;
+        compute_tip.min(params.jito_max_tip)
+    }
+}
+
+/// Transaction fees of a bundle in lamports, tip excluded: the swap transaction with its
+/// priority fee and the tip transaction. The rent of the temporary accounts is refunded.
+pub fn bundle_fees(compute_unit_limit: u32, compute_unit_price_micro_lamports: u64) -> u64 {
+    // 优先费向上取整到 lamport
+    let priority_fee =
+        (compute_unit_limit as u64 * compute_unit_price_micro_lamports + 999_999) / 1_000_000;
+    SWAP_TX_FEE + priority_fee + TIP_TX_FEE
+}
+
+// 记录 block engine 的返回，通过第一笔交易签名与 bundle 关联，并按区域计数
+fn record_bundle_result(
+    pnl: Option<&PnlHandle>,
+    signature: String,
+    region: String,
+    result: Result<String, String>,
+) {
+    metrics::BUNDLES_SENT
+        .with_label_values(&[&region, if result.is_ok() { "ok" } else { "error" }])
+        .inc();
+    if let (Some(pnl), Ok(bundle_id), Ok(first)) =
+        (pnl, &result, Signature::from_str(&signature))
+    {
+        pnl.record_accepted(AcceptedBundle {
+            signature: first,
+            url: region.clone(),
+            bundle_id: bundle_id.clone(),
+        });
+    }
+    let (bundle_id, error) = match result {
+        Ok(bundle_id) => (Some(bundle_id), None),
+        Err(e) => (None, Some(e)),
+    };
+    journal::record(JournalEvent::BundleResult {
+        signature,
+        region,
+        bundle_id,
+        error,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_price_tip_and_fees() {
+        let mut params = RuntimeParams::default();
+        params.jito_tip_bps = 0.5;
+        params.jito_max_tip = 1_000;
+        assert_eq!(calculate_tip(&params, 1_000, 200_000), 500);
+        assert_eq!(calculate_tip(&params, 10_000, 200_000), 1_000);
+
+        // 比例为 0 时取 CU 的 4.5 倍和 65% 收益中较小的
+        params.jito_tip_bps = 0.0;
+        assert_eq!(calculate_tip(&params, 10_000_000, 200_000), 900_000);
+        assert_eq!(calculate_tip(&params, 1_000_000, 200_000), 650_000);
+
+        // 200k CU 每 CU 10000 微 lamports 的优先费是 2000 lamports
+        assert_eq!(bundle_fees(200_000, 10_000), 12_000);
+        assert_eq!(bundle_fees(1, 1), 10_001);
+    }
+}
@@ -1,2 +1,5 @@
+pub mod dry_run_ix_sender;
 pub mod ix_sender;
-pub mod jito_ix_sender;
\ No newline at end of file
+pub mod jito_ix_sender;
+pub mod simulator;
+pub mod tip_pricer;
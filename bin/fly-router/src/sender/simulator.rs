@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use anchor_spl::token::spl_token;
+use rand::Rng;
+use router_config_lib::SimulationConfig;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig,
+};
+use solana_program::program_pack::Pack;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::transaction::VersionedTransaction;
+use thiserror::Error;
+
+use crate::routing_types::Route;
+
+/// margin added on top of the measured compute units
+const CU_MARGIN_PERCENT: u64 = 10;
+
+#[derive(Error, Debug)]
+pub enum SimulationError {
+    #[error("simulation failed: {0}")]
+    Failed(String),
+    #[error("net gain {0} below minimum {1}")]
+    NotProfitable(i64, i64),
+}
+
+#[derive(Debug, Default)]
+pub struct SimulationResult {
+    pub units_consumed: Option<u64>,
+    /// change of the source token account balance, tip transfer included
+    pub net_gain: Option<i64>,
+}
+
+/// Simulates the swap transaction of a bundle through RPC `simulateTransaction`
+/// and keeps the measured compute units per route
+pub struct TxSimulator {
+    rpc: RpcClient,
+    sample_rate: f64,
+    min_net_gain: i64,
+    cu_estimates: RwLock<HashMap<Vec<(Pubkey, Pubkey)>, u32>>,
+}
+
+impl TxSimulator {
+    pub fn new(rpc: RpcClient, config: &SimulationConfig) -> Self {
+        Self {
+            rpc,
+            sample_rate: config.sample_rate.unwrap_or(1.0).clamp(0.0, 1.0),
+            min_net_gain: config.min_net_gain.unwrap_or(0),
+            cu_estimates: Default::default(),
+        }
+    }
+
+    pub fn should_simulate(&self) -> bool {
+        rand::thread_rng().gen::<f64>() < self.sample_rate
+    }
+
+    pub async fn simulate(
+        &self,
+        tx: &VersionedTransaction,
+        source_ata: &Pubkey,
+    ) -> anyhow::Result<SimulationResult> {
+        let config = RpcSimulateTransactionConfig {
+            sig_verify: false,
+            replace_recent_blockhash: true,
+            commitment: Some(CommitmentConfig::processed()),
+            accounts: Some(RpcSimulateTransactionAccountsConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                addresses: vec![source_ata.to_string()],
+            }),
+            ..Default::default()
+        };
+
+        let (pre_balance, simulation) = tokio::try_join!(
+            self.rpc.get_token_account_balance(source_ata),
+            self.rpc.simulate_transaction_with_config(tx, config),
+        )?;
+        let simulation = simulation.value;
+
+        if let Some(err) = simulation.err {
+            let logs = simulation.logs.unwrap_or_default();
+            let tail = logs.iter().rev().take(3).rev().cloned().collect::<Vec<_>>();
+            return Err(SimulationError::Failed(format!("{:?} {:?}", err, tail)).into());
+        }
+
+        let pre_balance = pre_balance.amount.parse::<u64>()?;
+        let post_balance = simulation
+            .accounts
+            .and_then(|accounts| accounts.into_iter().next().flatten())
+            .and_then(|account| account.decode::<Account>())
+            .and_then(|account| spl_token::state::Account::unpack(&account.data).ok())
+            .map(|account| account.amount);
+
+        Ok(SimulationResult {
+            units_consumed: simulation.units_consumed,
+            net_gain: post_balance.map(|post| post as i64 - pre_balance as i64),
+        })
+    }
+
+    /// Drop transactions that lose more than allowed once the tip is paid
+    pub fn check(&self, result: &SimulationResult) -> Result<(), SimulationError> {
+        match result.net_gain {
+            Some(gain) if gain < self.min_net_gain => {
+                Err(SimulationError::NotProfitable(gain, self.min_net_gain))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn record_cu(&self, route: &Route, units_consumed: u64) {
+        let estimate = units_consumed * (100 + CU_MARGIN_PERCENT) / 100;
+        self.cu_estimates
+            .write()
+            .unwrap()
+            .insert(route_key(route), estimate.min(u32::MAX as u64) as u32);
+    }
+
+    /// Compute units measured the last time this route was simulated
+    pub fn cu_estimate(&self, route: &Route) -> Option<u32> {
+        self.cu_estimates
+            .read()
+            .unwrap()
+            .get(&route_key(route))
+            .copied()
+    }
+}
+
+fn route_key(route: &Route) -> Vec<(Pubkey, Pubkey)> {
+    route.steps.iter().map(|s| s.edge.unique_id()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edge::Edge;
+    use crate::mock::test::mock_edge;
+    use crate::routing_types::RouteStep;
+    use itertools::Itertools;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    fn simulator(sample_rate: Option<f64>, min_net_gain: Option<i64>) -> TxSimulator {
+        let config = SimulationConfig {
+            enabled: true,
+            sample_rate,
+            min_net_gain,
+        };
+        TxSimulator::new(RpcClient::new("http://localhost:8899".to_string()), &config)
+    }
+
+    fn route(edges: &[Arc<Edge>]) -> Route {
+        let steps = edges
+            .iter()
+            .map(|edge| RouteStep {
+                edge: edge.clone(),
+                in_amount: 100,
+                out_amount: 100,
+                fee_amount: 0,
+                fee_mint: Pubkey::default(),
+            })
+            .collect_vec();
+        Route {
+            id: Route::next_id(),
+            ring_id: None,
+            created_at: Instant::now(),
+            input_mint: edges[0].input_mint,
+            output_mint: edges[edges.len() - 1].output_mint,
+            in_amount: 100,
+            out_amount: 100,
+            price_impact_bps: 0,
+            steps,
+            slot: 0,
+            accounts: None,
+            ring_state: None,
+        }
+    }
+
+    fn edge() -> Arc<Edge> {
+        let [pool, input_mint, output_mint] = [(); 3].map(|_| Pubkey::new_unique());
+        Arc::new(mock_edge(pool, input_mint, output_mint, 1.0))
+    }
+
+    fn result(net_gain: Option<i64>) -> SimulationResult {
+        SimulationResult {
+            units_consumed: None,
+            net_gain,
+        }
+    }
+
+    #[test]
+    fn should_drop_results_below_the_min_net_gain() {
+        let simulator = simulator(None, Some(-100));
+        assert!(simulator.check(&result(Some(0))).is_ok());
+        assert!(simulator.check(&result(Some(-100))).is_ok());
+        assert!(matches!(
+            simulator.check(&result(Some(-101))),
+            Err(SimulationError::NotProfitable(-101, -100))
+        ));
+        // 没有读到余额时不丢弃
+        assert!(simulator.check(&result(None)).is_ok());
+    }
+
+    #[test]
+    fn should_clamp_the_sample_rate() {
+        let always = simulator(Some(2.0), None);
+        let never = simulator(Some(-1.0), None);
+        assert_eq!(always.sample_rate, 1.0);
+        assert_eq!(never.sample_rate, 0.0);
+        for _ in 0..100 {
+            assert!(always.should_simulate());
+            assert!(!never.should_simulate());
+        }
+        // 默认每笔都模拟
+        assert!(simulator(None, None).should_simulate());
+    }
+
+    #[test]
+    fn should_record_cu_per_route() {
+        let simulator = simulator(None, None);
+        let (a, b) = (edge(), edge());
+        let route_ab = route(&[a.clone(), b.clone()]);
+        assert_eq!(simulator.cu_estimate(&route_ab), None);
+
+        simulator.record_cu(&route_ab, 100_000);
+        assert_eq!(simulator.cu_estimate(&route_ab), Some(110_000));
+        // 同样的池子、另一个路由对象
+        assert_eq!(simulator.cu_estimate(&route(&[a.clone(), b.clone()])), Some(110_000));
+        assert_eq!(simulator.cu_estimate(&route(&[b, a])), None);
+    }
+}
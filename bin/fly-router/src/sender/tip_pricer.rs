@@ -0,0 +1,96 @@
+//! Values the profit of a ring in lamports, to size the tip of rings that do not start in SOL.
+
+use anchor_spl::token::spl_token::native_mint;
+use router_lib::price_feeds::price_cache::PriceCache;
+
+use crate::prelude::*;
+use crate::source::token_cache::TokenCache;
+
+#[derive(Clone)]
+pub struct TipPricer {
+    price_cache: PriceCache,
+    token_cache: Arc<TokenCache>,
+}
+
+impl TipPricer {
+    pub fn new(price_cache: PriceCache, token_cache: Arc<TokenCache>) -> Self {
+        Self {
+            price_cache,
+            token_cache,
+        }
+    }
+
+    /// `amount` native units of `mint` in lamports, at the USD prices of the price feed
+    pub fn to_lamports(&self, mint: &Pubkey, amount: u64) -> anyhow::Result<u64> {
+        if *mint == native_mint::ID {
+            return Ok(amount);
+        }
+        let decimals = self.token_cache.token(*mint)?.decimals;
+        let price = self
+            .price_cache
+            .price_ui(*mint)
+            .with_context(|| format!("no price for {}", mint))?;
+        let sol_price = self
+            .price_cache
+            .price_ui(native_mint::ID)
+            .context("no price for SOL")?;
+        convert_to_lamports(amount, decimals, price, sol_price)
+            .with_context(|| format!("invalid prices {} / {} for {}", price, sol_price, mint))
+    }
+
+    /// `lamports` in native units of `mint`, the inverse of `to_lamports`
+    pub fn from_lamports(&self, mint: &Pubkey, lamports: u64) -> anyhow::Result<u64> {
+        if *mint == native_mint::ID {
+            return Ok(lamports);
+        }
+        let decimals = self.token_cache.token(*mint)?.decimals;
+        let price = self
+            .price_cache
+            .price_ui(*mint)
+            .with_context(|| format!("no price for {}", mint))?;
+        let sol_price = self
+            .price_cache
+            .price_ui(native_mint::ID)
+            .context("no price for SOL")?;
+        convert(lamports, native_mint::DECIMALS, sol_price, decimals, price)
+            .with_context(|| format!("invalid prices {} / {} for {}", price, sol_price, mint))
+    }
+}
+
+fn convert_to_lamports(amount: u64, decimals: u8, price: f64, sol_price: f64) -> Option<u64> {
+    convert(amount, decimals, price, native_mint::DECIMALS, sol_price)
+}
+
+/// `amount` native units of a token priced `price` in native units of one priced `to_price`
+fn convert(amount: u64, decimals: u8, price: f64, to_decimals: u8, to_price: f64) -> Option<u64> {
+    if !(price.is_finite() && to_price.is_finite()) || price <= 0.0 || to_price <= 0.0 {
+        return None;
+    }
+    let ui_amount = amount as f64 / 10f64.powi(decimals as i32);
+    let converted = ui_amount * price / to_price * 10f64.powi(to_decimals as i32);
+    converted.is_finite().then_some(converted.round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_convert_profit_to_lamports() {
+        // 2.5 USDC at 1$ with SOL at 125$ is 0.02 SOL
+        assert_eq!(
+            convert_to_lamports(2_500_000, 6, 1.0, 125.0),
+            Some(20_000_000)
+        );
+        assert_eq!(convert_to_lamports(1, 0, 250.0, 125.0), Some(2_000_000_000));
+        assert_eq!(convert_to_lamports(1_000, 6, 0.0, 125.0), None);
+        assert_eq!(convert_to_lamports(1_000, 6, f64::NAN, 125.0), None);
+    }
+
+    #[test]
+    fn should_convert_lamports_back_to_the_token() {
+        // 0.02 SOL at 125$ is 2.5 USDC at 1$
+        assert_eq!(convert(20_000_000, 9, 125.0, 6, 1.0), Some(2_500_000));
+        assert_eq!(convert(20_000_000, 9, 125.0, 6, -1.0), None);
+    }
+}
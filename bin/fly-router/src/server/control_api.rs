@@ -0,0 +1,409 @@
+use std::collections::{BTreeMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::extract::{Query, Request, State};
+use axum::http::header;
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use router_config_lib::ControlApiConfig;
+use serde_derive::{Deserialize, Serialize};
+use solana_program::pubkey::Pubkey;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::control::{
+    ControlState, RouteFilter, RuntimeControl, RuntimeParams, TradingMintAmounts,
+};
+use crate::mint_manager::{MintChange, MintCommand, MintManagerHandle};
+use crate::ring::RingState;
+use crate::server::errors::AppError;
+use crate::util::tokio_spawn;
+
+const DEFAULT_CONTROL_ADDRESS: &str = "127.0.0.1:8899";
+const DEFAULT_RINGS_LIMIT: usize = 20;
+
+#[derive(Clone)]
+struct ApiState {
+    control: Arc<RuntimeControl>,
+    mints: Option<MintManagerHandle>,
+    token: Arc<String>,
+}
+
+/// Operator API, every request needs `Authorization: Bearer <token>`
+pub fn spawn_control_api(
+    config: &ControlApiConfig,
+    control: Arc<RuntimeControl>,
+    mints: Option<MintManagerHandle>,
+    mut exit: broadcast::Receiver<()>,
+) -> Option<JoinHandle<()>> {
+    if !config.enabled {
+        return None;
+    }
+    if config.token.is_empty() {
+        warn!("control api is enabled without a token, not starting it");
+        return None;
+    }
+    let address = config
+        .address
+        .clone()
+        .unwrap_or(DEFAULT_CONTROL_ADDRESS.to_string());
+    let state = ApiState {
+        control,
+        mints,
+        token: Arc::new(config.token.clone()),
+    };
+
+    let job = tokio_spawn("control_api", async move {
+        let listener = match TcpListener::bind(&address).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("failed to bind control api on {}: {}", address, e);
+                return;
+            }
+        };
+        info!("serving control api on http://{}/control", address);
+
+        let app = Router::new()
+            .route("/control/state", get(get_state))
+            .route("/control/pause", post(pause))
+            .route("/control/resume", post(resume))
+            .route("/control/params", post(update_params))
+            .route(
+                "/control/blacklist",
+                post(add_blacklist).delete(remove_blacklist),
+            )
+            .route(
+                "/control/whitelist",
+                post(add_whitelist).delete(remove_whitelist),
+            )
+            .route("/control/rings", get(list_rings))
+            .route("/control/mints", post(add_mint).delete(remove_mint))
+            .layer(middleware::from_fn_with_state(state.clone(), authorize))
+            .with_state(state);
+        let shutdown = async move {
+            let _ = exit.recv().await;
+        };
+        if let Err(e) = axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown)
+            .await
+        {
+            error!("control api failed: {}", e);
+        }
+    });
+    Some(job)
+}
+
+async fn authorize(
+    State(state): State<ApiState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map_or(false, |token| token == state.token.as_str());
+    if !authorized {
+        return Err(AppError::Unauthorized);
+    }
+    Ok(next.run(request).await)
+}
+
+#[derive(Serialize)]
+struct FilterView {
+    rings: Vec<String>,
+    pools: Vec<String>,
+    mints: Vec<String>,
+}
+
+impl From<&RouteFilter> for FilterView {
+    fn from(filter: &RouteFilter) -> Self {
+        let mut view = FilterView {
+            rings: filter.rings.iter().cloned().collect(),
+            pools: filter.pools.iter().map(|p| p.to_string()).collect(),
+            mints: filter.mints.iter().map(|m| m.to_string()).collect(),
+        };
+        view.rings.sort();
+        view.pools.sort();
+        view.mints.sort();
+        view
+    }
+}
+
+#[derive(Serialize)]
+struct StateView {
+    paused: bool,
+    params: RuntimeParams,
+    blacklist: FilterView,
+    whitelist: FilterView,
+}
+
+fn state_view(control: &RuntimeControl, state: &ControlState) -> Json<StateView> {
+    Json(StateView {
+        paused: control.is_paused(),
+        params: state.params.clone(),
+        blacklist: (&state.blacklist).into(),
+        whitelist: (&state.whitelist).into(),
+    })
+}
+
+async fn get_state(State(state): State<ApiState>) -> Json<StateView> {
+    state_view(&state.control, &state.control.snapshot())
+}
+
+async fn pause(State(state): State<ApiState>) -> Json<StateView> {
+    state.control.set_paused(true);
+    get_state(State(state)).await
+}
+
+async fn resume(State(state): State<ApiState>) -> Json<StateView> {
+    state.control.set_paused(false);
+    get_state(State(state)).await
+}
+
+/// Only the given fields are changed
+#[derive(Deserialize)]
+struct ParamsUpdate {
+    expected_gain: Option<u64>,
+    in_amounts: Option<Vec<u64>>,
+    jito_tip_bps: Option<f32>,
+    jito_max_tip: Option<u64>,
+    jito_regions: Option<Vec<String>>,
+    slippage_bps: Option<i32>,
+    ring_cooldown_secs: Option<u64>,
+    /// replaces every per mint entry
+    trading_mint_amounts: Option<BTreeMap<String, TradingMintAmounts>>,
+}
+
+async fn update_params(
+    State(state): State<ApiState>,
+    Json(update): Json<ParamsUpdate>,
+) -> Result<Json<StateView>, AppError> {
+    let new_state = state
+        .control
+        .update(|s| {
+            let params = &mut s.params;
+            if let Some(expected_gain) = update.expected_gain {
+                params.expected_gain = expected_gain;
+            }
+            if let Some(in_amounts) = update.in_amounts {
+                params.in_amounts = in_amounts;
+            }
+            if let Some(jito_tip_bps) = update.jito_tip_bps {
+                params.jito_tip_bps = jito_tip_bps;
+            }
+            if let Some(jito_max_tip) = update.jito_max_tip {
+                params.jito_max_tip = jito_max_tip;
+            }
+            if let Some(jito_regions) = update.jito_regions {
+                params.jito_regions = jito_regions;
+            }
+            if let Some(slippage_bps) = update.slippage_bps {
+                params.slippage_bps = slippage_bps;
+            }
+            if let Some(ring_cooldown_secs) = update.ring_cooldown_secs {
+                params.ring_cooldown_secs = ring_cooldown_secs;
+            }
+            if let Some(trading_mint_amounts) = update.trading_mint_amounts {
+                params.trading_mint_amounts = trading_mint_amounts;
+            }
+            Ok(())
+        })
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    info!(params = ?new_state.params, "runtime params updated");
+    Ok(state_view(&state.control, &new_state))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FilterRequest {
+    rings: Vec<String>,
+    pools: Vec<String>,
+    mints: Vec<String>,
+}
+
+impl FilterRequest {
+    fn parse(self) -> Result<RouteFilter, AppError> {
+        let parse_keys = |keys: Vec<String>| {
+            keys.iter()
+                .map(|k| {
+                    Pubkey::from_str(k)
+                        .map_err(|_| AppError::BadRequest(format!("invalid pubkey {}", k)))
+                })
+                .collect::<Result<HashSet<_>, _>>()
+        };
+        Ok(RouteFilter {
+            rings: self.rings.into_iter().collect(),
+            pools: parse_keys(self.pools)?,
+            mints: parse_keys(self.mints)?,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum FilterList {
+    Blacklist,
+    Whitelist,
+}
+
+fn change_filter(
+    state: &ApiState,
+    list: FilterList,
+    request: FilterRequest,
+    add: bool,
+) -> Result<Json<StateView>, AppError> {
+    let change = request.parse()?;
+    let new_state = state.control.update(|s| {
+        let filter = match list {
+            FilterList::Blacklist => &mut s.blacklist,
+            FilterList::Whitelist => &mut s.whitelist,
+        };
+        if add {
+            filter.rings.extend(change.rings);
+            filter.pools.extend(change.pools);
+            filter.mints.extend(change.mints);
+        } else {
+            filter.rings.retain(|r| !change.rings.contains(r));
+            filter.pools.retain(|p| !change.pools.contains(p));
+            filter.mints.retain(|m| !change.mints.contains(m));
+        }
+        Ok(())
+    })
+    .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    Ok(state_view(&state.control, &new_state))
+}
+
+async fn add_blacklist(
+    State(state): State<ApiState>,
+    Json(request): Json<FilterRequest>,
+) -> Result<Json<StateView>, AppError> {
+    change_filter(&state, FilterList::Blacklist, request, true)
+}
+
+async fn remove_blacklist(
+    State(state): State<ApiState>,
+    Json(request): Json<FilterRequest>,
+) -> Result<Json<StateView>, AppError> {
+    change_filter(&state, FilterList::Blacklist, request, false)
+}
+
+async fn add_whitelist(
+    State(state): State<ApiState>,
+    Json(request): Json<FilterRequest>,
+) -> Result<Json<StateView>, AppError> {
+    change_filter(&state, FilterList::Whitelist, request, true)
+}
+
+async fn remove_whitelist(
+    State(state): State<ApiState>,
+    Json(request): Json<FilterRequest>,
+) -> Result<Json<StateView>, AppError> {
+    change_filter(&state, FilterList::Whitelist, request, false)
+}
+
+#[derive(Deserialize)]
+struct MintRequest {
+    mint: String,
+    symbol: Option<String>,
+}
+
+async fn change_mint(
+    state: &ApiState,
+    command: MintCommand,
+) -> Result<Json<MintChange>, AppError> {
+    let Some(mints) = &state.mints else {
+        return Err(AppError::BadRequest(
+            "mint manager is not running".to_string(),
+        ));
+    };
+    let change = mints
+        .execute(command)
+        .await
+        .map_err(|e| AppError::BadRequest(format!("{:#}", e)))?;
+    Ok(Json(change))
+}
+
+fn parse_mint(mint: &str) -> Result<Pubkey, AppError> {
+    Pubkey::from_str(mint).map_err(|_| AppError::BadRequest(format!("invalid pubkey {}", mint)))
+}
+
+/// Starts trading the mint, its pools are looked up by the dex adapters first
+async fn add_mint(
+    State(state): State<ApiState>,
+    Json(request): Json<MintRequest>,
+) -> Result<Json<MintChange>, AppError> {
+    let mint = parse_mint(&request.mint)?;
+    change_mint(
+        &state,
+        MintCommand::Add {
+            mint,
+            symbol: request.symbol,
+        },
+    )
+    .await
+}
+
+async fn remove_mint(
+    State(state): State<ApiState>,
+    Json(request): Json<MintRequest>,
+) -> Result<Json<MintChange>, AppError> {
+    let mint = parse_mint(&request.mint)?;
+    change_mint(&state, MintCommand::Remove { mint }).await
+}
+
+#[derive(Deserialize)]
+struct RingsQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct RingView {
+    ring_id: String,
+    trading_mint: String,
+    mints: Vec<String>,
+    pools: Vec<String>,
+    allowed: bool,
+    state: RingState,
+}
+
+/// Rings of the last rebuild, best current gain first
+async fn list_rings(
+    State(state): State<ApiState>,
+    Query(query): Query<RingsQuery>,
+) -> Json<Vec<RingView>> {
+    let snapshot = state.control.snapshot();
+    let mut rings = state
+        .control
+        .rings()
+        .into_iter()
+        .map(|ring| {
+            let ring_state = ring.ring_state.read().unwrap().clone();
+            (ring, ring_state)
+        })
+        .collect::<Vec<_>>();
+    rings.sort_by(|a, b| b.1.current_gain.cmp(&a.1.current_gain));
+
+    let views = rings
+        .into_iter()
+        .take(query.limit.unwrap_or(DEFAULT_RINGS_LIMIT))
+        .map(|(ring, ring_state)| RingView {
+            ring_id: ring.ring_id.clone(),
+            trading_mint: ring.trading_mint.to_string(),
+            mints: ring
+                .edges
+                .iter()
+                .map(|e| e.input_mint.to_string())
+                .collect(),
+            pools: ring.edges.iter().map(|e| e.key().to_string()).collect(),
+            allowed: snapshot.allows_ring(&ring),
+            state: ring_state,
+        })
+        .collect();
+    Json(views)
+}
@@ -7,6 +7,8 @@ use serde_derive::Serialize;
 
 pub enum AppError {
     Anyhow(anyhow::Error),
+    BadRequest(String),
+    Unauthorized,
 }
 
 struct AppJson<T>(T);
@@ -28,12 +30,14 @@ impl IntoResponse for AppError {
             message: String,
         }
 
-        let anyhow_message = match self {
-            AppError::Anyhow(err) => err.to_string(),
+        let (status, anyhow_message) = match self {
+            AppError::Anyhow(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+            AppError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            AppError::Unauthorized => (StatusCode::UNAUTHORIZED, "unauthorized".to_string()),
         };
 
         (
-            StatusCode::INTERNAL_SERVER_ERROR,
+            status,
             AppJson(ErrorResponse {
                 message: anyhow_message,
             }),
@@ -1,12 +1,52 @@
 use async_trait::async_trait;
+use router_lib::dex::AccountProviderView;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_program::hash::Hash;
-use std::sync::RwLock;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::util::tokio_spawn;
 
 #[async_trait]
 pub trait HashProvider {
     async fn get_latest_hash(&self) -> anyhow::Result<Hash>;
+
+    /// Metadata of the hash returned by `get_latest_hash`, for providers that track it
+    fn latest_hash_info(&self) -> Option<BlockhashInfo> {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BlockhashInfo {
+    pub hash: Hash,
+    pub last_valid_block_height: u64,
+    /// block height when the hash was fetched
+    pub block_height: u64,
+    /// slot seen on the stream when the hash was fetched
+    pub fetched_slot: u64,
+    /// newest slot seen on the stream
+    pub current_slot: u64,
+    pub fetched_at: Instant,
+}
+
+impl BlockhashInfo {
+    pub fn age(&self) -> Duration {
+        self.fetched_at.elapsed()
+    }
+
+    /// Blocks left before the hash expires; slots elapsed since the fetch are counted
+    /// as blocks, which is conservative since skipped slots do not produce a block
+    pub fn blocks_left(&self) -> u64 {
+        let elapsed = self.current_slot.saturating_sub(self.fetched_slot);
+        self.last_valid_block_height
+            .saturating_sub(self.block_height)
+            .saturating_sub(elapsed)
+    }
 }
 
 pub struct RpcHashProvider {
@@ -32,3 +72,125 @@ impl HashProvider for RpcHashProvider {
         Ok(hash)
     }
 }
+
+/// Refreshes the blockhash in the background whenever the account stream moves to a new slot,
+/// so `get_latest_hash` never does an RPC round-trip
+pub struct StreamHashProvider {
+    latest: RwLock<Option<BlockhashInfo>>,
+}
+
+impl StreamHashProvider {
+    pub fn spawn(
+        rpc_client: RpcClient,
+        chain_data: AccountProviderView,
+        max_refresh_interval: Duration,
+        mut exit: broadcast::Receiver<()>,
+    ) -> (Arc<Self>, JoinHandle<()>) {
+        let provider = Arc::new(Self {
+            latest: RwLock::new(None),
+        });
+
+        let p = provider.clone();
+        let job = tokio_spawn("hash_provider", async move {
+            let mut interval = tokio::time::interval(Duration::from_millis(50));
+            let mut last_refresh: Option<(u64, Instant)> = None;
+
+            loop {
+                tokio::select! {
+                    _ = exit.recv() => {
+                        info!("shutting down hash provider task");
+                        break;
+                    }
+                    _ = interval.tick() => {
+                        let slot = chain_data.newest_processed_slot();
+                        p.set_current_slot(slot);
+
+                        let refresh_needed = match last_refresh {
+                            None => true,
+                            Some((refresh_slot, at)) => {
+                                slot > refresh_slot || at.elapsed() > max_refresh_interval
+                            }
+                        };
+                        if !refresh_needed {
+                            continue;
+                        }
+
+                        match p.refresh(&rpc_client, slot).await {
+                            Ok(_) => last_refresh = Some((slot, Instant::now())),
+                            Err(e) => warn!("failed to refresh blockhash: {:?}", e),
+                        }
+                    }
+                }
+            }
+        });
+
+        (provider, job)
+    }
+
+    async fn refresh(&self, rpc_client: &RpcClient, slot: u64) -> anyhow::Result<()> {
+        let commitment = CommitmentConfig::confirmed();
+        let ((hash, last_valid_block_height), block_height) = tokio::try_join!(
+            rpc_client.get_latest_blockhash_with_commitment(commitment),
+            rpc_client.get_block_height_with_commitment(commitment),
+        )?;
+
+        debug!(
+            "blockhash {} refreshed at slot {}, valid until block height {}",
+            hash, slot, last_valid_block_height
+        );
+
+        *self.latest.write().unwrap() = Some(BlockhashInfo {
+            hash,
+            last_valid_block_height,
+            block_height,
+            fetched_slot: slot,
+            current_slot: slot,
+            fetched_at: Instant::now(),
+        });
+        Ok(())
+    }
+
+    fn set_current_slot(&self, slot: u64) {
+        if let Some(info) = self.latest.write().unwrap().as_mut() {
+            info.current_slot = info.current_slot.max(slot);
+        }
+    }
+}
+
+#[async_trait]
+impl HashProvider for StreamHashProvider {
+    async fn get_latest_hash(&self) -> anyhow::Result<Hash> {
+        match *self.latest.read().unwrap() {
+            Some(info) => Ok(info.hash),
+            None => anyhow::bail!("no blockhash received yet"),
+        }
+    }
+
+    fn latest_hash_info(&self) -> Option<BlockhashInfo> {
+        *self.latest.read().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_count_elapsed_slots_against_validity() {
+        let mut info = BlockhashInfo {
+            hash: Hash::new_unique(),
+            last_valid_block_height: 1150,
+            block_height: 1000,
+            fetched_slot: 2000,
+            current_slot: 2000,
+            fetched_at: Instant::now(),
+        };
+        assert_eq!(info.blocks_left(), 150);
+
+        info.current_slot = 2100;
+        assert_eq!(info.blocks_left(), 50);
+
+        info.current_slot = 2200;
+        assert_eq!(info.blocks_left(), 0);
+    }
+}
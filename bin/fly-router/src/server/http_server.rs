@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::extract::{Query, State};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use router_config_lib::ServerConfig;
+use router_lib::dex::SwapMode;
+use router_lib::model::liquidity_request::LiquidityRequest;
+use router_lib::model::liquidity_response::LiquidityResponse;
+use router_lib::model::quote_request::QuoteRequest;
+use router_lib::model::quote_response::{QuoteResponse, RoutePlan, SwapInfo};
+use router_lib::model::swap_request::SwapRequest;
+use router_lib::model::swap_response::{InstructionResponse, SwapIxResponse};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use crate::ix_builder::SwapInstructionsBuilder;
+use crate::routing_types::Route;
+use crate::server::errors::AppError;
+use crate::server::route_provider::RouteProvider;
+use crate::util::tokio_spawn;
+
+/// Jupiter 的默认值
+const DEFAULT_MAX_ACCOUNTS: usize = 64;
+
+struct HttpState<TIxBuilder> {
+    route_provider: Arc<RouteProvider>,
+    ix_builder: Arc<TIxBuilder>,
+}
+
+impl<TIxBuilder> Clone for HttpState<TIxBuilder> {
+    fn clone(&self) -> Self {
+        Self {
+            route_provider: self.route_provider.clone(),
+            ix_builder: self.ix_builder.clone(),
+        }
+    }
+}
+
+/// Serves `/quote`, `/swap-instructions` and `/liquidity` for `RouterClient`
+pub fn spawn_http_server<TIxBuilder>(
+    config: &ServerConfig,
+    route_provider: Arc<RouteProvider>,
+    ix_builder: Arc<TIxBuilder>,
+    mut exit: broadcast::Receiver<()>,
+) -> Option<JoinHandle<()>>
+where
+    TIxBuilder: SwapInstructionsBuilder + Send + Sync + 'static,
+{
+    if !config.enabled {
+        return None;
+    }
+    let address = config.address.clone();
+    let state = HttpState {
+        route_provider,
+        ix_builder,
+    };
+
+    let job = tokio_spawn("http_server", async move {
+        let listener = match TcpListener::bind(&address).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("failed to bind http server on {}: {}", address, e);
+                return;
+            }
+        };
+        info!("serving quotes on http://{}", address);
+
+        let app = Router::new()
+            .route("/quote", get(quote::<TIxBuilder>))
+            .route("/swap-instructions", post(swap_instructions::<TIxBuilder>))
+            .route("/liquidity", get(liquidity::<TIxBuilder>))
+            .with_state(state);
+        let shutdown = async move {
+            let _ = exit.recv().await;
+        };
+        if let Err(e) = axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown)
+            .await
+        {
+            error!("http server failed: {}", e);
+        }
+    });
+    Some(job)
+}
+
+fn parse_pubkey(key: &str) -> Result<Pubkey, AppError> {
+    Pubkey::from_str(key.trim())
+        .map_err(|_| AppError::BadRequest(format!("invalid pubkey {}", key)))
+}
+
+fn parse_amount(amount: &str) -> Result<u64, AppError> {
+    amount
+        .parse()
+        .map_err(|_| AppError::BadRequest(format!("invalid amount {}", amount)))
+}
+
+fn other_amount_threshold(route: &Route, swap_mode: SwapMode, slippage_bps: u64) -> u64 {
+    match swap_mode {
+        SwapMode::ExactIn => {
+            (route.out_amount as u128 * 10_000u128.saturating_sub(slippage_bps as u128) / 10_000)
+                as u64
+        }
+        SwapMode::ExactOut => {
+            (route.in_amount as u128 * (10_000 + slippage_bps as u128) / 10_000) as u64
+        }
+    }
+}
+
+async fn quote<TIxBuilder>(
+    State(state): State<HttpState<TIxBuilder>>,
+    Query(input): Query<QuoteRequest>,
+) -> Result<Json<QuoteResponse>, AppError>
+where
+    TIxBuilder: Send + Sync + 'static,
+{
+    let started_at = Instant::now();
+    let input_mint = parse_pubkey(&input.input_mint)?;
+    let output_mint = parse_pubkey(&input.output_mint)?;
+    let swap_mode = input.swap_mode.or(input.mode).unwrap_or_default();
+    let max_accounts = input
+        .max_accounts
+        .map(|x| x as usize)
+        .unwrap_or(DEFAULT_MAX_ACCOUNTS);
+    let only_direct_routes = input.only_direct_routes.unwrap_or(false);
+
+    // 报价是同步计算，不占用 axum 的工作线程
+    let route_provider = state.route_provider.clone();
+    let route = tokio::task::spawn_blocking(move || {
+        route_provider.find_route(
+            input_mint,
+            output_mint,
+            input.amount,
+            max_accounts,
+            only_direct_routes,
+            swap_mode,
+        )
+    })
+    .await?
+    .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let route_plan = route
+        .steps
+        .iter()
+        .map(|step| RoutePlan {
+            percent: 100,
+            swap_info: Some(SwapInfo {
+                amm_key: step.edge.key().to_string(),
+                label: Some(step.edge.dex.name()),
+                input_mint: step.edge.input_mint.to_string(),
+                output_mint: step.edge.output_mint.to_string(),
+                in_amount: step.in_amount.to_string(),
+                out_amount: step.out_amount.to_string(),
+                fee_amount: step.fee_amount.to_string(),
+                fee_mint: step.fee_mint.to_string(),
+            }),
+        })
+        .collect();
+
+    Ok(Json(QuoteResponse {
+        input_mint: route.input_mint.to_string(),
+        in_amount: Some(route.in_amount.to_string()),
+        output_mint: route.output_mint.to_string(),
+        out_amount: route.out_amount.to_string(),
+        other_amount_threshold: other_amount_threshold(&route, swap_mode, input.slippage_bps)
+            .to_string(),
+        swap_mode: swap_mode.to_string(),
+        slippage_bps: input.slippage_bps as i32,
+        platform_fee: None,
+        price_impact_pct: (route.price_impact_bps as f64 / 100.0).to_string(),
+        route_plan,
+        accounts: None,
+        context_slot: state.route_provider.context_slot(),
+        time_taken: started_at.elapsed().as_secs_f64(),
+    }))
+}
+
+async fn swap_instructions<TIxBuilder>(
+    State(state): State<HttpState<TIxBuilder>>,
+    Json(input): Json<SwapRequest>,
+) -> Result<Json<SwapIxResponse>, AppError>
+where
+    TIxBuilder: SwapInstructionsBuilder + Send + Sync + 'static,
+{
+    let wallet_pk = parse_pubkey(&input.user_public_key)?;
+    let quote_response = &input.quote_response;
+    let swap_mode = SwapMode::from_str(&quote_response.swap_mode).map_err(|_| {
+        AppError::BadRequest(format!("invalid swap mode {}", quote_response.swap_mode))
+    })?;
+    let amount = match swap_mode {
+        SwapMode::ExactIn => parse_amount(quote_response.in_amount.as_deref().unwrap_or_default())?,
+        SwapMode::ExactOut => parse_amount(&quote_response.out_amount)?,
+    };
+    let threshold = parse_amount(&quote_response.other_amount_threshold)?;
+    let hops = quote_response
+        .route_plan
+        .iter()
+        .map(|plan| {
+            let info = plan
+                .swap_info
+                .as_ref()
+                .ok_or_else(|| AppError::BadRequest("route plan without swap info".to_string()))?;
+            Ok((
+                parse_pubkey(&info.amm_key)?,
+                parse_pubkey(&info.input_mint)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    // 报价之后池子可能已经变化，按当前状态重新报价
+    let route_provider = state.route_provider.clone();
+    let route =
+        tokio::task::spawn_blocking(move || route_provider.requote(&hops, amount, swap_mode))
+            .await?
+            .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    let still_valid = match swap_mode {
+        SwapMode::ExactIn => route.out_amount >= threshold,
+        SwapMode::ExactOut => route.in_amount <= threshold,
+    };
+    if !still_valid {
+        return Err(AppError::BadRequest(format!(
+            "quote is stale, now {} in for {} out",
+            route.in_amount, route.out_amount
+        )));
+    }
+
+    let swap = state
+        .ix_builder
+        .build_ixs(
+            &wallet_pk,
+            Arc::new(route),
+            &HashMap::new(),
+            quote_response.slippage_bps,
+            threshold,
+            swap_mode,
+        )
+        .await?;
+
+    let mut compute_budget_instructions = vec![InstructionResponse::from_ix(
+        ComputeBudgetInstruction::set_compute_unit_limit(swap.cu_estimate),
+    )?];
+    if let Some(price) = input.compute_unit_price_micro_lamports {
+        compute_budget_instructions.push(InstructionResponse::from_ix(
+            ComputeBudgetInstruction::set_compute_unit_price(price),
+        )?);
+    }
+    let setup_instructions = swap
+        .setup_instructions
+        .iter()
+        .map(|ix| InstructionResponse::from_ix(ix.clone()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let cleanup_instructions = swap
+        .cleanup_instructions
+        .iter()
+        .map(|ix| InstructionResponse::from_ix(ix.clone()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(Json(SwapIxResponse {
+        token_ledger_instruction: None,
+        compute_budget_instructions: Some(compute_budget_instructions),
+        setup_instructions: Some(setup_instructions),
+        swap_instruction: InstructionResponse::from_ix(swap.swap_instruction.clone())?,
+        cleanup_instructions: Some(cleanup_instructions),
+        address_lookup_table_addresses: Some(swap.address_lookup_table_addresses.clone()),
+    }))
+}
+
+async fn liquidity<TIxBuilder>(
+    State(state): State<HttpState<TIxBuilder>>,
+    Query(input): Query<LiquidityRequest>,
+) -> Result<Json<LiquidityResponse>, AppError>
+where
+    TIxBuilder: Send + Sync + 'static,
+{
+    let mints = input
+        .mints
+        .split(',')
+        .filter(|m| !m.trim().is_empty())
+        .map(parse_pubkey)
+        .collect::<Result<Vec<_>, _>>()?;
+    let liquidity = state
+        .route_provider
+        .liquidity(&mints)
+        .into_iter()
+        .map(|(mint, usd)| (mint.to_string(), usd))
+        .collect();
+    Ok(Json(LiquidityResponse { liquidity }))
+}
@@ -0,0 +1,65 @@
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use prometheus::{Encoder, TextEncoder};
+use router_config_lib::MetricsConfig;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use crate::util::tokio_spawn;
+
+const DEFAULT_PROMETHEUS_ADDRESS: &str = "0.0.0.0:9091";
+
+/// Serves the default prometheus registry on `/metrics` when `output_http` is set
+pub fn spawn_metrics_server(
+    config: &MetricsConfig,
+    mut exit: broadcast::Receiver<()>,
+) -> Option<JoinHandle<()>> {
+    if !config.output_http {
+        return None;
+    }
+    let address = config
+        .prometheus_address
+        .clone()
+        .unwrap_or(DEFAULT_PROMETHEUS_ADDRESS.to_string());
+
+    let job = tokio_spawn("metrics_server", async move {
+        let listener = match TcpListener::bind(&address).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("failed to bind metrics server on {}: {}", address, e);
+                return;
+            }
+        };
+        info!("serving metrics on http://{}/metrics", address);
+
+        let app = Router::new().route("/metrics", get(metrics_handler));
+        let shutdown = async move {
+            let _ = exit.recv().await;
+        };
+        if let Err(e) = axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown)
+            .await
+        {
+            error!("metrics server failed: {}", e);
+        }
+    });
+    Some(job)
+}
+
+async fn metrics_handler() -> impl IntoResponse {
+    let encoder = TextEncoder::new();
+    let mut buffer = vec![];
+    match encoder.encode(&prometheus::gather(), &mut buffer) {
+        Ok(()) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+            buffer,
+        )
+            .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
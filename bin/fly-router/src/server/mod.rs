@@ -1,7 +1,9 @@
 pub mod alt_provider;
+pub mod control_api;
 pub mod errors;
 pub mod hash_provider;
-// pub mod http_server;
+pub mod http_server;
 pub mod live_account_provider;
-// pub mod route_provider;
+pub mod metrics_server;
+pub mod route_provider;
 pub mod client_provider;
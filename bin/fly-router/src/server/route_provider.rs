@@ -0,0 +1,349 @@
+use std::time::Instant;
+
+use router_lib::dex::{AccountProviderView, SwapMode};
+use router_lib::price_feeds::price_cache::PriceCache;
+
+use crate::graph::Graph;
+use crate::prelude::*;
+use crate::routing_types::{Route, RouteStep};
+use crate::source::token_cache::TokenCache;
+
+/// 源代币账户、签名者、代币程序、关联代币账户程序、系统程序，不计入 edge.accounts_needed
+const BASE_ACCOUNTS: usize = 5;
+/// 按缓存价格排序后只精确报价前这么多条路径
+const MAX_QUOTED_PATHS: usize = 32;
+/// 计算流动性时允许的价格偏离
+const LIQUIDITY_MAX_PRICE_DEVIATION: f64 = 0.01;
+
+/// Point to point routes on the same edges and chain data as the ring engine
+pub struct RouteProvider {
+    // 运行时新增代币的边由 mint manager 加入
+    graph: RwLock<Graph>,
+    chain_data: AccountProviderView,
+    token_cache: Arc<TokenCache>,
+    price_cache: PriceCache,
+    max_hops: usize,
+}
+
+impl RouteProvider {
+    pub fn new(
+        edges: Vec<Arc<Edge>>,
+        chain_data: AccountProviderView,
+        token_cache: Arc<TokenCache>,
+        price_cache: PriceCache,
+        max_hops: usize,
+    ) -> Self {
+        let mut graph = Graph::new();
+        graph.add_edges(edges);
+        Self {
+            graph: RwLock::new(graph),
+            chain_data,
+            token_cache,
+            price_cache,
+            max_hops,
+        }
+    }
+
+    /// Edges of a mint added at runtime, the known ones are ignored
+    pub fn add_edges(&self, edges: Vec<Arc<Edge>>) {
+        self.graph.write().unwrap().add_edges(edges);
+    }
+
+    /// Best route for `amount`, which is the input for ExactIn and the output for ExactOut
+    pub fn find_route(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+        max_accounts: usize,
+        only_direct_routes: bool,
+        swap_mode: SwapMode,
+    ) -> anyhow::Result<Route> {
+        let max_hops = if only_direct_routes { 1 } else { self.max_hops };
+        let mut paths = self
+            .graph
+            .read()
+            .unwrap()
+            .find_paths(input_mint, output_mint, max_hops)
+            .into_iter()
+            .filter(|path| {
+                path.iter().map(|e| e.accounts_needed).sum::<usize>() + BASE_ACCOUNTS
+                    <= max_accounts
+            })
+            .filter(|path| {
+                swap_mode == SwapMode::ExactIn || path.iter().all(|e| e.supports_exact_out())
+            })
+            .filter_map(|path| Some((marginal_ln_price(&path)?, path)))
+            .collect_vec();
+        if paths.is_empty() {
+            bail!("no route from {} to {}", input_mint, output_mint);
+        }
+        paths.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let best = paths
+            .into_iter()
+            .take(MAX_QUOTED_PATHS)
+            .filter_map(
+                |(ln_price, path)| match self.quote_path(&path, amount, swap_mode) {
+                    Ok(steps) => Some((ln_price, steps)),
+                    Err(e) => {
+                        trace!("quote failed on {:?}: {}", path, e);
+                        None
+                    }
+                },
+            )
+            .max_by(|(_, a), (_, b)| match swap_mode {
+                SwapMode::ExactIn => a
+                    .last()
+                    .unwrap()
+                    .out_amount
+                    .cmp(&b.last().unwrap().out_amount),
+                // 精确输出时输入越少越好
+                SwapMode::ExactOut => b[0].in_amount.cmp(&a[0].in_amount),
+            });
+        let Some((ln_price, steps)) = best else {
+            bail!(
+                "could not quote any route from {} to {}",
+                input_mint,
+                output_mint
+            );
+        };
+        Ok(self.route_from_steps(steps, ln_price))
+    }
+
+    /// Quotes again the path of a previous quote, the pools are given by (pool, input mint)
+    pub fn requote(
+        &self,
+        hops: &[(Pubkey, Pubkey)],
+        amount: u64,
+        swap_mode: SwapMode,
+    ) -> anyhow::Result<Route> {
+        let graph = self.graph.read().unwrap();
+        let path = hops
+            .iter()
+            .map(|(pool, input_mint)| {
+                graph
+                    .edge(pool, input_mint)
+                    .with_context(|| format!("unknown pool {} for input {}", pool, input_mint))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        drop(graph);
+        if path.is_empty() {
+            bail!("empty route");
+        }
+        if path.windows(2).any(|w| w[0].output_mint != w[1].input_mint) {
+            bail!("route hops are not connected");
+        }
+        let ln_price = marginal_ln_price(&path).context("route uses an invalid pool")?;
+        let steps = self.quote_path(&path, amount, swap_mode)?;
+        Ok(self.route_from_steps(steps, ln_price))
+    }
+
+    fn quote_path(
+        &self,
+        path: &[Arc<Edge>],
+        amount: u64,
+        swap_mode: SwapMode,
+    ) -> anyhow::Result<Vec<RouteStep>> {
+        let chain_data = &self.chain_data;
+        let mut steps = Vec::with_capacity(path.len());
+        match swap_mode {
+            SwapMode::ExactIn => {
+                let mut in_amount = amount;
+                for edge in path {
+                    let prepared = edge.prepare(chain_data)?;
+                    let quote = edge.quote(&prepared, chain_data, in_amount)?;
+                    in_amount = quote.out_amount;
+                    steps.push(RouteStep {
+                        edge: edge.clone(),
+                        in_amount: quote.in_amount,
+                        out_amount: quote.out_amount,
+                        fee_amount: quote.fee_amount,
+                        fee_mint: quote.fee_mint,
+                    });
+                }
+            }
+            SwapMode::ExactOut => {
+                let mut out_amount = amount;
+                for edge in path.iter().rev() {
+                    let prepared = edge.prepare(chain_data)?;
+                    let quote = edge.quote_exact_out(&prepared, chain_data, out_amount)?;
+                    out_amount = quote.in_amount;
+                    steps.push(RouteStep {
+                        edge: edge.clone(),
+                        in_amount: quote.in_amount,
+                        out_amount: quote.out_amount,
+                        fee_amount: quote.fee_amount,
+                        fee_mint: quote.fee_mint,
+                    });
+                }
+                steps.reverse();
+            }
+        }
+        if steps.iter().any(|s| s.in_amount == 0 || s.out_amount == 0) {
+            bail!("route has an empty hop");
+        }
+        Ok(steps)
+    }
+
+    fn route_from_steps(&self, steps: Vec<RouteStep>, marginal_ln_price: f64) -> Route {
+        let first = steps.first().unwrap();
+        let last = steps.last().unwrap();
+        let (in_amount, out_amount) = (first.in_amount, last.out_amount);
+        let marginal_out = in_amount as f64 * marginal_ln_price.exp();
+        let price_impact_bps = if marginal_out > 0.0 {
+            ((1.0 - out_amount as f64 / marginal_out) * 10_000.0)
+                .max(0.0)
+                .round() as u64
+        } else {
+            0
+        };
+        let slot = steps
+            .iter()
+            .map(|s| s.edge.state.read().unwrap().last_update_slot)
+            .max()
+            .unwrap_or(0);
+
+        Route {
+            id: Route::next_id(),
+            ring_id: None,
+            created_at: Instant::now(),
+            input_mint: first.edge.input_mint,
+            output_mint: last.edge.output_mint,
+            in_amount,
+            out_amount,
+            price_impact_bps,
+            steps,
+            slot,
+            accounts: None,
+            ring_state: None,
+        }
+    }
+
+    /// USD value each mint can be sold for through its valid edges before the price
+    /// moves more than 1%, measured on the warmed amounts of the edges
+    pub fn liquidity(&self, mints: &[Pubkey]) -> HashMap<Pubkey, f64> {
+        let mut liquidity = mints.iter().map(|m| (*m, 0.0)).collect::<HashMap<_, _>>();
+        for edge in self.graph.read().unwrap().edges() {
+            let Some(total) = liquidity.get_mut(&edge.input_mint) else {
+                continue;
+            };
+            let Some(price_ui) = self.price_cache.price_ui(edge.input_mint) else {
+                continue;
+            };
+            let Ok(token) = self.token_cache.token(edge.input_mint) else {
+                continue;
+            };
+            let state = edge.state.read().unwrap();
+            if !state.is_valid() {
+                continue;
+            }
+            let Some(&(_, best_price, _)) = state.cached_prices.first() else {
+                continue;
+            };
+            let depth = state
+                .cached_prices
+                .iter()
+                .take_while(|(_, price, _)| {
+                    *price >= best_price * (1.0 - LIQUIDITY_MAX_PRICE_DEVIATION)
+                })
+                .map(|(in_amount, _, _)| *in_amount)
+                .last()
+                .unwrap_or(0);
+            *total += depth as f64 / 10f64.powi(token.decimals as i32) * price_ui;
+        }
+        liquidity
+    }
+
+    pub fn context_slot(&self) -> u64 {
+        self.chain_data.newest_processed_slot()
+    }
+}
+
+/// 用最小预热金额的缓存价格估算路径的边际价格（对数）
+fn marginal_ln_price(path: &[Arc<Edge>]) -> Option<f64> {
+    path.iter()
+        .map(|edge| {
+            edge.state
+                .read()
+                .unwrap()
+                .cached_price_for(0)
+                .map(|(_, ln)| ln)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::test::mock_edge;
+    use router_lib::chain_data::ChainDataArcRw;
+    use router_lib::dex::ChainDataAccountProvider;
+
+    fn edge(
+        chain_data: &AccountProviderView,
+        pool: Pubkey,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        price: f64,
+    ) -> Arc<Edge> {
+        let edge = mock_edge(pool, input_mint, output_mint, price);
+        edge.update_internal(chain_data, 6, 1.0, &vec![1, 10]);
+        Arc::new(edge)
+    }
+
+    #[tokio::test]
+    async fn should_pick_the_best_route_within_max_accounts() {
+        let (exit_sender, _) = broadcast::channel(1);
+        let (price_sender, _) = broadcast::channel(1);
+        let (price_cache, _price_cache_job) =
+            PriceCache::new(exit_sender.subscribe(), price_sender.subscribe());
+        let chain_data = Arc::new(ChainDataAccountProvider::new(ChainDataArcRw::new(
+            Default::default(),
+        ))) as AccountProviderView;
+        let [a, b, c] = [(); 3].map(|_| Pubkey::new_unique());
+        let [direct, via_1, via_2] = [(); 3].map(|_| Pubkey::new_unique());
+        let provider = RouteProvider::new(
+            vec![
+                edge(&chain_data, direct, a, c, 1.0),
+                edge(&chain_data, via_1, a, b, 2.0),
+                edge(&chain_data, via_2, b, c, 1.0),
+            ],
+            chain_data,
+            Arc::new(TokenCache::new(HashMap::new())),
+            price_cache,
+            3,
+        );
+
+        let route = provider
+            .find_route(a, c, 1_000, 64, false, SwapMode::ExactIn)
+            .unwrap();
+        assert_eq!(route.steps.len(), 2);
+        assert_eq!(route.out_amount, 2_000);
+
+        // 两跳需要 25 个账户
+        let route = provider
+            .find_route(a, c, 1_000, 20, false, SwapMode::ExactIn)
+            .unwrap();
+        assert_eq!(route.steps[0].edge.key(), direct);
+
+        let route = provider
+            .requote(&[(via_1, a), (via_2, b)], 1_000, SwapMode::ExactIn)
+            .unwrap();
+        assert_eq!(route.out_amount, 2_000);
+        assert!(provider
+            .requote(&[(via_2, b), (via_1, a)], 1_000, SwapMode::ExactIn)
+            .is_err());
+
+        // 运行时新增的代币
+        let (d, c_d) = (Pubkey::new_unique(), Pubkey::new_unique());
+        assert!(provider
+            .find_route(a, d, 1_000, 64, false, SwapMode::ExactIn)
+            .is_err());
+        provider.add_edges(vec![edge(&provider.chain_data, c_d, c, d, 3.0)]);
+        let route = provider
+            .find_route(a, d, 1_000, 64, false, SwapMode::ExactIn)
+            .unwrap();
+        assert_eq!(route.out_amount, 6_000);
+    }
+}
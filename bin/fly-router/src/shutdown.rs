@@ -0,0 +1,314 @@
+//! Orderly shutdown on SIGINT / SIGTERM or when a critical job exits: new routes are refused,
+//! the bundles in flight get some time to land, the ring states are saved, then the jobs are
+//! stopped (the sender sweeps its temporary tip accounts) and the journal is flushed.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use futures::stream::FuturesUnordered;
+use router_config_lib::ShutdownConfig;
+use solana_sdk::signature::Keypair;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::control::RuntimeControl;
+use crate::journal;
+use crate::pnl_tracker::PnlHandle;
+use crate::prelude::*;
+use crate::ring::{Ring, RingState};
+
+/// Shutdown requested by a signal
+pub const EXIT_REQUESTED: i32 = 0;
+/// A critical job exited on its own
+pub const EXIT_JOB_FAILED: i32 = 3;
+/// Second signal during the shutdown, nothing was waited for
+pub const EXIT_FORCED: i32 = 4;
+
+const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 15;
+const DEFAULT_JOB_TIMEOUT_SECS: u64 = 30;
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(200);
+const JOURNAL_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn wait_for_signal() -> &'static str {
+    let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+        _ = terminate.recv() => "SIGTERM",
+    }
+}
+
+/// Resolves on the first SIGINT / SIGTERM, a second one exits right away
+pub fn spawn_signal_handler() -> oneshot::Receiver<&'static str> {
+    let (sender, receiver) = oneshot::channel();
+    tokio::spawn(async move {
+        let name = wait_for_signal().await;
+        info!("Received {}, shutting down...", name);
+        let _ = sender.send(name);
+
+        let name = wait_for_signal().await;
+        warn!("Received {} again, exiting without waiting", name);
+        std::process::exit(EXIT_FORCED);
+    });
+    receiver
+}
+
+pub struct Shutdown {
+    drain_timeout: Duration,
+    job_timeout: Duration,
+    ring_state_path: Option<String>,
+    control: Arc<RuntimeControl>,
+    pnl: Option<PnlHandle>,
+}
+
+impl Shutdown {
+    pub fn new(
+        config: Option<&ShutdownConfig>,
+        control: Arc<RuntimeControl>,
+        pnl: Option<PnlHandle>,
+    ) -> Self {
+        Self {
+            drain_timeout: Duration::from_secs(
+                config
+                    .and_then(|c| c.drain_timeout_secs)
+                    .unwrap_or(DEFAULT_DRAIN_TIMEOUT_SECS),
+            ),
+            job_timeout: Duration::from_secs(
+                config
+                    .and_then(|c| c.job_timeout_secs)
+                    .unwrap_or(DEFAULT_JOB_TIMEOUT_SECS),
+            ),
+            ring_state_path: config
+                .and_then(|c| c.ring_state_path.as_ref())
+                .map(|p| shellexpand::tilde(p).to_string()),
+            control,
+            pnl,
+        }
+    }
+
+    /// Cooldowns saved by the previous run, for the rings that still exist
+    pub fn restore_ring_states(&self) {
+        let Some(path) = &self.ring_state_path else {
+            return;
+        };
+        match restore_ring_states(Path::new(path), &self.control.rings()) {
+            Ok(restored) => info!("restored the state of {} ring(s) from {}", restored, path),
+            Err(e) => warn!("failed to restore the ring states from {}: {:?}", path, e),
+        }
+    }
+
+    /// Bundles that neither landed, failed nor expired, None when their outcome is not tracked
+    fn in_flight(&self) -> Option<usize> {
+        self.pnl.as_ref().map(|pnl| pnl.pending_bundles())
+    }
+
+    async fn drain(&self) {
+        self.control.begin_shutdown();
+        let deadline = Instant::now() + self.drain_timeout;
+        loop {
+            let Some(in_flight) = self.in_flight() else {
+                // 钱包池的预留只是一个估计，不能说明 bundle 是否落地
+                warn!("pnl_tracker is disabled, the bundles in flight are not waited for");
+                return;
+            };
+            if in_flight == 0 {
+                info!("no bundle in flight");
+                return;
+            }
+            if Instant::now() >= deadline {
+                warn!(
+                    "{} bundle(s) still in flight after {:?}",
+                    in_flight, self.drain_timeout
+                );
+                return;
+            }
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+    }
+
+    fn save_ring_states(&self) {
+        let Some(path) = &self.ring_state_path else {
+            return;
+        };
+        let rings = self.control.rings();
+        match save_ring_states(Path::new(path), &rings) {
+            Ok(()) => info!("saved the state of {} ring(s) to {}", rings.len(), path),
+            Err(e) => error!("failed to save the ring states to {}: {:?}", path, e),
+        }
+    }
+
+    async fn stop_jobs(
+        &self,
+        exit_sender: &broadcast::Sender<()>,
+        jobs: &mut FuturesUnordered<JoinHandle<()>>,
+    ) {
+        let _ = exit_sender.send(());
+        let stopped = tokio::time::timeout(self.job_timeout, async {
+            while jobs.next().await.is_some() {}
+        })
+        .await;
+        if stopped.is_err() {
+            warn!(
+                "{} job(s) did not stop within {:?}",
+                jobs.len(),
+                self.job_timeout
+            );
+        }
+    }
+
+    pub async fn run(
+        &self,
+        exit_sender: &broadcast::Sender<()>,
+        jobs: &mut FuturesUnordered<JoinHandle<()>>,
+    ) {
+        self.drain().await;
+        self.save_ring_states();
+        self.stop_jobs(exit_sender, jobs).await;
+        journal::flush(JOURNAL_FLUSH_TIMEOUT);
+        info!("shutdown complete");
+    }
+}
+
+fn save_ring_states(path: &Path, rings: &[Arc<Ring>]) -> anyhow::Result<()> {
+    let states: BTreeMap<&str, RingState> = rings
+        .iter()
+        .map(|ring| {
+            (
+                ring.ring_id.as_str(),
+                ring.ring_state.read().unwrap().clone(),
+            )
+        })
+        .collect();
+    // 先写临时文件再改名，关闭中途被杀掉也不会留下半个文件
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec(&states)?)
+        .with_context(|| format!("writing {}", tmp.display()))?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Only the cooldowns are restored, the cached prices are stale by now
+fn restore_ring_states(path: &Path, rings: &[Arc<Ring>]) -> anyhow::Result<usize> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    let saved: HashMap<String, RingState> = serde_json::from_slice(&fs::read(path)?)?;
+    let now = millis_since_epoch();
+    let mut restored = 0;
+    for ring in rings {
+        let Some(saved) = saved.get(&ring.ring_id) else {
+            continue;
+        };
+        let mut state = ring.ring_state.write().unwrap();
+        state.cooldown_event = saved.cooldown_event;
+        state.cooldown_until = saved.cooldown_until.filter(|until| *until > now);
+        restored += 1;
+    }
+    Ok(restored)
+}
+
+/// Same format as a keypair file per entry, the file is removed once nothing is left to sweep
+pub fn save_tip_accounts(path: &Path, keypairs: &[Keypair]) -> anyhow::Result<()> {
+    if keypairs.is_empty() {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        return Ok(());
+    }
+    let bytes = keypairs.iter().map(|k| k.to_bytes().to_vec()).collect_vec();
+    let json = serde_json::to_vec(&bytes)?;
+    let tmp = path.with_extension("tmp");
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&tmp)
+        .and_then(|mut file| file.write_all(&json))
+        .with_context(|| format!("writing {}", tmp.display()))?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+pub fn load_tip_accounts(path: &Path) -> anyhow::Result<Vec<Keypair>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let bytes: Vec<Vec<u8>> = serde_json::from_slice(&fs::read(path)?)?;
+    bytes
+        .iter()
+        .map(|b| Keypair::from_bytes(b).map_err(|e| anyhow!("invalid tip keypair: {}", e)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_restore_the_cooldowns_of_saved_rings() {
+        let ring = || Arc::new(Ring::new(Pubkey::new_unique(), vec![], HashSet::new()));
+        let (cooling, expired, new) = (ring(), ring(), ring());
+        cooling
+            .ring_state
+            .write()
+            .unwrap()
+            .add_cooldown(&Duration::from_secs(60));
+        {
+            let mut state = expired.ring_state.write().unwrap();
+            state.cooldown_event = 2;
+            state.cooldown_until = Some(millis_since_epoch() - 1);
+        }
+        let path =
+            std::env::temp_dir().join(format!("fly-router-ring-state-{}.json", std::process::id()));
+        save_ring_states(&path, &[cooling.clone(), expired.clone()]).unwrap();
+
+        // 重启后重建的环有相同的 id
+        let restart = |ring: &Arc<Ring>| {
+            Arc::new(Ring::new(
+                ring.trading_mint,
+                ring.edges.clone(),
+                HashSet::new(),
+            ))
+        };
+        let rings = [restart(&cooling), restart(&expired), new];
+        assert_eq!(restore_ring_states(&path, &rings).unwrap(), 2);
+        fs::remove_file(&path).unwrap();
+
+        let state = rings[0].ring_state.read().unwrap();
+        assert_eq!(state.cooldown_event, 1);
+        assert_eq!(
+            state.cooldown_until,
+            cooling.ring_state.read().unwrap().cooldown_until
+        );
+        // 已经过期的冷却不再恢复
+        let state = rings[1].ring_state.read().unwrap();
+        assert_eq!(state.cooldown_event, 2);
+        assert_eq!(state.cooldown_until, None);
+        assert_eq!(rings[2].ring_state.read().unwrap().cooldown_event, 0);
+    }
+
+    #[test]
+    fn should_keep_unswept_tip_accounts_across_restarts() {
+        let path = std::env::temp_dir().join(format!(
+            "fly-router-tip-accounts-{}.json",
+            std::process::id()
+        ));
+        let keypairs = vec![Keypair::new(), Keypair::new()];
+        save_tip_accounts(&path, &keypairs).unwrap();
+
+        let loaded = load_tip_accounts(&path).unwrap();
+        assert_eq!(
+            loaded.iter().map(|k| k.to_bytes()).collect_vec(),
+            keypairs.iter().map(|k| k.to_bytes()).collect_vec()
+        );
+        // 全部回收后删除文件
+        save_tip_accounts(&path, &[]).unwrap();
+        assert!(!path.exists());
+        assert!(load_tip_accounts(&path).unwrap().is_empty());
+    }
+}
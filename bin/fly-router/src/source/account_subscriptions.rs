@@ -0,0 +1,57 @@
+use solana_program::pubkey::Pubkey;
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+
+/// Accounts the geyser source subscribes to and forwards to chain data, shared with the
+/// mint manager so the pools of a mint added at runtime are streamed without a restart
+#[derive(Clone)]
+pub struct AccountSubscriptions {
+    /// subscribed by pubkey, kept so a reconnect subscribes the added ones too
+    accounts: Arc<RwLock<HashSet<Pubkey>>>,
+    /// writes of other accounts are dropped, programs subscriptions stream much more than needed
+    filters: Arc<RwLock<HashSet<Pubkey>>>,
+    added: broadcast::Sender<Vec<Pubkey>>,
+}
+
+impl AccountSubscriptions {
+    pub fn new(accounts: HashSet<Pubkey>, filters: HashSet<Pubkey>) -> Self {
+        let (added, _) = broadcast::channel(64);
+        Self {
+            accounts: Arc::new(RwLock::new(accounts)),
+            filters: Arc::new(RwLock::new(filters)),
+            added,
+        }
+    }
+
+    pub fn accounts(&self) -> HashSet<Pubkey> {
+        self.accounts.read().unwrap().clone()
+    }
+
+    pub fn is_forwarded(&self, pubkey: &Pubkey) -> bool {
+        self.filters.read().unwrap().contains(pubkey)
+    }
+
+    /// Subscribe to and forward the writes of `accounts`, returns the ones not subscribed yet
+    pub fn add(&self, accounts: impl IntoIterator<Item = Pubkey>) -> Vec<Pubkey> {
+        let mut subscribed = self.accounts.write().unwrap();
+        let mut filters = self.filters.write().unwrap();
+        let added = accounts
+            .into_iter()
+            .filter(|pk| {
+                filters.insert(*pk);
+                subscribed.insert(*pk)
+            })
+            .collect::<Vec<_>>();
+        if !added.is_empty() {
+            // 没有连接时重连会订阅全部账户
+            let _ = self.added.send(added.clone());
+        }
+        added
+    }
+
+    /// Accounts added after the connection subscribed
+    pub fn subscribe(&self) -> broadcast::Receiver<Vec<Pubkey>> {
+        self.added.subscribe()
+    }
+}
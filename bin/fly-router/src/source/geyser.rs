@@ -7,7 +7,9 @@ use router_config_lib::AccountDataSourceConfig;
 use router_feed_lib::account_write::AccountOrSnapshotUpdate;
 use router_feed_lib::get_program_account::FeedMetadata;
 
+use crate::source::account_subscriptions::AccountSubscriptions;
 use crate::source::grpc_plugin_source;
+use crate::source::wallet_writes::WalletWatch;
 
 //use super::quic_plugin_source;
 
@@ -19,10 +21,10 @@ pub async fn spawn_geyser_source(
     account_write_sender: async_channel::Sender<AccountOrSnapshotUpdate>,
     metadata_write_sender: async_channel::Sender<FeedMetadata>,
     slot_sender: async_channel::Sender<SlotUpdate>,
-    subscribed_accounts: &HashSet<Pubkey>,
+    account_subscriptions: &AccountSubscriptions,
     subscribed_programs: &HashSet<Pubkey>,
     subscribed_token_accounts: &HashSet<Pubkey>,
-    filters: &HashSet<Pubkey>,
+    wallet_watch: Option<WalletWatch>,
 ) {
     // if config.quic_sources.is_some() {
     //     quic_plugin_source::process_events(
@@ -41,13 +43,13 @@ pub async fn spawn_geyser_source(
     // } else if config.grpc_sources.is_some() {
         grpc_plugin_source::process_events(
             config.clone(),
-            subscribed_accounts.clone(),
+            account_subscriptions.clone(),
             subscribed_programs.clone(),
             subscribed_token_accounts.clone(),
-            filters.clone(),
             account_write_sender,
             Some(metadata_write_sender),
             slot_sender,
+            wallet_watch,
             exit_receiver,
         )
         .await;
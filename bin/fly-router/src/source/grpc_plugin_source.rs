@@ -3,6 +3,7 @@ use itertools::Itertools;
 use jsonrpc_core::futures::StreamExt;
 
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
 
 use tokio_stream::StreamMap;
 use yellowstone_grpc_client::GeyserGrpcClient;
@@ -13,6 +14,7 @@ use yellowstone_grpc_proto::tonic::{
 };
 
 use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022;
 use async_channel::{Receiver, Sender};
 use std::collections::HashSet;
 use std::sync::Arc;
@@ -26,6 +28,8 @@ use yellowstone_grpc_proto::prelude::{
 };
 
 use crate::metrics;
+use crate::source::account_subscriptions::AccountSubscriptions;
+use crate::source::wallet_writes::{WalletWatch, WalletWrite};
 use mango_feeds_connector::{chain_data::SlotStatus, SlotUpdate};
 use router_config_lib::{AccountDataSourceConfig, GrpcSourceConfig};
 use router_feed_lib::account_write::{AccountOrSnapshotUpdate, AccountWrite};
@@ -66,11 +70,14 @@ pub async fn feed_data_geyser(
     grpc_config: &GrpcSourceConfig,
     tls_config: Option<ClientTlsConfig>,
     snapshot_config: AccountDataSourceConfig,
-    subscribed_accounts: &HashSet<Pubkey>,
+    account_subscriptions: &AccountSubscriptions,
     subscribed_programs: &HashSet<Pubkey>,
     subscribed_token_accounts: &HashSet<Pubkey>,
     sender: async_channel::Sender<SourceMessage>,
 ) -> anyhow::Result<()> {
+    // 先订阅新增账户，再读取当前账户，两者之间新增的账户不会漏掉
+    let mut added_accounts = account_subscriptions.subscribe();
+    let subscribed_accounts = account_subscriptions.accounts();
     let use_compression = snapshot_config.rpc_support_compression.unwrap_or(false);
     let number_of_accounts_per_gma = snapshot_config.number_of_accounts_per_gma.unwrap_or(100);
     let grpc_connection_string = match &grpc_config.connection_string.chars().next().unwrap() {
@@ -164,6 +171,22 @@ pub async fn feed_data_geyser(
                 ],
             },
         );
+        // Token-2022 账户的大小取决于扩展，只按 owner 过滤
+        accounts.insert(
+            format!("client_token_2022_{owner_id}").to_owned(),
+            SubscribeRequestFilterAccounts {
+                account: vec![],
+                owner: vec![spl_token_2022::ID.to_string()],
+                filters: vec![SubscribeRequestFilterAccountsFilter {
+                    filter: Some(Filter::Memcmp(SubscribeRequestFilterAccountsFilterMemcmp {
+                        offset: 32,
+                        data: Some(subscribe_request_filter_accounts_filter_memcmp::Data::Bytes(
+                            owner_id.to_bytes().into_iter().collect(),
+                        )),
+                    })),
+                }],
+            },
+        );
     }
 
     if subscribed_accounts.len() > 0 {
@@ -175,7 +198,7 @@ pub async fn feed_data_geyser(
                 filters: vec![],
             },
         );
-        accounts_filter.extend(subscribed_accounts);
+        accounts_filter.extend(&subscribed_accounts);
     }
 
     slots.insert(
@@ -210,6 +233,7 @@ pub async fn feed_data_geyser(
         .into_iter()
         .map(|chunk| chunk.collect::<HashMap<String, SubscribeRequestFilterAccounts>>())
         .collect_vec();
+    let mut next_subscription_id = account_chunks.len();
     for (i, accounts) in account_chunks.into_iter().enumerate() {
         let request = SubscribeRequest {
             accounts,
@@ -360,6 +384,8 @@ pub async fn feed_data_geyser(
                                 }
 
                                 info!("Requesting snapshot from gPA for {} program filter accounts", subscribed_programs.len());
+                                // also for the programs replayed from the snapshot cache: accounts unchanged
+                                // since the cache slot are only refreshed by this snapshot
                                 for program_id in subscribed_programs {
                                     let rpc_http_url = snapshot_rpc_http_url.clone();
                                     let program_id = *program_id;
@@ -509,6 +535,42 @@ pub async fn feed_data_geyser(
                     .expect("send success");
 
             },
+            Ok(new_accounts) = added_accounts.recv() => {
+                // 运行时新增代币的池子账户，单独订阅，快照已经做过时再补一次 gMA
+                info!("subscribing to {} added accounts", new_accounts.len());
+                let request = SubscribeRequest {
+                    accounts: HashMap::from([(
+                        format!("client_accounts_{next_subscription_id}"),
+                        SubscribeRequestFilterAccounts {
+                            account: new_accounts.iter().map(Pubkey::to_string).collect(),
+                            owner: vec![],
+                            filters: vec![],
+                        },
+                    )]),
+                    commitment: Some(CommitmentLevel::Processed as i32),
+                    accounts_data_slice: vec![],
+                    ping: None,
+                    ..Default::default()
+                };
+                let response = client.subscribe(once(async move { request })).await?;
+                subscriptions.insert(next_subscription_id, response.into_inner());
+                next_subscription_id += 1;
+                accounts_filter.extend(&new_accounts);
+
+                if !snapshot_needed {
+                    for pubkey_chunk in new_accounts.chunks(number_of_accounts_per_gma) {
+                        let rpc_http_url = snapshot_rpc_http_url.clone();
+                        let account_ids = pubkey_chunk.to_vec();
+                        let sender = snapshot_gma_sender.clone();
+                        tokio::spawn(async move {
+                            let snapshot = get_snapshot_gma(&rpc_http_url, &account_ids).await;
+                            if sender.send(snapshot).is_err() {
+                                warn!("Could not send snapshot, grpc has probably reconnected");
+                            }
+                        });
+                    }
+                }
+            },
             _ = tokio::time::sleep(fatal_idle_timeout) => {
                 anyhow::bail!("geyser plugin hasn't sent a message in too long");
             }
@@ -522,13 +584,13 @@ pub async fn feed_data_geyser(
 
 pub async fn process_events(
     config: AccountDataSourceConfig,
-    subscription_accounts: HashSet<Pubkey>,
+    account_subscriptions: AccountSubscriptions,
     subscription_programs: HashSet<Pubkey>,
     subscription_token_accounts: HashSet<Pubkey>,
-    filters: HashSet<Pubkey>,
     account_write_queue_sender: async_channel::Sender<AccountOrSnapshotUpdate>,
     metdata_write_queue_sender: Option<async_channel::Sender<FeedMetadata>>,
     slot_queue_sender: async_channel::Sender<SlotUpdate>,
+    wallet_watch: Option<WalletWatch>,
     mut exit: tokio::sync::broadcast::Receiver<()>,
 ) {
     // Subscribe to geyser
@@ -544,7 +606,7 @@ pub async fn process_events(
     assert_eq!(grpc_sources.len(), 1, "only one grpc source supported");
     for grpc_source in grpc_sources.clone() {
         let msg_sender = msg_sender.clone();
-        let sub_accounts = subscription_accounts.clone();
+        let sub_accounts = account_subscriptions.clone();
         let sub_programs = subscription_programs.clone();
         let sub_token_accounts = subscription_token_accounts.clone();
 
@@ -676,7 +738,8 @@ pub async fn process_events(
                             &mut latest_write,
                             latest_write_retention,
                             &metdata_write_queue_sender,
-                            &filters,
+                            &account_subscriptions,
+                            &wallet_watch,
                             ).await ;
                     }
                     Err(e) => {
@@ -711,7 +774,8 @@ async fn process_account_updated_from_sources(
     // metric_snapshots: &mut MetricU64,
     // metric_snapshot_account_writes: &mut MetricU64,
     metdata_write_queue_sender: &Option<Sender<FeedMetadata>>,
-    filters: &HashSet<Pubkey>,
+    account_subscriptions: &AccountSubscriptions,
+    wallet_watch: &Option<WalletWatch>,
 ) {
     let metadata_sender = |msg| {
         if let Some(sender) = &metdata_write_queue_sender {
@@ -733,7 +797,25 @@ async fn process_account_updated_from_sources(
             // Skip writes that a different server has already sent
             let pubkey_writes = latest_write.entry(slot).or_default();
             let pubkey = Pubkey::try_from(update.pubkey.clone()).unwrap();
-            if !filters.contains(&pubkey) {
+            // 钱包账户不在 chain data 过滤器中，先转发给 pnl tracker
+            if let Some(watch) = wallet_watch {
+                let owner = Pubkey::try_from(update.owner.clone()).unwrap();
+                if watch.is_wallet_account(&pubkey, &owner, &update.data) {
+                    watch.forward(WalletWrite {
+                        pubkey,
+                        owner,
+                        slot,
+                        write_version: update.write_version,
+                        lamports: update.lamports,
+                        data: update.data.clone(),
+                        txn_signature: update
+                            .txn_signature
+                            .as_ref()
+                            .and_then(|s| Signature::try_from(s.as_slice()).ok()),
+                    });
+                }
+            }
+            if !account_subscriptions.is_forwarded(&pubkey) {
                 return;
             }
 
@@ -815,7 +897,20 @@ async fn process_account_updated_from_sources(
                 metrics::GRPC_SNAPSHOT_ACCOUNT_WRITES.inc();
                 metrics::GRPC_ACCOUNT_WRITE_QUEUE.set(account_write_queue_sender.len() as i64);
 
-                if !filters.contains(&account.pubkey) {
+                if let Some(watch) = wallet_watch {
+                    if watch.is_wallet_account(&account.pubkey, &account.owner, &account.data) {
+                        watch.forward(WalletWrite {
+                            pubkey: account.pubkey,
+                            owner: account.owner,
+                            slot: account.slot,
+                            write_version: account.write_version,
+                            lamports: account.lamports,
+                            data: account.data.clone(),
+                            txn_signature: None,
+                        });
+                    }
+                }
+                if !account_subscriptions.is_forwarded(&account.pubkey) {
                     continue;
                 }
 
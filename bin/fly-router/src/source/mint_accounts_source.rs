@@ -1,5 +1,3 @@
-use anchor_lang::AccountDeserialize;
-use anchor_spl::token::Mint;
 use futures_util::future::join_all;
 use itertools::Itertools;
 use jsonrpc_core_client::transports::http;
@@ -17,10 +15,10 @@ use std::time::Instant;
 use std::str::FromStr;
 use anyhow::Context;
 use tokio::sync::Semaphore;
-use tracing::{info, trace};
+use tracing::{info, trace, warn};
 use serde_derive::{Deserialize, Serialize};
 
-use super::token_cache::Token;
+use super::token_cache::{decode_mint, Token};
 
 // 4: 388028 mints -> 61 sec
 // 16: 388028 mints -> 35 sec
@@ -38,7 +36,7 @@ pub async fn request_mint_metadata(
     rpc_http_url: &str,
     mint_account_ids: &HashSet<Pubkey>,
     max_gma_accounts: usize,
-) -> HashMap<Pubkey, Token> {
+) -> anyhow::Result<HashMap<Pubkey, Token>> {
     info!(
         "Requesting data for mint accounts via chunked gMA for {} pubkey ..",
         mint_account_ids.len()
@@ -48,7 +46,7 @@ pub async fn request_mint_metadata(
     let permits_parallel_rpc_requests = Arc::new(Semaphore::new(MAX_PARALLEL_HEAVY_RPC_REQUESTS));
     let rpc_client = http::connect::<RpcAccountsScanClient>(rpc_http_url)
         .await
-        .unwrap();
+        .map_err(|e| anyhow::anyhow!("connecting to {}: {:?}", rpc_http_url, e))?;
     let rpc_client = Arc::new(rpc_client);
     let account_info_config = RpcAccountInfoConfig {
         encoding: Some(UiAccountEncoding::Binary),
@@ -71,38 +69,36 @@ pub async fn request_mint_metadata(
             let accounts = rpc_client
                 .get_multiple_accounts(account_ids.clone(), Some(account_info_config))
                 .await
-                .unwrap()
+                .map_err(|e| anyhow::anyhow!("gMA for mint accounts: {:?}", e))?
                 .value;
             let accounts = pubkey_chunk.iter().cloned().zip(accounts).collect_vec();
 
             let mut mint_accounts: HashMap<Pubkey, Token> = HashMap::with_capacity(accounts.len());
             for (account_pk, ui_account) in accounts {
-                if let Some(ui_account) = ui_account {
-                    let mut account: Account = ui_account.decode().unwrap();
-                    let data = account.data.as_mut_slice();
-                    let mint_account = Mint::try_deserialize(&mut &*data).unwrap();
-                    trace!(
-                        "Mint Account {}: decimals={}",
-                        account_pk.to_string(),
-                        mint_account.decimals
-                    );
-                    mint_accounts.insert(
-                        account_pk,
-                        Token {
-                            mint: account_pk,
-                            decimals: mint_account.decimals,
-                            symbol: mint_account
-                                .supply
-                                .to_string()
-                                .chars()
-                                .take(8)
-                                .collect::<String>(),
-                        },
-                    );
-                    count.fetch_add(1, Ordering::Relaxed);
-                }
+                let Some(account) = ui_account.and_then(|a| a.decode::<Account>()) else {
+                    continue;
+                };
+                let Ok((decimals, mint_info)) = decode_mint(&account.owner, &account.data) else {
+                    warn!("account {} is not a mint", account_pk);
+                    continue;
+                };
+                trace!(
+                    "Mint Account {}: decimals={}",
+                    account_pk.to_string(),
+                    decimals
+                );
+                mint_accounts.insert(
+                    account_pk,
+                    Token {
+                        mint: account_pk,
+                        decimals,
+                        symbol: account_pk.to_string().chars().take(8).collect::<String>(),
+                        mint_info: Some(mint_info),
+                    },
+                );
+                count.fetch_add(1, Ordering::Relaxed);
             }
-            mint_accounts
+            anyhow::Ok(mint_accounts)
         });
         threads.push(jh_thread);
     } // -- chunks
@@ -110,30 +106,38 @@ pub async fn request_mint_metadata(
     let mut merged: HashMap<Pubkey, Token> = HashMap::with_capacity(mint_account_ids.len());
     let maps = join_all(threads).await;
     for map in maps {
-        let map = map.expect("thread must succeed");
-        merged.extend(map);
+        merged.extend(map??);
     }
 
-    assert_eq!(merged.len() as u64, count.load(Ordering::Relaxed));
-
     info!(
         "Received {} mint accounts via gMA in {:?}ms",
         count.load(Ordering::Relaxed),
         started_at.elapsed().as_secs_f64() * 1000.0
     );
 
-    merged
+    Ok(merged)
 }
 
 
 pub async fn request_v24h_usd_mint_metadata_by_birdeye(
     api_token: String,
 ) -> HashMap<Pubkey, Token> {
+    try_request_v24h_usd_mint_metadata_by_birdeye(api_token, 50)
+        .await
+        .unwrap_or_else(|e| panic!("error requesting tokenlis : {}", e))
+}
+
+/// Top tokens by 24h volume
+pub async fn try_request_v24h_usd_mint_metadata_by_birdeye(
+    api_token: String,
+    limit: usize,
+) -> anyhow::Result<HashMap<Pubkey, Token>> {
     let http_client = reqwest::Client::new();
     
+    let limit = limit.to_string();
     let query_args = vec![("sort_by", "v24hUSD"),
         ("sort_type", "desc"),
-        ("limit", "50"),
+        ("limit", limit.as_str()),
         ("offset", "0"),
         ("min_liquidity", "100"),
     ];
@@ -146,57 +150,42 @@ pub async fn request_v24h_usd_mint_metadata_by_birdeye(
     .header("x-chain", "solana")
     .send()
     .await
-    .context("birdeye request").unwrap();
-
-    let bird_tokenlis_response: anyhow::Result<BirdTokenlisResponse> =
-        router_lib::utils::http_error_handling(response).await;
-
-    let bird_tokenlis_response = match bird_tokenlis_response {
-        Ok(r) => {
-            r
-        },
-        Err(e) => {
-            panic!(
-                "error requesting tokenlis : {}",
-                e
-            );
-        }
-    };
+    .context("birdeye request")?;
+
+    let bird_tokenlis_response: BirdTokenlisResponse =
+        router_lib::utils::http_error_handling(response)
+            .await
+            .context("requesting tokenlis")?;
 
     let tokens = bird_tokenlis_response
         .data
       //  .unwrap()
         .tokens; 
-    let tokens = tokens
-        .into_iter()
-        .filter(|token| token.is_some())
-        .collect_vec();
-    //info!("tokens: {:?}", tokens);
     let mut mint_accounts: HashMap<Pubkey, Token> = HashMap::with_capacity(tokens.len());
-    for token in tokens {
-        if let Some(token) = token {
-            let mint = Pubkey::from_str(&token.address).unwrap();
-            let decimals = token.decimals;
-            let symbol = token.symbol.clone();
-            // let v24h_usd = token.v24h_usd;
-            // let v24h_change_percent = token.v24h_change_percent;
-            mint_accounts.insert(
+    for token in tokens.into_iter().flatten() {
+        let Ok(mint) = Pubkey::from_str(&token.address) else {
+            warn!("birdeye returned an invalid mint {}", token.address);
+            continue;
+        };
+        let decimals = token.decimals;
+        let symbol = token.symbol.clone();
+        // let v24h_usd = token.v24h_usd;
+        // let v24h_change_percent = token.v24h_change_percent;
+        mint_accounts.insert(
+            mint,
+            Token {
                 mint,
-                Token {
-                    mint,
-                    decimals,
-                    symbol,
-                },
-            );
-        }
+                decimals,
+                symbol,
+                mint_info: None,
+            },
+        );
     }
     info!(
         "request_v24h_usd_mint_metadata_by_birdeye for {} pubkey ..",
         mint_accounts.len()
     );
-    mint_accounts
-   
-
+    Ok(mint_accounts)
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -1,4 +1,9 @@
+pub mod account_subscriptions;
 pub mod geyser;
 pub mod grpc_plugin_source;
 pub mod mint_accounts_source;
+pub mod snapshot_cache;
+pub mod stream_recorder;
 pub mod token_cache;
+pub mod token_universe;
+pub mod wallet_writes;
@@ -0,0 +1,334 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use router_config_lib::SnapshotCacheConfig;
+use router_feed_lib::account_write::{AccountOrSnapshotUpdate, AccountWrite};
+use router_feed_lib::get_program_account::FeedMetadata;
+use router_feed_lib::router_rpc_client::{RouterRpcClient, RouterRpcClientTrait};
+use router_test_lib::serialize;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_client::client_error::Result as ClientResult;
+use solana_client::rpc_config::RpcProgramAccountsConfig;
+use solana_client::rpc_request::TokenAccountsFilter;
+use solana_client::rpc_response::RpcKeyedAccount;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use tracing::{info, warn};
+
+const DEFAULT_MAX_AGE_SECS: u64 = 60 * 60;
+
+/// On-disk gPA result for one program, stored with the same bincode + lz4 encoding
+/// as the `rpc_dumper_client` dumps
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ProgramSnapshot {
+    pub program_id: Pubkey,
+    pub config_key: String,
+    pub slot: u64,
+    pub saved_at: u64,
+    pub accounts: Vec<AccountWrite>,
+}
+
+/// Keeps `getProgramAccounts` results on disk, keyed by program id and slot,
+/// so a restart can skip the heavy scans done by the dex adapters. The gRPC snapshot still
+/// runs in the background and replaces the cached accounts when it arrives
+pub struct SnapshotCache {
+    dir: PathBuf,
+    max_age: Duration,
+    force_refresh: bool,
+    /// everything served during this run, replayed into chain data before the geyser source starts
+    served: Mutex<HashMap<Pubkey, Vec<AccountWrite>>>,
+}
+
+impl SnapshotCache {
+    pub fn new(config: &SnapshotCacheConfig) -> anyhow::Result<Self> {
+        let dir = PathBuf::from(shellexpand::tilde(&config.path).to_string());
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            max_age: Duration::from_secs(config.max_age_secs.unwrap_or(DEFAULT_MAX_AGE_SECS)),
+            force_refresh: config.force_refresh.unwrap_or(false),
+            served: Default::default(),
+        })
+    }
+
+    /// Newest snapshot for this program/config, if it is young enough to be used
+    pub fn load(&self, program_id: &Pubkey, config_key: &str) -> Option<ProgramSnapshot> {
+        if self.force_refresh {
+            return None;
+        }
+
+        let (_, path) = self
+            .files_for(program_id, config_key)
+            .into_iter()
+            .max_by_key(|(slot, _)| *slot)?;
+
+        let snapshot = match serialize::deserialize_from_file::<ProgramSnapshot>(
+            &path.to_string_lossy().to_string(),
+        ) {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("ignoring unreadable snapshot {:?}: {:?}", path, e);
+                return None;
+            }
+        };
+
+        let age = millis_since_epoch().saturating_sub(snapshot.saved_at);
+        if age > self.max_age.as_millis() as u64 {
+            info!(
+                "snapshot for {} at slot {} is {}s old, refreshing",
+                program_id,
+                snapshot.slot,
+                age / 1000
+            );
+            return None;
+        }
+
+        Some(snapshot)
+    }
+
+    /// Write a fresh snapshot and drop the older ones for the same key
+    pub fn store(&self, program_id: &Pubkey, config_key: &str, accounts: &[AccountWrite]) {
+        let slot = accounts.iter().map(|a| a.slot).max().unwrap_or(0);
+        let old_files = self.files_for(program_id, config_key);
+
+        let snapshot = ProgramSnapshot {
+            program_id: *program_id,
+            config_key: config_key.to_string(),
+            slot,
+            saved_at: millis_since_epoch(),
+            accounts: accounts.to_vec(),
+        };
+        let path = self.dir.join(format!("{}-{}-{}.lz4", program_id, config_key, slot));
+        serialize::serialize_to_file(&snapshot, &path.to_string_lossy());
+        info!(
+            "stored snapshot for {} ({} accounts) at slot {}",
+            program_id,
+            accounts.len(),
+            slot
+        );
+
+        for (old_slot, old_path) in old_files {
+            if old_slot != slot {
+                let _ = fs::remove_file(old_path);
+            }
+        }
+    }
+
+    fn files_for(&self, program_id: &Pubkey, config_key: &str) -> Vec<(u64, PathBuf)> {
+        let prefix = format!("{}-{}-", program_id, config_key);
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return vec![];
+        };
+
+        entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let name = e.file_name().to_string_lossy().to_string();
+                let slot = name.strip_prefix(&prefix)?.strip_suffix(".lz4")?;
+                Some((slot.parse::<u64>().ok()?, e.path()))
+            })
+            .collect()
+    }
+
+    fn record_served(&self, program_id: &Pubkey, accounts: &[AccountWrite]) {
+        self.served
+            .lock()
+            .unwrap()
+            .entry(*program_id)
+            .or_default()
+            .extend_from_slice(accounts);
+    }
+
+    /// Push everything served during startup into chain data as program snapshots,
+    /// they are used until the gRPC snapshot of the program arrives
+    pub async fn replay(
+        &self,
+        account_write_sender: &async_channel::Sender<AccountOrSnapshotUpdate>,
+        metadata_write_sender: &async_channel::Sender<FeedMetadata>,
+        subscribed_programs: &HashSet<Pubkey>,
+        filters: &HashSet<Pubkey>,
+    ) {
+        let served = std::mem::take(&mut *self.served.lock().unwrap());
+
+        for (program_id, accounts) in served {
+            if !subscribed_programs.contains(&program_id) {
+                continue;
+            }
+
+            let accounts = accounts
+                .into_iter()
+                .filter(|a| filters.contains(&a.pubkey))
+                .collect::<Vec<_>>();
+            info!(
+                "replaying cached snapshot for {} ({} accounts)",
+                program_id,
+                accounts.len()
+            );
+
+            let _ = metadata_write_sender
+                .send(FeedMetadata::SnapshotStart(Some(program_id)))
+                .await;
+            if account_write_sender
+                .send(AccountOrSnapshotUpdate::SnapshotUpdate(accounts))
+                .await
+                .is_err()
+            {
+                warn!("account write channel closed while replaying snapshots");
+                break;
+            }
+            let _ = metadata_write_sender
+                .send(FeedMetadata::SnapshotEnd(Some(program_id)))
+                .await;
+        }
+    }
+}
+
+fn millis_since_epoch() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+fn config_key(config: &RpcProgramAccountsConfig) -> anyhow::Result<String> {
+    let serialized = serde_json::to_string(config)?;
+    let hash = Sha256::digest(serialized.as_bytes());
+    Ok(hash.iter().take(8).map(|b| format!("{:02x}", b)).collect())
+}
+
+/// `RouterRpcClientTrait` wrapper serving gPA from the on-disk snapshot cache
+pub struct SnapshotCacheRpcClient {
+    pub rpc: RouterRpcClient,
+    pub cache: Arc<SnapshotCache>,
+}
+
+pub fn wrap_rpc_client(rpc: RouterRpcClient, cache: Arc<SnapshotCache>) -> RouterRpcClient {
+    let gpa_compression_enabled = rpc.gpa_compression_enabled;
+    RouterRpcClient {
+        rpc: Box::new(SnapshotCacheRpcClient { rpc, cache }),
+        gpa_compression_enabled,
+    }
+}
+
+#[async_trait::async_trait]
+impl RouterRpcClientTrait for SnapshotCacheRpcClient {
+    async fn get_account(&mut self, pubkey: &Pubkey) -> anyhow::Result<Option<Account>> {
+        self.rpc.get_account(pubkey).await
+    }
+
+    async fn get_multiple_accounts(
+        &mut self,
+        pubkeys: &HashSet<Pubkey>,
+    ) -> anyhow::Result<Vec<(Pubkey, Account)>> {
+        self.rpc.get_multiple_accounts(pubkeys).await
+    }
+
+    async fn get_program_accounts_with_config(
+        &mut self,
+        pubkey: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> anyhow::Result<Vec<AccountWrite>> {
+        let key = config_key(&config)?;
+
+        if let Some(snapshot) = self.cache.load(pubkey, &key) {
+            info!(
+                "using cached snapshot for {} ({} accounts) from slot {}",
+                pubkey,
+                snapshot.accounts.len(),
+                snapshot.slot
+            );
+            self.cache.record_served(pubkey, &snapshot.accounts);
+            return Ok(snapshot.accounts);
+        }
+
+        let accounts = self
+            .rpc
+            .get_program_accounts_with_config(pubkey, config)
+            .await?;
+        self.cache.store(pubkey, &key, &accounts);
+        self.cache.record_served(pubkey, &accounts);
+        Ok(accounts)
+    }
+
+    async fn get_token_accounts_by_owner_with_commitment(
+        &self,
+        owner: &Pubkey,
+        token_account_filter: TokenAccountsFilter,
+        commitment_config: CommitmentConfig,
+    ) -> ClientResult<Vec<RpcKeyedAccount>> {
+        self.rpc
+            .get_token_accounts_by_owner_with_commitment(
+                owner,
+                token_account_filter,
+                commitment_config,
+            )
+            .await
+    }
+
+    fn is_gpa_compression_enabled(&self) -> bool {
+        self.rpc.is_gpa_compression_enabled()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(slot: u64) -> AccountWrite {
+        AccountWrite {
+            pubkey: Pubkey::new_unique(),
+            slot,
+            write_version: 0,
+            lamports: 1,
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+            data: vec![1, 2, 3],
+        }
+    }
+
+    fn cache(force_refresh: bool, max_age_secs: u64) -> SnapshotCache {
+        let dir = std::env::temp_dir().join(format!("fly-snapshot-{}", Pubkey::new_unique()));
+        SnapshotCache::new(&SnapshotCacheConfig {
+            enabled: true,
+            path: dir.to_string_lossy().to_string(),
+            max_age_secs: Some(max_age_secs),
+            force_refresh: Some(force_refresh),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn should_reload_newest_snapshot_only() {
+        let cache = cache(false, 60);
+        let program_id = Pubkey::new_unique();
+
+        cache.store(&program_id, "abc", &[account(10)]);
+        cache.store(&program_id, "abc", &[account(12), account(11)]);
+
+        let snapshot = cache.load(&program_id, "abc").unwrap();
+        assert_eq!(snapshot.slot, 12);
+        assert_eq!(snapshot.accounts.len(), 2);
+        assert_eq!(cache.files_for(&program_id, "abc").len(), 1);
+        assert!(cache.load(&program_id, "other").is_none());
+    }
+
+    #[test]
+    fn should_ignore_snapshot_when_forced_or_too_old() {
+        let forced = cache(true, 60);
+        let program_id = Pubkey::new_unique();
+        forced.store(&program_id, "abc", &[account(10)]);
+        assert!(forced.load(&program_id, "abc").is_none());
+
+        let expired = cache(false, 0);
+        expired.store(&program_id, "abc", &[account(10)]);
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(expired.load(&program_id, "abc").is_none());
+    }
+}
@@ -0,0 +1,263 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use mango_feeds_connector::{chain_data::SlotStatus, SlotUpdate};
+use router_feed_lib::account_write::{AccountOrSnapshotUpdate, AccountWrite};
+use router_feed_lib::get_program_account::FeedMetadata;
+use serde_derive::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use tracing::{error, info, warn};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One event of the geyser stream, as received by the chain data updater
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum RecordedEvent {
+    Account(AccountWrite),
+    Snapshot(Vec<AccountWrite>),
+    Slot {
+        slot: u64,
+        parent: Option<u64>,
+        status: RecordedSlotStatus,
+    },
+    SnapshotStart(Option<Pubkey>),
+    SnapshotEnd(Option<Pubkey>),
+    InvalidAccount(Pubkey),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecordedSlotStatus {
+    Processed,
+    Confirmed,
+    Rooted,
+}
+
+impl From<SlotStatus> for RecordedSlotStatus {
+    fn from(status: SlotStatus) -> Self {
+        match status {
+            SlotStatus::Processed => RecordedSlotStatus::Processed,
+            SlotStatus::Confirmed => RecordedSlotStatus::Confirmed,
+            SlotStatus::Rooted => RecordedSlotStatus::Rooted,
+        }
+    }
+}
+
+impl From<RecordedSlotStatus> for SlotStatus {
+    fn from(status: RecordedSlotStatus) -> Self {
+        match status {
+            RecordedSlotStatus::Processed => SlotStatus::Processed,
+            RecordedSlotStatus::Confirmed => SlotStatus::Confirmed,
+            RecordedSlotStatus::Rooted => SlotStatus::Rooted,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedEntry {
+    /// wall clock when the event was received, used to replay at the original pace
+    pub received_at_ms: u64,
+    pub event: RecordedEvent,
+}
+
+/// Appends the geyser stream to an LZ4 log of bincode entries, written from a dedicated thread
+#[derive(Clone)]
+pub struct StreamRecorder {
+    sender: mpsc::Sender<RecordedEntry>,
+}
+
+impl StreamRecorder {
+    pub fn create(path: &str) -> anyhow::Result<(Self, JoinHandle<()>)> {
+        let path = shellexpand::tilde(path).to_string();
+        let file = File::create(&path).with_context(|| format!("creating stream log {}", path))?;
+        let mut encoder = lz4::EncoderBuilder::new().build(BufWriter::new(file))?;
+        let (sender, receiver) = mpsc::channel::<RecordedEntry>();
+        info!("recording account stream to {}", path);
+
+        let writer = std::thread::Builder::new()
+            .name("stream_recorder".to_string())
+            .spawn(move || {
+                let mut count = 0u64;
+                let mut last_flush = Instant::now();
+                // 所有发送端都关闭后结束
+                while let Ok(entry) = receiver.recv() {
+                    if let Err(e) = bincode::serialize_into(&mut encoder, &entry) {
+                        error!("failed to record stream entry: {:?}", e);
+                        break;
+                    }
+                    count += 1;
+                    if last_flush.elapsed() > FLUSH_INTERVAL {
+                        let _ = encoder.flush();
+                        last_flush = Instant::now();
+                    }
+                }
+
+                let _ = encoder.flush();
+                let (_, result) = encoder.finish();
+                if let Err(e) = result {
+                    error!("failed to finish stream log: {:?}", e);
+                }
+                info!("stream recorder stopped after {} entries", count);
+            })?;
+
+        Ok((Self { sender }, writer))
+    }
+
+    fn record(&self, event: RecordedEvent) {
+        let entry = RecordedEntry {
+            received_at_ms: now_ms(),
+            event,
+        };
+        // ignore failing sends once the writer is gone
+        let _ = self.sender.send(entry);
+    }
+
+    pub fn record_update(&self, update: &AccountOrSnapshotUpdate) {
+        self.record(match update {
+            AccountOrSnapshotUpdate::AccountUpdate(write) => RecordedEvent::Account(write.clone()),
+            AccountOrSnapshotUpdate::SnapshotUpdate(writes) => {
+                RecordedEvent::Snapshot(writes.clone())
+            }
+        });
+    }
+
+    pub fn record_slot(&self, update: &SlotUpdate) {
+        self.record(RecordedEvent::Slot {
+            slot: update.slot,
+            parent: update.parent,
+            status: update.status.into(),
+        });
+    }
+
+    pub fn record_metadata(&self, metadata: &FeedMetadata) {
+        self.record(match metadata {
+            FeedMetadata::InvalidAccount(key) => RecordedEvent::InvalidAccount(*key),
+            FeedMetadata::SnapshotStart(program) => RecordedEvent::SnapshotStart(*program),
+            FeedMetadata::SnapshotEnd(program) => RecordedEvent::SnapshotEnd(*program),
+        });
+    }
+}
+
+/// Reads back a stream log, a truncated log (recorder killed) ends at the last complete entry
+pub struct StreamReader {
+    decoder: lz4::Decoder<BufReader<File>>,
+    done: bool,
+}
+
+impl StreamReader {
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let path = shellexpand::tilde(path).to_string();
+        let file = File::open(&path).with_context(|| format!("opening stream log {}", path))?;
+        Ok(Self {
+            decoder: lz4::Decoder::new(BufReader::new(file))?,
+            done: false,
+        })
+    }
+}
+
+impl Iterator for StreamReader {
+    type Item = RecordedEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match bincode::deserialize_from(&mut self.decoder) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                self.done = true;
+                match *e {
+                    bincode::ErrorKind::Io(ref io) if io.kind() == ErrorKind::UnexpectedEof => {}
+                    _ => warn!("stream log ends with an unreadable entry: {:?}", e),
+                }
+                None
+            }
+        }
+    }
+}
+
+impl RecordedEvent {
+    /// Events the geyser source turns into `AccountOrSnapshotUpdate`s
+    pub fn into_account_update(self) -> Option<AccountOrSnapshotUpdate> {
+        match self {
+            RecordedEvent::Account(write) => Some(AccountOrSnapshotUpdate::AccountUpdate(write)),
+            RecordedEvent::Snapshot(writes) => Some(AccountOrSnapshotUpdate::SnapshotUpdate(writes)),
+            _ => None,
+        }
+    }
+
+    pub fn metadata(&self) -> Option<FeedMetadata> {
+        match self {
+            RecordedEvent::SnapshotStart(program) => Some(FeedMetadata::SnapshotStart(*program)),
+            RecordedEvent::SnapshotEnd(program) => Some(FeedMetadata::SnapshotEnd(*program)),
+            RecordedEvent::InvalidAccount(key) => Some(FeedMetadata::InvalidAccount(*key)),
+            _ => None,
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(slot: u64) -> AccountWrite {
+        AccountWrite {
+            pubkey: Pubkey::new_unique(),
+            slot,
+            write_version: 1,
+            lamports: 1,
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+            data: vec![1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn should_replay_recorded_stream() {
+        let dir = std::env::temp_dir().join(format!("stream-recorder-{}", now_ms()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("stream.lz4").to_string_lossy().to_string();
+
+        let (recorder, writer) = StreamRecorder::create(&path).unwrap();
+        recorder.record_update(&AccountOrSnapshotUpdate::AccountUpdate(write(10)));
+        recorder.record_update(&AccountOrSnapshotUpdate::SnapshotUpdate(vec![
+            write(11),
+            write(11),
+        ]));
+        recorder.record_slot(&SlotUpdate {
+            slot: 12,
+            parent: Some(11),
+            status: SlotStatus::Processed,
+        });
+        recorder.record_metadata(&FeedMetadata::SnapshotEnd(None));
+        drop(recorder);
+        writer.join().unwrap();
+
+        let entries = StreamReader::open(&path).unwrap().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 4);
+        assert!(matches!(&entries[0].event, RecordedEvent::Account(w) if w.slot == 10));
+        assert!(matches!(&entries[1].event, RecordedEvent::Snapshot(w) if w.len() == 2));
+        assert!(matches!(
+            entries[2].event,
+            RecordedEvent::Slot {
+                slot: 12,
+                status: RecordedSlotStatus::Processed,
+                ..
+            }
+        ));
+        assert!(entries[3].event.metadata().is_some());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,7 +1,12 @@
+use anchor_spl::token_2022::spl_token_2022::extension::{
+    BaseStateWithExtensions, StateWithExtensions,
+};
+use anchor_spl::token_2022::spl_token_2022::state::Mint;
 use solana_program::pubkey::Pubkey;
 use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
-use tracing::warn;
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+use tracing::{debug, warn};
 
 pub type Decimals = u8;
 
@@ -11,25 +16,69 @@ pub struct Token {
     pub mint: Pubkey,
     pub decimals: Decimals,
     pub symbol: String,
+    /// decoded from the mint account, None until the mint was seen on the stream
+    pub mint_info: Option<MintInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MintInfo {
+    pub token_program: Pubkey,
+    pub freeze_authority: Option<Pubkey>,
+    /// Token-2022 extension names
+    pub extensions: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum TokenUpdate {
+    Added(Token),
+    Removed(Pubkey),
+}
+
+/// Decode a spl-token or Token-2022 mint account
+pub fn decode_mint(owner: &Pubkey, data: &[u8]) -> anyhow::Result<(Decimals, MintInfo)> {
+    let mint = StateWithExtensions::<Mint>::unpack(data)?;
+    let extensions = mint
+        .get_extension_types()
+        .unwrap_or_default()
+        .iter()
+        .map(|e| format!("{:?}", e))
+        .collect();
+
+    Ok((
+        mint.base.decimals,
+        MintInfo {
+            token_program: *owner,
+            freeze_authority: mint.base.freeze_authority.into(),
+            extensions,
+        },
+    ))
 }
 
 #[derive(Clone)]
 pub struct TokenCache {
-    tokens: Arc<HashMap<Pubkey, Token>>,
+    tokens: Arc<RwLock<HashMap<Pubkey, Token>>>,
+    /// metadata of every mint seen on the stream, including mints not traded yet
+    mint_infos: Arc<RwLock<HashMap<Pubkey, (Decimals, MintInfo)>>>,
+    updates: broadcast::Sender<TokenUpdate>,
 }
 
 impl TokenCache {
     pub fn new(data: HashMap<Pubkey, Token>) -> Self {
+        let (updates, _) = broadcast::channel(1024);
         Self {
-            tokens: Arc::new(data),
+            tokens: Arc::new(RwLock::new(data)),
+            mint_infos: Default::default(),
+            updates,
         }
     }
 
     // use Result over Option to be compatible
     pub fn token(&self, mint: Pubkey) -> anyhow::Result<Token> {
         self.tokens
+            .read()
+            .unwrap()
             .get(&mint)
-            .map(|token| Token { mint, decimals: token.decimals, symbol: token.symbol.clone() })
+            .cloned()
             .ok_or_else(|| {
                 // this should never happen
                 warn!("Token not found in cache: {}", mint);
@@ -37,21 +86,163 @@ impl TokenCache {
             })
     }
 
+    pub fn contains(&self, mint: &Pubkey) -> bool {
+        self.tokens.read().unwrap().contains_key(mint)
+    }
+
     pub fn tokens(&self) -> HashSet<Pubkey> {
         self.tokens
-            .iter()
-            .map(|(k, _)| *k)
+            .read()
+            .unwrap()
+            .keys()
+            .copied()
             .collect::<HashSet<Pubkey>>()
     }
 
     pub fn string_tokens(&self) -> Vec<String> {
         self.tokens
-            .iter()
-            .map(|(k, _)| k.to_string())
+            .read()
+            .unwrap()
+            .keys()
+            .map(|k| k.to_string())
             .collect::<Vec<String>>()
     }
 
     pub fn get_symbol_by_mint(&self, mint: Pubkey) -> Option<String> {
-        self.tokens.get(&mint).map(|token| token.symbol.clone())
+        self.tokens
+            .read()
+            .unwrap()
+            .get(&mint)
+            .map(|token| token.symbol.clone())
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TokenUpdate> {
+        self.updates.subscribe()
+    }
+
+    /// Copy of the tokens and mint metadata that does not notify this cache's subscribers,
+    /// e.g. to initialize the dexs with one more mint before trading it
+    pub fn detached(&self) -> TokenCache {
+        let copy = TokenCache::new(self.tokens.read().unwrap().clone());
+        *copy.mint_infos.write().unwrap() = self.mint_infos.read().unwrap().clone();
+        copy
+    }
+
+    /// Start trading a mint, returns false if it was already there
+    pub fn insert(&self, token: Token) -> bool {
+        let mut token = token;
+        if token.mint_info.is_none() {
+            token.mint_info = self
+                .mint_infos
+                .read()
+                .unwrap()
+                .get(&token.mint)
+                .map(|(_, info)| info.clone());
+        }
+
+        let added = self
+            .tokens
+            .write()
+            .unwrap()
+            .insert(token.mint, token.clone())
+            .is_none();
+        if added {
+            // ignore failing sends when there are no receivers
+            let _ = self.updates.send(TokenUpdate::Added(token));
+        }
+        added
+    }
+
+    /// Start trading a mint whose metadata was decoded from the stream
+    pub fn insert_from_stream(&self, mint: Pubkey, symbol: Option<String>) -> anyhow::Result<bool> {
+        let Some((decimals, info)) = self.mint_infos.read().unwrap().get(&mint).cloned() else {
+            anyhow::bail!("mint {} was not seen on the stream", mint);
+        };
+        Ok(self.insert(Token {
+            mint,
+            decimals,
+            symbol: symbol.unwrap_or_else(|| mint.to_string().chars().take(8).collect()),
+            mint_info: Some(info),
+        }))
+    }
+
+    /// Stop trading a mint
+    pub fn remove(&self, mint: &Pubkey) -> Option<Token> {
+        let removed = self.tokens.write().unwrap().remove(mint);
+        if removed.is_some() {
+            let _ = self.updates.send(TokenUpdate::Removed(*mint));
+        }
+        removed
+    }
+
+    /// Record the mint account as seen on the stream
+    pub fn update_mint(&self, mint: Pubkey, decimals: Decimals, info: MintInfo) {
+        if let Some(token) = self.tokens.write().unwrap().get_mut(&mint) {
+            if token.decimals != decimals {
+                warn!(
+                    "decimals of {} changed from {} to {}",
+                    mint, token.decimals, decimals
+                );
+                token.decimals = decimals;
+            }
+            if token.mint_info.as_ref() != Some(&info) {
+                debug!("mint info of {} updated: {:?}", mint, info);
+                token.mint_info = Some(info.clone());
+            }
+        }
+        self.mint_infos
+            .write()
+            .unwrap()
+            .insert(mint, (decimals, info));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(mint: Pubkey) -> Token {
+        Token {
+            mint,
+            decimals: 6,
+            symbol: "TEST".to_string(),
+            mint_info: None,
+        }
+    }
+
+    #[test]
+    fn should_notify_added_and_removed_mints() {
+        let cache = TokenCache::new(HashMap::new());
+        let mut updates = cache.subscribe();
+        let mint = Pubkey::new_unique();
+
+        assert!(cache.insert(token(mint)));
+        assert!(!cache.insert(token(mint)));
+        assert!(cache.contains(&mint));
+        assert!(cache.remove(&mint).is_some());
+        assert!(cache.remove(&mint).is_none());
+
+        assert!(matches!(updates.try_recv(), Ok(TokenUpdate::Added(t)) if t.mint == mint));
+        assert!(matches!(updates.try_recv(), Ok(TokenUpdate::Removed(m)) if m == mint));
+        assert!(updates.try_recv().is_err());
+    }
+
+    #[test]
+    fn should_insert_mints_seen_on_stream_only() {
+        let cache = TokenCache::new(HashMap::new());
+        let mint = Pubkey::new_unique();
+        assert!(cache.insert_from_stream(mint, None).is_err());
+
+        let info = MintInfo {
+            token_program: Pubkey::new_unique(),
+            freeze_authority: None,
+            extensions: vec![],
+        };
+        cache.update_mint(mint, 9, info.clone());
+        assert!(cache.insert_from_stream(mint, Some("NEW".to_string())).unwrap());
+
+        let token = cache.token(mint).unwrap();
+        assert_eq!(token.decimals, 9);
+        assert_eq!(token.mint_info, Some(info));
     }
 }
@@ -0,0 +1,341 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Context;
+use itertools::Itertools;
+use router_config_lib::{TokenCombineMode, TokenEntryConfig, TokenSource, TokenUniverseConfig};
+use serde_derive::{Deserialize, Serialize};
+use solana_program::pubkey::Pubkey;
+use tracing::{info, warn};
+
+use super::mint_accounts_source::{
+    request_mint_metadata, try_request_v24h_usd_mint_metadata_by_birdeye,
+};
+use super::token_cache::Token;
+
+const DEFAULT_BIRDEYE_LIMIT: usize = 50;
+
+/// One token as found in a file, the pinned snapshot or the config
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenEntry {
+    pub mint: String,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+}
+
+impl From<&TokenEntryConfig> for TokenEntry {
+    fn from(c: &TokenEntryConfig) -> Self {
+        Self {
+            mint: c.mint.clone(),
+            symbol: c.symbol.clone(),
+            decimals: c.decimals,
+        }
+    }
+}
+
+impl From<&Token> for TokenEntry {
+    fn from(t: &Token) -> Self {
+        Self {
+            mint: t.mint.to_string(),
+            symbol: Some(t.symbol.clone()),
+            decimals: Some(t.decimals),
+        }
+    }
+}
+
+type PartialTokens = HashMap<Pubkey, TokenEntry>;
+
+/// Where the mints used by the router come from
+pub struct TokenUniverse<'a> {
+    pub config: &'a TokenUniverseConfig,
+    pub rpc_http_url: &'a str,
+    pub max_gma_accounts: usize,
+    pub birdeye_token: &'a str,
+}
+
+impl TokenUniverse<'_> {
+    /// Current behaviour when nothing is configured: top tokens from Birdeye
+    pub fn default_config() -> TokenUniverseConfig {
+        TokenUniverseConfig {
+            sources: vec![TokenSource::Birdeye],
+            ..Default::default()
+        }
+    }
+
+    pub async fn build(&self) -> anyhow::Result<HashMap<Pubkey, Token>> {
+        if let Some(pinned) = &self.config.pinned_snapshot {
+            if Path::new(pinned).exists() {
+                let entries = read_json(pinned)?;
+                info!("using pinned token universe {} ({} tokens)", pinned, entries.len());
+                return self.resolve(to_partial(entries)).await;
+            }
+        }
+
+        let mode = self.config.combine.clone().unwrap_or_default();
+        let mut sets = vec![];
+        for source in &self.config.sources {
+            match self.load_source(source).await {
+                Ok(tokens) => {
+                    info!("token source {:?} returned {} tokens", source, tokens.len());
+                    sets.push(tokens);
+                }
+                // 取交集时任何来源失败都会改变结果
+                Err(e) if mode == TokenCombineMode::Intersection => {
+                    return Err(e.context(format!("token source {:?}", source)));
+                }
+                Err(e) => warn!("skipping token source {:?}: {:?}", source, e),
+            }
+        }
+
+        let tokens = self.resolve(combine(sets, &mode)).await?;
+
+        if let Some(pinned) = &self.config.pinned_snapshot {
+            let entries = tokens
+                .values()
+                .map(TokenEntry::from)
+                .sorted_by(|a, b| a.mint.cmp(&b.mint))
+                .collect_vec();
+            fs::write(pinned, serde_json::to_string_pretty(&entries)?)
+                .with_context(|| format!("writing pinned token universe {}", pinned))?;
+            info!("pinned token universe to {}", pinned);
+        }
+
+        Ok(tokens)
+    }
+
+    async fn load_source(&self, source: &TokenSource) -> anyhow::Result<PartialTokens> {
+        match source {
+            TokenSource::Static => Ok(to_partial(
+                self.config
+                    .tokens
+                    .iter()
+                    .flatten()
+                    .map(TokenEntry::from)
+                    .collect(),
+            )),
+            TokenSource::File => {
+                let path = self
+                    .config
+                    .file_path
+                    .as_ref()
+                    .context("file token source needs file_path")?;
+                Ok(to_partial(read_file(path)?))
+            }
+            TokenSource::OnChain => {
+                let mints = self
+                    .config
+                    .onchain_mints
+                    .iter()
+                    .flatten()
+                    .map(|m| TokenEntry {
+                        mint: m.clone(),
+                        ..Default::default()
+                    })
+                    .collect();
+                let mut tokens = to_partial(mints);
+                self.fill_decimals(&mut tokens).await?;
+                Ok(tokens)
+            }
+            TokenSource::Birdeye => {
+                let tokens = try_request_v24h_usd_mint_metadata_by_birdeye(
+                    self.birdeye_token.to_string(),
+                    self.config.birdeye_limit.unwrap_or(DEFAULT_BIRDEYE_LIMIT),
+                )
+                .await?;
+                Ok(tokens
+                    .iter()
+                    .map(|(mint, token)| (*mint, TokenEntry::from(token)))
+                    .collect())
+            }
+        }
+    }
+
+    async fn fill_decimals(&self, tokens: &mut PartialTokens) -> anyhow::Result<()> {
+        let missing = tokens
+            .iter()
+            .filter(|(_, t)| t.decimals.is_none())
+            .map(|(mint, _)| *mint)
+            .collect::<HashSet<_>>();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let onchain =
+            request_mint_metadata(self.rpc_http_url, &missing, self.max_gma_accounts).await?;
+        for (mint, token) in onchain {
+            if let Some(entry) = tokens.get_mut(&mint) {
+                entry.decimals = Some(token.decimals);
+            }
+        }
+        Ok(())
+    }
+
+    /// Fill missing decimals from the mint accounts, tokens still without decimals are reported and dropped
+    async fn resolve(&self, mut tokens: PartialTokens) -> anyhow::Result<HashMap<Pubkey, Token>> {
+        if let Err(e) = self.fill_decimals(&mut tokens).await {
+            warn!("failed to read decimals from mint accounts: {:?}", e);
+        }
+
+        let mut result = HashMap::with_capacity(tokens.len());
+        let mut missing = vec![];
+        for (mint, entry) in tokens {
+            let Some(decimals) = entry.decimals else {
+                missing.push(mint);
+                continue;
+            };
+            let symbol = entry
+                .symbol
+                .unwrap_or_else(|| mint.to_string().chars().take(8).collect());
+            result.insert(
+                mint,
+                Token {
+                    mint,
+                    decimals,
+                    symbol,
+                    mint_info: None,
+                },
+            );
+        }
+
+        if !missing.is_empty() {
+            warn!(
+                "ignoring {} tokens with unknown decimals: {}",
+                missing.len(),
+                missing.iter().join(", ")
+            );
+        }
+        if result.is_empty() {
+            anyhow::bail!("token universe is empty");
+        }
+
+        info!("token universe has {} tokens", result.len());
+        Ok(result)
+    }
+}
+
+fn to_partial(entries: Vec<TokenEntry>) -> PartialTokens {
+    let mut tokens = PartialTokens::with_capacity(entries.len());
+    for entry in entries {
+        match Pubkey::from_str(entry.mint.trim()) {
+            Ok(mint) => {
+                tokens.insert(mint, entry);
+            }
+            Err(_) => warn!("ignoring invalid mint {}", entry.mint),
+        }
+    }
+    tokens
+}
+
+/// Union or intersection of the mint sets, metadata from the first source that has it
+fn combine(sets: Vec<PartialTokens>, mode: &TokenCombineMode) -> PartialTokens {
+    let mut result = PartialTokens::new();
+    for (i, set) in sets.iter().enumerate() {
+        for (mint, entry) in set {
+            if *mode == TokenCombineMode::Intersection && i > 0 && !result.contains_key(mint) {
+                continue;
+            }
+            let current = result.entry(*mint).or_insert_with(|| entry.clone());
+            current.symbol = current.symbol.take().or_else(|| entry.symbol.clone());
+            current.decimals = current.decimals.or(entry.decimals);
+        }
+        if *mode == TokenCombineMode::Intersection && i > 0 {
+            result.retain(|mint, _| set.contains_key(mint));
+        }
+    }
+    result
+}
+
+fn read_json(path: &str) -> anyhow::Result<Vec<TokenEntry>> {
+    let content = fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+    serde_json::from_str(&content).with_context(|| format!("parsing {}", path))
+}
+
+fn read_file(path: &str) -> anyhow::Result<Vec<TokenEntry>> {
+    if path.ends_with(".json") {
+        return read_json(path);
+    }
+    let content = fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+    Ok(parse_csv(&content))
+}
+
+/// `mint[,symbol[,decimals]]` per line, with an optional header
+/// 只有第一行可能是表头，base58 的 mint 地址本身也可能以 "mint" 开头
+fn is_csv_header(line: &str) -> bool {
+    let line = line.trim();
+    line == "mint" || line.starts_with("mint,symbol")
+}
+
+fn parse_csv(content: &str) -> Vec<TokenEntry> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(i, l)| !(*i == 0 && is_csv_header(l)))
+        .map(|(_, l)| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|line| {
+            let mut fields = line.split(',').map(str::trim);
+            TokenEntry {
+                mint: fields.next().unwrap_or_default().to_string(),
+                symbol: fields.next().filter(|s| !s.is_empty()).map(str::to_string),
+                decimals: fields.next().and_then(|d| d.parse().ok()),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(mint: &Pubkey, symbol: Option<&str>, decimals: Option<u8>) -> TokenEntry {
+        TokenEntry {
+            mint: mint.to_string(),
+            symbol: symbol.map(str::to_string),
+            decimals,
+        }
+    }
+
+    #[test]
+    fn should_combine_sources() {
+        let a = Pubkey::new_unique();
+        let b = Pubkey::new_unique();
+        let c = Pubkey::new_unique();
+        let first = to_partial(vec![entry(&a, Some("A"), None), entry(&b, None, Some(6))]);
+        let second = to_partial(vec![entry(&a, Some("X"), Some(9)), entry(&c, None, None)]);
+
+        let union = combine(vec![first.clone(), second.clone()], &TokenCombineMode::Union);
+        assert_eq!(union.len(), 3);
+        assert_eq!(union[&a], entry(&a, Some("A"), Some(9)));
+
+        let intersection = combine(vec![first, second], &TokenCombineMode::Intersection);
+        assert_eq!(intersection.len(), 1);
+        assert_eq!(intersection[&a], entry(&a, Some("A"), Some(9)));
+    }
+
+    #[test]
+    fn should_parse_csv() {
+        let a = Pubkey::new_unique();
+        let b = Pubkey::new_unique();
+        let content = format!("mint,symbol,decimals\n{},USDC,6\n\n# comment\n{}\n", a, b);
+
+        let entries = parse_csv(&content);
+        assert_eq!(
+            entries,
+            vec![entry(&a, Some("USDC"), Some(6)), entry(&b, None, None)]
+        );
+    }
+
+    #[test]
+    fn should_keep_mints_that_look_like_a_header() {
+        let mint = "mintCbKYnkSPsVmQ8sh7Vn3Z6PqwNwFt5mP3NfCJvbD";
+        let entries = parse_csv(&format!("{}\nmint\n", mint));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].mint, mint);
+
+        let entries = parse_csv(&format!("mint\n{},BONK\n", mint));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].symbol.as_deref(), Some("BONK"));
+    }
+}
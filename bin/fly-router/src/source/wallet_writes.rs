@@ -0,0 +1,71 @@
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use std::collections::HashSet;
+use tracing::warn;
+
+const TOKEN_ACCOUNT_LEN: usize = 165;
+/// Token-2022 account type stored after the base account when it has extensions
+const ACCOUNT_TYPE_ACCOUNT: u8 = 2;
+
+/// Mint and owner of an spl-token or Token-2022 token account, both programs use the same
+/// layout for the first 165 bytes: mint(32) | owner(32) | amount(8) ...
+pub fn parse_token_account(program: &Pubkey, data: &[u8]) -> Option<(Pubkey, Pubkey)> {
+    let is_account = if *program == spl_token::ID {
+        data.len() == TOKEN_ACCOUNT_LEN
+    } else if *program == spl_token_2022::ID {
+        // 有扩展的 mint 也会超过 165 字节，用账户类型区分
+        data.len() == TOKEN_ACCOUNT_LEN
+            || (data.len() > TOKEN_ACCOUNT_LEN && data[TOKEN_ACCOUNT_LEN] == ACCOUNT_TYPE_ACCOUNT)
+    } else {
+        false
+    };
+    if !is_account {
+        return None;
+    }
+    Some((
+        Pubkey::new_from_array(data[0..32].try_into().unwrap()),
+        Pubkey::new_from_array(data[32..64].try_into().unwrap()),
+    ))
+}
+
+/// A write to a wallet or to one of its token accounts, with the transaction that caused it
+#[derive(Clone, Debug)]
+pub struct WalletWrite {
+    pub pubkey: Pubkey,
+    pub owner: Pubkey,
+    pub slot: u64,
+    pub write_version: u64,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    /// None for snapshot writes
+    pub txn_signature: Option<Signature>,
+}
+
+/// Forwards the writes of the wallet accounts, before the chain data filters drop them
+#[derive(Clone)]
+pub struct WalletWatch {
+    pub wallets: HashSet<Pubkey>,
+    /// pnl tracker and ATA provider
+    pub senders: Vec<async_channel::Sender<WalletWrite>>,
+}
+
+impl WalletWatch {
+    pub fn is_wallet_account(&self, pubkey: &Pubkey, owner: &Pubkey, data: &[u8]) -> bool {
+        if self.wallets.contains(pubkey) {
+            return true;
+        }
+        parse_token_account(owner, data).map_or(false, |(_, token_owner)| {
+            self.wallets.contains(&token_owner)
+        })
+    }
+
+    pub fn forward(&self, write: WalletWrite) {
+        for sender in &self.senders {
+            if let Err(e) = sender.try_send(write.clone()) {
+                warn!("failed to forward wallet write: {}", e);
+            }
+        }
+    }
+}
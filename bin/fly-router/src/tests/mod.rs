@@ -2,6 +2,7 @@
 pub mod dex_test_utils;
 
 //pub mod dump_all_dex;
-//pub mod performance_tests;
-//pub mod warmup_performance_tests;
+pub mod performance_tests;
+pub mod ring_engine_tests;
+pub mod warmup_performance_tests;
 //pub mod graph_test;
@@ -0,0 +1,383 @@
+#[cfg(test)]
+mod tests {
+    use crate::control::RuntimeControl;
+    use crate::dex::generic::build_dex_internal;
+    use crate::edge_updater::spawn_updater_job;
+    use crate::hot_mints::HotMintsCache;
+    use crate::ix_sender_executor::{spawn_sender_loop, SenderExecutor};
+    use crate::mint_manager::MintManager;
+    use crate::mock::test::{
+        mock_pool_data, MockAccountProvider, MockAltProvider, MockHashProvider, MockIxBuilder,
+        MockPoolDex, RecordingIxSender,
+    };
+    use crate::ring_executor::spawn_ring_executor_job;
+    use crate::routing_types::Route;
+    use crate::source::account_subscriptions::AccountSubscriptions;
+    use crate::source::token_cache::{MintInfo, Token, TokenCache};
+    use router_config_lib::Config;
+    use router_feed_lib::get_program_account::FeedMetadata;
+    use router_lib::dex::{AccountProviderView, DexInterface};
+    use router_lib::price_feeds::price_cache::PriceCache;
+    use router_lib::price_feeds::price_feed::PriceUpdate;
+    use solana_program::pubkey::Pubkey;
+    use std::collections::{HashMap, HashSet};
+    use std::str::FromStr;
+    use std::sync::atomic::Ordering;
+    use std::sync::{Arc, RwLock};
+    use std::time::Duration;
+    use tokio::sync::broadcast;
+    use tokio::task::JoinHandle;
+
+    const IN_AMOUNT: u64 = 1_000_000_000;
+    const EXPECTED_GAIN: u64 = 1_000_000;
+
+    /// sol -> a -> b -> sol, each pool quotes both directions at a fixed price
+    struct Harness {
+        sol: Pubkey,
+        a: Pubkey,
+        program_id: Pubkey,
+        sol_a: Pubkey,
+        a_b: Pubkey,
+        sol_b: Pubkey,
+        token_cache: Arc<TokenCache>,
+        control: Arc<RuntimeControl>,
+        mint_manager: MintManager,
+        subscriptions: AccountSubscriptions,
+        accounts: Arc<MockAccountProvider>,
+        account_update_sender: broadcast::Sender<(Pubkey, Pubkey, u64)>,
+        ready_receiver: async_channel::Receiver<()>,
+        sender: RecordingIxSender,
+        exit_sender: broadcast::Sender<()>,
+        jobs: Vec<JoinHandle<()>>,
+        slot: u64,
+        // 保持 channel 打开
+        _register_mint_receiver: async_channel::Receiver<Pubkey>,
+        _price_sender: broadcast::Sender<PriceUpdate>,
+        _slot_sender: broadcast::Sender<u64>,
+        _metadata_sender: broadcast::Sender<FeedMetadata>,
+    }
+
+    impl Harness {
+        async fn start() -> Self {
+            let sol = Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap();
+            let a = Pubkey::new_unique();
+            let b = Pubkey::new_unique();
+            let token_cache = Arc::new(TokenCache::new(HashMap::from_iter(
+                [(sol, "SOL"), (a, "A"), (b, "B")].map(|(mint, symbol)| {
+                    (
+                        mint,
+                        Token {
+                            mint,
+                            decimals: 9,
+                            symbol: symbol.to_string(),
+                            mint_info: None,
+                        },
+                    )
+                }),
+            )));
+
+            let program_id = Pubkey::new_unique();
+            let (sol_a, a_b, sol_b) = (
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+            );
+            let mock_dex = Arc::new(MockPoolDex {
+                program_id,
+                pools: vec![(sol_a, sol, a), (a_b, a, b), (sol_b, sol, b)],
+            }) as Arc<dyn DexInterface>;
+            let dex = build_dex_internal(mock_dex, &None, token_cache.clone(), true, false, true, &vec![])
+                .await
+                .unwrap();
+
+            let mut config = Config::default();
+            config.ring.trading_mints = Some(vec![sol.to_string()]);
+            config.ring.max_path_length = Some(3);
+            config.sender.in_amounts = Some(vec![IN_AMOUNT]);
+            config.sender.expected_gain = Some(EXPECTED_GAIN);
+
+            let accounts = Arc::new(MockAccountProvider::default());
+            let chain_data = accounts.clone() as AccountProviderView;
+            let path_warming_amounts = vec![100];
+
+            let (exit_sender, _) = broadcast::channel(1);
+            let (account_update_sender, _) = broadcast::channel(1024);
+            let (slot_sender, _) = broadcast::channel(16);
+            let (metadata_sender, _) = broadcast::channel(16);
+            let (price_sender, _) = broadcast::channel(16);
+            let (register_mint_sender, register_mint_receiver) = async_channel::unbounded();
+            let (ready_sender, ready_receiver) = async_channel::bounded(1);
+            let (edge_price_sender, edge_price_updates) = async_channel::unbounded();
+            let (dex_edge_sender, _) = broadcast::channel(16);
+            let (ring_edge_sender, ring_edge_receiver) = async_channel::unbounded();
+            let (route_sender, route_receiver) = async_channel::unbounded::<Arc<Route>>();
+            let (price_cache, price_cache_job) =
+                PriceCache::new(exit_sender.subscribe(), price_sender.subscribe());
+
+            let updater_job = spawn_updater_job(
+                &dex,
+                &config,
+                chain_data.clone(),
+                token_cache.clone(),
+                price_cache,
+                path_warming_amounts.clone(),
+                register_mint_sender,
+                ready_sender,
+                slot_sender.subscribe(),
+                account_update_sender.subscribe(),
+                metadata_sender.subscribe(),
+                price_sender.subscribe(),
+                dex_edge_sender.subscribe(),
+                exit_sender.subscribe(),
+                edge_price_sender,
+            )
+            .unwrap();
+
+            let control = Arc::new(RuntimeControl::new(&config));
+            let subscriptions = AccountSubscriptions::new(HashSet::new(), HashSet::new());
+            let mint_manager = MintManager::new(
+                token_cache.clone(),
+                &dex.edges(),
+                subscriptions.clone(),
+                dex_edge_sender,
+                ring_edge_sender,
+                None,
+            );
+
+            let ring_executor_job = spawn_ring_executor_job(
+                &config,
+                chain_data,
+                token_cache.clone(),
+                path_warming_amounts,
+                dex.edges(),
+                edge_price_updates,
+                route_sender,
+                token_cache.subscribe(),
+                ring_edge_receiver,
+                Arc::new(RwLock::new(HotMintsCache::new(&None))),
+                control.clone(),
+                None,
+                exit_sender.subscribe(),
+            );
+
+            let sender = RecordingIxSender::default();
+            let executor = SenderExecutor::with_ix_sender(
+                &config,
+                Pubkey::new_unique(),
+                Arc::new(MockHashProvider {}),
+                Arc::new(MockAltProvider {}),
+                accounts.clone(),
+                Arc::new(MockIxBuilder {}),
+                Arc::new(Box::new(sender.clone())),
+            );
+            let sender_job = spawn_sender_loop(executor, route_receiver, exit_sender.subscribe());
+
+            Self {
+                sol,
+                a,
+                program_id,
+                sol_a,
+                a_b,
+                sol_b,
+                token_cache,
+                control,
+                mint_manager,
+                subscriptions,
+                accounts,
+                account_update_sender,
+                ready_receiver,
+                sender,
+                exit_sender,
+                jobs: vec![price_cache_job, updater_job, ring_executor_job, sender_job],
+                slot: 0,
+                _register_mint_receiver: register_mint_receiver,
+                _price_sender: price_sender,
+                _slot_sender: slot_sender,
+                _metadata_sender: metadata_sender,
+            }
+        }
+
+        /// Writes the pool like the chain data updater does, then notifies the edge updater
+        fn set_pool(&mut self, pool: Pubkey, price_ab: f64, price_ba: f64) {
+            self.slot += 1;
+            self.accounts
+                .set_account(pool, self.program_id, mock_pool_data(price_ab, price_ba), self.slot);
+            self.account_update_sender
+                .send((pool, self.program_id, self.slot))
+                .unwrap();
+        }
+
+        /// sol -> a -> b -> sol and its reverse both lose money
+        async fn set_unprofitable_prices(&mut self) {
+            self.set_pool(self.sol_a, 10.0, 0.0995);
+            self.set_pool(self.a_b, 2.0, 0.495);
+            self.set_pool(self.sol_b, 19.0, 0.0499);
+            tokio::time::timeout(Duration::from_secs(5), self.ready_receiver.recv())
+                .await
+                .expect("edge updater ready")
+                .unwrap();
+        }
+
+        fn routes(&self) -> Vec<Arc<Route>> {
+            self.sender.extended.lock().unwrap().clone()
+        }
+
+        async fn wait_for_routes(&self) -> Vec<Arc<Route>> {
+            for _ in 0..50 {
+                let routes = self.routes();
+                if !routes.is_empty() {
+                    return routes;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            vec![]
+        }
+
+        fn has_ring_through(&self, mint: Pubkey) -> bool {
+            self.control
+                .rings()
+                .iter()
+                .any(|ring| ring.edges.iter().any(|e| e.input_mint == mint))
+        }
+
+        async fn wait_for_ring_through(&self, mint: Pubkey) -> bool {
+            for _ in 0..50 {
+                if self.has_ring_through(mint) {
+                    return true;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            false
+        }
+
+        /// Lets the updater (10ms) and the ring executor (100ms) go through a few refreshes
+        async fn settle(&self) {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+
+        async fn stop(self) {
+            self.exit_sender.send(()).unwrap();
+            for job in self.jobs {
+                let _ = tokio::time::timeout(Duration::from_secs(5), job).await;
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn should_only_send_profitable_cycles() {
+        let mut harness = Harness::start().await;
+        harness.set_unprofitable_prices().await;
+        harness.settle().await;
+        assert!(harness.routes().is_empty());
+
+        // b -> sol 变贵: sol -> a -> b -> sol = 10 * 2 * 0.051 = 1.02
+        harness.set_pool(harness.sol_b, 19.0, 0.051);
+        let routes = harness.wait_for_routes().await;
+        assert!(!routes.is_empty(), "profitable cycle was not sent");
+        harness.settle().await;
+
+        let routes = harness.routes();
+        for route in &routes {
+            assert_eq!(route.input_mint, harness.sol);
+            assert_eq!(route.output_mint, harness.sol);
+            assert_eq!(route.in_amount, IN_AMOUNT);
+            assert!(route.out_amount > IN_AMOUNT + EXPECTED_GAIN);
+            let pools = route.steps.iter().map(|s| s.edge.key()).collect::<Vec<_>>();
+            assert_eq!(pools, vec![harness.sol_a, harness.a_b, harness.sol_b]);
+            assert_eq!(route.steps[0].edge.output_mint, route.steps[1].edge.input_mint);
+        }
+        assert_eq!(harness.sender.sent.load(Ordering::Relaxed), routes.len());
+
+        harness.stop().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn should_not_send_cycles_below_expected_gain() {
+        let mut harness = Harness::start().await;
+        harness.set_unprofitable_prices().await;
+
+        // 10 * 2 * 0.05005 = 1.001，收益不超过 expected_gain
+        harness.set_pool(harness.sol_b, 19.0, 0.05005);
+        harness.settle().await;
+        assert!(harness.routes().is_empty());
+        assert_eq!(harness.sender.sent.load(Ordering::Relaxed), 0);
+
+        harness.stop().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn should_trade_a_mint_added_at_runtime() {
+        let mut harness = Harness::start().await;
+        harness.set_unprofitable_prices().await;
+
+        // 新上市的代币 c，池子 sol-c 和 c-a
+        let c = Pubkey::new_unique();
+        let (sol_c, c_a) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mock_dex = Arc::new(MockPoolDex {
+            program_id: harness.program_id,
+            pools: vec![
+                (harness.sol_a, harness.sol, harness.a),
+                (sol_c, harness.sol, c),
+                (c_a, c, harness.a),
+            ],
+        }) as Arc<dyn DexInterface>;
+        let dex = build_dex_internal(
+            mock_dex,
+            &None,
+            harness.token_cache.clone(),
+            true,
+            false,
+            true,
+            &vec![],
+        )
+        .await
+        .unwrap();
+        harness.token_cache.update_mint(
+            c,
+            9,
+            MintInfo {
+                token_program: Pubkey::new_unique(),
+                freeze_authority: None,
+                extensions: vec![],
+            },
+        );
+
+        // 已知的 sol-a 池子不会重复添加
+        assert_eq!(harness.mint_manager.add_edges(c, vec![dex]), 4);
+        for account in [c, sol_c, c_a] {
+            assert!(harness.subscriptions.is_forwarded(&account));
+        }
+        assert!(!harness.subscriptions.is_forwarded(&harness.a_b));
+
+        // 代币加入缓存之前没有经过它的环
+        harness.settle().await;
+        assert!(!harness.has_ring_through(c));
+        assert!(harness
+            .token_cache
+            .insert_from_stream(c, Some("C".to_string()))
+            .unwrap());
+        assert!(
+            harness.wait_for_ring_through(c).await,
+            "no ring through the added mint"
+        );
+
+        // sol -> c -> a -> sol = 5 * 2.05 * 0.0995 = 1.02
+        harness.set_pool(sol_c, 5.0, 0.19);
+        harness.set_pool(c_a, 2.05, 0.48);
+        let routes = harness.wait_for_routes().await;
+        assert!(
+            !routes.is_empty(),
+            "profitable cycle through the added mint was not sent"
+        );
+        for route in &routes {
+            let pools = route.steps.iter().map(|s| s.edge.key()).collect::<Vec<_>>();
+            assert_eq!(pools, vec![sol_c, c_a, harness.sol_a]);
+        }
+
+        harness.token_cache.remove(&c);
+        harness.settle().await;
+        assert!(!harness.has_ring_through(c));
+
+        harness.stop().await;
+    }
+}
@@ -0,0 +1,171 @@
+use solana_sdk::transaction::VersionedTransaction;
+use thiserror::Error;
+
+use crate::prelude::*;
+
+// make sure the transaction can be executed
+pub const MAX_ACCOUNTS_PER_TX: usize = 64;
+pub const MAX_TX_SIZE: usize = 1232;
+
+/// Accounts of the bundle's first transaction not counted by `Edge::accounts_needed`:
+/// payer, source/temp ATAs, temp wallet, native mint, jupiter authority/event accounts and programs
+const FIXED_ACCOUNTS: usize = 16;
+/// Signer and top-level programs (compute budget, token, system, ATA, memo, jupiter),
+/// these can never be loaded from a lookup table
+const STATIC_ACCOUNTS: usize = 7;
+/// signature count + signature + message header + blockhash + account/ix/lookup compact lengths
+const MESSAGE_OVERHEAD: usize = 1 + 64 + 3 + 32 + 3;
+/// compute budget, create ATA, token/system transfers and memo instructions
+const FIXED_IX_BYTES: usize = 96;
+/// jupiter route data: discriminator, amounts, slippage and fee
+const SWAP_IX_BYTES: usize = 40;
+/// per step: route plan entry and the step's dex program
+const STEP_IX_BYTES: usize = 6;
+/// table key + compact lengths of the writable/readonly index lists
+const LOOKUP_TABLE_OVERHEAD: usize = 32 + 2;
+/// create ATA instruction: program index, 6 account indexes, data and their compact lengths
+const CREATE_ATA_IX_BYTES: usize = 10;
+
+#[derive(Error, Debug)]
+pub enum TxPreflightError {
+    #[error("too many accounts: {0} > {MAX_ACCOUNTS_PER_TX}")]
+    TooManyAccounts(usize),
+    #[error("transaction too large: {0} > {MAX_TX_SIZE} bytes")]
+    TooLarge(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TxEstimate {
+    pub accounts: usize,
+    pub size: usize,
+}
+
+impl TxEstimate {
+    /// Estimate the first transaction of the bundle for a ring going through these edges,
+    /// `covered` tells if the accounts of an edge can be loaded from a lookup table
+    pub fn for_edges(edges: &[Arc<Edge>], covered: impl Fn(&Edge) -> bool) -> Self {
+        let mut accounts = FIXED_ACCOUNTS;
+        let mut size = MESSAGE_OVERHEAD
+            + FIXED_IX_BYTES
+            + SWAP_IX_BYTES
+            + STATIC_ACCOUNTS * 32
+            + (FIXED_ACCOUNTS - STATIC_ACCOUNTS) * 32;
+        let mut looked_up = 0;
+
+        for edge in edges {
+            accounts += edge.accounts_needed;
+            // every account is referenced once by the swap instruction
+            size += STEP_IX_BYTES + edge.accounts_needed;
+            if covered(edge) {
+                looked_up += edge.accounts_needed;
+                size += edge.accounts_needed;
+            } else {
+                size += edge.accounts_needed * 32;
+            }
+        }
+
+        if looked_up > 0 {
+            size += LOOKUP_TABLE_OVERHEAD;
+        }
+
+        Self { accounts, size }
+    }
+
+    /// Best case, every edge account resolved through a lookup table. This is the reject
+    /// threshold: the lookup tables of the swap are only known once it is built, the compiled
+    /// transaction goes through `check_transaction`
+    pub fn best_case(edges: &[Arc<Edge>]) -> Self {
+        Self::for_edges(edges, |_| true)
+    }
+
+    /// Adds the create instructions of ATAs the wallet does not have, the new accounts are
+    /// never in a lookup table
+    pub fn with_created_atas(self, count: usize) -> Self {
+        Self {
+            accounts: self.accounts + count,
+            size: self.size + count * (32 + CREATE_ATA_IX_BYTES),
+        }
+    }
+
+    pub fn check(&self) -> Result<(), TxPreflightError> {
+        if self.accounts > MAX_ACCOUNTS_PER_TX {
+            return Err(TxPreflightError::TooManyAccounts(self.accounts));
+        }
+        if self.size > MAX_TX_SIZE {
+            return Err(TxPreflightError::TooLarge(self.size));
+        }
+        Ok(())
+    }
+}
+
+/// Exact limits check on a compiled transaction
+pub fn check_transaction(tx: &VersionedTransaction) -> Result<(), TxPreflightError> {
+    let looked_up = tx
+        .message
+        .address_table_lookups()
+        .map(|lookups| {
+            lookups
+                .iter()
+                .map(|l| l.writable_indexes.len() + l.readonly_indexes.len())
+                .sum::<usize>()
+        })
+        .unwrap_or(0);
+    let accounts = tx.message.static_account_keys().len() + looked_up;
+    if accounts > MAX_ACCOUNTS_PER_TX {
+        return Err(TxPreflightError::TooManyAccounts(accounts));
+    }
+
+    let size = bincode::serialized_size(tx).unwrap_or(u64::MAX) as usize;
+    if size > MAX_TX_SIZE {
+        return Err(TxPreflightError::TooLarge(size));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::test::mock_edge;
+
+    fn edge(accounts_needed: usize) -> Arc<Edge> {
+        let [pool, input_mint, output_mint] = [(); 3].map(|_| Pubkey::new_unique());
+        Arc::new(Edge {
+            accounts_needed,
+            ..mock_edge(pool, input_mint, output_mint, 1.0)
+        })
+    }
+
+    #[test]
+    fn should_reject_rings_with_too_many_accounts() {
+        let edges = vec![edge(20), edge(20), edge(20)];
+        assert!(matches!(
+            TxEstimate::best_case(&edges).check(),
+            Err(TxPreflightError::TooManyAccounts(_))
+        ));
+
+        let edges = vec![edge(10), edge(10)];
+        assert!(TxEstimate::best_case(&edges).check().is_ok());
+
+        // 需要创建的 ATA 也占账户和大小
+        let best = TxEstimate::best_case(&edges);
+        assert_eq!(best.with_created_atas(3).accounts, best.accounts + 3);
+        assert!(matches!(
+            best.with_created_atas(20).check(),
+            Err(TxPreflightError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn should_count_uncovered_edges_at_full_size() {
+        let edges = vec![edge(12), edge(12), edge(12)];
+        assert!(TxEstimate::best_case(&edges).check().is_ok());
+
+        let covered = edges[0].key();
+        let estimate = TxEstimate::for_edges(&edges, |edge| edge.key() == covered);
+        assert!(matches!(
+            estimate.check(),
+            Err(TxPreflightError::TooLarge(_))
+        ));
+        assert!(estimate.size > TxEstimate::best_case(&edges).size);
+    }
+}
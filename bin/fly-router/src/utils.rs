@@ -13,8 +13,9 @@ use solana_sdk::{
     account::ReadableAccount,
     commitment_config::CommitmentConfig,
 };
-use spl_associated_token_account::get_associated_token_address;
+use spl_associated_token_account::get_associated_token_address_with_program_id;
 use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022;
 
 pub fn get_configured_mints(
     mango_metadata: &Option<MangoMetadata>,
@@ -72,32 +73,35 @@ pub async fn get_source_atas(
     owner: &Pubkey,
 ) -> anyhow::Result<HashMap<Pubkey, Pubkey>> {
     
-    // 查询所有者的所有代币账户
-    let token_accounts = client.get_token_accounts_by_owner_with_commitment(
-            owner,
-            TokenAccountsFilter::ProgramId(spl_token::ID),
-            CommitmentConfig::confirmed(),
-        ).await?;  
-    let source_atas = token_accounts.value
-        .into_iter()
-        .filter_map(|kv| {
-            // 链上账户地址
-            let ata_pubkey = Pubkey::from_str(&kv.pubkey).ok()?;
-            // 解析账户数据，提取 mint
-            if let UiAccountData::Json(parsed) = kv.account.data {
-                if let Ok(UiTokenAccount { mint, .. }) = serde_json::from_value::<UiTokenAccount>(parsed.parsed.pointer("/info").unwrap().clone()) {
-                    let mint_pubkey = Pubkey::from_str(&mint).ok()?;   
-                    // 派生官方 ATA
-                    let derived = get_associated_token_address(owner, &mint_pubkey);
-                    // 仅保留与官方派生地址相同的账户
-                    if ata_pubkey == derived {
-                        return Some((mint_pubkey, ata_pubkey));
+    let mut source_atas = HashMap::new();
+    // spl-token 和 Token-2022 的账户分别查询
+    for token_program in [spl_token::ID, spl_token_2022::ID] {
+        // 查询所有者的所有代币账户
+        let token_accounts = client.get_token_accounts_by_owner_with_commitment(
+                owner,
+                TokenAccountsFilter::ProgramId(token_program),
+                CommitmentConfig::confirmed(),
+            ).await?;  
+        source_atas.extend(token_accounts.value
+            .into_iter()
+            .filter_map(|kv| {
+                // 链上账户地址
+                let ata_pubkey = Pubkey::from_str(&kv.pubkey).ok()?;
+                // 解析账户数据，提取 mint
+                if let UiAccountData::Json(parsed) = kv.account.data {
+                    if let Ok(UiTokenAccount { mint, .. }) = serde_json::from_value::<UiTokenAccount>(parsed.parsed.pointer("/info")?.clone()) {
+                        let mint_pubkey = Pubkey::from_str(&mint).ok()?;   
+                        // 派生官方 ATA
+                        let derived = get_associated_token_address_with_program_id(owner, &mint_pubkey, &token_program);
+                        // 仅保留与官方派生地址相同的账户
+                        if ata_pubkey == derived {
+                            return Some((mint_pubkey, ata_pubkey));
+                        }
                     }
                 }
-            }
-            None
-        })
-        .collect();
+                None
+            }));
+    }
 
     Ok(source_atas)
 }
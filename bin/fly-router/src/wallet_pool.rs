@@ -0,0 +1,238 @@
+//! Pool of trading wallets. Each wallet trades its own inventory of the trading mints:
+//! a route goes to a wallet with enough free balance, preferring one without a bundle
+//! in flight so that bundles sent back to back do not write the same accounts.
+
+use std::cmp::Reverse;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use router_config_lib::WalletPoolConfig;
+
+use crate::metrics;
+use crate::prelude::*;
+
+/// 大约是一个 bundle 落地或被丢弃需要的时间
+const DEFAULT_RESERVATION_MS: u64 = 2_000;
+
+/// Inventory set aside for a route until its bundle lands or the reservation expires
+#[derive(Clone, Debug)]
+pub struct Reservation {
+    id: u64,
+    pub wallet: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    expires_at: Instant,
+}
+
+#[derive(Default)]
+struct Inventory {
+    /// token balances by mint, None until the pnl tracker reports them: the wallet is then not limited
+    balances: Option<HashMap<Pubkey, u64>>,
+    reservations: Vec<Reservation>,
+    // SOL 不够支付手续费和小费
+    low_sol: bool,
+    last_used: Option<Instant>,
+}
+
+impl Inventory {
+    fn expire(&mut self, now: Instant) {
+        self.reservations.retain(|r| r.expires_at > now);
+    }
+
+    fn is_busy(&self) -> bool {
+        !self.reservations.is_empty()
+    }
+
+    fn free_balance(&self, mint: &Pubkey) -> Option<u64> {
+        let balance = self
+            .balances
+            .as_ref()?
+            .get(mint)
+            .copied()
+            .unwrap_or_default();
+        let reserved: u64 = self
+            .reservations
+            .iter()
+            .filter(|r| r.mint == *mint)
+            .map(|r| r.amount)
+            .sum();
+        Some(balance.saturating_sub(reserved))
+    }
+}
+
+pub struct WalletPool {
+    /// the first one is the `key_source` wallet
+    wallets: Vec<Pubkey>,
+    reservation_ttl: Duration,
+    inventories: Mutex<HashMap<Pubkey, Inventory>>,
+    next_id: atomic::AtomicU64,
+}
+
+impl WalletPool {
+    pub fn new(wallets: Vec<Pubkey>, config: Option<&WalletPoolConfig>) -> Self {
+        let reservation_ms = config
+            .and_then(|c| c.reservation_ms)
+            .unwrap_or(DEFAULT_RESERVATION_MS);
+        let inventories = wallets
+            .iter()
+            .map(|wallet| (*wallet, Inventory::default()))
+            .collect();
+        Self {
+            wallets,
+            reservation_ttl: Duration::from_millis(reservation_ms),
+            inventories: Mutex::new(inventories),
+            next_id: atomic::AtomicU64::new(0),
+        }
+    }
+
+    pub fn wallets(&self) -> &[Pubkey] {
+        &self.wallets
+    }
+
+    pub fn set_balance(&self, wallet: &Pubkey, mint: Pubkey, amount: u64) {
+        let mut inventories = self.inventories.lock().unwrap();
+        if let Some(inventory) = inventories.get_mut(wallet) {
+            inventory
+                .balances
+                .get_or_insert_with(HashMap::new)
+                .insert(mint, amount);
+        }
+    }
+
+    pub fn set_low_sol(&self, wallet: &Pubkey, low_sol: bool) {
+        let mut inventories = self.inventories.lock().unwrap();
+        if let Some(inventory) = inventories.get_mut(wallet) {
+            if inventory.low_sol != low_sol {
+                if low_sol {
+                    warn!(
+                        "wallet {} is low on SOL, no routes until it is refilled",
+                        wallet
+                    );
+                } else {
+                    info!("wallet {} has enough SOL again", wallet);
+                }
+                inventory.low_sol = low_sol;
+            }
+        }
+    }
+
+    /// Largest free balance of `mint` a route can use, None while some wallet is not limited
+    pub fn max_free_balance(&self, mint: &Pubkey) -> Option<u64> {
+        let now = Instant::now();
+        let mut inventories = self.inventories.lock().unwrap();
+        let mut max_free = 0;
+        for inventory in inventories.values_mut() {
+            if inventory.low_sol {
+                continue;
+            }
+            inventory.expire(now);
+            max_free = max_free.max(inventory.free_balance(mint)?);
+        }
+        Some(max_free)
+    }
+
+    /// Reservations neither released nor expired
+    #[cfg(test)]
+    pub fn in_flight(&self) -> usize {
+        let now = Instant::now();
+        let mut inventories = self.inventories.lock().unwrap();
+        inventories
+            .values_mut()
+            .map(|inventory| {
+                inventory.expire(now);
+                inventory.reservations.len()
+            })
+            .sum()
+    }
+
+    /// Reserves `amount` of `mint` in the idle wallet with the most free balance,
+    /// a busy wallet is only used when no idle one can fund the route
+    pub fn reserve(&self, mint: Pubkey, amount: u64) -> Option<Reservation> {
+        let now = Instant::now();
+        let mut inventories = self.inventories.lock().unwrap();
+        let wallet = self
+            .wallets
+            .iter()
+            .filter_map(|wallet| {
+                let inventory = inventories.get_mut(wallet)?;
+                inventory.expire(now);
+                if inventory.low_sol {
+                    return None;
+                }
+                let free = inventory.free_balance(&mint).unwrap_or(u64::MAX);
+                (free >= amount).then_some((
+                    (inventory.is_busy(), Reverse(free), inventory.last_used),
+                    *wallet,
+                ))
+            })
+            .min_by_key(|(key, _)| *key)
+            .map(|(_, wallet)| wallet);
+        let Some(wallet) = wallet else {
+            metrics::ROUTES_WITHOUT_WALLET.inc();
+            return None;
+        };
+
+        let reservation = Reservation {
+            id: self.next_id.fetch_add(1, atomic::Ordering::Relaxed),
+            wallet,
+            mint,
+            amount,
+            expires_at: now + self.reservation_ttl,
+        };
+        let inventory = inventories.get_mut(&wallet).unwrap();
+        inventory.reservations.push(reservation.clone());
+        inventory.last_used = Some(now);
+        metrics::WALLET_ROUTES
+            .with_label_values(&[&wallet.to_string()])
+            .inc();
+        Some(reservation)
+    }
+
+    /// Gives the inventory back when the bundle was not sent, or once it landed
+    /// and the new balance is known
+    pub fn release(&self, reservation: &Reservation) {
+        let mut inventories = self.inventories.lock().unwrap();
+        if let Some(inventory) = inventories.get_mut(&reservation.wallet) {
+            inventory.reservations.retain(|r| r.id != reservation.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_spread_routes_over_wallets_with_free_inventory() {
+        let (w1, w2, w3) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mint = Pubkey::new_unique();
+        let pool = WalletPool::new(vec![w1, w2, w3], None);
+        pool.set_balance(&w1, mint, 1_000);
+        pool.set_balance(&w2, mint, 600);
+        pool.set_balance(&w3, mint, 5_000);
+        pool.set_low_sol(&w3, true);
+
+        // 余额最多的钱包优先，w3 的 SOL 不够
+        let first = pool.reserve(mint, 500).unwrap();
+        assert_eq!(first.wallet, w1);
+        // w1 有交易在途，换一个钱包
+        let second = pool.reserve(mint, 500).unwrap();
+        assert_eq!(second.wallet, w2);
+        // 都在途时使用仍有余额的钱包
+        assert_eq!(pool.reserve(mint, 500).unwrap().wallet, w1);
+        assert!(pool.reserve(mint, 500).is_none());
+        assert_eq!(pool.max_free_balance(&mint), Some(100));
+        assert_eq!(pool.in_flight(), 3);
+
+        pool.release(&second);
+        assert_eq!(pool.reserve(mint, 500).unwrap().wallet, w2);
+
+        // 没有余额信息的钱包不受限制
+        let pool = WalletPool::new(vec![w1], None);
+        assert_eq!(pool.reserve(mint, u64::MAX).unwrap().wallet, w1);
+    }
+}
@@ -16,8 +16,8 @@ use whirlpools_client::state::Whirlpool;
 
 use router_feed_lib::router_rpc_client::{RouterRpcClient, RouterRpcClientTrait};
 use router_lib::dex::{
-    AccountProviderView, DexEdge, DexEdgeIdentifier, DexInterface, DexSubscriptionMode, Quote,
-    SwapInstruction,
+    pool_mint_option, AccountProviderView, DexEdge, DexEdgeIdentifier, DexInterface,
+    DexSubscriptionMode, Quote, SwapInstruction,
 };
 
 use crate::orca::{fetch_all_whirlpools, load_whirpool, simulate_swap, whirlpool_tick_array_pks};
@@ -59,6 +59,7 @@ impl DexInterface for OrcaDex {
     where
         Self: Sized,
     {
+        let pool_mint = pool_mint_option(&options)?;
         let mut result = OrcaDex {
             program_id: Pubkey::from_str(options.get("program_id").unwrap()).unwrap(),
             program_name: options.get("program_name").unwrap().clone(),
@@ -66,7 +67,8 @@ impl DexInterface for OrcaDex {
         };
 
         result.edges.extend(
-            Self::load_edge_identifiers(rpc, &result.program_name, &result.program_id).await?,
+            Self::load_edge_identifiers(rpc, &result.program_name, &result.program_id, pool_mint)
+                .await?,
         );
 
         Ok(Arc::new(result))
@@ -218,8 +220,17 @@ impl OrcaDex {
         rpc: &mut RouterRpcClient,
         program_name: &str,
         program_id: &Pubkey,
+        pool_mint: Option<Pubkey>,
     ) -> anyhow::Result<HashMap<Pubkey, Vec<Arc<dyn DexEdgeIdentifier>>>> {
-        let whirlpools = fetch_all_whirlpools(rpc, program_id).await?;
+        let whirlpools = fetch_all_whirlpools(rpc, program_id)
+            .await?
+            .into_iter()
+            .filter(|(_wp_pk, wp)| {
+                pool_mint.map_or(true, |mint| {
+                    wp.token_mint_a == mint || wp.token_mint_b == mint
+                })
+            })
+            .collect_vec();
 
         let vaults = whirlpools
             .iter()
@@ -11,8 +11,8 @@ use raydium_cp_swap::program::RaydiumCpSwap;
 use raydium_cp_swap::states::{AmmConfig, PoolState, PoolStatusBitIndex};
 use router_feed_lib::router_rpc_client::{RouterRpcClient, RouterRpcClientTrait};
 use router_lib::dex::{
-    AccountProviderView, DexEdge, DexEdgeIdentifier, DexInterface, DexSubscriptionMode,
-    MixedDexSubscription, Quote, SwapInstruction,
+    pool_mint_option, AccountProviderView, DexEdge, DexEdgeIdentifier, DexInterface,
+    DexSubscriptionMode, MixedDexSubscription, Quote, SwapInstruction,
 };
 use router_lib::utils;
 use solana_account_decoder::UiAccountEncoding;
@@ -41,7 +41,7 @@ pub struct RaydiumCpDex {
 impl DexInterface for RaydiumCpDex {
     async fn initialize(
         rpc: &mut RouterRpcClient,
-        _options: HashMap<String, String>,
+        options: HashMap<String, String>,
         take_all_mints: bool,
         mints: &Vec<String>,
     ) -> anyhow::Result<Arc<dyn DexInterface>>
@@ -49,8 +49,17 @@ impl DexInterface for RaydiumCpDex {
         Self: Sized,
     {
         // info!( "Initializing RaydiumCpDex");
+        let pool_mint = pool_mint_option(&options)?;
         let pools =
             fetch_raydium_account::<PoolState>(rpc, RaydiumCpSwap::id(), PoolState::LEN).await?;
+        let pools = pools
+            .into_iter()
+            .filter(|(_pool_pk, pool)| {
+                pool_mint.map_or(true, |mint| {
+                    pool.token_0_mint == mint || pool.token_1_mint == mint
+                })
+            })
+            .collect_vec();
         
         info!( "RaydiumCpDex Found {} pools", pools.len());
 
@@ -11,8 +11,8 @@ use chrono::Utc;
 use itertools::Itertools;
 use router_feed_lib::router_rpc_client::{RouterRpcClient, RouterRpcClientTrait};
 use router_lib::dex::{
-    AccountProviderView, DexEdge, DexEdgeIdentifier, DexInterface, DexSubscriptionMode,
-    MixedDexSubscription, Quote, SwapInstruction,
+    pool_mint_option, AccountProviderView, DexEdge, DexEdgeIdentifier, DexInterface,
+    DexSubscriptionMode, MixedDexSubscription, Quote, SwapInstruction,
 };
 use solana_account_decoder::UiAccountEncoding;
 use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
@@ -33,13 +33,14 @@ pub struct RaydiumDex {
 impl DexInterface for RaydiumDex {
     async fn initialize(
         rpc: &mut RouterRpcClient,
-        _options: HashMap<String, String>,
+        options: HashMap<String, String>,
         take_all_mints: bool,
         mints: &Vec<String>,
     ) -> anyhow::Result<Arc<dyn DexInterface>>
     where
         Self: Sized,
     {
+        let pool_mint = pool_mint_option(&options)?;
         let pools = fetch_raydium_accounts(rpc, crate::id()).await?;
 
         info!("Number of raydium AMM: {:?}", pools.len());
@@ -52,6 +53,11 @@ impl DexInterface for RaydiumDex {
                     || (mints.contains(&amm.coin_vault_mint.to_string()) && mints.contains(&amm.pc_vault_mint.to_string()));
                 keep
             })
+            .filter(|(_, amm)| {
+                pool_mint.map_or(true, |mint| {
+                    amm.coin_vault_mint == mint || amm.pc_vault_mint == mint
+                })
+            })
             .filter(|(_, amm)| {
                 AmmStatus::from_u64(amm.status).swap_permission()
                     && !AmmStatus::from_u64(amm.status).orderbook_permission()
@@ -10,8 +10,8 @@ use async_trait::async_trait;
 use itertools::Itertools;
 use router_feed_lib::router_rpc_client::{RouterRpcClient, RouterRpcClientTrait};
 use router_lib::dex::{
-    AccountProviderView, DexEdge, DexEdgeIdentifier, DexInterface, DexSubscriptionMode, Quote,
-    SwapInstruction,
+    pool_mint_option, AccountProviderView, DexEdge, DexEdgeIdentifier, DexInterface,
+    DexSubscriptionMode, Quote, SwapInstruction,
 };
 use solana_account_decoder::UiAccountEncoding;
 use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
@@ -33,24 +33,35 @@ pub struct SaberDex {
 impl DexInterface for SaberDex {
     async fn initialize(
         rpc: &mut RouterRpcClient,
-        _options: HashMap<String, String>,
+        options: HashMap<String, String>,
         take_all_mints: bool,
         mints: &Vec<String>,
     ) -> anyhow::Result<Arc<dyn DexInterface>>
     where
         Self: Sized,
     {
+        let pool_mint = pool_mint_option(&options)?;
         let pools =
             fetch_saber_account::<SwapInfo>(rpc, stable_swap_client::id(), SwapInfo::LEN).await?;
 
-        let edge_pairs = pools
-            .iter()
+        // 先过滤池，下面按顺序与 edge_pairs 配对
+        let pools = pools
+            .into_iter()
             //增加对池的过滤，避免请求过多的账户，如果不是加载所有mints，只加载mints中包含的池
             .filter(|(_pool_pk, pool)| {
                 let keep = take_all_mints
                     || (mints.contains(&pool.token_a.mint.to_string()) && mints.contains(&pool.token_b.mint.to_string()));
                 keep
             })
+            .filter(|(_pool_pk, pool)| {
+                pool_mint.map_or(true, |mint| {
+                    pool.token_a.mint == mint || pool.token_b.mint == mint
+                })
+            })
+            .collect_vec();
+
+        let edge_pairs = pools
+            .iter()
             .map(|(pool_pk, pool)| {
                 (
                     Arc::new(SaberEdgeIdentifier {
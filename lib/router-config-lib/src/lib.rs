@@ -1,6 +1,12 @@
-use std::{env, fs::File, io::Read};
+use std::{collections::{BTreeMap, HashMap}, env, fs::File, io::Read};
 
-use serde::{Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer};
+
+mod validation;
+pub use validation::{
+    check_file, check_str, is_valid_pubkey, ConfigError, ConfigErrors, JITO_REGIONS,
+    REGION_SEND_TYPES, SEND_MODES,
+};
 
 #[derive(Clone, Debug, Default, serde_derive::Deserialize)]
 pub struct GrpcSourceConfig {
@@ -49,6 +55,18 @@ pub struct Config {
     pub hot_mints: Option<HotMintsConfig>,
     pub debug_config: Option<DebugConfig>,
     pub snapshot_timeout_in_seconds: Option<u64>,
+    pub snapshot_cache: Option<SnapshotCacheConfig>,
+    pub alt_manager: Option<AltManagerConfig>,
+    pub token_universe: Option<TokenUniverseConfig>,
+    pub recorder: Option<StreamRecorderConfig>,
+    pub journal: Option<JournalConfig>,
+    pub pnl_tracker: Option<PnlTrackerConfig>,
+    pub control_api: Option<ControlApiConfig>,
+    pub config_reload: Option<ConfigReloadConfig>,
+    pub key_source: Option<KeySourceConfig>,
+    pub wallet_pool: Option<WalletPoolConfig>,
+    pub ata_provider: Option<AtaProviderConfig>,
+    pub shutdown: Option<ShutdownConfig>,
 }
 
 impl Config {
@@ -140,10 +158,191 @@ pub struct RoutingConfig {
 
 #[derive(Clone, Debug, Default, serde_derive::Deserialize)]
 pub struct ServerConfig {
+    /// serve /quote, /swap-instructions and /liquidity from the live edges
+    #[serde(default)]
+    pub enabled: bool,
     #[serde(deserialize_with = "serde_string_or_env")]
     pub address: String,
 }
 
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct SnapshotCacheConfig {
+    pub enabled: bool,
+    /// Directory holding the lz4 program snapshots
+    pub path: String,
+    /// Snapshots older than this are fetched again from the RPC
+    pub max_age_secs: Option<u64>,
+    /// Ignore existing snapshots and refresh all of them
+    pub force_refresh: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize)]
+pub enum TokenSource {
+    /// `tokens` from this config
+    Static,
+    /// JSON or CSV file at `file_path`
+    File,
+    /// `onchain_mints` decoded from their mint accounts
+    OnChain,
+    /// top tokens by 24h volume
+    Birdeye,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Deserialize)]
+pub enum TokenCombineMode {
+    #[default]
+    Union,
+    Intersection,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct TokenEntryConfig {
+    pub mint: String,
+    pub symbol: Option<String>,
+    /// Read from the mint account when missing
+    pub decimals: Option<u8>,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct TokenUniverseConfig {
+    pub sources: Vec<TokenSource>,
+    pub combine: Option<TokenCombineMode>,
+    pub tokens: Option<Vec<TokenEntryConfig>>,
+    pub onchain_mints: Option<Vec<String>>,
+    pub file_path: Option<String>,
+    pub birdeye_limit: Option<usize>,
+    /// Use the universe stored in this file if it exists, write it there otherwise
+    pub pinned_snapshot: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct StreamRecorderConfig {
+    pub enabled: bool,
+    /// LZ4 log of the account, slot and snapshot events received from geyser
+    pub path: String,
+    /// Dump of the RPC calls made while initializing the dexes, needed to replay the log offline
+    pub rpc_dump_path: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct JournalConfig {
+    pub enabled: bool,
+    /// JSONL log of the routes given to the sender, their bundles and what happened to them
+    pub path: String,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct PnlTrackerConfig {
+    pub enabled: bool,
+    /// pause trading once the realized PnL of all mints, valued in SOL, falls this far below its peak (lamports)
+    pub max_drawdown_lamports: Option<u64>,
+    /// a wallet whose SOL + WSOL is below this gets no routes, trading pauses once all are (lamports)
+    pub min_sol_balance_lamports: Option<u64>,
+    /// sent bundles that did not land within this are forgotten
+    pub bundle_ttl_secs: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct ControlApiConfig {
+    pub enabled: bool,
+    /// keep it on localhost, defaults to 127.0.0.1:8899
+    pub address: Option<String>,
+    /// bearer token required on every request
+    #[serde(deserialize_with = "serde_string_or_env")]
+    pub token: String,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct ConfigReloadConfig {
+    /// reload on SIGHUP
+    pub enabled: bool,
+    /// also reload when the file changes, checked at this interval
+    pub poll_interval_secs: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Deserialize)]
+pub enum KeySourceKind {
+    /// encrypted base58 key, decrypted with a passphrase
+    #[default]
+    Encrypted,
+    /// keypair file written by `solana-keygen`
+    JsonFile,
+    /// signing requests sent to a local process over a unix socket, the key never enters the router
+    RemoteSigner,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct KeySourceConfig {
+    #[serde(default)]
+    pub kind: KeySourceKind,
+    /// Encrypted: the encrypted key, defaults to the second command line argument
+    #[serde(default, deserialize_with = "serde_opt_string_or_env")]
+    pub encrypted_key: Option<String>,
+    /// Encrypted: read the passphrase from this environment variable, it is removed once read
+    pub passphrase_env: Option<String>,
+    /// Encrypted: read the passphrase from this inherited file descriptor, e.g. a systemd credential
+    pub passphrase_fd: Option<i32>,
+    /// JsonFile: path of the keypair file
+    pub path: Option<String>,
+    /// RemoteSigner: path of the signer socket
+    pub socket_path: Option<String>,
+    /// RemoteSigner: refuse to start if the signer reports another pubkey
+    pub pubkey: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct WalletPoolConfig {
+    /// trading wallets besides the `key_source` wallet, which is always the first of the pool
+    #[serde(default)]
+    pub wallets: Vec<KeySourceConfig>,
+    /// inventory spent by a sent bundle stays reserved, and the wallet busy, for this long (ms)
+    pub reservation_ms: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct AltManagerConfig {
+    pub enabled: bool,
+    /// Only log the tables that would be created or extended
+    pub dry_run: Option<bool>,
+    pub refresh_interval_secs: Option<u64>,
+    /// Number of sent swaps an account must appear in before it is added to a table
+    pub min_account_hits: Option<u32>,
+    /// Maximum number of wallet-owned tables
+    pub max_tables: Option<usize>,
+    /// Delay before newly added addresses are used, they only resolve from the next slot
+    pub warmup_ms: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct AtaProviderConfig {
+    /// Add idempotent create-ATA instructions for the route mints a wallet has no ATA for, defaults to true
+    pub create_missing: Option<bool>,
+    /// Pre-create the ATAs of the mints of all active rings in the background
+    pub precreate: bool,
+    /// Rent the pre-create job may spend in total, required with `precreate`
+    pub rent_budget_lamports: Option<u64>,
+    /// The rent spent so far is saved there so that a restart does not get a fresh budget,
+    /// required with `precreate`
+    pub rent_spent_path: Option<String>,
+    /// Number of ATAs created per transaction
+    pub precreate_batch_size: Option<usize>,
+    /// Interval of the RPC refresh of the wallet token accounts and of the pre-creation
+    pub refresh_interval_secs: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct ShutdownConfig {
+    /// How long to wait for the bundles in flight once new routes are refused, defaults to 15
+    pub drain_timeout_secs: Option<u64>,
+    /// How long the jobs get to stop, the sender sweeps the temporary tip accounts meanwhile, defaults to 30
+    pub job_timeout_secs: Option<u64>,
+    /// The RingState of every ring is saved there on shutdown, the cooldowns are restored on start
+    pub ring_state_path: Option<String>,
+    /// Temporary tip wallets that could not be swept on shutdown are saved there (secret keys,
+    /// written 0600) and swept on the next start
+    pub tip_account_path: Option<String>,
+}
+
 #[derive(Clone, Debug, Default, serde_derive::Deserialize)]
 pub struct DebugConfig {
     pub reprice_using_live_rpc: bool,
@@ -163,6 +362,22 @@ pub struct PriceFeedConfig {
     pub birdeye_token: String,
     pub birdeye_single_mode: Option<bool>,
     pub refresh_interval_secs: u64,
+    /// Derive prices from the router's own pools, the HTTP feed is only used as a reference
+    pub onchain: Option<OnChainPriceFeedConfig>,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct OnChainPriceFeedConfig {
+    pub enabled: bool,
+    /// SOL/USDC and SOL/USDT pools anchoring the SOL/USD price, every such pool when empty
+    pub sol_usd_pools: Option<Vec<String>>,
+    /// Size of the round trip used to measure the depth of a pool
+    pub probe_usd: Option<f64>,
+    /// Number of deepest pools averaged per mint
+    pub max_pools: Option<usize>,
+    /// Drop on-chain prices deviating more than this from the HTTP feed, disabled without a birdeye token
+    pub max_deviation_pct: Option<f64>,
+    pub refresh_interval_ms: Option<u64>,
 }
 
 /// Get a string content, or the content of an Env variable it the string start with $
@@ -171,51 +386,38 @@ pub struct PriceFeedConfig {
 ///  - "abc" -> "abc"
 ///  - "$something" -> read env variable named something and return it's content
 ///
-/// *WARNING*: May kill the program if we are asking for anv environment variable that does not exist
+/// A missing environment variable is a deserialization error
 pub fn serde_string_or_env<'de, D>(deserializer: D) -> Result<String, D::Error>
 where
     D: Deserializer<'de>,
 {
     let value_or_env = String::deserialize(deserializer)?;
-    let value = match &value_or_env.chars().next().unwrap() {
-        '$' => env::var(&value_or_env[1..])
-            .unwrap_or_else(|_| panic!("reading `{}` from env", &value_or_env[1..])),
-        _ => value_or_env,
-    };
-    Ok(value)
+    try_string_or_env(value_or_env).map_err(de::Error::custom)
 }
 
-/// Get a string content, or the content of an Env variable it the string start with $
-///
-/// Example:
-///  - "abc" -> "abc"
-///  - "$something" -> read env variable named something and return it's content
-///
-/// *WARNING*: May kill the program if we are asking for anv environment variable that does not exist
+/// Same as `serde_string_or_env` for optional fields
 pub fn serde_opt_string_or_env<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let value_or_env = Option::<String>::deserialize(deserializer)?;
-    if value_or_env.is_none() {
-        return Ok(None);
-    }
+    Option::<String>::deserialize(deserializer)?
+        .map(try_string_or_env)
+        .transpose()
+        .map_err(de::Error::custom)
+}
 
-    let value_or_env = value_or_env.unwrap();
-    let value = match &value_or_env.chars().next().unwrap() {
-        '$' => env::var(&value_or_env[1..]).expect("reading from env"),
-        _ => value_or_env,
-    };
-    Ok(Some(value))
+pub fn try_string_or_env(value_or_env: String) -> anyhow::Result<String> {
+    match value_or_env.strip_prefix('$') {
+        Some(name) => env::var(name)
+            .map_err(|_| anyhow::anyhow!("environment variable `{}` is not set", name)),
+        None => Ok(value_or_env),
+    }
 }
 
+/// *WARNING*: May kill the program if we are asking for anv environment variable that does not exist,
+/// only use it on values already checked by `check_file`
 pub fn string_or_env(value_or_env: String) -> String {
-    let value = match &value_or_env.chars().next().unwrap() {
-        '$' => env::var(&value_or_env[1..]).expect("reading from env"),
-        _ => value_or_env,
-    };
-
-    value
+    try_string_or_env(value_or_env).unwrap_or_else(|e| panic!("{}", e))
 }
 
 
@@ -223,7 +425,18 @@ pub fn string_or_env(value_or_env: String) -> String {
 pub struct RingConfig {
     pub trading_mints: Option<Vec<String>>,
     pub max_path_length: Option<usize>,
-    
+    /// Order in which dirty rings are evaluated
+    pub priority: Option<RingPriorityConfig>,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct RingPriorityConfig {
+    pub hot_mint_weight: Option<f64>,
+    pub gain_weight: Option<f64>,
+    pub liquidity_weight: Option<f64>,
+    pub staleness_weight: Option<f64>,
+    /// A ring not evaluated for this long gets half of the staleness score
+    pub staleness_half_life_ms: Option<u64>,
 }
 
 #[derive(Clone, Debug, Default, serde_derive::Deserialize)]
@@ -232,6 +445,9 @@ pub struct SenderConfig {
     pub name: Option<String>,
     pub in_amounts: Option<Vec<u64>>,
     pub expected_gain: Option<u64>,
+    /// in_amounts and expected_gain of a trading mint in its native units, keyed by mint;
+    /// mints not listed use the two settings above
+    pub trading_mint_amounts: Option<BTreeMap<String, TradingMintAmountsConfig>>,
     pub lookup_tables: Option<Vec<String>>,
    // pub wrap_and_unwrap_sol: Option<bool>,
     pub send_mode: Option<String>,
@@ -241,6 +457,42 @@ pub struct SenderConfig {
     pub jito_tip_bps: Option<f32>,
     pub jito_max_tip: Option<u64>,
     pub jito_regions: Option<Vec<String>>,
+    /// a ring that failed pre-flight, simulation or tx building is skipped for this long,
+    /// times n * 1.2^n on its n-th failure with n capped at 5
+    pub ring_cooldown_secs: Option<u64>,
     pub region_send_type: Option<String>,
     pub jupiter_url: Option<String>,
+    /// refresh the blockhash at least this often even if no new slot was seen
+    pub blockhash_refresh_interval_ms: Option<u64>,
+    /// refuse to send with a blockhash older than this
+    pub max_blockhash_age_ms: Option<u64>,
+    /// refuse to send when the blockhash expires within this many blocks
+    pub min_blockhash_blocks_left: Option<u64>,
+    /// simulation before sending, keyed by send mode name
+    pub simulation: Option<HashMap<String, SimulationConfig>>,
+    /// paper trading, used when send_mode = "DryRun"
+    pub dry_run: Option<DryRunConfig>,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct TradingMintAmountsConfig {
+    pub in_amounts: Option<Vec<u64>>,
+    pub expected_gain: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct DryRunConfig {
+    /// JSONL ledger of the bundles that would have been sent
+    pub ledger_path: Option<String>,
+    /// simulate the swap transaction against the current chain state, defaults to true
+    pub simulate: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct SimulationConfig {
+    pub enabled: bool,
+    /// fraction of the transactions simulated, between 0 and 1
+    pub sample_rate: Option<f64>,
+    /// minimum change of the source token account in the simulated swap transaction, tip included
+    pub min_net_gain: Option<i64>,
 }
\ No newline at end of file
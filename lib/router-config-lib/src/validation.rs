@@ -0,0 +1,709 @@
+//! Checks a config file without starting anything.
+//! Every problem is reported with its toml path instead of stopping at the first one.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+
+use crate::{Config, KeySourceConfig, KeySourceKind, PathWarmingMode, TokenSource};
+
+/// Names accepted by `sender.send_mode`, and as keys of `sender.simulation`
+pub const SEND_MODES: &[&str] = &["JitoBundle", "DryRun"];
+/// `sender.region_send_type`
+pub const REGION_SEND_TYPES: &[&str] = &["serial", "parallel"];
+/// Jito block engines, `https://<region>.mainnet.block-engine.jito.wtf`
+pub const JITO_REGIONS: &[&str] = &[
+    "amsterdam",
+    "dublin",
+    "frankfurt",
+    "london",
+    "ny",
+    "slc",
+    "singapore",
+    "tokyo",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigError {
+    /// toml path, e.g. `sources[0].rpc_http_url`, empty for the whole file
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// All the errors of a config, displayed one per line
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} config error(s):", self.0.len())?;
+        for error in self.0.iter() {
+            writeln!(f, "  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// Loads and validates the config at `path`
+pub fn check_file(path: &str) -> Result<Config, ConfigErrors> {
+    let contents = fs::read_to_string(path).map_err(|e| {
+        ConfigErrors(vec![ConfigError {
+            path: String::new(),
+            message: format!("cannot read {}: {}", path, e),
+        }])
+    })?;
+    check_str(&contents)
+}
+
+pub fn check_str(contents: &str) -> Result<Config, ConfigErrors> {
+    let mut value = contents.parse::<toml::Value>().map_err(|e| {
+        ConfigErrors(vec![ConfigError {
+            path: String::new(),
+            message: e.to_string(),
+        }])
+    })?;
+
+    // 缺少的环境变量先全部找出来，再用空字符串代替，以便继续检查其它字段
+    let mut errors = vec![];
+    resolve_missing_env(&mut value, "", &mut errors);
+    let config = if errors.is_empty() {
+        // 直接从文本解析，错误信息带有行号
+        toml::from_str::<Config>(contents)
+    } else {
+        value.try_into::<Config>()
+    };
+    match config {
+        Ok(config) => {
+            errors.extend(config.validate());
+            if errors.is_empty() {
+                Ok(config)
+            } else {
+                Err(ConfigErrors(errors))
+            }
+        }
+        Err(e) => {
+            errors.push(ConfigError {
+                path: String::new(),
+                message: e.to_string(),
+            });
+            Err(ConfigErrors(errors))
+        }
+    }
+}
+
+/// Fields read with `serde_string_or_env` / `serde_opt_string_or_env`, array indices left out
+const ENV_FIELDS: &[&str] = &[
+    "server.address",
+    "sources.rpc_http_url",
+    "sources.quic_sources.connection_string",
+    "control_api.token",
+    "key_source.encrypted_key",
+    "wallet_pool.wallets.encrypted_key",
+    "price_feed.birdeye_token",
+];
+
+fn is_env_field(path: &str) -> bool {
+    let mut field = String::with_capacity(path.len());
+    let mut in_index = false;
+    for c in path.chars() {
+        match c {
+            '[' => in_index = true,
+            ']' => in_index = false,
+            c if !in_index => field.push(c),
+            _ => {}
+        }
+    }
+    ENV_FIELDS.contains(&field.as_str())
+}
+
+// 其它字段里以 `$` 开头的字符串（例如代币符号）不是环境变量
+fn resolve_missing_env(value: &mut toml::Value, path: &str, errors: &mut Vec<ConfigError>) {
+    match value {
+        toml::Value::String(s) if is_env_field(path) => {
+            if let Some(name) = s.strip_prefix('$') {
+                if std::env::var(name).is_err() {
+                    errors.push(ConfigError {
+                        path: path.to_string(),
+                        message: format!("environment variable `{}` is not set", name),
+                    });
+                    s.clear();
+                }
+            }
+        }
+        toml::Value::Array(values) => {
+            for (i, v) in values.iter_mut().enumerate() {
+                resolve_missing_env(v, &format!("{}[{}]", path, i), errors);
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, v) in table.iter_mut() {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                resolve_missing_env(v, &path, errors);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn is_valid_pubkey(key: &str) -> bool {
+    bs58::decode(key)
+        .into_vec()
+        .map_or(false, |bytes| bytes.len() == 32)
+}
+
+#[derive(Default)]
+struct Validator {
+    errors: Vec<ConfigError>,
+}
+
+impl Validator {
+    fn error(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.errors.push(ConfigError {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    fn pubkeys<'a>(&mut self, path: &str, keys: impl IntoIterator<Item = &'a String>) {
+        for (i, key) in keys.into_iter().enumerate() {
+            if !is_valid_pubkey(key) {
+                self.error(
+                    format!("{}[{}]", path, i),
+                    format!("invalid pubkey `{}`", key),
+                );
+            }
+        }
+    }
+
+    fn one_of(&mut self, path: &str, value: &str, allowed: &[&str]) {
+        if !allowed.contains(&value) {
+            self.error(
+                path,
+                format!(
+                    "unknown value `{}`, expected one of {}",
+                    value,
+                    allowed.join(", ")
+                ),
+            );
+        }
+    }
+
+    fn address(&mut self, path: &str, address: &str) {
+        if address.parse::<SocketAddr>().is_err() {
+            self.error(
+                path,
+                format!("invalid address `{}`, expected ip:port", address),
+            );
+        }
+    }
+
+    fn amounts(&mut self, path: &str, amounts: &[u64]) {
+        if amounts.is_empty() {
+            self.error(path, "must not be empty");
+        }
+        for (i, amount) in amounts.iter().enumerate() {
+            if *amount == 0 {
+                self.error(format!("{}[{}]", path, i), "must be greater than 0");
+            }
+        }
+    }
+
+    fn range<T: PartialOrd + fmt::Display>(&mut self, path: &str, value: T, min: T, max: T) {
+        if !(min..=max).contains(&value) {
+            self.error(
+                path,
+                format!("{} is out of range, expected {} to {}", value, min, max),
+            );
+        }
+    }
+
+    fn not_empty(&mut self, path: &str, value: &str) {
+        if value.trim().is_empty() {
+            self.error(path, "must not be empty");
+        }
+    }
+
+    fn key_source(&mut self, path: &str, key_source: &KeySourceConfig, needs_encrypted_key: bool) {
+        match key_source.kind {
+            KeySourceKind::Encrypted => {
+                if key_source.passphrase_env.is_some() && key_source.passphrase_fd.is_some() {
+                    self.error(
+                        format!("{}.passphrase_fd", path),
+                        "passphrase_env and passphrase_fd are mutually exclusive",
+                    );
+                }
+                if needs_encrypted_key {
+                    self.not_empty(
+                        &format!("{}.encrypted_key", path),
+                        key_source.encrypted_key.as_deref().unwrap_or(""),
+                    );
+                }
+            }
+            KeySourceKind::JsonFile => self.not_empty(
+                &format!("{}.path", path),
+                key_source.path.as_deref().unwrap_or(""),
+            ),
+            KeySourceKind::RemoteSigner => {
+                self.not_empty(
+                    &format!("{}.socket_path", path),
+                    key_source.socket_path.as_deref().unwrap_or(""),
+                );
+                if let Some(pubkey) = &key_source.pubkey {
+                    if !is_valid_pubkey(pubkey) {
+                        self.error(
+                            format!("{}.pubkey", path),
+                            format!("invalid pubkey `{}`", pubkey),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Config {
+    /// Every error of an already parsed config
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut v = Validator::default();
+
+        // ring
+        let ring = &self.ring;
+        v.pubkeys("ring.trading_mints", ring.trading_mints.iter().flatten());
+        if ring.max_path_length.map_or(false, |l| l < 2) {
+            v.error("ring.max_path_length", "a ring needs at least 2 hops");
+        }
+        if let Some(priority) = &ring.priority {
+            for (name, weight) in [
+                ("hot_mint_weight", priority.hot_mint_weight),
+                ("gain_weight", priority.gain_weight),
+                ("liquidity_weight", priority.liquidity_weight),
+                ("staleness_weight", priority.staleness_weight),
+            ] {
+                if weight.map_or(false, |w| w < 0.0) {
+                    v.error(format!("ring.priority.{}", name), "must not be negative");
+                }
+            }
+            if priority.staleness_half_life_ms == Some(0) {
+                v.error(
+                    "ring.priority.staleness_half_life_ms",
+                    "must be greater than 0",
+                );
+            }
+        }
+
+        // sender
+        let sender = &self.sender;
+        if let Some(in_amounts) = &sender.in_amounts {
+            v.amounts("sender.in_amounts", in_amounts);
+        }
+        for (mint, amounts) in sender.trading_mint_amounts.iter().flatten() {
+            let path = format!("sender.trading_mint_amounts.{}", mint);
+            if !is_valid_pubkey(mint) {
+                v.error(&path, format!("invalid pubkey `{}`", mint));
+            }
+            if let Some(in_amounts) = &amounts.in_amounts {
+                v.amounts(&format!("{}.in_amounts", path), in_amounts);
+            }
+        }
+        v.pubkeys(
+            "sender.lookup_tables",
+            sender.lookup_tables.iter().flatten(),
+        );
+        let send_mode = sender.send_mode.as_deref().unwrap_or("JitoBundle");
+        v.one_of("sender.send_mode", send_mode, SEND_MODES);
+        if let Some(slippage_bps) = sender.slippage_bps {
+            v.range("sender.slippage_bps", slippage_bps, 0, 10_000);
+        }
+        if sender.ring_cooldown_secs == Some(0) {
+            v.error("sender.ring_cooldown_secs", "must be greater than 0");
+        }
+        if let Some(jito_tip_bps) = sender.jito_tip_bps {
+            v.range("sender.jito_tip_bps", jito_tip_bps, 0.0, 1.0);
+        }
+        if let Some(jito_regions) = &sender.jito_regions {
+            if jito_regions.is_empty() {
+                v.error("sender.jito_regions", "must not be empty");
+            }
+            let mut seen = HashSet::new();
+            for (i, region) in jito_regions.iter().enumerate() {
+                let path = format!("sender.jito_regions[{}]", i);
+                v.one_of(&path, region, JITO_REGIONS);
+                if !seen.insert(region) {
+                    v.error(path, format!("duplicate region `{}`", region));
+                }
+            }
+        }
+        if let Some(region_send_type) = &sender.region_send_type {
+            v.one_of(
+                "sender.region_send_type",
+                region_send_type,
+                REGION_SEND_TYPES,
+            );
+        }
+        if let Some(max_blockhash_age_ms) = sender.max_blockhash_age_ms {
+            if max_blockhash_age_ms == 0 {
+                v.error("sender.max_blockhash_age_ms", "must be greater than 0");
+            }
+        }
+        for (mode, simulation) in sender.simulation.iter().flatten() {
+            let path = format!("sender.simulation.{}", mode);
+            v.one_of(&path, mode, SEND_MODES);
+            if let Some(sample_rate) = simulation.sample_rate {
+                v.range(&format!("{}.sample_rate", path), sample_rate, 0.0, 1.0);
+            }
+        }
+        // 模拟交易时不能真的创建或扩展地址查找表
+        if send_mode == "DryRun" {
+            if let Some(alt_manager) = &self.alt_manager {
+                if alt_manager.enabled && !alt_manager.dry_run.unwrap_or(false) {
+                    v.error(
+                        "alt_manager.dry_run",
+                        "must be true when sender.send_mode is DryRun, tables would be paid for",
+                    );
+                }
+            }
+        }
+
+        // routing
+        let routing = &self.routing;
+        v.pubkeys("routing.lookup_tables", routing.lookup_tables.iter());
+        v.pubkeys(
+            "routing.path_warming_for_mints",
+            routing.path_warming_for_mints.iter().flatten(),
+        );
+        if let Some(PathWarmingMode::ConfiguredMints) = routing.path_warming_mode {
+            if routing
+                .path_warming_for_mints
+                .as_ref()
+                .map_or(true, |m| m.is_empty())
+            {
+                v.error(
+                    "routing.path_warming_for_mints",
+                    "required when routing.path_warming_mode is ConfiguredMints",
+                );
+            }
+        }
+        if let Some(amounts) = &routing.path_warming_amounts {
+            v.amounts("routing.path_warming_amounts", amounts);
+        }
+        if let Some(overquote) = routing.overquote {
+            v.range("routing.overquote", overquote, 0.0, 1.0);
+        }
+
+        // servers
+        if self.server.enabled {
+            v.address("server.address", &self.server.address);
+        }
+        if self.metrics.output_http {
+            if let Some(address) = &self.metrics.prometheus_address {
+                v.address("metrics.prometheus_address", address);
+            }
+        }
+        if let Some(control_api) = &self.control_api {
+            if control_api.enabled {
+                v.not_empty("control_api.token", &control_api.token);
+                if let Some(address) = &control_api.address {
+                    v.address("control_api.address", address);
+                }
+            }
+        }
+
+        // sources
+        // main 使用没有 region 的数据源
+        let default_source = self
+            .sources
+            .iter()
+            .enumerate()
+            .find(|(_, s)| s.region.is_none());
+        if default_source.is_none() {
+            v.error("sources", "a source without region is required");
+        }
+        for (i, source) in self.sources.iter().enumerate() {
+            let path = format!("sources[{}]", i);
+            v.not_empty(&format!("{}.rpc_http_url", path), &source.rpc_http_url);
+            for (j, grpc) in source.grpc_sources.iter().flatten().enumerate() {
+                v.not_empty(
+                    &format!("{}.grpc_sources[{}].connection_string", path, j),
+                    &grpc.connection_string,
+                );
+            }
+            for (j, quic) in source.quic_sources.iter().flatten().enumerate() {
+                v.not_empty(
+                    &format!("{}.quic_sources[{}].connection_string", path, j),
+                    &quic.connection_string,
+                );
+            }
+        }
+
+        // prices and tokens
+        if let Some(onchain) = &self.price_feed.onchain {
+            v.pubkeys(
+                "price_feed.onchain.sol_usd_pools",
+                onchain.sol_usd_pools.iter().flatten(),
+            );
+            if onchain.probe_usd.map_or(false, |p| p <= 0.0) {
+                v.error("price_feed.onchain.probe_usd", "must be greater than 0");
+            }
+            if onchain.max_pools == Some(0) {
+                v.error("price_feed.onchain.max_pools", "must be greater than 0");
+            }
+        }
+        if let Some(hot_mints) = &self.hot_mints {
+            v.pubkeys(
+                "hot_mints.always_hot_mints",
+                hot_mints.always_hot_mints.iter(),
+            );
+        }
+        if let Some(universe) = &self.token_universe {
+            if universe.sources.is_empty() {
+                v.error("token_universe.sources", "must not be empty");
+            }
+            for source in universe.sources.iter() {
+                match source {
+                    TokenSource::Static if universe.tokens.is_none() => v.error(
+                        "token_universe.tokens",
+                        "required by the Static token source",
+                    ),
+                    TokenSource::File if universe.file_path.is_none() => v.error(
+                        "token_universe.file_path",
+                        "required by the File token source",
+                    ),
+                    TokenSource::OnChain if universe.onchain_mints.is_none() => v.error(
+                        "token_universe.onchain_mints",
+                        "required by the OnChain token source",
+                    ),
+                    TokenSource::Birdeye => {
+                        if let Some((i, _)) =
+                            default_source.filter(|(_, s)| s.birdeye_token.is_empty())
+                        {
+                            v.error(
+                                format!("sources[{}].birdeye_token", i),
+                                "required by the Birdeye token source",
+                            )
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            for (i, token) in universe.tokens.iter().flatten().enumerate() {
+                if !is_valid_pubkey(&token.mint) {
+                    v.error(
+                        format!("token_universe.tokens[{}].mint", i),
+                        format!("invalid pubkey `{}`", token.mint),
+                    );
+                }
+            }
+            v.pubkeys(
+                "token_universe.onchain_mints",
+                universe.onchain_mints.iter().flatten(),
+            );
+        }
+
+        // misc
+        if let Some(debug_config) = &self.debug_config {
+            v.range(
+                "debug_config.reprice_probability",
+                debug_config.reprice_probability,
+                0.0,
+                1.0,
+            );
+        }
+        if let Some(snapshot_cache) = &self.snapshot_cache {
+            if snapshot_cache.enabled {
+                v.not_empty("snapshot_cache.path", &snapshot_cache.path);
+            }
+        }
+        if let Some(recorder) = &self.recorder {
+            if recorder.enabled {
+                v.not_empty("recorder.path", &recorder.path);
+            }
+        }
+        if let Some(journal) = &self.journal {
+            if journal.enabled {
+                v.not_empty("journal.path", &journal.path);
+            }
+        }
+        if let Some(alt_manager) = &self.alt_manager {
+            if alt_manager.max_tables == Some(0) {
+                v.error("alt_manager.max_tables", "must be greater than 0");
+            }
+        }
+
+        if let Some(key_source) = &self.key_source {
+            v.key_source("key_source", key_source, false);
+        }
+        if let Some(wallet_pool) = &self.wallet_pool {
+            for (i, wallet) in wallet_pool.wallets.iter().enumerate() {
+                // 只有主钱包可以从命令行传入加密密钥
+                v.key_source(&format!("wallet_pool.wallets[{}]", i), wallet, true);
+            }
+            if wallet_pool.reservation_ms == Some(0) {
+                v.error("wallet_pool.reservation_ms", "must be greater than 0");
+            }
+        }
+        if let Some(ata_provider) = &self.ata_provider {
+            if ata_provider.precreate && ata_provider.rent_budget_lamports.is_none() {
+                v.error(
+                    "ata_provider.rent_budget_lamports",
+                    "is required when precreate is enabled",
+                );
+            }
+            match &ata_provider.rent_spent_path {
+                Some(path) => v.not_empty("ata_provider.rent_spent_path", path),
+                None if ata_provider.precreate => v.error(
+                    "ata_provider.rent_spent_path",
+                    "is required when precreate is enabled",
+                ),
+                None => {}
+            }
+            // 每个创建指令有 6 个账户，更多放不进一笔交易
+            if let Some(batch_size) = ata_provider.precreate_batch_size {
+                if !(1..=10).contains(&batch_size) {
+                    v.error("ata_provider.precreate_batch_size", "must be between 1 and 10");
+                }
+            }
+            if ata_provider.refresh_interval_secs == Some(0) {
+                v.error("ata_provider.refresh_interval_secs", "must be greater than 0");
+            }
+        }
+        if let Some(shutdown) = &self.shutdown {
+            if let Some(path) = &shutdown.ring_state_path {
+                v.not_empty("shutdown.ring_state_path", path);
+            }
+            if let Some(path) = &shutdown.tip_account_path {
+                v.not_empty("shutdown.tip_account_path", path);
+            }
+        }
+
+        v.errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOL: &str = "So11111111111111111111111111111111111111112";
+
+    #[test]
+    fn should_only_resolve_env_in_env_fields() {
+        let mut value = r#"
+            [[sources]]
+            rpc_http_url = "$FLY_ROUTER_TEST_MISSING_ENV"
+
+            [token_universe]
+            tokens = [{ mint = "So11111111111111111111111111111111111111112", symbol = "$WIF" }]
+            "#
+        .parse::<toml::Value>()
+        .unwrap();
+        let mut errors = vec![];
+        resolve_missing_env(&mut value, "", &mut errors);
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "sources[0].rpc_http_url");
+        assert_eq!(
+            value["token_universe"]["tokens"][0]["symbol"].as_str(),
+            Some("$WIF")
+        );
+    }
+
+    #[test]
+    fn should_report_every_error_with_its_path() {
+        let contents = format!(
+            r#"
+            [ring]
+            trading_mints = ["{SOL}", "not-a-mint"]
+
+            [sender]
+            in_amounts = [100, 0]
+            send_mode = "Jitobundle"
+            jito_regions = ["frankfurt", "mars"]
+            lookup_tables = ["123"]
+
+            [routing]
+            path_cache_validity_ms = 1000
+            lookup_tables = []
+            path_warming_mode = "ConfiguredMints"
+
+            [server]
+            address = "$FLY_ROUTER_TEST_MISSING_ENV"
+
+            [metrics]
+            output_http = false
+            output_stdout = false
+
+            [price_feed]
+            birdeye_token = ""
+            refresh_interval_secs = 600
+
+            [orca]
+            enabled = false
+            add_mango_tokens = false
+            take_all_mints = false
+
+            [cropper]
+            enabled = false
+            add_mango_tokens = false
+            take_all_mints = false
+
+            [raydium_cp]
+            enabled = false
+            add_mango_tokens = false
+            take_all_mints = false
+
+            [raydium]
+            enabled = false
+            add_mango_tokens = false
+            take_all_mints = false
+
+            [saber]
+            enabled = false
+            add_mango_tokens = false
+            take_all_mints = false
+
+            [[sources]]
+            rpc_http_url = "http://localhost:8899"
+            dedup_queue_size = 100
+            birdeye_token = ""
+            "#
+        );
+
+        let errors = check_str(&contents).unwrap_err().0;
+        let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                "server.address: environment variable `FLY_ROUTER_TEST_MISSING_ENV` is not set",
+                "ring.trading_mints[1]: invalid pubkey `not-a-mint`",
+                "sender.in_amounts[1]: must be greater than 0",
+                "sender.lookup_tables[0]: invalid pubkey `123`",
+                "sender.send_mode: unknown value `Jitobundle`, expected one of JitoBundle, DryRun",
+                "sender.jito_regions[1]: unknown value `mars`, expected one of amsterdam, dublin, frankfurt, london, ny, slc, singapore, tokyo",
+                "routing.path_warming_for_mints: required when routing.path_warming_mode is ConfiguredMints",
+            ]
+        );
+
+        let syntax_error = check_str("[sender\nname = 1").unwrap_err().0;
+        assert_eq!(syntax_error.len(), 1);
+        assert!(syntax_error[0].path.is_empty());
+    }
+}
@@ -152,6 +152,19 @@ impl AccountProvider for ChainDataAccountProvider {
 
 pub type AccountProviderView = Arc<dyn AccountProvider>;
 
+/// `DexInterface::initialize` option: only load the pools trading this mint,
+/// used when a mint is added at runtime
+pub const POOL_MINT_OPTION: &str = "pool_mint";
+
+/// Mint of the `POOL_MINT_OPTION` option, None when all the pools are loaded
+pub fn pool_mint_option(options: &HashMap<String, String>) -> anyhow::Result<Option<Pubkey>> {
+    options
+        .get(POOL_MINT_OPTION)
+        .map(|mint| Pubkey::from_str(mint))
+        .transpose()
+        .map_err(|e| anyhow::anyhow!("invalid {} option: {}", POOL_MINT_OPTION, e))
+}
+
 #[async_trait::async_trait]
 pub trait DexInterface: Sync + Send {
     /// Called on router boot, with the options read from the dex adapters's
@@ -175,7 +175,7 @@ impl RouterClient {
             ("slippageBps", format!("{}", slippage_bps)),
             ("onlyDirectRoutes", only_direct_routes.to_string()),
             ("maxAccounts", format!("{}", max_account)),
-            ("isExactOut", format!("{}", swap_mode.to_string())),
+            ("swapMode", swap_mode.to_string()),
         ];
 
         let response = self